@@ -0,0 +1 @@
+DROP TABLE context_shards;
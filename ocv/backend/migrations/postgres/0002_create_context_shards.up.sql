@@ -0,0 +1,16 @@
+-- The vector column stays a plain FLOAT[] until it moves to pgvector;
+-- an ivfflat index cannot be built on an array column.
+CREATE TABLE context_shards (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    domain TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    vector_representation FLOAT[] NULL,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    content BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    version INT NOT NULL DEFAULT 1
+);
+
+CREATE INDEX idx_context_shards_user_domain ON context_shards(user_id, domain);
@@ -0,0 +1,14 @@
+CREATE TABLE access_grants (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    context_domains TEXT[] NOT NULL,
+    expires_at TIMESTAMPTZ NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_access_grants_user ON access_grants(user_id);
+CREATE INDEX idx_access_grants_client ON access_grants(client_id);
+CREATE INDEX idx_access_grants_expires ON access_grants(expires_at)
+WHERE expires_at IS NOT NULL;
@@ -0,0 +1 @@
+DROP TABLE consent_audit_logs;
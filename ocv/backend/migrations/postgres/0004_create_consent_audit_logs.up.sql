@@ -0,0 +1,11 @@
+CREATE TABLE consent_audit_logs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_consent_audit_logs_user ON consent_audit_logs(user_id);
+CREATE INDEX idx_consent_audit_logs_timestamp ON consent_audit_logs(timestamp);
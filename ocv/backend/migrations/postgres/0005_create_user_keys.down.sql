@@ -0,0 +1,2 @@
+DROP TABLE master_key_check;
+DROP TABLE user_keys;
@@ -0,0 +1,13 @@
+-- Per-user data keys, sealed under the vault master key
+CREATE TABLE user_keys (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    wrapped_key BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A known value sealed under the master key, used to detect a wrong key on startup
+CREATE TABLE master_key_check (
+    id SMALLINT PRIMARY KEY CHECK (id = 1),
+    sealed_value BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,7 @@
+-- Only the first version of each key fits the old schema
+DELETE FROM user_keys WHERE version <> 1 OR wrapped_key IS NULL;
+ALTER TABLE user_keys DROP CONSTRAINT user_keys_pkey;
+ALTER TABLE user_keys DROP COLUMN retired_at;
+ALTER TABLE user_keys DROP COLUMN version;
+ALTER TABLE user_keys ALTER COLUMN wrapped_key SET NOT NULL;
+ALTER TABLE user_keys ADD PRIMARY KEY (user_id);
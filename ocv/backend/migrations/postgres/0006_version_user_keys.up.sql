@@ -0,0 +1,7 @@
+-- Users can hold several key versions while their shards are re-encrypted.
+-- Retired versions keep their row but lose the key material.
+ALTER TABLE user_keys DROP CONSTRAINT user_keys_pkey;
+ALTER TABLE user_keys ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE user_keys ADD COLUMN retired_at TIMESTAMPTZ NULL;
+ALTER TABLE user_keys ALTER COLUMN wrapped_key DROP NOT NULL;
+ALTER TABLE user_keys ADD PRIMARY KEY (user_id, version);
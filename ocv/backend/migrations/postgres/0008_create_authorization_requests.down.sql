@@ -0,0 +1,4 @@
+DROP TABLE authorization_requests;
+DELETE FROM sessions WHERE client_id IS NOT NULL;
+ALTER TABLE sessions DROP COLUMN grant_id;
+ALTER TABLE sessions DROP COLUMN client_id;
@@ -0,0 +1,3 @@
+DROP TABLE client_assertions;
+
+ALTER TABLE clients DROP COLUMN jwks;
@@ -0,0 +1,11 @@
+-- Public keys of clients that authenticate with private_key_jwt
+ALTER TABLE clients ADD COLUMN jwks JSONB NULL;
+
+-- IDs of client assertions already used, kept until they expire so none is
+-- accepted twice
+CREATE TABLE client_assertions (
+    client_id TEXT NOT NULL REFERENCES clients(client_id) ON DELETE CASCADE,
+    jti TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (client_id, jti)
+);
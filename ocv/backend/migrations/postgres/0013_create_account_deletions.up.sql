@@ -0,0 +1,9 @@
+-- One record per deleted account, kept after the user row is gone. It holds
+-- no user ID or other personal data, only what was erased and when.
+CREATE TABLE account_deletions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    details JSONB NOT NULL DEFAULT '{}',
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_account_deletions_deleted_at ON account_deletions(deleted_at);
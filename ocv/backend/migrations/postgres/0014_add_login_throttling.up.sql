@@ -0,0 +1,23 @@
+-- Recent failed logins per account or source address. Rows are keyed by a
+-- hash of the email address or IP, so unknown emails are not stored.
+CREATE TABLE login_attempts (
+    subject_hash TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failure_at TIMESTAMPTZ NOT NULL,
+    locked_until TIMESTAMPTZ NULL
+);
+
+CREATE INDEX idx_login_attempts_last_failure ON login_attempts(last_failure_at);
+
+-- Sign-ins, lockouts and credential changes that users can review
+CREATE TABLE security_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_security_events_user ON security_events(user_id, created_at);
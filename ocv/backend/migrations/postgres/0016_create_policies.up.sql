@@ -0,0 +1,18 @@
+-- Rego policies the engine evaluates. Every change adds a version, and the
+-- current version of an enabled policy is the one in force.
+CREATE TABLE policies (
+    name TEXT PRIMARY KEY,
+    current_version INTEGER NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE policy_versions (
+    policy_name TEXT NOT NULL REFERENCES policies(name) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    author_id UUID NULL REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (policy_name, version)
+);
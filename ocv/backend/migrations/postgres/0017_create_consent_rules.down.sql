@@ -0,0 +1,2 @@
+DROP TABLE access_approvals;
+DROP TABLE consent_rules;
@@ -0,0 +1,31 @@
+-- Standing rules users set for the clients they share context with, checked
+-- after the global consent policy.
+CREATE TABLE consent_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    effect TEXT NOT NULL,
+    client_id TEXT NULL,
+    domains TEXT[] NOT NULL,
+    scopes TEXT[] NOT NULL,
+    inactive_days INTEGER NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_consent_rules_user ON consent_rules(user_id);
+
+-- Accesses held back by a rule until the user approves them. An approval
+-- lets the client through once.
+CREATE TABLE access_approvals (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    rule_id UUID NOT NULL REFERENCES consent_rules(id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    approved_at TIMESTAMPTZ NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_access_approvals_user ON access_approvals(user_id);
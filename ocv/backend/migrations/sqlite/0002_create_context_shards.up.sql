@@ -0,0 +1,15 @@
+CREATE TABLE context_shards (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    domain TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    -- JSON array of floats
+    vector_representation TEXT NULL,
+    metadata TEXT NOT NULL DEFAULT '{}',
+    content BLOB NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE INDEX idx_context_shards_user_domain ON context_shards(user_id, domain);
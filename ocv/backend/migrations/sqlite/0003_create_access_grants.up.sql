@@ -0,0 +1,15 @@
+-- scopes and context_domains are JSON arrays of strings
+CREATE TABLE access_grants (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    context_domains TEXT NOT NULL,
+    expires_at TEXT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_access_grants_user ON access_grants(user_id);
+CREATE INDEX idx_access_grants_client ON access_grants(client_id);
+CREATE INDEX idx_access_grants_expires ON access_grants(expires_at)
+WHERE expires_at IS NOT NULL;
@@ -0,0 +1,11 @@
+CREATE TABLE consent_audit_logs (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT NOT NULL DEFAULT '{}',
+    timestamp TEXT NOT NULL
+);
+
+CREATE INDEX idx_consent_audit_logs_user ON consent_audit_logs(user_id);
+CREATE INDEX idx_consent_audit_logs_timestamp ON consent_audit_logs(timestamp);
@@ -0,0 +1,13 @@
+-- Per-user data keys, sealed under the vault master key
+CREATE TABLE user_keys (
+    user_id BLOB PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    wrapped_key BLOB NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- A known value sealed under the master key, used to detect a wrong key on startup
+CREATE TABLE master_key_check (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    sealed_value BLOB NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Only the first version of each key fits the old schema
+CREATE TABLE user_keys_unversioned (
+    user_id BLOB PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    wrapped_key BLOB NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+INSERT INTO user_keys_unversioned (user_id, wrapped_key, created_at)
+SELECT user_id, wrapped_key, created_at FROM user_keys
+WHERE version = 1 AND wrapped_key IS NOT NULL;
+
+DROP TABLE user_keys;
+ALTER TABLE user_keys_unversioned RENAME TO user_keys;
@@ -0,0 +1,16 @@
+-- Users can hold several key versions while their shards are re-encrypted.
+-- Retired versions keep their row but lose the key material.
+CREATE TABLE user_keys_versioned (
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL DEFAULT 1,
+    wrapped_key BLOB NULL,
+    created_at TEXT NOT NULL,
+    retired_at TEXT NULL,
+    PRIMARY KEY (user_id, version)
+);
+
+INSERT INTO user_keys_versioned (user_id, version, wrapped_key, created_at)
+SELECT user_id, 1, wrapped_key, created_at FROM user_keys;
+
+DROP TABLE user_keys;
+ALTER TABLE user_keys_versioned RENAME TO user_keys;
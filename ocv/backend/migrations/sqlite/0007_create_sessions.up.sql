@@ -0,0 +1,15 @@
+-- Login sessions. The refresh token is `<session id>.<secret>`; only the
+-- SHA-256 of the current secret is stored and it changes on every refresh.
+CREATE TABLE sessions (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    refresh_token_hash TEXT NOT NULL,
+    user_agent TEXT NULL,
+    ip_address TEXT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT NULL
+);
+
+CREATE INDEX idx_sessions_user ON sessions(user_id);
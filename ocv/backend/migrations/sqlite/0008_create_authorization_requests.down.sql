@@ -0,0 +1,22 @@
+DROP TABLE authorization_requests;
+
+-- SQLite cannot drop a column that references another table
+CREATE TABLE sessions_unbound (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    refresh_token_hash TEXT NOT NULL,
+    user_agent TEXT NULL,
+    ip_address TEXT NULL,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT NULL
+);
+
+INSERT INTO sessions_unbound
+SELECT id, user_id, refresh_token_hash, user_agent, ip_address, created_at, last_used_at, expires_at, revoked_at
+FROM sessions WHERE client_id IS NULL;
+
+DROP TABLE sessions;
+ALTER TABLE sessions_unbound RENAME TO sessions;
+CREATE INDEX idx_sessions_user ON sessions(user_id);
@@ -0,0 +1,23 @@
+-- Sessions started by an OAuth client act under one access grant and end
+-- when the grant is revoked.
+ALTER TABLE sessions ADD COLUMN client_id TEXT NULL;
+ALTER TABLE sessions ADD COLUMN grant_id BLOB NULL REFERENCES access_grants(id) ON DELETE CASCADE;
+
+-- OAuth authorization requests, from the client's redirect through the
+-- user's consent to the one-time exchange of their code.
+CREATE TABLE authorization_requests (
+    id BLOB PRIMARY KEY NOT NULL,
+    client_id TEXT NOT NULL,
+    redirect_uri TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    context_domains TEXT NOT NULL,
+    state TEXT NULL,
+    code_challenge TEXT NOT NULL,
+    user_id BLOB NULL REFERENCES users(id) ON DELETE CASCADE,
+    grant_id BLOB NULL REFERENCES access_grants(id) ON DELETE CASCADE,
+    code_hash TEXT NULL UNIQUE,
+    session_id BLOB NULL REFERENCES sessions(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    exchanged_at TEXT NULL
+);
@@ -0,0 +1,21 @@
+-- Client applications that may ask users for access. Only the SHA-256 of a
+-- confidential client's secret is stored. redirect_uris, allowed_scopes and
+-- allowed_domains are JSON arrays of strings. A client outlives the account
+-- that registered it, so other users' grants to it are revoked explicitly.
+CREATE TABLE clients (
+    client_id TEXT PRIMARY KEY NOT NULL,
+    owner_id BLOB NULL REFERENCES users(id) ON DELETE SET NULL,
+    name TEXT NOT NULL,
+    logo_uri TEXT NULL,
+    homepage_uri TEXT NULL,
+    redirect_uris TEXT NOT NULL,
+    token_endpoint_auth_method TEXT NOT NULL,
+    client_secret_hash TEXT NULL,
+    allowed_scopes TEXT NOT NULL,
+    allowed_domains TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_clients_owner ON clients(owner_id);
@@ -0,0 +1,14 @@
+-- Set once the user follows the link in their verification email
+ALTER TABLE users ADD COLUMN email_verified_at TEXT NULL;
+
+-- Single-use tokens sent by email to verify the address or reset the
+-- password. Only the SHA-256 of each token is stored.
+CREATE TABLE email_tokens (
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_email_tokens_user ON email_tokens(user_id, purpose);
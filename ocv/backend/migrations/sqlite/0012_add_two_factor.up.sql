@@ -0,0 +1,26 @@
+-- TOTP secrets, sealed with the user's data key. Two-factor authentication
+-- is on once `enabled_at` is set by confirming a first code.
+CREATE TABLE totp_credentials (
+    user_id BLOB PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    secret BLOB NOT NULL,
+    enabled_at TEXT NULL,
+    -- Time step of the last accepted code, so no code is accepted twice
+    last_used_step INTEGER NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Single-use recovery codes, stored as SHA-256 hashes
+CREATE TABLE recovery_codes (
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
+
+-- Logins waiting for their second factor. Only the SHA-256 of each
+-- challenge token is stored.
+CREATE TABLE login_challenges (
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- One record per deleted account, kept after the user row is gone. It holds
+-- no user ID or other personal data, only what was erased and when.
+CREATE TABLE account_deletions (
+    id BLOB PRIMARY KEY NOT NULL,
+    details TEXT NOT NULL DEFAULT '{}',
+    deleted_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_account_deletions_deleted_at ON account_deletions(deleted_at);
@@ -0,0 +1,16 @@
+-- Personal access tokens for scripts and command-line tools, each limited to
+-- its own scopes and domains. Only the SHA-256 of the token is stored.
+CREATE TABLE personal_access_tokens (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL,
+    domains TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    last_used_at TEXT NULL,
+    created_at TEXT NOT NULL,
+    revoked_at TEXT NULL
+);
+
+CREATE INDEX idx_personal_access_tokens_user ON personal_access_tokens(user_id);
@@ -0,0 +1,32 @@
+-- Standing rules users set for the clients they share context with, checked
+-- after the global consent policy. domains and scopes are JSON arrays of
+-- strings.
+CREATE TABLE consent_rules (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    effect TEXT NOT NULL,
+    client_id TEXT NULL,
+    domains TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    inactive_days INTEGER NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_consent_rules_user ON consent_rules(user_id);
+
+-- Accesses held back by a rule until the user approves them. An approval
+-- lets the client through once.
+CREATE TABLE access_approvals (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    rule_id BLOB NOT NULL REFERENCES consent_rules(id) ON DELETE CASCADE,
+    client_id TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    approved_at TEXT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_access_approvals_user ON access_approvals(user_id);
@@ -0,0 +1,254 @@
+//! Behaviour every `MemoryAdapter` implementation must share
+//!
+//! Each adapter's test module calls [`run`] with two distinct, existing users.
+
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::MemoryAdapter;
+use crate::context_management::models::{ContextShard, CreateShardInput, UpdateShardInput};
+
+/// Run the whole suite against an adapter
+pub async fn run<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid, other_user_id: Uuid) {
+    store_and_get_round_trip(adapter, user_id).await;
+    get_missing_returns_none(adapter).await;
+    update_bumps_version_and_merges_metadata(adapter, user_id).await;
+    update_with_stale_version_fails(adapter, user_id).await;
+    update_missing_returns_none(adapter).await;
+    delete_removes_item(adapter, user_id).await;
+    get_by_domain_filters_and_limits(adapter, user_id, other_user_id).await;
+    search_matches_domain_and_metadata(adapter, user_id, other_user_id).await;
+    get_by_user_lists_only_that_user(adapter, user_id, other_user_id).await;
+}
+
+fn shard_input(user_id: Uuid, domain: &str, metadata: serde_json::Value) -> CreateShardInput {
+    let metadata: HashMap<String, serde_json::Value> =
+        serde_json::from_value(metadata).expect("metadata must be an object");
+
+    CreateShardInput {
+        user_id,
+        domain: domain.to_string(),
+        content_type: "preferences".to_string(),
+        vector_representation: Some(vec![0.25, 0.5]),
+        metadata,
+        content: serde_json::Value::Null,
+    }
+}
+
+fn empty_update(current_version: i32) -> UpdateShardInput {
+    UpdateShardInput {
+        domain: None,
+        content_type: None,
+        vector_representation: None,
+        metadata: None,
+        content: None,
+        current_version,
+    }
+}
+
+fn ids(shards: &[ContextShard]) -> Vec<Uuid> {
+    let mut ids: Vec<Uuid> = shards.iter().map(|s| s.id).collect();
+    ids.sort();
+    ids
+}
+
+/// Unique domain so runs against a shared database do not collide
+fn unique_domain(prefix: &str) -> String {
+    format!("{}-{}", prefix, Uuid::new_v4().simple())
+}
+
+async fn store_and_get_round_trip<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid) {
+    let domain = unique_domain("round-trip");
+    let id = Uuid::new_v4();
+    let stored = adapter
+        .store_item(id, shard_input(user_id, &domain, serde_json::json!({ "source": "test" })), vec![1, 2, 3])
+        .await
+        .unwrap();
+
+    assert_eq!(stored.id, id);
+    assert_eq!(stored.user_id, user_id);
+    assert_eq!(stored.domain, domain);
+    assert_eq!(stored.version, 1);
+    assert_eq!(stored.content, vec![1, 2, 3]);
+
+    let fetched = adapter.get_item(&stored.id.to_string()).await.unwrap().unwrap();
+    assert_eq!(fetched.id, stored.id);
+    assert_eq!(fetched.content_type, "preferences");
+    assert_eq!(fetched.vector_representation, Some(vec![0.25, 0.5]));
+    assert_eq!(fetched.metadata.0.get("source"), Some(&serde_json::json!("test")));
+    assert_eq!(fetched.content, vec![1, 2, 3]);
+}
+
+async fn get_missing_returns_none<A: MemoryAdapter + ?Sized>(adapter: &A) {
+    let missing = adapter.get_item(&Uuid::new_v4().to_string()).await.unwrap();
+    assert!(missing.is_none());
+}
+
+async fn update_bumps_version_and_merges_metadata<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid) {
+    let stored = adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &unique_domain("update"), serde_json::json!({ "a": 1 })), vec![1])
+        .await
+        .unwrap();
+
+    let mut update = empty_update(stored.version);
+    update.content_type = Some("history".to_string());
+    update.metadata = Some(HashMap::from([("b".to_string(), serde_json::json!(2))]));
+
+    let updated = adapter
+        .update_item(&stored.id.to_string(), update, Some(vec![9, 9]))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(updated.version, stored.version + 1);
+    assert_eq!(updated.content_type, "history");
+    assert_eq!(updated.domain, stored.domain);
+    assert_eq!(updated.content, vec![9, 9]);
+    assert_eq!(updated.metadata.0.get("a"), Some(&serde_json::json!(1)));
+    assert_eq!(updated.metadata.0.get("b"), Some(&serde_json::json!(2)));
+
+    // Content is left alone when no new ciphertext is supplied
+    let again = adapter
+        .update_item(&stored.id.to_string(), empty_update(updated.version), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(again.version, updated.version + 1);
+    assert_eq!(again.content, vec![9, 9]);
+}
+
+async fn update_with_stale_version_fails<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid) {
+    let stored = adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &unique_domain("stale"), serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+
+    adapter
+        .update_item(&stored.id.to_string(), empty_update(stored.version), None)
+        .await
+        .unwrap();
+
+    let stale = adapter
+        .update_item(&stored.id.to_string(), empty_update(stored.version), Some(vec![2]))
+        .await;
+    assert!(stale.is_err());
+
+    let current = adapter.get_item(&stored.id.to_string()).await.unwrap().unwrap();
+    assert_eq!(current.version, stored.version + 1);
+    assert_eq!(current.content, vec![1]);
+}
+
+async fn update_missing_returns_none<A: MemoryAdapter + ?Sized>(adapter: &A) {
+    let missing = adapter
+        .update_item(&Uuid::new_v4().to_string(), empty_update(1), None)
+        .await
+        .unwrap();
+    assert!(missing.is_none());
+}
+
+async fn delete_removes_item<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid) {
+    let stored = adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &unique_domain("delete"), serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+    let id = stored.id.to_string();
+
+    assert!(adapter.delete_item(&id).await.unwrap());
+    assert!(adapter.get_item(&id).await.unwrap().is_none());
+    assert!(!adapter.delete_item(&id).await.unwrap());
+}
+
+async fn get_by_domain_filters_and_limits<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid, other_user_id: Uuid) {
+    let domain = unique_domain("by-domain");
+    let mut expected = Vec::new();
+    for _ in 0..3 {
+        let shard = adapter
+            .store_item(Uuid::new_v4(), shard_input(user_id, &domain, serde_json::json!({})), vec![1])
+            .await
+            .unwrap();
+        expected.push(shard);
+    }
+
+    // Same domain for another user, and another domain for this user
+    adapter
+        .store_item(Uuid::new_v4(), shard_input(other_user_id, &domain, serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+    adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &unique_domain("by-domain"), serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+
+    let all = adapter
+        .get_items_by_domain(&user_id.to_string(), &domain, None)
+        .await
+        .unwrap();
+    assert_eq!(ids(&all), ids(&expected));
+
+    let limited = adapter
+        .get_items_by_domain(&user_id.to_string(), &domain, Some(2))
+        .await
+        .unwrap();
+    assert_eq!(limited.len(), 2);
+    assert!(limited.iter().all(|s| s.user_id == user_id && s.domain == domain));
+}
+
+async fn search_matches_domain_and_metadata<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid, other_user_id: Uuid) {
+    let marker = Uuid::new_v4().simple().to_string();
+    let travel = format!("travel-{}", marker);
+    let food = format!("food-{}", marker);
+
+    let by_domain = adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &travel, serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+    let by_metadata = adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &food, serde_json::json!({ "cuisine": format!("Thai-{}", marker) })), vec![1])
+        .await
+        .unwrap();
+    adapter
+        .store_item(Uuid::new_v4(), shard_input(other_user_id, &travel, serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+
+    let user = user_id.to_string();
+
+    // Substring of the domain
+    let results = adapter.search_items(&user, &travel, None, None).await.unwrap();
+    assert_eq!(ids(&results), ids(std::slice::from_ref(&by_domain)));
+
+    // Metadata values, case-insensitively
+    let query = format!("thai-{}", marker);
+    let results = adapter.search_items(&user, &query, None, None).await.unwrap();
+    assert_eq!(ids(&results), ids(std::slice::from_ref(&by_metadata)));
+
+    // Both shards share the marker, and the domain filter narrows the result
+    let results = adapter.search_items(&user, &marker, None, None).await.unwrap();
+    assert_eq!(ids(&results), ids(&[by_domain.clone(), by_metadata.clone()]));
+
+    let results = adapter.search_items(&user, &marker, Some(&food), None).await.unwrap();
+    assert_eq!(ids(&results), ids(std::slice::from_ref(&by_metadata)));
+
+    let results = adapter.search_items(&user, &marker, None, Some(1)).await.unwrap();
+    assert_eq!(results.len(), 1);
+
+    let results = adapter.search_items(&user, &Uuid::new_v4().to_string(), None, None).await.unwrap();
+    assert!(results.is_empty());
+}
+
+async fn get_by_user_lists_only_that_user<A: MemoryAdapter + ?Sized>(adapter: &A, user_id: Uuid, other_user_id: Uuid) {
+    let stored = adapter
+        .store_item(Uuid::new_v4(), shard_input(user_id, &unique_domain("by-user"), serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+    let other = adapter
+        .store_item(Uuid::new_v4(), shard_input(other_user_id, &unique_domain("by-user"), serde_json::json!({})), vec![1])
+        .await
+        .unwrap();
+
+    let all = adapter.get_items_by_user(&user_id.to_string()).await.unwrap();
+    assert!(all.iter().all(|s| s.user_id == user_id));
+    assert!(all.iter().any(|s| s.id == stored.id));
+    assert!(!all.iter().any(|s| s.id == other.id));
+    assert!(all.windows(2).all(|w| w[0].created_at <= w[1].created_at));
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::types::Json;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use super::MemoryAdapter;
+use crate::context_management::models::{ContextShard, CreateShardInput, UpdateShardInput};
+
+/// Adapter keeping context shards in process memory
+///
+/// Nothing is persisted, so this is only suitable for tests and
+/// single-process deployments that can afford to lose data on restart.
+#[derive(Default)]
+pub struct InMemoryAdapter {
+    shards: RwLock<HashMap<Uuid, ContextShard>>,
+}
+
+impl InMemoryAdapter {
+    /// Create a new, empty in-memory adapter
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+/// Check whether a shard's domain or metadata contains the query
+fn matches_query(shard: &ContextShard, query: &str) -> bool {
+    let query = query.to_lowercase();
+
+    if shard.domain.to_lowercase().contains(&query) {
+        return true;
+    }
+
+    shard.metadata.0.iter().any(|(key, value)| {
+        key.to_lowercase().contains(&query) || value.to_string().to_lowercase().contains(&query)
+    })
+}
+
+/// Apply an optional limit to a result set
+fn apply_limit(mut shards: Vec<ContextShard>, limit: Option<i64>) -> Vec<ContextShard> {
+    if let Some(limit) = limit {
+        shards.truncate(limit.max(0) as usize);
+    }
+    shards
+}
+
+#[async_trait]
+impl MemoryAdapter for InMemoryAdapter {
+    async fn store_item(&self, id: Uuid, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard> {
+        let now = Utc::now();
+        let shard = ContextShard {
+            id,
+            user_id: input.user_id,
+            domain: input.domain,
+            content_type: input.content_type,
+            vector_representation: input.vector_representation,
+            metadata: Json(input.metadata),
+            content: encrypted_content,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        };
+
+        let mut shards = self.shards.write().unwrap();
+        shards.insert(shard.id, shard.clone());
+
+        Ok(shard)
+    }
+
+    async fn get_item(&self, id: &str) -> Result<Option<ContextShard>> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let shards = self.shards.read().unwrap();
+        Ok(shards.get(&id).cloned())
+    }
+
+    async fn update_item(&self, id: &str, input: UpdateShardInput, encrypted_content: Option<Vec<u8>>) -> Result<Option<ContextShard>> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let mut shards = self.shards.write().unwrap();
+        let Some(shard) = shards.get_mut(&id) else {
+            return Ok(None);
+        };
+
+        // Check version
+        if shard.version != input.current_version {
+            return Err(anyhow::anyhow!("Version mismatch"));
+        }
+
+        if let Some(domain) = input.domain {
+            shard.domain = domain;
+        }
+
+        if let Some(content_type) = input.content_type {
+            shard.content_type = content_type;
+        }
+
+        if let Some(vector) = input.vector_representation {
+            shard.vector_representation = Some(vector);
+        }
+
+        if let Some(metadata) = input.metadata {
+            shard.metadata.0.extend(metadata);
+        }
+
+        if let Some(content) = encrypted_content {
+            shard.content = content;
+        }
+
+        shard.version += 1;
+        shard.updated_at = Utc::now();
+
+        Ok(Some(shard.clone()))
+    }
+
+    async fn delete_item(&self, id: &str) -> Result<bool> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(false);
+        };
+
+        let mut shards = self.shards.write().unwrap();
+        Ok(shards.remove(&id).is_some())
+    }
+
+    async fn search_items(
+        &self,
+        user_id: &str,
+        query: &str,
+        domain: Option<&str>,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        let mut results: Vec<ContextShard> = {
+            let shards = self.shards.read().unwrap();
+            shards
+                .values()
+                .filter(|s| s.user_id == user_id)
+                .filter(|s| domain.is_none_or(|d| s.domain == d))
+                .filter(|s| matches_query(s, query))
+                .cloned()
+                .collect()
+        };
+
+        results.sort_by_key(|s| Reverse(s.updated_at));
+
+        Ok(apply_limit(results, limit))
+    }
+
+    async fn get_items_by_domain(
+        &self,
+        user_id: &str,
+        domain: &str,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        let mut results: Vec<ContextShard> = {
+            let shards = self.shards.read().unwrap();
+            shards
+                .values()
+                .filter(|s| s.user_id == user_id && s.domain == domain)
+                .cloned()
+                .collect()
+        };
+
+        results.sort_by_key(|s| Reverse(s.created_at));
+
+        Ok(apply_limit(results, limit))
+    }
+
+    async fn get_items_by_user(&self, user_id: &str) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        let mut results: Vec<ContextShard> = {
+            let shards = self.shards.read().unwrap();
+            shards
+                .values()
+                .filter(|s| s.user_id == user_id)
+                .cloned()
+                .collect()
+        };
+
+        results.sort_by_key(|s| s.created_at);
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::conformance;
+
+    #[tokio::test]
+    async fn passes_conformance_suite() {
+        let adapter = InMemoryAdapter::new();
+
+        conformance::run(adapter.as_ref(), Uuid::new_v4(), Uuid::new_v4()).await;
+    }
+}
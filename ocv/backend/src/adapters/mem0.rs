@@ -5,6 +5,7 @@ use uuid::Uuid;
 use reqwest::Client;
 use async_trait::async_trait;
 
+use super::MemoryAdapter;
 use crate::context_management::models::{ContextShard, CreateShardInput, UpdateShardInput};
 
 /// Configuration for mem0 client
@@ -57,38 +58,6 @@ pub struct Mem0Adapter {
     client: Client,
 }
 
-#[async_trait]
-pub trait MemoryAdapter {
-    /// Store a memory item
-    async fn store_item(&self, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard>;
-    
-    /// Retrieve a memory item
-    async fn get_item(&self, id: &str) -> Result<Option<ContextShard>>;
-    
-    /// Update a memory item
-    async fn update_item(&self, id: &str, input: UpdateShardInput, encrypted_content: Option<Vec<u8>>) -> Result<Option<ContextShard>>;
-    
-    /// Delete a memory item
-    async fn delete_item(&self, id: &str) -> Result<bool>;
-    
-    /// Search for memory items
-    async fn search_items(
-        &self, 
-        user_id: &str,
-        query: &str, 
-        domain: Option<&str>,
-        limit: Option<i64>
-    ) -> Result<Vec<ContextShard>>;
-    
-    /// Get items by domain
-    async fn get_items_by_domain(
-        &self,
-        user_id: &str,
-        domain: &str,
-        limit: Option<i64>
-    ) -> Result<Vec<ContextShard>>;
-}
-
 impl Mem0Adapter {
     /// Create a new mem0 adapter
     pub fn new(config: Mem0Config) -> Arc<Self> {
@@ -109,8 +78,10 @@ impl Mem0Adapter {
             metadata: sqlx::types::Json(
                 item.metadata
                     .as_object()
-                    .unwrap_or(&serde_json::Map::new())
-                    .clone()
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect()
             ),
             content: base64::decode(&item.content)?,
             created_at: chrono::DateTime::parse_from_rfc3339(&item.created_at)?.into(),
@@ -118,36 +89,20 @@ impl Mem0Adapter {
             version: item.version,
         })
     }
-    
-    /// Convert a context shard to a mem0 item
-    fn to_mem0_item(&self, shard: &ContextShard) -> Result<Mem0Item> {
-        Ok(Mem0Item {
-            id: shard.id.to_string(),
-            user_id: shard.user_id.to_string(),
-            domain: shard.domain.clone(),
-            content_type: shard.content_type.clone(),
-            embedding: shard.vector_representation.clone(),
-            metadata: serde_json::Value::Object(shard.metadata.0.clone()),
-            content: base64::encode(&shard.content),
-            created_at: shard.created_at.to_rfc3339(),
-            updated_at: shard.updated_at.to_rfc3339(),
-            version: shard.version,
-        })
-    }
 }
 
 #[async_trait]
 impl MemoryAdapter for Mem0Adapter {
-    async fn store_item(&self, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard> {
+    async fn store_item(&self, id: Uuid, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard> {
         let url = format!("{}/memory", self.config.base_url);
         
         let item = Mem0Item {
-            id: Uuid::new_v4().to_string(),
+            id: id.to_string(),
             user_id: input.user_id.to_string(),
             domain: input.domain.clone(),
             content_type: input.content_type.clone(),
             embedding: input.vector_representation.clone(),
-            metadata: serde_json::Value::Object(input.metadata.clone()),
+            metadata: serde_json::Value::Object(input.metadata.clone().into_iter().collect()),
             content: base64::encode(&encrypted_content),
             created_at: chrono::Utc::now().to_rfc3339(),
             updated_at: chrono::Utc::now().to_rfc3339(),
@@ -336,4 +291,28 @@ impl MemoryAdapter for Mem0Adapter {
         
         Ok(shards)
     }
+    
+    async fn get_items_by_user(&self, user_id: &str) -> Result<Vec<ContextShard>> {
+        let url = format!("{}/memory?user_id={}", self.config.base_url, user_id);
+        
+        let response = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .send()
+            .await?;
+        
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to get items by user: {}", response.status()));
+        }
+        
+        let items: Vec<Mem0Item> = response.json().await?;
+        let mut shards = Vec::with_capacity(items.len());
+        
+        for item in items {
+            shards.push(self.to_context_shard(item)?);
+        }
+        
+        shards.sort_by_key(|s| s.created_at);
+        
+        Ok(shards)
+    }
 }
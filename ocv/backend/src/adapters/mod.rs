@@ -1 +1,51 @@
+pub mod in_memory;
 pub mod mem0;
+pub mod postgres;
+pub mod sqlite;
+
+#[cfg(test)]
+mod conformance;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::context_management::models::{ContextShard, CreateShardInput, UpdateShardInput};
+
+/// Storage backend for context shards
+#[async_trait]
+pub trait MemoryAdapter {
+    /// Store a memory item under an id chosen by the caller
+    ///
+    /// The id is picked up front because the ciphertext is bound to it.
+    async fn store_item(&self, id: Uuid, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard>;
+    
+    /// Retrieve a memory item
+    async fn get_item(&self, id: &str) -> Result<Option<ContextShard>>;
+    
+    /// Update a memory item
+    async fn update_item(&self, id: &str, input: UpdateShardInput, encrypted_content: Option<Vec<u8>>) -> Result<Option<ContextShard>>;
+    
+    /// Delete a memory item
+    async fn delete_item(&self, id: &str) -> Result<bool>;
+    
+    /// Search for memory items
+    async fn search_items(
+        &self, 
+        user_id: &str,
+        query: &str, 
+        domain: Option<&str>,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>>;
+    
+    /// Get items by domain
+    async fn get_items_by_domain(
+        &self,
+        user_id: &str,
+        domain: &str,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>>;
+    
+    /// Get every item owned by a user, oldest first
+    async fn get_items_by_user(&self, user_id: &str) -> Result<Vec<ContextShard>>;
+}
@@ -0,0 +1,271 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::MemoryAdapter;
+use crate::context_management::models::{ContextShard, CreateShardInput, UpdateShardInput};
+
+/// Columns selected for every shard query
+///
+/// The vector column is stored as `FLOAT[]`, so it is cast back to `REAL[]`
+/// to match the `f32` representation used by `ContextShard`.
+const SHARD_COLUMNS: &str = r#"
+    id, user_id, domain, content_type,
+    vector_representation::REAL[] AS vector_representation,
+    metadata, content, created_at, updated_at, version
+"#;
+
+/// Adapter storing context shards in the local `context_shards` table
+pub struct PgMemoryAdapter {
+    pool: PgPool,
+}
+
+impl PgMemoryAdapter {
+    /// Create a new Postgres adapter
+    pub fn new(pool: PgPool) -> Arc<Self> {
+        Arc::new(Self { pool })
+    }
+}
+
+/// Escape LIKE wildcards so user queries are matched literally
+fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[async_trait]
+impl MemoryAdapter for PgMemoryAdapter {
+    async fn store_item(&self, id: Uuid, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard> {
+        let shard = sqlx::query_as::<_, ContextShard>(&format!(
+            r#"
+            INSERT INTO context_shards (
+                id, user_id, domain, content_type, vector_representation, metadata, content
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {}
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(id)
+        .bind(input.user_id)
+        .bind(input.domain)
+        .bind(input.content_type)
+        .bind(input.vector_representation)
+        .bind(Json(input.metadata))
+        .bind(encrypted_content)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(shard)
+    }
+
+    async fn get_item(&self, id: &str) -> Result<Option<ContextShard>> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let shard = sqlx::query_as::<_, ContextShard>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE id = $1
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(shard)
+    }
+
+    async fn update_item(&self, id: &str, input: UpdateShardInput, encrypted_content: Option<Vec<u8>>) -> Result<Option<ContextShard>> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        // Only the row at the expected version is updated
+        let shard = sqlx::query_as::<_, ContextShard>(&format!(
+            r#"
+            UPDATE context_shards
+            SET
+                domain = COALESCE($2, domain),
+                content_type = COALESCE($3, content_type),
+                vector_representation = COALESCE($4, vector_representation),
+                metadata = COALESCE(metadata || $5, metadata),
+                content = COALESCE($6, content),
+                version = version + 1,
+                updated_at = NOW()
+            WHERE id = $1
+              AND version = $7
+            RETURNING {}
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(id)
+        .bind(input.domain)
+        .bind(input.content_type)
+        .bind(input.vector_representation)
+        .bind(input.metadata.map(Json))
+        .bind(encrypted_content)
+        .bind(input.current_version)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if shard.is_some() {
+            return Ok(shard);
+        }
+
+        // Nothing matched, so either the shard is gone or the version is stale
+        let exists: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS(SELECT 1 FROM context_shards WHERE id = $1)
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        if exists {
+            return Err(anyhow::anyhow!("Version mismatch"));
+        }
+
+        Ok(None)
+    }
+
+    async fn delete_item(&self, id: &str) -> Result<bool> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(false);
+        };
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM context_shards
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn search_items(
+        &self,
+        user_id: &str,
+        query: &str,
+        domain: Option<&str>,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+        let pattern = format!("%{}%", escape_like(query));
+
+        // Content is encrypted, so only the domain and metadata are searchable
+        let shards = sqlx::query_as::<_, ContextShard>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE user_id = $1
+              AND ($2::TEXT IS NULL OR domain = $2)
+              AND (domain ILIKE $3 OR metadata::TEXT ILIKE $3)
+            ORDER BY updated_at DESC
+            LIMIT $4
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(domain)
+        .bind(pattern)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(shards)
+    }
+
+    async fn get_items_by_domain(
+        &self,
+        user_id: &str,
+        domain: &str,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        // Served by idx_context_shards_user_domain
+        let shards = sqlx::query_as::<_, ContextShard>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE user_id = $1
+              AND domain = $2
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(domain)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(shards)
+    }
+
+    async fn get_items_by_user(&self, user_id: &str) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        let shards = sqlx::query_as::<_, ContextShard>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(shards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::conformance;
+
+    /// Insert a throwaway user so shards satisfy the foreign key
+    async fn create_user(pool: &PgPool) -> Uuid {
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO users (email, display_name, password_hash)
+            VALUES ($1, 'Conformance', 'unused')
+            RETURNING id
+            "#,
+        )
+        .bind(format!("{}@conformance.test", Uuid::new_v4()))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    /// Needs TEST_DATABASE_URL to point at a migrated database
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn passes_conformance_suite() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let pool = PgPool::connect(&url).await.unwrap();
+        let adapter = PgMemoryAdapter::new(pool.clone());
+
+        conformance::run(adapter.as_ref(), create_user(&pool).await, create_user(&pool).await).await;
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::MemoryAdapter;
+use crate::context_management::models::{ContextShard, CreateShardInput, UpdateShardInput};
+
+/// Columns selected for every shard query
+const SHARD_COLUMNS: &str = r#"
+    id, user_id, domain, content_type, vector_representation,
+    metadata, content, created_at, updated_at, version
+"#;
+
+/// Shard row as stored in SQLite, with the vector kept as JSON text
+#[derive(sqlx::FromRow)]
+struct ShardRow {
+    id: Uuid,
+    user_id: Uuid,
+    domain: String,
+    content_type: String,
+    vector_representation: Option<Json<Vec<f32>>>,
+    metadata: Json<HashMap<String, serde_json::Value>>,
+    content: Vec<u8>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    version: i32,
+}
+
+impl From<ShardRow> for ContextShard {
+    fn from(row: ShardRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            domain: row.domain,
+            content_type: row.content_type,
+            vector_representation: row.vector_representation.map(|v| v.0),
+            metadata: row.metadata,
+            content: row.content,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            version: row.version,
+        }
+    }
+}
+
+/// Adapter storing context shards in a local SQLite vault
+pub struct SqliteMemoryAdapter {
+    pool: SqlitePool,
+}
+
+impl SqliteMemoryAdapter {
+    /// Create a new SQLite adapter
+    pub fn new(pool: SqlitePool) -> Arc<Self> {
+        Arc::new(Self { pool })
+    }
+
+    /// Fetch a single shard row by id
+    async fn fetch(&self, id: Uuid) -> Result<Option<ShardRow>> {
+        let row = sqlx::query_as::<_, ShardRow>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE id = $1
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row)
+    }
+}
+
+/// Escape LIKE wildcards so user queries are matched literally
+fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[async_trait]
+impl MemoryAdapter for SqliteMemoryAdapter {
+    async fn store_item(&self, id: Uuid, input: CreateShardInput, encrypted_content: Vec<u8>) -> Result<ContextShard> {
+        let now = Utc::now();
+
+        let row = sqlx::query_as::<_, ShardRow>(&format!(
+            r#"
+            INSERT INTO context_shards (
+                id, user_id, domain, content_type, vector_representation, metadata, content,
+                created_at, updated_at, version
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, 1)
+            RETURNING {}
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(id)
+        .bind(input.user_id)
+        .bind(input.domain)
+        .bind(input.content_type)
+        .bind(input.vector_representation.map(Json))
+        .bind(Json(input.metadata))
+        .bind(encrypted_content)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    async fn get_item(&self, id: &str) -> Result<Option<ContextShard>> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        Ok(self.fetch(id).await?.map(Into::into))
+    }
+
+    async fn update_item(&self, id: &str, input: UpdateShardInput, encrypted_content: Option<Vec<u8>>) -> Result<Option<ContextShard>> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(None);
+        };
+
+        let Some(current) = self.fetch(id).await? else {
+            return Ok(None);
+        };
+
+        if current.version != input.current_version {
+            return Err(anyhow::anyhow!("Version mismatch"));
+        }
+
+        // Metadata is merged here rather than with json_patch, which would
+        // also merge nested objects and drop keys set to null
+        let mut metadata = current.metadata.0;
+        if let Some(updates) = input.metadata {
+            metadata.extend(updates);
+        }
+
+        // Only the row at the expected version is updated
+        let row = sqlx::query_as::<_, ShardRow>(&format!(
+            r#"
+            UPDATE context_shards
+            SET
+                domain = COALESCE($2, domain),
+                content_type = COALESCE($3, content_type),
+                vector_representation = COALESCE($4, vector_representation),
+                metadata = $5,
+                content = COALESCE($6, content),
+                version = version + 1,
+                updated_at = $7
+            WHERE id = $1
+              AND version = $8
+            RETURNING {}
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(id)
+        .bind(input.domain)
+        .bind(input.content_type)
+        .bind(input.vector_representation.map(Json))
+        .bind(Json(metadata))
+        .bind(encrypted_content)
+        .bind(Utc::now())
+        .bind(input.current_version)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(row.into())),
+            // A concurrent writer got in between the read and the update
+            None if self.fetch(id).await?.is_some() => Err(anyhow::anyhow!("Version mismatch")),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_item(&self, id: &str) -> Result<bool> {
+        let Ok(id) = Uuid::parse_str(id) else {
+            return Ok(false);
+        };
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM context_shards
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn search_items(
+        &self,
+        user_id: &str,
+        query: &str,
+        domain: Option<&str>,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+        let pattern = format!("%{}%", escape_like(query));
+
+        // Content is encrypted, so only the domain and metadata are searchable.
+        // SQLite's LIKE is already case-insensitive for ASCII; a negative
+        // LIMIT means no limit.
+        let rows = sqlx::query_as::<_, ShardRow>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE user_id = $1
+              AND ($2 IS NULL OR domain = $2)
+              AND (domain LIKE $3 ESCAPE '\' OR metadata LIKE $3 ESCAPE '\')
+            ORDER BY updated_at DESC
+            LIMIT $4
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(domain)
+        .bind(pattern)
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_items_by_domain(
+        &self,
+        user_id: &str,
+        domain: &str,
+        limit: Option<i64>
+    ) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        // Served by idx_context_shards_user_domain
+        let rows = sqlx::query_as::<_, ShardRow>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE user_id = $1
+              AND domain = $2
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(domain)
+        .bind(limit.unwrap_or(-1))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_items_by_user(&self, user_id: &str) -> Result<Vec<ContextShard>> {
+        let user_id = Uuid::parse_str(user_id)?;
+
+        let rows = sqlx::query_as::<_, ShardRow>(&format!(
+            r#"
+            SELECT {}
+            FROM context_shards
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+            SHARD_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::conformance;
+    use crate::storage::{migrations, Database};
+
+    /// Insert a throwaway user so shards satisfy the foreign key
+    async fn create_user(pool: &SqlitePool) -> Uuid {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, email, display_name, password_hash, created_at, updated_at)
+            VALUES ($1, $2, 'Conformance', 'unused', $3, $3)
+            "#,
+        )
+        .bind(id)
+        .bind(format!("{}@conformance.test", id))
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .unwrap();
+
+        id
+    }
+
+    #[tokio::test]
+    async fn passes_conformance_suite() {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let Database::Sqlite(pool) = db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+        let adapter = SqliteMemoryAdapter::new(pool.clone());
+
+        conformance::run(adapter.as_ref(), create_user(&pool).await, create_user(&pool).await).await;
+    }
+}
@@ -0,0 +1,312 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error, HttpMessage, HttpResponse,
+};
+use async_graphql::{Context, ErrorExtensions, ID};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::AppState;
+use crate::identity::{DeviceInfo, Principal, Session};
+use crate::utils::errors::AppError;
+
+/// Validate the bearer token, if one is sent, and attach its principal and session to the request
+///
+/// The token is either an access token or a personal access token, which has
+/// no session. Requests without an `Authorization` header pass through
+/// unauthenticated and resolvers that need a principal reject them. A header
+/// that does not carry a valid bearer token is rejected here with 401; if the
+/// token cannot be checked, the request fails with 500 so clients keep it.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(value) = req.headers().get(header::AUTHORIZATION) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    let token = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim().to_string());
+
+    let authenticated = match token {
+        Some(token) => {
+            let state = req.app_data::<web::Data<AppState>>().expect("AppState is registered");
+            match state.identity_service.validate_token(&token).await {
+                Ok(authenticated) => authenticated,
+                Err(err) => {
+                    log::error!("Could not validate bearer token: {}", err);
+                    return Ok(req.into_response(validation_failed()));
+                },
+            }
+        },
+        None => None,
+    };
+
+    match authenticated {
+        Some(authenticated) => {
+            req.extensions_mut().insert(authenticated.principal);
+            if let Some(session) = authenticated.session {
+                req.extensions_mut().insert(session);
+            }
+            Ok(next.call(req).await?.map_into_boxed_body())
+        },
+        None => Ok(req.into_response(invalid_token())),
+    }
+}
+
+/// 401 response in the shape of a GraphQL error
+fn invalid_token() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, r#"Bearer error="invalid_token""#))
+        .json(serde_json::json!({
+            "errors": [{
+                "message": "Unauthorized: invalid bearer token",
+                "extensions": { "code": "UNAUTHORIZED" }
+            }]
+        }))
+}
+
+/// 500 response in the shape of a GraphQL error, for tokens that could not be checked
+fn validation_failed() -> HttpResponse {
+    HttpResponse::InternalServerError()
+        .json(serde_json::json!({
+            "errors": [{
+                "message": "Internal error: could not validate the bearer token",
+                "extensions": { "code": "INTERNAL_ERROR" }
+            }]
+        }))
+}
+
+/// Principal the GraphQL request was authenticated as
+pub fn principal<'a>(ctx: &'a Context<'_>) -> async_graphql::Result<&'a Principal> {
+    ctx.data_opt::<Principal>()
+        .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()).extend())
+}
+
+/// Session the request's access token belongs to
+pub fn session<'a>(ctx: &'a Context<'_>) -> async_graphql::Result<&'a Session> {
+    ctx.data_opt::<Session>()
+        .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()).extend())
+}
+
+/// Device the request came from
+pub fn device(ctx: &Context<'_>) -> DeviceInfo {
+    ctx.data_opt::<DeviceInfo>().cloned().unwrap_or_default()
+}
+
+/// User the request acts for
+///
+/// A user ID passed by the caller must name that same user.
+pub fn acting_user(ctx: &Context<'_>, claimed: Option<&ID>) -> async_graphql::Result<Uuid> {
+    let user_id = principal(ctx)?.user_id();
+
+    if let Some(claimed) = claimed {
+        ensure_owner(user_id, Uuid::parse_str(&claimed.0).ok())?;
+    }
+
+    Ok(user_id)
+}
+
+/// User the request acts for, when the request manages the account itself
+///
+/// Clients acting on a user's behalf and personal access tokens cannot change
+/// grants, keys or the profile.
+pub fn account_owner(ctx: &Context<'_>, claimed: Option<&ID>) -> async_graphql::Result<Uuid> {
+    match principal(ctx)? {
+        Principal::Client { client_id, .. } => {
+            return Err(AppError::Unauthorized(format!("Client {} cannot manage the account", client_id)).extend());
+        },
+        Principal::Token { .. } => {
+            return Err(AppError::Unauthorized("Personal access tokens cannot manage the account".to_string()).extend());
+        },
+        Principal::User(_) => {},
+    }
+
+    acting_user(ctx, claimed)
+}
+
+/// Administrator the request acts for
+///
+/// Administrators are the users listed in `ADMIN_EMAILS`, signed in
+/// themselves rather than through a client or personal access token.
+pub async fn admin(ctx: &Context<'_>) -> async_graphql::Result<Uuid> {
+    let user_id = account_owner(ctx, None)?;
+    let state = ctx.data::<Arc<AppState>>()?;
+
+    if state.identity_service.is_admin(user_id).await? {
+        Ok(user_id)
+    } else {
+        Err(AppError::Unauthorized("Only administrators can do this".to_string()).extend())
+    }
+}
+
+/// Reject a client whose grants, or a personal access token whose own limits,
+/// do not cover `scope` on a context domain
+///
+/// Users have full access to their own context. Each permitted client or token
+/// access is written to the user's audit log.
+pub async fn ensure_access(ctx: &Context<'_>, domain: &str, scope: &str) -> async_graphql::Result<()> {
+    let principal = principal(ctx)?;
+    let state = ctx.data::<Arc<AppState>>()?;
+
+    if state.consent_manager.check_access(principal, domain, scope).await? {
+        Ok(())
+    } else {
+        let caller = match principal {
+            Principal::Token { token_id, .. } => format!("Personal access token {}", token_id),
+            _ => format!("Client {}", principal.client_id().unwrap_or_default()),
+        };
+        Err(AppError::Unauthorized(format!("{} has no {} access to {}", caller, scope, domain)).extend())
+    }
+}
+
+/// Reject access to a record owned by another user
+pub fn ensure_owner(user_id: Uuid, owner: Option<Uuid>) -> async_graphql::Result<()> {
+    if owner == Some(user_id) {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized(format!("Not permitted for user {}", user_id)).extend())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{error_code, graphql, login, state};
+    use crate::context_management::models::CreateShardInput;
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    async fn resolvers_act_for_the_token_holder() {
+        let state = state().await;
+        let (ada, token) = login(&state, "ada").await;
+
+        let (status, body) = graphql(state, Some(&token), "{ me { id } activeGrants { id } }").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["data"]["me"]["id"], ada.to_string());
+        assert_eq!(body["data"]["activeGrants"], serde_json::json!([]));
+    }
+
+    #[actix_web::test]
+    async fn rejects_another_users_id() {
+        let state = state().await;
+        let (_, token) = login(&state, "ada").await;
+        let (bob, _) = login(&state, "bob").await;
+
+        let query = format!(r#"{{ auditLogs(userId: "{}") {{ id }} }}"#, bob);
+        let (_, body) = graphql(state, Some(&token), &query).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+    }
+
+    #[actix_web::test]
+    async fn requires_a_principal() {
+        let state = state().await;
+
+        let (status, body) = graphql(state, None, "{ activeGrants { id } }").await;
+        assert_eq!(status, 200);
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+    }
+
+    #[actix_web::test]
+    async fn rejects_an_invalid_token() {
+        let state = state().await;
+
+        let (status, body) = graphql(state, Some("not-a-jwt"), "{ activeGrants { id } }").await;
+        assert_eq!(status, 401);
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+    }
+
+    #[actix_web::test]
+    async fn storage_failures_do_not_reject_the_token() {
+        let state = state().await;
+        let (_, token) = login(&state, "ada").await;
+        state.db.close().await;
+
+        let (status, body) = graphql(state, Some(&token), "{ me { id } }").await;
+        assert_eq!(status, 500);
+        assert_eq!(error_code(&body), "INTERNAL_ERROR");
+    }
+
+    #[actix_web::test]
+    async fn logout_revokes_the_access_token() {
+        let state = state().await;
+        let (_, token) = login(&state, "ada").await;
+        let (_, other) = login(&state, "ada2").await;
+
+        let (_, body) = graphql(state.clone(), Some(&token), "{ sessions { current } }").await;
+        assert_eq!(body["data"]["sessions"], serde_json::json!([{ "current": true }]));
+
+        let (_, body) = graphql(state.clone(), Some(&token), "mutation { logout }").await;
+        assert_eq!(body["data"]["logout"], true);
+
+        let (status, _) = graphql(state.clone(), Some(&token), "{ me { id } }").await;
+        assert_eq!(status, 401);
+
+        // Other users' sessions are untouched
+        let (status, _) = graphql(state, Some(&other), "{ me { id } }").await;
+        assert_eq!(status, 200);
+    }
+
+    #[actix_web::test]
+    async fn personal_access_tokens_only_reach_their_scopes_and_domains() {
+        let state = state().await;
+        let (ada, token) = login(&state, "ada").await;
+        let shard = |domain: &str| CreateShardInput {
+            user_id: ada,
+            domain: domain.to_string(),
+            content_type: "note".to_string(),
+            vector_representation: None,
+            metadata: HashMap::new(),
+            content: serde_json::json!({ "text": "Flight at 9" }),
+        };
+        state.context_service.create_shard(shard("travel")).await.unwrap();
+        state.context_service.create_shard(shard("health")).await.unwrap();
+
+        let create = r#"mutation {
+            createPersonalAccessToken(input: { name: "backup", scopes: ["read"], domains: ["travel"] }) {
+                token personalAccessToken { id }
+            }
+        }"#;
+        let (_, body) = graphql(state.clone(), Some(&token), create).await;
+        let created = &body["data"]["createPersonalAccessToken"];
+        let pat = created["token"].as_str().unwrap().to_string();
+        let pat_id = created["personalAccessToken"]["id"].as_str().unwrap().to_string();
+        assert!(pat.starts_with("ocv_pat_"));
+
+        let (_, body) = graphql(state.clone(), Some(&pat), r#"{ shardsByDomain(domain: "travel") { domain } }"#).await;
+        assert_eq!(body["data"]["shardsByDomain"], serde_json::json!([{ "domain": "travel" }]));
+
+        // Other domains, other scopes, unscoped searches and the account are out of reach
+        let (_, body) = graphql(state.clone(), Some(&pat), r#"{ shardsByDomain(domain: "health") { id } }"#).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let write = r#"mutation { createShard(input: { domain: "travel", contentType: "note", metadata: {}, content: {} }) { id } }"#;
+        let (_, body) = graphql(state.clone(), Some(&pat), write).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let (_, body) = graphql(state.clone(), Some(&pat), r#"{ searchShards(query: "flight") { id } }"#).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+        let (_, body) = graphql(state.clone(), Some(&pat), "{ activeGrants { id } }").await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let (_, body) = graphql(state.clone(), Some(&pat), create).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let check = r#"{ travel: checkAccess(domain: "travel", scope: "read") health: checkAccess(domain: "health", scope: "read") }"#;
+        let (_, body) = graphql(state.clone(), Some(&pat), check).await;
+        assert_eq!(body["data"], serde_json::json!({ "travel": true, "health": false }));
+
+        // The user sees when it was last used and what it read
+        let (_, body) = graphql(state.clone(), Some(&token), "{ personalAccessTokens { lastUsedAt } auditLogs { clientId } }").await;
+        assert!(body["data"]["personalAccessTokens"][0]["lastUsedAt"].is_string());
+        assert_eq!(body["data"]["auditLogs"][0]["clientId"], format!("personal_access_token:{}", pat_id));
+
+        let revoke = format!(r#"mutation {{ revokePersonalAccessToken(id: "{}") }}"#, pat_id);
+        let (_, body) = graphql(state.clone(), Some(&token), &revoke).await;
+        assert_eq!(body["data"]["revokePersonalAccessToken"], true);
+        let (status, _) = graphql(state, Some(&pat), "{ me { id } }").await;
+        assert_eq!(status, 401);
+    }
+}
@@ -0,0 +1,127 @@
+//! Address of the client behind a request
+
+use actix_web::{http::header, web, HttpRequest};
+use std::net::{IpAddr, SocketAddr};
+
+/// Proxies whose `Forwarded` and `X-Forwarded-For` headers are believed
+///
+/// Registered as app data; without it only the peer address is used.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// Trust the proxies at `addresses`
+    pub fn new(addresses: Vec<IpAddr>) -> Self {
+        Self(addresses)
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        self.0.contains(addr)
+    }
+}
+
+/// Client address of a request
+///
+/// Forwarded addresses are only read when the peer is a trusted proxy. The
+/// chain is walked from the nearest hop and the first untrusted address is
+/// the client, so entries a client prepends itself are never reached.
+pub(crate) fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let Some(proxies) = req.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer);
+    };
+    if !proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    let mut client = peer;
+    for hop in forwarded_chain(req).into_iter().rev() {
+        // An unparseable hop could hide anything before it
+        let Some(addr) = hop else { break };
+        client = addr;
+        if !proxies.contains(&addr) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Addresses listed by the `Forwarded` header, or `X-Forwarded-For` without it, oldest first
+fn forwarded_chain(req: &HttpRequest) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values(header::FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node))
+            })
+            .collect();
+    }
+    values(header::X_FORWARDED_FOR).into_iter().map(parse_node).collect()
+}
+
+/// IP address of a forwarded node such as `192.0.2.1`, `"[2001:db8::1]:4711"` or `"192.0.2.1:80"`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| node.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(peer: &str, headers: &[(&str, &str)], proxies: &[&str]) -> HttpRequest {
+        let mut req = TestRequest::default()
+            .peer_addr(format!("{}:4711", peer).parse().unwrap())
+            .app_data(web::Data::new(TrustedProxies::new(proxies.iter().map(|p| p.parse().unwrap()).collect())));
+        for (name, value) in headers {
+            req = req.insert_header((*name, *value));
+        }
+        req.to_http_request()
+    }
+
+    fn ip(addr: &str) -> Option<IpAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn ignores_forwarded_headers_from_untrusted_peers() {
+        let req = request("203.0.113.9", &[("X-Forwarded-For", "192.0.2.1")], &[]);
+        assert_eq!(client_ip(&req), ip("203.0.113.9"));
+        let req = request("203.0.113.9", &[("Forwarded", "for=192.0.2.1")], &["10.0.0.1"]);
+        assert_eq!(client_ip(&req), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn takes_the_nearest_untrusted_hop_behind_a_proxy() {
+        let proxies = ["10.0.0.1", "10.0.0.2"];
+        let req = request("10.0.0.1", &[("X-Forwarded-For", "198.51.100.7, 192.0.2.1, 10.0.0.2")], &proxies);
+        assert_eq!(client_ip(&req), ip("192.0.2.1"));
+
+        let req = request("10.0.0.1", &[("Forwarded", r#"for=198.51.100.7, for="[2001:db8::1]:4711";proto=https"#)], &proxies);
+        assert_eq!(client_ip(&req), ip("2001:db8::1"));
+
+        // Nothing forwarded, or nothing usable, leaves the proxy itself
+        let req = request("10.0.0.1", &[], &proxies);
+        assert_eq!(client_ip(&req), ip("10.0.0.1"));
+        let req = request("10.0.0.1", &[("X-Forwarded-For", "198.51.100.7, unknown")], &proxies);
+        assert_eq!(client_ip(&req), ip("10.0.0.1"));
+    }
+}
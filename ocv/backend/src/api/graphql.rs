@@ -1,16 +1,46 @@
-use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use actix_web::{get, http::header, post, web, HttpRequest, HttpResponse, Result};
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use async_graphql::BatchRequest;
 
-use crate::api::schema::OcvSchema;
+use crate::api::{client_address::client_ip, schema::OcvSchema};
+use crate::identity::{DeviceInfo, Principal, Session};
 
 /// GraphQL endpoint handler
+///
+/// The principal and session set by the authentication middleware, and the
+/// device the request came from, are passed to resolvers through the GraphQL
+/// context.
 #[post("/graphql")]
 pub async fn graphql_handler(
     schema: web::Data<OcvSchema>,
-    req: GraphQLRequest,
-) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
+    http_request: HttpRequest,
+    principal: Option<web::ReqData<Principal>>,
+    session: Option<web::ReqData<Session>>,
+    req: web::Json<BatchRequest>,
+) -> HttpResponse {
+    let mut request = req.into_inner().data(device_info(&http_request));
+    if let Some(principal) = principal {
+        request = request.data(principal.into_inner());
+    }
+    if let Some(session) = session {
+        request = request.data(session.into_inner());
+    }
+
+    let response = schema.execute_batch(request).await;
+    HttpResponse::Ok().json(response)
+}
+
+/// User agent and client address of a request
+///
+/// The address is the peer's unless it is a trusted proxy, see `client_ip`.
+pub(crate) fn device_info(req: &HttpRequest) -> DeviceInfo {
+    DeviceInfo {
+        user_agent: req.headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        ip_address: client_ip(req).map(|addr| addr.to_string()),
+    }
 }
 
 /// GraphQL playground UI handler
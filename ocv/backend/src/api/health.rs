@@ -14,9 +14,10 @@ struct HealthResponse {
 #[get("/health")]
 pub async fn health_check(state: web::Data<AppState>) -> HttpResponse {
     // Check database connection
-    let db_status = match state.pool.acquire().await {
-        Ok(_) => "connected",
-        Err(_) => "disconnected",
+    let db_status = if state.db.ping().await {
+        "connected"
+    } else {
+        "disconnected"
     };
 
     let response = HealthResponse {
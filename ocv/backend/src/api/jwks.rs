@@ -0,0 +1,28 @@
+use actix_web::{get, http::header, web, HttpResponse};
+
+use crate::api::AppState;
+
+/// Public keys that verify the tokens this vault issues
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(state.identity_service.jwks())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{get, login, state};
+
+    #[actix_web::test]
+    async fn publishes_the_signing_key() {
+        let state = state().await;
+        let (_, token) = login(&state, "ada").await;
+        let kid = jsonwebtoken::decode_header(&token).unwrap().kid.unwrap();
+
+        let (status, body) = get(state, "/.well-known/jwks.json").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["keys"][0]["kid"], kid);
+        assert!(body["keys"][0].get("d").is_none());
+    }
+}
@@ -1,37 +1,56 @@
+pub mod auth;
 pub mod schema;
+mod client_address;
 mod graphql;
 mod health;
+mod jwks;
+mod oauth;
+#[cfg(test)]
+pub(crate) mod testing;
 
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+pub use client_address::TrustedProxies;
+
+use actix_web::{middleware::from_fn, web, HttpResponse};
 use std::sync::Arc;
 
 use crate::{
+    clients::service::ClientService,
     consent_manager::service::ConsentManager,
-    context_management::service::ContextService,
+    context_management::{rotation::KeyRotationJob, service::ContextService},
     encryption::service::EncryptionService,
-    identity::service::IdentityService,
+    identity::{deletion::AccountDeletionJob, service::IdentityService},
+    oauth::service::OAuthService,
     policy_engine::service::PolicyEngine,
+    storage::Database,
 };
 
 /// Application state shared across all routes
 pub struct AppState {
-    pub pool: PgPool,
+    pub db: Database,
     pub context_service: Arc<ContextService>,
     pub consent_manager: Arc<ConsentManager>,
     pub encryption_service: Arc<EncryptionService>,
     pub policy_engine: Arc<PolicyEngine>,
     pub identity_service: Arc<IdentityService>,
+    pub key_rotation: Arc<KeyRotationJob>,
+    pub account_deletion: Arc<AccountDeletionJob>,
+    pub oauth_service: Arc<OAuthService>,
+    pub client_service: Arc<ClientService>,
 }
 
 /// Configure all application routes and middleware
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
+            .wrap(from_fn(auth::authenticate))
             .service(health::health_check)
             .service(graphql::graphql_handler)
             .service(graphql::graphql_playground)
     )
+    .service(jwks::jwks)
+    .service(oauth::authorize)
+    .service(oauth::token)
+    .service(oauth::register)
     .route("/", web::get().to(index));
 }
 
@@ -0,0 +1,580 @@
+use actix_web::{get, http::header, post, web, HttpRequest, HttpResponse};
+
+use crate::api::{graphql::device_info, AppState};
+use crate::clients::{models::ClientMetadata, ClientError};
+use crate::oauth::{
+    models::{AuthorizeParams, TokenParams},
+    OAuthError,
+};
+
+/// OAuth authorization endpoint
+///
+/// Records the request and sends the user agent to the consent UI.
+#[get("/oauth/authorize")]
+pub async fn authorize(state: web::Data<AppState>, params: web::Query<AuthorizeParams>) -> HttpResponse {
+    match state.oauth_service.authorize(params.into_inner()).await {
+        Ok(consent_page) => HttpResponse::Found()
+            .insert_header((header::LOCATION, consent_page))
+            .finish(),
+        Err(err) => error_response(err),
+    }
+}
+
+/// OAuth token endpoint
+#[post("/oauth/token")]
+pub async fn token(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    params: web::Form<TokenParams>,
+) -> HttpResponse {
+    let basic = match basic_credentials(&req) {
+        Ok(basic) => basic,
+        Err(err) => return error_response(err.into()),
+    };
+
+    match state.oauth_service.token(params.into_inner(), basic, device_info(&req)).await {
+        Ok(token) => HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(token),
+        Err(err) => error_response(err),
+    }
+}
+
+/// Client ID and secret from an `Authorization: Basic` header (RFC 6749 section 2.3.1)
+fn basic_credentials(req: &HttpRequest) -> Result<Option<(String, String)>, ClientError> {
+    let Some(value) = req.headers().get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let invalid = || ClientError::InvalidClient("Malformed Basic credentials".to_string());
+
+    let encoded = value.to_str().ok()
+        .and_then(|value| value.strip_prefix("Basic "))
+        .ok_or_else(invalid)?;
+    let decoded = base64::decode(encoded.trim()).ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(invalid)?;
+    let (client_id, client_secret) = decoded.split_once(':').ok_or_else(invalid)?;
+
+    // Both parts are form-urlencoded before being joined
+    let decode = |part: &str| urlencoding::decode(&part.replace('+', " ")).map(|part| part.into_owned());
+    Ok(Some((
+        decode(client_id).map_err(|_| invalid())?,
+        decode(client_secret).map_err(|_| invalid())?,
+    )))
+}
+
+/// Dynamic client registration endpoint (RFC 7591)
+///
+/// Disabled unless `DYNAMIC_CLIENT_REGISTRATION` is set, and each request must
+/// pass the `client_registration` policy.
+#[post("/oauth/register")]
+pub async fn register(state: web::Data<AppState>, metadata: web::Json<ClientMetadata>) -> HttpResponse {
+    match state.client_service.register_dynamic(metadata.into_inner()).await {
+        Ok(client) => HttpResponse::Created()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(client),
+        Err(err) => error_response(err),
+    }
+}
+
+/// OAuth error response (RFC 6749 section 5.2, RFC 7591 section 3.2.2)
+fn error_response(err: anyhow::Error) -> HttpResponse {
+    let (status, code) = if let Some(oauth_err) = err.downcast_ref::<OAuthError>() {
+        (HttpResponse::BadRequest(), oauth_err.code())
+    } else if let Some(client_err) = err.downcast_ref::<ClientError>() {
+        match client_err {
+            ClientError::RegistrationDenied | ClientError::PolicyDenied(_) => {
+                (HttpResponse::Forbidden(), client_err.code())
+            }
+            ClientError::InvalidClient(_) => {
+                let mut status = HttpResponse::Unauthorized();
+                status.insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"oauth\""));
+                (status, client_err.code())
+            }
+            _ => (HttpResponse::BadRequest(), client_err.code()),
+        }
+    } else {
+        log::error!("OAuth request failed: {:#}", err);
+        (HttpResponse::InternalServerError(), "server_error")
+    };
+
+    let mut response = status;
+    response
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(serde_json::json!({
+            "error": code,
+            "error_description": err.to_string(),
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test::{self, TestRequest}};
+    use chrono::{DateTime, Duration, Utc};
+    use std::sync::Arc;
+    use url::Url;
+
+    use crate::api::testing::{error_code, graphql, login, send, state, CONSENT_URL};
+    use crate::api::AppState;
+    use crate::clients::{assertion::JWT_BEARER, models::RegisterClientInput};
+    use crate::identity::{signing::DEFAULT_ISSUER, JwtKeys};
+    use crate::utils::tokens::sha256_base64url;
+
+    /// Code verifier from RFC 7636 appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+
+    const REDIRECT_URI: &str = "https://friday.example.com/callback";
+
+    /// Register a public client that may read and write the travel domain
+    async fn client(state: &AppState, name: &str) -> String {
+        register(state, name, "none", None).await.0
+    }
+
+    /// Register a client authenticating with `method` and return its ID and secret
+    async fn register(
+        state: &AppState,
+        name: &str,
+        method: &str,
+        jwks: Option<serde_json::Value>,
+    ) -> (String, Option<String>) {
+        let registered = state.client_service
+            .register(None, RegisterClientInput {
+                name: name.to_string(),
+                logo_uri: None,
+                homepage_uri: None,
+                redirect_uris: vec![REDIRECT_URI.to_string()],
+                token_endpoint_auth_method: method.to_string(),
+                jwks,
+                allowed_scopes: vec!["read".to_string(), "write".to_string()],
+                allowed_domains: vec!["travel".to_string()],
+            })
+            .await
+            .unwrap();
+
+        (registered.client.client_id, registered.client_secret)
+    }
+
+    fn authorize_uri(client_id: &str, scope: &str) -> String {
+        format!(
+            "/oauth/authorize?response_type=code&client_id={}&redirect_uri={}&scope={}&state=xyz&code_challenge={}&code_challenge_method=S256",
+            client_id,
+            urlencoding::encode(REDIRECT_URI),
+            urlencoding::encode(scope),
+            sha256_base64url(VERIFIER),
+        )
+    }
+
+    /// Start an authorization request and return its ID
+    async fn authorize(state: Arc<AppState>, client_id: &str, scope: &str) -> String {
+        let resp = send(state, TestRequest::get().uri(&authorize_uri(client_id, scope))).await;
+        assert_eq!(resp.status(), 302);
+
+        let location = resp.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+        location.strip_prefix(&format!("{}/", CONSENT_URL)).unwrap().to_string()
+    }
+
+    /// Approve a request as the token holder and return the code it redirects with
+    async fn approve(state: Arc<AppState>, token: &str, request_id: &str) -> String {
+        let query = format!(r#"mutation {{ approveAuthorization(id: "{}") }}"#, request_id);
+        let (_, body) = graphql(state, Some(token), &query).await;
+
+        let redirect = Url::parse(body["data"]["approveAuthorization"].as_str().unwrap()).unwrap();
+        assert!(redirect.as_str().starts_with(REDIRECT_URI));
+        assert!(redirect.query_pairs().any(|(k, v)| k == "state" && v == "xyz"));
+        redirect.query_pairs().find(|(k, _)| k == "code").unwrap().1.into_owned()
+    }
+
+    /// Run the whole flow for a client and return its access and refresh tokens
+    async fn client_tokens(state: Arc<AppState>, user_token: &str, client_id: &str, scope: &str) -> (String, String) {
+        let request_id = authorize(state.clone(), client_id, scope).await;
+        let code = approve(state.clone(), user_token, &request_id).await;
+        let (status, body) = token(state, &code_grant(&code, client_id, VERIFIER)).await;
+        assert_eq!(status, 200, "{}", body);
+
+        (
+            body["access_token"].as_str().unwrap().to_string(),
+            body["refresh_token"].as_str().unwrap().to_string(),
+        )
+    }
+
+    async fn token(state: Arc<AppState>, form: &[(&str, &str)]) -> (u16, serde_json::Value) {
+        let resp = send(state, TestRequest::post().uri("/oauth/token").set_form(form)).await;
+        let status = resp.status().as_u16();
+        (status, test::read_body_json(resp).await)
+    }
+
+    /// Token request authenticated with an `Authorization: Basic` header
+    async fn basic_token(state: Arc<AppState>, form: &[(&str, &str)], client_id: &str, secret: &str) -> u16 {
+        let credentials = base64::encode(format!("{}:{}", client_id, secret));
+        let req = TestRequest::post()
+            .uri("/oauth/token")
+            .insert_header((header::AUTHORIZATION, format!("Basic {}", credentials)))
+            .set_form(form);
+        send(state, req).await.status().as_u16()
+    }
+
+    /// Client assertion for `private_key_jwt`, signed with `keys`
+    fn assertion(keys: &JwtKeys, client_id: &str) -> String {
+        keys.encode(&serde_json::json!({
+            "iss": client_id,
+            "sub": client_id,
+            "aud": DEFAULT_ISSUER,
+            "jti": uuid::Uuid::new_v4().to_string(),
+            "exp": Utc::now().timestamp() + 60,
+        }))
+        .unwrap()
+    }
+
+    fn code_grant<'a>(code: &'a str, client_id: &'a str, verifier: &'a str) -> [(&'a str, &'a str); 5] {
+        [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("client_id", client_id),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", verifier),
+        ]
+    }
+
+    fn create_shard(domain: &str) -> String {
+        format!(
+            r#"mutation {{ createShard(input: {{ domain: "{}", contentType: "note", metadata: {{}}, content: {{ text: "hi" }} }}) {{ id }} }}"#,
+            domain
+        )
+    }
+
+    #[actix_web::test]
+    async fn client_tokens_act_within_the_grant() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let friday = client(&state, "Friday").await;
+
+        let request_id = authorize(state.clone(), &friday, "read write domain:travel").await;
+        let query = format!(r#"{{ authorizationRequest(id: "{}") {{ client {{ name }} }} }}"#, request_id);
+        let (_, body) = graphql(state.clone(), Some(&user_token), &query).await;
+        assert_eq!(body["data"]["authorizationRequest"]["client"]["name"], "Friday");
+
+        let code = approve(state.clone(), &user_token, &request_id).await;
+        let (status, body) = token(state.clone(), &code_grant(&code, &friday, VERIFIER)).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["token_type"], "Bearer");
+        assert_eq!(body["scope"], "read write domain:travel");
+        let access_token = body["access_token"].as_str().unwrap().to_string();
+
+        let (_, body) = graphql(state.clone(), Some(&access_token), &create_shard("travel")).await;
+        assert!(body["data"]["createShard"]["id"].is_string(), "{}", body);
+
+        let (_, body) = graphql(state.clone(), Some(&access_token), &create_shard("health")).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        let (_, body) = graphql(state.clone(), Some(&access_token), r#"{ searchShards(query: "hi") { id } }"#).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        // Clients cannot manage the account
+        let (_, body) = graphql(state.clone(), Some(&access_token), "{ sessions { id } }").await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        // Replaying the code fails and ends the session it was exchanged for
+        let (status, body) = token(state.clone(), &code_grant(&code, &friday, VERIFIER)).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "invalid_grant");
+        let (status, _) = graphql(state, Some(&access_token), "{ activeGrants { id } }").await;
+        assert_eq!(status, 401);
+    }
+
+    #[actix_web::test]
+    async fn revoking_the_grant_ends_client_tokens() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let friday = client(&state, "Friday").await;
+        let jarvis = client(&state, "Jarvis").await;
+
+        let (access_token, refresh_token) = client_tokens(state.clone(), &user_token, &friday, "read domain:travel").await;
+
+        // Refresh tokens only work for the client they were issued to
+        let refresh = |client_id: String, refresh_token: String| {
+            let state = state.clone();
+            async move {
+                let form = [("grant_type", "refresh_token"), ("refresh_token", &*refresh_token), ("client_id", &*client_id)];
+                token(state, &form).await
+            }
+        };
+        let (status, _) = refresh(jarvis, refresh_token.clone()).await;
+        assert_eq!(status, 400);
+        let (status, body) = refresh(friday.clone(), refresh_token).await;
+        assert_eq!(status, 200, "{}", body);
+
+        let (_, body) = graphql(state.clone(), Some(&user_token), "{ activeGrants { id } }").await;
+        let grant_id = body["data"]["activeGrants"][0]["id"].as_str().unwrap().to_string();
+        let query = format!(r#"mutation {{ revokeAccess(grantId: "{}", clientId: "{}") }}"#, grant_id, friday);
+        graphql(state.clone(), Some(&user_token), &query).await;
+
+        let (status, _) = graphql(state, Some(&access_token), r#"{ shardsByDomain(domain: "travel") { id } }"#).await;
+        assert_eq!(status, 401);
+    }
+
+    #[actix_web::test]
+    async fn expiry_obligations_cap_client_sessions() {
+        let state = state().await;
+        let (ada, user_token) = login(&state, "ada").await;
+        state.policy_engine
+            .update_policy(ada, "consent", r#"
+                package consent
+                import rego.v1
+
+                default allow := false
+                allow if input.action == "grant"
+                obligations contains {"type": "expire", "days": 7}
+            "#)
+            .await
+            .unwrap();
+        let friday = client(&state, "Friday").await;
+
+        let (_, refresh_token) = client_tokens(state.clone(), &user_token, &friday, "read domain:travel").await;
+        let form = [("grant_type", "refresh_token"), ("refresh_token", &*refresh_token), ("client_id", &*friday)];
+        let (status, body) = token(state.clone(), &form).await;
+        assert_eq!(status, 200, "{}", body);
+
+        let (_, body) = graphql(state.clone(), Some(&user_token), "{ activeGrants { expiresAt } }").await;
+        let grant_expires_at = body["data"]["activeGrants"][0]["expiresAt"].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+        assert!(grant_expires_at < Utc::now() + Duration::days(8));
+
+        // Neither the session the code started nor its refresh outlives the grant
+        let (_, body) = graphql(state, Some(&user_token), "{ sessions { current expiresAt } }").await;
+        let sessions = body["data"]["sessions"].as_array().unwrap();
+        let client_session = sessions.iter().find(|session| session["current"] == false).unwrap();
+        let expires_at = client_session["expiresAt"].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+        assert!(expires_at <= grant_expires_at);
+    }
+
+    #[actix_web::test]
+    async fn rejects_bad_authorization_and_token_requests() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let friday = client(&state, "Friday").await;
+
+        let uri = format!(
+            "/oauth/authorize?response_type=code&client_id={}&redirect_uri={}&scope=read+domain%3Atravel",
+            friday,
+            urlencoding::encode(REDIRECT_URI)
+        );
+        let resp = send(state.clone(), TestRequest::get().uri(&uri)).await;
+        assert_eq!(resp.status(), 400);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "invalid_request");
+
+        // A code is bound to its PKCE verifier, and a wrong one does not use it up
+        let request_id = authorize(state.clone(), &friday, "read domain:travel").await;
+        let code = approve(state.clone(), &user_token, &request_id).await;
+        let wrong = "x".repeat(43);
+        let (status, body) = token(state.clone(), &code_grant(&code, &friday, &wrong)).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "invalid_grant");
+        let (status, body) = token(state.clone(), &code_grant(&code, &friday, VERIFIER)).await;
+        assert_eq!(status, 200, "{}", body);
+
+        let (_, body) = token(state.clone(), &[("grant_type", "password")]).await;
+        assert_eq!(body["error"], "unsupported_grant_type");
+
+        // Denying sends the user back with an error
+        let request_id = authorize(state.clone(), &friday, "read domain:travel").await;
+        let query = format!(r#"mutation {{ denyAuthorization(id: "{}") }}"#, request_id);
+        let (_, body) = graphql(state.clone(), Some(&user_token), &query).await;
+        assert_eq!(
+            body["data"]["denyAuthorization"],
+            format!("{}?error=access_denied&state=xyz", REDIRECT_URI)
+        );
+        let (_, body) = graphql(state, Some(&user_token), &query).await;
+        assert_eq!(error_code(&body), "NOT_FOUND");
+    }
+
+    #[actix_web::test]
+    async fn only_registered_clients_get_access() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let friday = client(&state, "Friday").await;
+
+        let error = |uri: String| {
+            let state = state.clone();
+            async move {
+                let resp = send(state, TestRequest::get().uri(&uri)).await;
+                assert_eq!(resp.status(), 400);
+                let body: serde_json::Value = test::read_body_json(resp).await;
+                body["error"].as_str().unwrap().to_string()
+            }
+        };
+        assert_eq!(error(authorize_uri("jarvis", "read domain:travel")).await, "invalid_request");
+        assert_eq!(error(authorize_uri(&friday, "read domain:health")).await, "invalid_scope");
+        let elsewhere = authorize_uri(&friday, "read domain:travel").replace("friday.example.com", "evil.example.com");
+        assert_eq!(error(elsewhere).await, "invalid_request");
+
+        let query = r#"mutation { grantAccess(input: { clientId: "jarvis", scopes: ["read"], contextDomains: ["travel"] }) { id } }"#;
+        let (_, body) = graphql(state.clone(), Some(&user_token), query).await;
+        assert!(body["errors"][0]["message"].as_str().unwrap().contains("Unknown or disabled client"));
+    }
+
+    #[actix_web::test]
+    async fn disabling_a_client_ends_its_access() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+
+        let query = format!(
+            r#"mutation {{ registerClient(input: {{ name: "Friday", redirectUris: ["{}"], tokenEndpointAuthMethod: "none", allowedScopes: ["read", "write"], allowedDomains: ["travel"] }}) {{ client {{ clientId }} clientSecret }} }}"#,
+            REDIRECT_URI
+        );
+        let (_, body) = graphql(state.clone(), Some(&user_token), &query).await;
+        assert!(body["data"]["registerClient"]["clientSecret"].is_null(), "{}", body);
+        let friday = body["data"]["registerClient"]["client"]["clientId"].as_str().unwrap().to_string();
+
+        let (access_token, _) = client_tokens(state.clone(), &user_token, &friday, "write domain:travel").await;
+        let (_, body) = graphql(state.clone(), Some(&access_token), &create_shard("travel")).await;
+        assert!(body["data"]["createShard"]["id"].is_string(), "{}", body);
+
+        // Clients cannot register or manage clients
+        let (_, body) = graphql(state.clone(), Some(&access_token), "{ clients { clientId } }").await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        let query = format!(r#"mutation {{ updateClient(clientId: "{}", input: {{ enabled: false }}) {{ enabled }} }}"#, friday);
+        let (_, body) = graphql(state.clone(), Some(&user_token), &query).await;
+        assert_eq!(body["data"]["updateClient"]["enabled"], false);
+
+        let (_, body) = graphql(state.clone(), Some(&access_token), &create_shard("travel")).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        let query = format!(r#"mutation {{ updateClient(clientId: "{}", input: {{ allowedScopes: ["admin"] }}) {{ enabled }} }}"#, friday);
+        let (_, body) = graphql(state, Some(&user_token), &query).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+    }
+
+    #[actix_web::test]
+    async fn confidential_clients_authenticate_with_their_secret() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let (friday, secret) = register(&state, "Friday", "client_secret_basic", None).await;
+        let secret = secret.unwrap();
+
+        let request_id = authorize(state.clone(), &friday, "read domain:travel").await;
+        let code = approve(state.clone(), &user_token, &request_id).await;
+        let form = code_grant(&code, &friday, VERIFIER);
+
+        // Failed authentication leaves the code unused
+        let resp = send(state.clone(), TestRequest::post().uri("/oauth/token").set_form(form)).await;
+        assert_eq!(resp.status(), 401);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "invalid_client");
+
+        assert_eq!(basic_token(state.clone(), &form, &friday, "guess").await, 401);
+        let (status, body) = token(state.clone(), &[&form[..], &[("client_secret", &*secret)]].concat()).await;
+        assert_eq!((status, body["error"].as_str()), (401, Some("invalid_client")));
+        assert_eq!(basic_token(state.clone(), &form, "jarvis", &secret).await, 401);
+
+        assert_eq!(basic_token(state, &form, &friday, &secret).await, 200);
+    }
+
+    #[actix_web::test]
+    async fn clients_authenticate_with_signed_assertions() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let keys = JwtKeys::generate();
+        let jwks = serde_json::to_value(keys.jwks()).unwrap();
+        let (friday, secret) = register(&state, "Friday", "private_key_jwt", Some(jwks)).await;
+        assert!(secret.is_none());
+
+        let request_id = authorize(state.clone(), &friday, "read domain:travel").await;
+        let code = approve(state.clone(), &user_token, &request_id).await;
+        let signed = assertion(&keys, &friday);
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", &*code),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", VERIFIER),
+            ("client_assertion_type", JWT_BEARER),
+            ("client_assertion", &*signed),
+        ];
+        let (status, body) = token(state.clone(), &form).await;
+        assert_eq!(status, 200, "{}", body);
+        let refresh_token = body["refresh_token"].as_str().unwrap().to_string();
+
+        // Assertions work once, and must be signed with the registered key
+        let refresh = |signed: String| {
+            let state = state.clone();
+            let refresh_token = refresh_token.clone();
+            async move {
+                let form = [
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", &*refresh_token),
+                    ("client_assertion_type", JWT_BEARER),
+                    ("client_assertion", &*signed),
+                ];
+                token(state, &form).await
+            }
+        };
+        let (status, body) = refresh(signed).await;
+        assert_eq!((status, body["error"].as_str()), (401, Some("invalid_client")));
+        let (status, _) = refresh(assertion(&JwtKeys::generate(), &friday)).await;
+        assert_eq!(status, 401);
+        let (status, body) = refresh(assertion(&keys, &friday)).await;
+        assert_eq!(status, 200, "{}", body);
+    }
+
+    #[actix_web::test]
+    async fn clients_only_check_their_own_access() {
+        let state = state().await;
+        let (_, user_token) = login(&state, "ada").await;
+        let friday = client(&state, "Friday").await;
+        let jarvis = client(&state, "Jarvis").await;
+
+        let (friday_token, _) = client_tokens(state.clone(), &user_token, &friday, "read domain:travel").await;
+        client_tokens(state.clone(), &user_token, &jarvis, "write domain:travel").await;
+
+        let check = |token: String, client_id: Option<&str>, scope: &str| {
+            let state = state.clone();
+            let client_id = client_id.map(|id| format!(r#"clientId: "{}", "#, id)).unwrap_or_default();
+            let query = format!(r#"{{ checkAccess({}domain: "travel", scope: "{}") }}"#, client_id, scope);
+            async move { graphql(state, Some(&token), &query).await.1 }
+        };
+
+        let body = check(friday_token.clone(), None, "read").await;
+        assert_eq!(body["data"]["checkAccess"], true);
+        let body = check(friday_token.clone(), Some(&friday), "write").await;
+        assert_eq!(body["data"]["checkAccess"], false);
+        let body = check(friday_token.clone(), Some(&jarvis), "write").await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        let body = check(user_token.clone(), Some(&jarvis), "write").await;
+        assert_eq!(body["data"]["checkAccess"], true);
+        let body = check(user_token, None, "write").await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        // Nor read the user's grants or audit log
+        for query in ["{ activeGrants { id } }", "{ auditLogs { id } }"] {
+            let (_, body) = graphql(state.clone(), Some(&friday_token), query).await;
+            assert_eq!(error_code(&body), "UNAUTHORIZED");
+        }
+    }
+
+    #[actix_web::test]
+    async fn registers_clients_dynamically() {
+        let state = state().await;
+
+        let metadata = serde_json::json!({
+            "client_name": "Friday",
+            "redirect_uris": [REDIRECT_URI],
+            "scope": "read domain:travel",
+            "token_endpoint_auth_method": "none",
+        });
+        let resp = send(state.clone(), TestRequest::post().uri("/oauth/register").set_json(&metadata)).await;
+        assert_eq!(resp.status(), 201);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["scope"], "read domain:travel");
+        assert!(body.get("client_secret").is_none());
+
+        authorize(state.clone(), body["client_id"].as_str().unwrap(), "read domain:travel").await;
+
+        let metadata = serde_json::json!({ "redirect_uris": [], "scope": "read domain:travel" });
+        let resp = send(state, TestRequest::post().uri("/oauth/register").set_json(&metadata)).await;
+        assert_eq!(resp.status(), 400);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "invalid_redirect_uri");
+    }
+}
@@ -1,18 +1,21 @@
 use async_graphql::{EmptySubscription, Schema, SchemaBuilder};
 
 use crate::{
+    clients::graphql::{ClientMutation, ClientQuery},
     context_management::graphql::{ContextMutation, ContextQuery},
     consent_manager::graphql::{ConsentMutation, ConsentQuery},
     identity::graphql::{IdentityMutation, IdentityQuery},
+    oauth::graphql::{OAuthMutation, OAuthQuery},
+    policy_engine::graphql::{PolicyMutation, PolicyQuery},
 };
 
 /// Root query object combining all query fields
 #[derive(async_graphql::MergedObject, Default)]
-pub struct Query(ContextQuery, ConsentQuery, IdentityQuery);
+pub struct Query(ContextQuery, ConsentQuery, IdentityQuery, OAuthQuery, ClientQuery, PolicyQuery);
 
 /// Root mutation object combining all mutation fields
 #[derive(async_graphql::MergedObject, Default)]
-pub struct Mutation(ContextMutation, ConsentMutation, IdentityMutation);
+pub struct Mutation(ContextMutation, ConsentMutation, IdentityMutation, OAuthMutation, ClientMutation, PolicyMutation);
 
 /// Create the GraphQL schema with all queries and mutations
 pub type OcvSchema = Schema<Query, Mutation, EmptySubscription>;
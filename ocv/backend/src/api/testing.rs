@@ -0,0 +1,175 @@
+//! Helpers for tests that drive the HTTP API
+
+use actix_web::{dev::ServiceResponse, http::header, test, web, App};
+use chrono::Duration;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::{self, schema, AppState};
+use crate::clients::ClientService;
+use crate::consent_manager::ConsentManager;
+use crate::context_management::{ContextService, KeyRotationJob};
+use crate::encryption::{EncryptionService, MasterKey};
+use crate::identity::{
+    models::Credentials, repository::IdentityRepository, signing::DEFAULT_ISSUER, sqlite::SqliteIdentityRepository,
+    throttle::LoginLimits,
+    AccountDeletionJob, CreateUserInput, DeviceInfo, IdentityService, JwtKeys,
+};
+use crate::mailer::{FileMailer, Mailer};
+use crate::oauth::OAuthService;
+use crate::policy_engine::PolicyEngine;
+use crate::storage::{migrations, Database};
+use crate::utils::clock::{Clock, SystemClock};
+
+/// Consent page OAuth requests are sent to
+pub const CONSENT_URL: &str = "https://vault.example.com/consent";
+
+/// Web UI that emailed links point to
+pub const UI_URL: &str = "https://vault.example.com";
+
+/// Services backed by a fresh in-memory SQLite vault
+pub async fn state() -> Arc<AppState> {
+    state_with_clock(Arc::new(SystemClock)).await
+}
+
+/// Services that tell the time from `clock`
+pub async fn state_with_clock(clock: Arc<dyn Clock>) -> Arc<AppState> {
+    build_state(Arc::new(FileMailer::stdout()), clock).await
+}
+
+/// Services whose emails are kept in a temporary directory
+pub async fn state_with_mailbox() -> (Arc<AppState>, Arc<FileMailer>) {
+    let dir = std::env::temp_dir().join(format!("ocv-mail-{}", Uuid::new_v4()));
+    let mailbox = Arc::new(FileMailer::new(dir).unwrap());
+    (build_state(mailbox.clone(), Arc::new(SystemClock)).await, mailbox)
+}
+
+async fn build_state(mailer: Arc<dyn Mailer>, clock: Arc<dyn Clock>) -> Arc<AppState> {
+    let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+    migrations::run_migrations(&db).await.unwrap();
+
+    let encryption_service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+    let policy_engine = PolicyEngine::new(&db);
+    policy_engine.install_default_policies().await.unwrap();
+    let context_service = ContextService::new_with_database(&db, encryption_service.clone());
+    let client_service = ClientService::new(&db, policy_engine.clone(), true, DEFAULT_ISSUER);
+    let identity_service = IdentityService::new(
+        &db,
+        encryption_service.clone(),
+        JwtKeys::generate(),
+        mailer,
+        UI_URL,
+        // Tests retry right after a wrong password; only lockouts are kept
+        LoginLimits { base_delay: Duration::zero(), ..Default::default() },
+        clock.clone(),
+    );
+    let consent_manager = ConsentManager::new(
+        &db,
+        policy_engine.clone(),
+        client_service.clone(),
+        identity_service.clone(),
+        clock,
+    );
+    let key_rotation = KeyRotationJob::new(
+        context_service.clone(),
+        encryption_service.clone(),
+        consent_manager.clone(),
+        identity_service.clone(),
+    );
+    let account_deletion = AccountDeletionJob::new(
+        identity_service.clone(),
+        consent_manager.clone(),
+        client_service.clone(),
+        context_service.clone(),
+        encryption_service.clone(),
+    );
+    let oauth_service = OAuthService::new(
+        &db,
+        consent_manager.clone(),
+        identity_service.clone(),
+        client_service.clone(),
+        CONSENT_URL,
+    );
+
+    Arc::new(AppState {
+        db,
+        context_service,
+        consent_manager,
+        encryption_service,
+        policy_engine,
+        identity_service,
+        key_rotation,
+        account_deletion,
+        oauth_service,
+        client_service,
+    })
+}
+
+/// Register a user with a verified email and log them in, returning their ID and token
+pub async fn login(state: &AppState, name: &str) -> (Uuid, String) {
+    let user = state.identity_service
+        .create_user(CreateUserInput {
+            email: format!("{}@example.com", name),
+            display_name: name.to_string(),
+            password: "correct horse".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // Skip the emailed link; tests of verification register users themselves
+    let Database::Sqlite(pool) = &state.db else {
+        unreachable!("test state uses SQLite");
+    };
+    SqliteIdentityRepository::new(pool.clone()).mark_email_verified(user.id).await.unwrap();
+
+    let token = state.identity_service
+        .authenticate(Credentials {
+            email: user.email.clone(),
+            password: "correct horse".to_string(),
+        }, DeviceInfo::default())
+        .await
+        .unwrap()
+        .unwrap();
+
+    (user.id, token.token)
+}
+
+/// Send a request to the API
+pub async fn send(state: Arc<AppState>, req: test::TestRequest) -> ServiceResponse {
+    let schema = schema::schema_builder().data(state.clone()).finish();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(state))
+            .app_data(web::Data::new(schema))
+            .configure(api::configure),
+    )
+    .await;
+
+    test::call_service(&app, req.to_request()).await
+}
+
+/// Run a GET request and return the JSON body
+pub async fn get(state: Arc<AppState>, uri: &str) -> (u16, serde_json::Value) {
+    let resp = send(state, test::TestRequest::get().uri(uri)).await;
+    let status = resp.status().as_u16();
+    (status, test::read_body_json(resp).await)
+}
+
+/// Run a GraphQL query with an optional bearer token
+pub async fn graphql(state: Arc<AppState>, token: Option<&str>, query: &str) -> (u16, serde_json::Value) {
+    let mut req = test::TestRequest::post()
+        .uri("/api/graphql")
+        .set_json(serde_json::json!({ "query": query }));
+    if let Some(token) = token {
+        req = req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+    }
+
+    let resp = send(state, req).await;
+    let status = resp.status().as_u16();
+    (status, test::read_body_json(resp).await)
+}
+
+/// Code of the first GraphQL error
+pub fn error_code(body: &serde_json::Value) -> &str {
+    body["errors"][0]["extensions"]["code"].as_str().unwrap_or_default()
+}
@@ -1,6 +1,80 @@
-// This file is a placeholder to avoid build errors
-// We're not actually using migrations in the minimal version
+use anyhow::{Context, Result};
+use std::env;
 
-fn main() {
-    println!("Migrations are not implemented in this minimal version");
+use open_context_vault::storage::{migrations, Database};
+
+const USAGE: &str = "\
+Usage: migrate [COMMAND]
+
+Commands:
+  up          Apply all pending migrations (default)
+  down <n>    Revert the last <n> applied migrations
+  status      Show which migrations have been applied
+  create-db   Create the database if it does not exist
+
+The database is read from DATABASE_URL (postgres:// or sqlite:).";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = args.first().map(String::as_str).unwrap_or("up");
+
+    if matches!(command, "-h" | "--help" | "help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+
+    match command {
+        "up" => {
+            let db = Database::connect(&url, 1).await?;
+            migrations::run_migrations(&db).await?;
+            println!("Migrations are up to date");
+        },
+        "down" => {
+            let count: usize = args
+                .get(1)
+                .context("down requires the number of migrations to revert")?
+                .parse()
+                .context("down expects a non-negative number")?;
+
+            let db = Database::connect(&url, 1).await?;
+            let reverted = migrations::revert_migrations(&db, count).await?;
+
+            if reverted.is_empty() {
+                println!("No migrations to revert");
+            }
+            for version in reverted {
+                println!("Reverted {}", version);
+            }
+        },
+        "status" => {
+            let db = Database::connect(&url, 1).await?;
+
+            for migration in migrations::migration_status(&db).await? {
+                let state = match (migration.applied, migration.checksum_mismatch) {
+                    (true, true) => "applied (checksum mismatch)",
+                    (true, false) => "applied",
+                    (false, _) => "pending",
+                };
+                println!("{:>4}  {:<40} {}", migration.version, migration.description, state);
+            }
+        },
+        "create-db" => {
+            if migrations::create_database(&url).await? {
+                println!("Database created");
+            } else {
+                println!("Database already exists");
+            }
+        },
+        other => {
+            eprintln!("Unknown command: {}\n\n{}", other, USAGE);
+            std::process::exit(2);
+        },
+    }
+
+    Ok(())
 }
@@ -0,0 +1,160 @@
+//! Client assertions for `private_key_jwt` authentication (RFC 7523)
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// `client_assertion_type` of JWT client assertions
+pub const JWT_BEARER: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Furthest in the future an assertion may expire
+const MAX_LIFETIME: Duration = Duration::minutes(5);
+
+/// Signature algorithms accepted for assertions
+///
+/// HMAC is excluded: it would verify with the public key as a shared secret.
+const ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Claims of a verified client assertion
+#[derive(Debug, Deserialize)]
+pub struct AssertionClaims {
+    /// Client ID
+    pub sub: String,
+    /// Unique ID, which may only be used once
+    pub jti: String,
+    /// Expiry as a Unix timestamp
+    pub exp: i64,
+}
+
+/// Parse a client's registered JWKS, checking that every key is a public signing key
+pub fn parse_jwks(jwks: &serde_json::Value) -> Result<JwkSet> {
+    let jwks: JwkSet = serde_json::from_value(jwks.clone())
+        .map_err(|err| anyhow::anyhow!("jwks is not a valid JWK set: {}", err))?;
+
+    if jwks.keys.is_empty() {
+        return Err(anyhow::anyhow!("jwks must contain at least one key"));
+    }
+    for jwk in &jwks.keys {
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err(anyhow::anyhow!("jwks must only contain public keys"));
+        }
+        DecodingKey::from_jwk(jwk)?;
+    }
+
+    Ok(jwks)
+}
+
+/// Subject of an assertion, read without checking its signature
+///
+/// Only used to find the client whose keys then verify the assertion.
+pub fn unverified_subject(assertion: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Subject {
+        sub: String,
+    }
+
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    decode::<Subject>(assertion, &DecodingKey::from_secret(&[]), &validation)
+        .ok()
+        .map(|data| data.claims.sub)
+}
+
+/// Verify an assertion signed by one of the client's keys
+///
+/// The client must be both issuer and subject, the audience must be one of
+/// `audiences`, and the assertion must expire within a few minutes.
+pub fn verify(assertion: &str, jwks: &JwkSet, client_id: &str, audiences: &[String]) -> Result<AssertionClaims> {
+    let header = decode_header(assertion)?;
+    if !ALGORITHMS.contains(&header.alg) {
+        return Err(anyhow::anyhow!("Unsupported algorithm {:?}", header.alg));
+    }
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| anyhow::anyhow!("Assertion is not signed by a registered key"))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[client_id]);
+    validation.set_audience(audiences);
+    validation.sub = Some(client_id.to_string());
+    validation.set_required_spec_claims(&["exp", "iss", "sub", "aud"]);
+
+    let claims = decode::<AssertionClaims>(assertion, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+
+    if claims.exp > (Utc::now() + MAX_LIFETIME).timestamp() {
+        return Err(anyhow::anyhow!("Assertion expires too far in the future"));
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::JwtKeys;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Claims<'a> {
+        iss: &'a str,
+        sub: &'a str,
+        aud: &'a str,
+        jti: &'a str,
+        exp: i64,
+    }
+
+    fn claims<'a>(client_id: &'a str, aud: &'a str, exp: i64) -> Claims<'a> {
+        Claims { iss: client_id, sub: client_id, aud, jti: "1", exp }
+    }
+
+    #[test]
+    fn verifies_assertions_from_the_clients_keys() {
+        let keys = JwtKeys::generate();
+        let jwks = parse_jwks(&serde_json::to_value(keys.jwks()).unwrap()).unwrap();
+        let audiences = ["https://vault.example.com/oauth/token".to_string()];
+        let exp = Utc::now().timestamp() + 60;
+
+        let assertion = keys.encode(&claims("friday", &audiences[0], exp)).unwrap();
+        assert_eq!(unverified_subject(&assertion).as_deref(), Some("friday"));
+        let verified = verify(&assertion, &jwks, "friday", &audiences).unwrap();
+        assert_eq!(verified.jti, "1");
+
+        // Signed for another client, audience or lifetime
+        assert!(verify(&assertion, &jwks, "jarvis", &audiences).is_err());
+        let elsewhere = keys.encode(&claims("friday", "https://evil.example.com", exp)).unwrap();
+        assert!(verify(&elsewhere, &jwks, "friday", &audiences).is_err());
+        let long_lived = keys.encode(&claims("friday", &audiences[0], exp + 3600)).unwrap();
+        assert!(verify(&long_lived, &jwks, "friday", &audiences).is_err());
+
+        // Signed by a key the client did not register
+        let forged = JwtKeys::generate().encode(&claims("friday", &audiences[0], exp)).unwrap();
+        assert!(verify(&forged, &jwks, "friday", &audiences).is_err());
+    }
+
+    #[test]
+    fn rejects_symmetric_keys() {
+        let jwks = serde_json::json!({ "keys": [{ "kty": "oct", "k": "c2VjcmV0" }] });
+        assert!(parse_jwks(&jwks).is_err());
+        assert!(parse_jwks(&serde_json::json!({ "keys": [] })).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use async_graphql::{Context, ErrorExtensions, InputObject, Json, Object};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+use super::models::{Client, ClientError, RegisterClientInput, UpdateClientInput};
+use crate::api::auth::{account_owner, principal};
+use crate::api::AppState;
+use crate::utils::errors::AppError;
+
+/// GraphQL representation of a registered client application
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLClient {
+    /// Public client identifier
+    pub client_id: String,
+    /// Name shown on the consent page
+    pub name: String,
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+    /// Client's home page
+    pub homepage_uri: Option<String>,
+    /// Registered redirect URIs
+    pub redirect_uris: Vec<String>,
+    /// How the client authenticates at the token endpoint
+    pub token_endpoint_auth_method: String,
+    /// Public keys verifying the client's assertions
+    pub jwks: Option<Json<serde_json::Value>>,
+    /// Scopes the client may request
+    pub allowed_scopes: Vec<String>,
+    /// Context domains the client may request
+    pub allowed_domains: Vec<String>,
+    /// Whether the client can be granted and use access
+    pub enabled: bool,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last update timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Client> for GraphQLClient {
+    fn from(client: Client) -> Self {
+        Self {
+            client_id: client.client_id,
+            name: client.name,
+            logo_uri: client.logo_uri,
+            homepage_uri: client.homepage_uri,
+            redirect_uris: client.redirect_uris,
+            token_endpoint_auth_method: client.token_endpoint_auth_method,
+            jwks: client.jwks.map(Json),
+            allowed_scopes: client.allowed_scopes,
+            allowed_domains: client.allowed_domains,
+            enabled: client.enabled,
+            created_at: client.created_at,
+            updated_at: client.updated_at,
+        }
+    }
+}
+
+/// A newly registered client with its one-time secret
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLRegisteredClient {
+    /// The registered client
+    pub client: GraphQLClient,
+    /// Client secret, for confidential clients; it is not shown again
+    pub client_secret: Option<String>,
+}
+
+/// GraphQL input for registering a client
+#[derive(InputObject)]
+pub struct GraphQLRegisterClientInput {
+    /// Name shown on the consent page
+    pub name: String,
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+    /// Client's home page
+    pub homepage_uri: Option<String>,
+    /// Redirect URIs authorization requests may use
+    pub redirect_uris: Vec<String>,
+    /// `none` for public clients, `client_secret_basic` (the default), `client_secret_post`
+    /// or `private_key_jwt`
+    pub token_endpoint_auth_method: Option<String>,
+    /// Public keys verifying the client's assertions, required for `private_key_jwt`
+    pub jwks: Option<Json<serde_json::Value>>,
+    /// Scopes the client may request
+    pub allowed_scopes: Vec<String>,
+    /// Context domains the client may request
+    pub allowed_domains: Vec<String>,
+}
+
+impl From<GraphQLRegisterClientInput> for RegisterClientInput {
+    fn from(input: GraphQLRegisterClientInput) -> Self {
+        Self {
+            name: input.name,
+            logo_uri: input.logo_uri,
+            homepage_uri: input.homepage_uri,
+            redirect_uris: input.redirect_uris,
+            token_endpoint_auth_method: input.token_endpoint_auth_method
+                .unwrap_or_else(|| "client_secret_basic".to_string()),
+            jwks: input.jwks.map(|jwks| jwks.0),
+            allowed_scopes: input.allowed_scopes,
+            allowed_domains: input.allowed_domains,
+        }
+    }
+}
+
+/// GraphQL input for updating a client; omitted fields are kept
+#[derive(InputObject)]
+pub struct GraphQLUpdateClientInput {
+    /// Name shown on the consent page
+    pub name: Option<String>,
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+    /// Client's home page
+    pub homepage_uri: Option<String>,
+    /// Redirect URIs authorization requests may use
+    pub redirect_uris: Option<Vec<String>>,
+    /// Public keys verifying the client's assertions
+    pub jwks: Option<Json<serde_json::Value>>,
+    /// Scopes the client may request
+    pub allowed_scopes: Option<Vec<String>>,
+    /// Context domains the client may request
+    pub allowed_domains: Option<Vec<String>>,
+    /// Enable or disable the client
+    pub enabled: Option<bool>,
+}
+
+impl From<GraphQLUpdateClientInput> for UpdateClientInput {
+    fn from(input: GraphQLUpdateClientInput) -> Self {
+        Self {
+            name: input.name,
+            logo_uri: input.logo_uri,
+            homepage_uri: input.homepage_uri,
+            redirect_uris: input.redirect_uris,
+            jwks: input.jwks.map(|jwks| jwks.0),
+            allowed_scopes: input.allowed_scopes,
+            allowed_domains: input.allowed_domains,
+            enabled: input.enabled,
+        }
+    }
+}
+
+/// Client query root
+#[derive(Default)]
+pub struct ClientQuery;
+
+#[Object]
+impl ClientQuery {
+    /// Get the clients the authenticated user registered
+    async fn clients(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GraphQLClient>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        let clients = state.client_service.clients(user_uuid).await?;
+        
+        Ok(clients.into_iter().map(GraphQLClient::from).collect())
+    }
+    
+    /// Get a registered client by its client ID
+    async fn client(&self, ctx: &Context<'_>, client_id: String) -> async_graphql::Result<Option<GraphQLClient>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        principal(ctx)?;
+        
+        let client = state.client_service.get_client(&client_id).await?;
+        
+        Ok(client.map(GraphQLClient::from))
+    }
+}
+
+/// Client mutation root
+#[derive(Default)]
+pub struct ClientMutation;
+
+#[Object]
+impl ClientMutation {
+    /// Register a client application owned by the authenticated user
+    async fn register_client(
+        &self,
+        ctx: &Context<'_>,
+        input: GraphQLRegisterClientInput,
+    ) -> async_graphql::Result<GraphQLRegisteredClient> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        let registered = state.client_service
+            .register(Some(user_uuid), input.into())
+            .await
+            .map_err(validation_error)?;
+        
+        Ok(GraphQLRegisteredClient {
+            client: GraphQLClient::from(registered.client),
+            client_secret: registered.client_secret,
+        })
+    }
+    
+    /// Update a client the authenticated user registered
+    async fn update_client(
+        &self,
+        ctx: &Context<'_>,
+        client_id: String,
+        input: GraphQLUpdateClientInput,
+    ) -> async_graphql::Result<GraphQLClient> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        state.client_service
+            .update_client(user_uuid, &client_id, input.into())
+            .await
+            .map_err(validation_error)?
+            .map(GraphQLClient::from)
+            .ok_or_else(|| not_found(&client_id))
+    }
+    
+    /// Replace a confidential client's secret, returning the new one
+    async fn rotate_client_secret(&self, ctx: &Context<'_>, client_id: String) -> async_graphql::Result<String> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        state.client_service.rotate_secret(user_uuid, &client_id).await?
+            .ok_or_else(|| not_found(&client_id))
+    }
+    
+    /// Delete a client the authenticated user registered
+    async fn delete_client(&self, ctx: &Context<'_>, client_id: String) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        Ok(state.client_service.delete_client(user_uuid, &client_id).await?)
+    }
+}
+
+/// Report invalid client metadata as a validation error
+fn validation_error(err: anyhow::Error) -> async_graphql::Error {
+    match err.downcast_ref::<ClientError>() {
+        Some(client_err) => AppError::ValidationError(client_err.to_string()).extend(),
+        None => err.into(),
+    }
+}
+
+fn not_found(client_id: &str) -> async_graphql::Error {
+    AppError::NotFound(format!("No client {} registered by you", client_id)).extend()
+}
@@ -0,0 +1,10 @@
+pub mod assertion;
+pub mod models;
+pub mod repository;
+pub mod service;
+pub mod sqlite;
+pub mod graphql;
+
+// Re-export key types
+pub use models::{Client, ClientError};
+pub use service::ClientService;
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use crate::policy_engine::Decision;
+
+/// A registered client application
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Client {
+    /// Public identifier used in OAuth requests and grants
+    pub client_id: String,
+
+    /// User who registered the client, or `None` for dynamic registrations
+    pub owner_id: Option<Uuid>,
+
+    /// Name shown to users on the consent page
+    pub name: String,
+
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+
+    /// Client's home page
+    pub homepage_uri: Option<String>,
+
+    /// Redirect URIs authorization requests may use, matched exactly
+    pub redirect_uris: Vec<String>,
+
+    /// How the client authenticates at the token endpoint
+    pub token_endpoint_auth_method: String,
+
+    /// SHA-256 of the client secret, for clients authenticating with one
+    #[serde(skip_serializing)]
+    pub client_secret_hash: Option<String>,
+
+    /// Public keys verifying the client's assertions, for `private_key_jwt`
+    pub jwks: Option<serde_json::Value>,
+
+    /// Scopes the client may ever request
+    pub allowed_scopes: Vec<String>,
+
+    /// Context domains the client may ever request
+    pub allowed_domains: Vec<String>,
+
+    /// Disabled clients cannot be granted or use access
+    pub enabled: bool,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Last update timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Client {
+    /// Whether the client may request these scopes and domains
+    pub fn allows(&self, scopes: &[String], domains: &[String]) -> bool {
+        scopes.iter().all(|scope| self.allowed_scopes.contains(scope))
+            && domains.iter().all(|domain| self.allowed_domains.contains(domain))
+    }
+}
+
+/// Input for registering a client
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegisterClientInput {
+    /// Name shown to users on the consent page
+    pub name: String,
+
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+
+    /// Client's home page
+    pub homepage_uri: Option<String>,
+
+    /// Redirect URIs authorization requests may use
+    pub redirect_uris: Vec<String>,
+
+    /// `none` for public clients, `client_secret_basic`, `client_secret_post` or `private_key_jwt`
+    pub token_endpoint_auth_method: String,
+
+    /// Public keys verifying the client's assertions, required for `private_key_jwt`
+    pub jwks: Option<serde_json::Value>,
+
+    /// Scopes the client may ever request
+    pub allowed_scopes: Vec<String>,
+
+    /// Context domains the client may ever request
+    pub allowed_domains: Vec<String>,
+}
+
+/// Changes to a registered client; fields left `None` are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateClientInput {
+    /// Name shown to users on the consent page
+    pub name: Option<String>,
+
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+
+    /// Client's home page
+    pub homepage_uri: Option<String>,
+
+    /// Redirect URIs authorization requests may use
+    pub redirect_uris: Option<Vec<String>>,
+
+    /// Public keys verifying the client's assertions
+    pub jwks: Option<serde_json::Value>,
+
+    /// Scopes the client may ever request
+    pub allowed_scopes: Option<Vec<String>>,
+
+    /// Context domains the client may ever request
+    pub allowed_domains: Option<Vec<String>>,
+
+    /// Enable or disable the client
+    pub enabled: Option<bool>,
+}
+
+/// A newly registered client and its secret, which is only shown once
+#[derive(Debug, Clone)]
+pub struct RegisteredClient {
+    /// The stored client
+    pub client: Client,
+
+    /// Client secret, for clients authenticating with one
+    pub client_secret: Option<String>,
+}
+
+/// Credentials a client presents at the token endpoint (RFC 6749 section 2.3)
+#[derive(Debug, Clone, Default)]
+pub struct ClientCredentials {
+    /// `client_id` form parameter
+    pub client_id: Option<String>,
+    /// Client ID and secret from an `Authorization: Basic` header
+    pub basic: Option<(String, String)>,
+    /// `client_secret` form parameter
+    pub client_secret: Option<String>,
+    /// Must be `urn:ietf:params:oauth:client-assertion-type:jwt-bearer`
+    pub client_assertion_type: Option<String>,
+    /// JWT signed with one of the client's registered keys (RFC 7523)
+    pub client_assertion: Option<String>,
+}
+
+/// Client metadata of a dynamic registration request (RFC 7591 section 2)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClientMetadata {
+    /// Redirect URIs authorization requests may use
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    /// Name shown to users on the consent page
+    pub client_name: Option<String>,
+    /// Logo shown on the consent page
+    pub logo_uri: Option<String>,
+    /// Client's home page
+    pub client_uri: Option<String>,
+    /// Space-separated scopes and `domain:<name>` entries the client may request
+    pub scope: Option<String>,
+    /// Defaults to `client_secret_basic`
+    pub token_endpoint_auth_method: Option<String>,
+    /// Public keys for `private_key_jwt`
+    pub jwks: Option<serde_json::Value>,
+    /// Not supported; keys must be registered inline with `jwks`
+    pub jwks_uri: Option<String>,
+    /// Must only name `authorization_code` and `refresh_token`
+    pub grant_types: Option<Vec<String>>,
+    /// Must only name `code`
+    pub response_types: Option<Vec<String>>,
+}
+
+/// Dynamic registration response (RFC 7591 section 3.2.1)
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInformation {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub client_id_issued_at: i64,
+    /// Zero, since secrets do not expire
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret_expires_at: Option<i64>,
+    pub client_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_uri: Option<String>,
+    pub redirect_uris: Vec<String>,
+    pub scope: String,
+    pub token_endpoint_auth_method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<serde_json::Value>,
+    pub grant_types: Vec<&'static str>,
+    pub response_types: Vec<&'static str>,
+}
+
+/// Errors reported to registration and token requests (RFC 7591 section 3.2.2, RFC 6749 section 5.2)
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("{0}")]
+    InvalidRedirectUri(String),
+
+    #[error("{0}")]
+    InvalidClientMetadata(String),
+
+    #[error("Dynamic client registration is not permitted")]
+    RegistrationDenied,
+
+    #[error("Dynamic client registration is not permitted: {}", .0.explanation())]
+    PolicyDenied(Decision),
+
+    #[error("{0}")]
+    InvalidClient(String),
+}
+
+impl ClientError {
+    /// Value of the `error` parameter
+    pub fn code(&self) -> &'static str {
+        match self {
+            ClientError::InvalidRedirectUri(_) => "invalid_redirect_uri",
+            ClientError::InvalidClientMetadata(_) => "invalid_client_metadata",
+            ClientError::RegistrationDenied | ClientError::PolicyDenied(_) => "access_denied",
+            ClientError::InvalidClient(_) => "invalid_client",
+        }
+    }
+}
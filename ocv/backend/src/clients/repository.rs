@@ -0,0 +1,254 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::models::{Client, RegisterClientInput, UpdateClientInput};
+
+/// Storage for registered client applications
+#[async_trait]
+pub trait ClientRepository: Send + Sync {
+    /// Register a client
+    async fn create_client(
+        &self,
+        client_id: &str,
+        owner_id: Option<Uuid>,
+        input: RegisterClientInput,
+        client_secret_hash: Option<String>,
+    ) -> Result<Client>;
+
+    /// Get a client by its client ID
+    async fn get_client(&self, client_id: &str) -> Result<Option<Client>>;
+
+    /// List the clients a user registered
+    async fn list_clients(&self, owner_id: Uuid) -> Result<Vec<Client>>;
+
+    /// Update a client registered by `owner_id`
+    async fn update_client(
+        &self,
+        owner_id: Uuid,
+        client_id: &str,
+        input: UpdateClientInput,
+    ) -> Result<Option<Client>>;
+
+    /// Replace the secret of a confidential client registered by `owner_id`
+    async fn set_client_secret(&self, owner_id: Uuid, client_id: &str, client_secret_hash: &str) -> Result<bool>;
+
+    /// Delete a client registered by `owner_id`
+    async fn delete_client(&self, owner_id: Uuid, client_id: &str) -> Result<bool>;
+
+    /// Disable every client registered by `owner_id`, returning their client IDs
+    async fn disable_clients(&self, owner_id: Uuid) -> Result<Vec<String>>;
+
+    /// Remember a client assertion's ID until it expires
+    ///
+    /// Returns `false` if the client already used this ID.
+    async fn record_assertion(&self, client_id: &str, jti: &str, expires_at: DateTime<Utc>) -> Result<bool>;
+}
+
+/// Postgres repository for registered clients
+pub struct PgClientRepository {
+    pool: PgPool,
+}
+
+impl PgClientRepository {
+    /// Create a new client repository
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ClientRepository for PgClientRepository {
+    async fn create_client(
+        &self,
+        client_id: &str,
+        owner_id: Option<Uuid>,
+        input: RegisterClientInput,
+        client_secret_hash: Option<String>,
+    ) -> Result<Client> {
+        let client = sqlx::query_as::<_, Client>(
+            r#"
+            INSERT INTO clients (
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .bind(input.name)
+        .bind(input.logo_uri)
+        .bind(input.homepage_uri)
+        .bind(&input.redirect_uris)
+        .bind(input.token_endpoint_auth_method)
+        .bind(client_secret_hash)
+        .bind(input.jwks)
+        .bind(&input.allowed_scopes)
+        .bind(&input.allowed_domains)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(client)
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<Option<Client>> {
+        let client = sqlx::query_as::<_, Client>(
+            r#"
+            SELECT
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            FROM clients
+            WHERE client_id = $1
+            "#,
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(client)
+    }
+
+    async fn list_clients(&self, owner_id: Uuid) -> Result<Vec<Client>> {
+        let clients = sqlx::query_as::<_, Client>(
+            r#"
+            SELECT
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            FROM clients
+            WHERE owner_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(clients)
+    }
+
+    async fn update_client(
+        &self,
+        owner_id: Uuid,
+        client_id: &str,
+        input: UpdateClientInput,
+    ) -> Result<Option<Client>> {
+        let client = sqlx::query_as::<_, Client>(
+            r#"
+            UPDATE clients
+            SET
+                name = COALESCE($3, name),
+                logo_uri = COALESCE($4, logo_uri),
+                homepage_uri = COALESCE($5, homepage_uri),
+                redirect_uris = COALESCE($6, redirect_uris),
+                allowed_scopes = COALESCE($7, allowed_scopes),
+                allowed_domains = COALESCE($8, allowed_domains),
+                enabled = COALESCE($9, enabled),
+                jwks = COALESCE($10, jwks),
+                updated_at = NOW()
+            WHERE client_id = $1 AND owner_id = $2
+            RETURNING
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .bind(input.name)
+        .bind(input.logo_uri)
+        .bind(input.homepage_uri)
+        .bind(input.redirect_uris)
+        .bind(input.allowed_scopes)
+        .bind(input.allowed_domains)
+        .bind(input.enabled)
+        .bind(input.jwks)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(client)
+    }
+
+    async fn set_client_secret(&self, owner_id: Uuid, client_id: &str, client_secret_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE clients
+            SET client_secret_hash = $3, updated_at = NOW()
+            WHERE client_id = $1 AND owner_id = $2 AND client_secret_hash IS NOT NULL
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .bind(client_secret_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_client(&self, owner_id: Uuid, client_id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM clients
+            WHERE client_id = $1 AND owner_id = $2
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn disable_clients(&self, owner_id: Uuid) -> Result<Vec<String>> {
+        let client_ids = sqlx::query_scalar(
+            r#"
+            UPDATE clients
+            SET enabled = FALSE, updated_at = NOW()
+            WHERE owner_id = $1
+            RETURNING client_id
+            "#,
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(client_ids)
+    }
+
+    async fn record_assertion(&self, client_id: &str, jti: &str, expires_at: DateTime<Utc>) -> Result<bool> {
+        sqlx::query(
+            r#"
+            DELETE FROM client_assertions
+            WHERE client_id = $1 AND expires_at < NOW()
+            "#,
+        )
+        .bind(client_id)
+        .execute(&self.pool)
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO client_assertions (client_id, jti, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(client_id)
+        .bind(jti)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,580 @@
+use anyhow::Result;
+use chrono::DateTime;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use url::Url;
+use uuid::Uuid;
+
+use super::{
+    assertion::{self, JWT_BEARER},
+    models::{
+        Client, ClientCredentials, ClientError, ClientInformation, ClientMetadata, RegisterClientInput,
+        RegisteredClient, UpdateClientInput,
+    },
+    repository::{ClientRepository, PgClientRepository},
+    sqlite::SqliteClientRepository,
+};
+use crate::oauth::service::{format_scope, parse_scope};
+use crate::policy_engine::service::PolicyEngine;
+use crate::storage::Database;
+use crate::utils::tokens::{random_token, sha256_base64url};
+
+/// Scopes a client can be granted on a context domain
+pub const SCOPES: [&str; 2] = ["read", "write"];
+
+/// Ways a client can authenticate at the token endpoint
+const TOKEN_ENDPOINT_AUTH_METHODS: [&str; 4] =
+    ["none", "client_secret_basic", "client_secret_post", "private_key_jwt"];
+
+/// Methods authenticating with a client secret
+const SECRET_AUTH_METHODS: [&str; 2] = ["client_secret_basic", "client_secret_post"];
+
+/// Grant types registered clients can use
+const GRANT_TYPES: [&str; 2] = ["authorization_code", "refresh_token"];
+
+/// Response types registered clients can use
+const RESPONSE_TYPES: [&str; 1] = ["code"];
+
+/// Service for registering and managing client applications
+///
+/// Only registered, enabled clients can be granted access, and only to the
+/// scopes and domains they were registered for.
+pub struct ClientService {
+    repository: Arc<dyn ClientRepository>,
+    policy_engine: Arc<PolicyEngine>,
+    dynamic_registration: bool,
+    audiences: Vec<String>,
+}
+
+impl ClientService {
+    /// Create a new client service
+    ///
+    /// With `dynamic_registration` off, the RFC 7591 endpoint rejects every
+    /// request; with it on, each one is checked against the
+    /// `client_registration` policy. Client assertions must be addressed to
+    /// `issuer` or its token endpoint.
+    pub fn new(
+        db: &Database,
+        policy_engine: Arc<PolicyEngine>,
+        dynamic_registration: bool,
+        issuer: &str,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            repository: match db {
+                Database::Postgres(pool) => Arc::new(PgClientRepository::new(pool.clone())),
+                Database::Sqlite(pool) => Arc::new(SqliteClientRepository::new(pool.clone())),
+            },
+            policy_engine,
+            dynamic_registration,
+            audiences: vec![
+                issuer.to_string(),
+                format!("{}/oauth/token", issuer.trim_end_matches('/')),
+            ],
+        })
+    }
+
+    /// Register a client, owned by `owner_id` if a user registers it
+    ///
+    /// Clients authenticating with a secret get one, which is returned here and
+    /// never again.
+    pub async fn register(&self, owner_id: Option<Uuid>, input: RegisterClientInput) -> Result<RegisteredClient> {
+        validate(&input)?;
+
+        let client_secret = SECRET_AUTH_METHODS
+            .contains(&input.token_endpoint_auth_method.as_str())
+            .then(random_token);
+        let client = self.repository
+            .create_client(
+                &Uuid::new_v4().to_string(),
+                owner_id,
+                input,
+                client_secret.as_deref().map(sha256_base64url),
+            )
+            .await?;
+
+        Ok(RegisteredClient { client, client_secret })
+    }
+
+    /// Handle a dynamic client registration request (RFC 7591)
+    pub async fn register_dynamic(&self, metadata: ClientMetadata) -> Result<ClientInformation> {
+        if !self.dynamic_registration {
+            return Err(ClientError::RegistrationDenied.into());
+        }
+
+        let unsupported = |values: &Option<Vec<String>>, supported: &[&str]| {
+            values.iter().flatten().any(|value| !supported.contains(&value.as_str()))
+        };
+        if unsupported(&metadata.grant_types, &GRANT_TYPES) {
+            return Err(invalid_metadata("grant_types may only include authorization_code and refresh_token"));
+        }
+        if unsupported(&metadata.response_types, &RESPONSE_TYPES) {
+            return Err(invalid_metadata("response_types may only include code"));
+        }
+        if metadata.jwks_uri.is_some() {
+            return Err(invalid_metadata("jwks_uri is not supported; register keys with jwks"));
+        }
+
+        let scope = metadata.scope.ok_or_else(|| invalid_metadata("scope is required"))?;
+        let (allowed_scopes, allowed_domains) = parse_scope(&scope).map_err(|err| invalid_metadata(&err.to_string()))?;
+
+        let input = RegisterClientInput {
+            // client_name is optional, so fall back to where the client lives
+            name: metadata.client_name
+                .or_else(|| {
+                    let redirect_uri = Url::parse(metadata.redirect_uris.first()?).ok()?;
+                    redirect_uri.host_str().map(str::to_string)
+                })
+                .unwrap_or_default(),
+            logo_uri: metadata.logo_uri,
+            homepage_uri: metadata.client_uri,
+            redirect_uris: metadata.redirect_uris,
+            token_endpoint_auth_method: metadata.token_endpoint_auth_method
+                .unwrap_or_else(|| "client_secret_basic".to_string()),
+            jwks: metadata.jwks,
+            allowed_scopes,
+            allowed_domains,
+        };
+        validate(&input)?;
+
+        let policy_input = serde_json::json!({
+            "action": "register",
+            "client_name": input.name,
+            "redirect_uris": input.redirect_uris,
+            "token_endpoint_auth_method": input.token_endpoint_auth_method,
+            "scopes": input.allowed_scopes,
+            "domains": input.allowed_domains
+        });
+
+        let decision = self.policy_engine.evaluate("client_registration", policy_input).await?;
+        if !decision.allow {
+            return Err(ClientError::PolicyDenied(decision).into());
+        }
+
+        let RegisteredClient { client, client_secret } = self.register(None, input).await?;
+        log::info!("Dynamically registered client {} ({})", client.client_id, client.name);
+
+        Ok(ClientInformation {
+            client_secret_expires_at: client_secret.as_ref().map(|_| 0),
+            client_secret,
+            client_id_issued_at: client.created_at.timestamp(),
+            scope: format_scope(&client.allowed_scopes, &client.allowed_domains),
+            client_id: client.client_id,
+            client_name: client.name,
+            logo_uri: client.logo_uri,
+            client_uri: client.homepage_uri,
+            redirect_uris: client.redirect_uris,
+            token_endpoint_auth_method: client.token_endpoint_auth_method,
+            jwks: client.jwks,
+            grant_types: GRANT_TYPES.to_vec(),
+            response_types: RESPONSE_TYPES.to_vec(),
+        })
+    }
+
+    /// Get a client by its client ID
+    pub async fn get_client(&self, client_id: &str) -> Result<Option<Client>> {
+        self.repository.get_client(client_id).await
+    }
+
+    /// Get a client that is registered and enabled
+    pub async fn active_client(&self, client_id: &str) -> Result<Option<Client>> {
+        Ok(self.repository.get_client(client_id).await?.filter(|client| client.enabled))
+    }
+
+    /// Authenticate a client at the token endpoint
+    ///
+    /// The client must use the method it registered: no credentials for public
+    /// clients, its secret for `client_secret_basic` and `client_secret_post`,
+    /// or an assertion signed with one of its keys for `private_key_jwt`.
+    pub async fn authenticate(&self, credentials: ClientCredentials) -> Result<Client> {
+        let presented = [
+            credentials.basic.is_some(),
+            credentials.client_secret.is_some(),
+            credentials.client_assertion.is_some(),
+        ];
+        if presented.iter().filter(|presented| **presented).count() > 1 {
+            return Err(invalid_client("Use only one client authentication method"));
+        }
+
+        let (method, client_id) = if let Some((client_id, _)) = &credentials.basic {
+            ("client_secret_basic", Some(client_id.clone()))
+        } else if credentials.client_secret.is_some() {
+            ("client_secret_post", credentials.client_id.clone())
+        } else if let Some(client_assertion) = &credentials.client_assertion {
+            if credentials.client_assertion_type.as_deref() != Some(JWT_BEARER) {
+                return Err(invalid_client("Unsupported client_assertion_type"));
+            }
+            ("private_key_jwt", assertion::unverified_subject(client_assertion))
+        } else {
+            ("none", credentials.client_id.clone())
+        };
+
+        let client_id = client_id.ok_or_else(|| invalid_client("client_id is required"))?;
+        if credentials.client_id.as_ref().is_some_and(|id| *id != client_id) {
+            return Err(invalid_client("client_id does not match the client credentials"));
+        }
+
+        let client = self.active_client(&client_id).await?
+            .ok_or_else(|| invalid_client(&format!("Unknown or disabled client: {}", client_id)))?;
+        if client.token_endpoint_auth_method != method {
+            return Err(invalid_client(&format!(
+                "Client {} must authenticate with {}",
+                client.client_id, client.token_endpoint_auth_method
+            )));
+        }
+
+        match method {
+            "client_secret_basic" | "client_secret_post" => {
+                let secret = credentials.basic.map(|(_, secret)| secret)
+                    .or(credentials.client_secret)
+                    .unwrap_or_default();
+                let stored = client.client_secret_hash.as_deref().unwrap_or_default();
+                let matches = stored.as_bytes().ct_eq(sha256_base64url(&secret).as_bytes());
+                if stored.is_empty() || !bool::from(matches) {
+                    return Err(invalid_client("Client authentication failed"));
+                }
+            }
+            "private_key_jwt" => {
+                let claims = client.jwks.as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("Client has no registered keys"))
+                    .and_then(assertion::parse_jwks)
+                    .and_then(|jwks| assertion::verify(
+                        credentials.client_assertion.as_deref().unwrap_or_default(),
+                        &jwks,
+                        &client.client_id,
+                        &self.audiences,
+                    ))
+                    .map_err(|err| invalid_client(&format!("Invalid client assertion: {}", err)))?;
+
+                let expires_at = DateTime::from_timestamp(claims.exp, 0)
+                    .ok_or_else(|| invalid_client("Invalid client assertion expiry"))?;
+                if !self.repository.record_assertion(&client.client_id, &claims.jti, expires_at).await? {
+                    return Err(invalid_client("Client assertion was already used"));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(client)
+    }
+
+    /// List the clients a user registered
+    pub async fn clients(&self, owner_id: Uuid) -> Result<Vec<Client>> {
+        self.repository.list_clients(owner_id).await
+    }
+
+    /// Update a client the user registered
+    pub async fn update_client(
+        &self,
+        owner_id: Uuid,
+        client_id: &str,
+        input: UpdateClientInput,
+    ) -> Result<Option<Client>> {
+        if let Some(name) = &input.name {
+            validate_name(name)?;
+        }
+        if let Some(redirect_uris) = &input.redirect_uris {
+            validate_redirect_uris(redirect_uris)?;
+        }
+        validate_links(input.logo_uri.as_deref(), input.homepage_uri.as_deref())?;
+        if let Some(scopes) = &input.allowed_scopes {
+            validate_scopes(scopes)?;
+        }
+        if let Some(domains) = &input.allowed_domains {
+            validate_domains(domains)?;
+        }
+        if let Some(jwks) = &input.jwks {
+            validate_jwks(jwks)?;
+        }
+
+        self.repository.update_client(owner_id, client_id, input).await
+    }
+
+    /// Replace the secret of a confidential client the user registered
+    ///
+    /// Returns the new secret, or `None` if there is no such confidential client.
+    pub async fn rotate_secret(&self, owner_id: Uuid, client_id: &str) -> Result<Option<String>> {
+        let client_secret = random_token();
+        let rotated = self.repository
+            .set_client_secret(owner_id, client_id, &sha256_base64url(&client_secret))
+            .await?;
+
+        Ok(rotated.then_some(client_secret))
+    }
+
+    /// Delete a client the user registered
+    ///
+    /// Its grants stay in the audit trail but no longer give access.
+    pub async fn delete_client(&self, owner_id: Uuid, client_id: &str) -> Result<bool> {
+        self.repository.delete_client(owner_id, client_id).await
+    }
+
+    /// Disable every client a user registered, returning their client IDs
+    ///
+    /// Used when the account is deleted: the clients are kept, without an
+    /// owner, so the grants other users made to them can be revoked openly.
+    pub async fn disable_clients(&self, owner_id: Uuid) -> Result<Vec<String>> {
+        self.repository.disable_clients(owner_id).await
+    }
+}
+
+/// Check everything about a registration
+fn validate(input: &RegisterClientInput) -> Result<()> {
+    validate_redirect_uris(&input.redirect_uris)?;
+    validate_name(&input.name)?;
+    validate_links(input.logo_uri.as_deref(), input.homepage_uri.as_deref())?;
+    validate_scopes(&input.allowed_scopes)?;
+    validate_domains(&input.allowed_domains)?;
+
+    if !TOKEN_ENDPOINT_AUTH_METHODS.contains(&input.token_endpoint_auth_method.as_str()) {
+        return Err(invalid_metadata(&format!(
+            "Unsupported token_endpoint_auth_method {}",
+            input.token_endpoint_auth_method
+        )));
+    }
+
+    match &input.jwks {
+        Some(jwks) => validate_jwks(jwks),
+        None if input.token_endpoint_auth_method == "private_key_jwt" => {
+            Err(invalid_metadata("private_key_jwt clients must register jwks"))
+        }
+        None => Ok(()),
+    }
+}
+
+fn validate_jwks(jwks: &serde_json::Value) -> Result<()> {
+    assertion::parse_jwks(jwks).map(|_| ()).map_err(|err| invalid_metadata(&err.to_string()))
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(invalid_metadata("Client name is required"));
+    }
+    Ok(())
+}
+
+fn validate_redirect_uris(redirect_uris: &[String]) -> Result<()> {
+    if redirect_uris.is_empty() {
+        return Err(ClientError::InvalidRedirectUri("At least one redirect URI is required".to_string()).into());
+    }
+    redirect_uris.iter().try_for_each(|redirect_uri| validate_redirect_uri(redirect_uri))
+}
+
+/// Check that the logo and home page, if given, are web URLs
+fn validate_links(logo_uri: Option<&str>, homepage_uri: Option<&str>) -> Result<()> {
+    for uri in logo_uri.into_iter().chain(homepage_uri) {
+        let scheme = Url::parse(uri).map(|url| url.scheme().to_string());
+        if !matches!(scheme.as_deref(), Ok("https" | "http")) {
+            return Err(invalid_metadata(&format!("{} is not an http(s) URL", uri)));
+        }
+    }
+    Ok(())
+}
+
+/// Accept HTTPS redirect URIs, and plain HTTP only to the loopback interface
+fn validate_redirect_uri(redirect_uri: &str) -> Result<()> {
+    let invalid = |message: &str| ClientError::InvalidRedirectUri(format!("{}: {}", redirect_uri, message)).into();
+    let url = Url::parse(redirect_uri).map_err(|_| invalid("redirect URIs must be absolute URLs"))?;
+
+    if url.fragment().is_some() {
+        return Err(invalid("redirect URIs must not contain a fragment"));
+    }
+
+    let loopback = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if loopback => Ok(()),
+        _ => Err(invalid("redirect URIs must use https")),
+    }
+}
+
+fn validate_scopes(scopes: &[String]) -> Result<()> {
+    if scopes.is_empty() {
+        return Err(invalid_metadata("A client must be allowed at least one scope"));
+    }
+    match scopes.iter().find(|scope| !SCOPES.contains(&scope.as_str())) {
+        Some(scope) => Err(invalid_metadata(&format!("Unknown scope {}", scope))),
+        None => Ok(()),
+    }
+}
+
+fn validate_domains(domains: &[String]) -> Result<()> {
+    if domains.is_empty() {
+        return Err(invalid_metadata("A client must be allowed at least one domain"));
+    }
+    if domains.iter().any(|domain| domain.is_empty() || domain.contains(char::is_whitespace)) {
+        return Err(invalid_metadata("Domain names must be non-empty and contain no spaces"));
+    }
+    Ok(())
+}
+
+fn invalid_metadata(message: &str) -> anyhow::Error {
+    ClientError::InvalidClientMetadata(message.to_string()).into()
+}
+
+fn invalid_client(message: &str) -> anyhow::Error {
+    ClientError::InvalidClient(message.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::JwtKeys;
+    use crate::storage::{migrations, Database};
+
+    const ISSUER: &str = "https://vault.example.com";
+
+    async fn service(dynamic_registration: bool) -> Arc<ClientService> {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+        let policy_engine = PolicyEngine::new(&db);
+        policy_engine.install_default_policies().await.unwrap();
+        ClientService::new(&db, policy_engine, dynamic_registration, ISSUER)
+    }
+
+    fn metadata() -> ClientMetadata {
+        ClientMetadata {
+            redirect_uris: vec!["https://friday.example.com/callback".to_string()],
+            client_name: Some("Friday".to_string()),
+            scope: Some("read domain:travel".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn error_code(err: anyhow::Error) -> &'static str {
+        err.downcast_ref::<ClientError>().unwrap().code()
+    }
+
+    #[test]
+    fn redirect_uris_must_be_https_or_loopback() {
+        assert!(validate_redirect_uri("https://friday.example.com/cb?x=1").is_ok());
+        assert!(validate_redirect_uri("http://localhost:3001/callback").is_ok());
+        assert!(validate_redirect_uri("http://127.0.0.1/callback").is_ok());
+
+        assert!(validate_redirect_uri("http://friday.example.com/cb").is_err());
+        assert!(validate_redirect_uri("https://friday.example.com/cb#frag").is_err());
+        assert!(validate_redirect_uri("javascript:alert(1)").is_err());
+        assert!(validate_redirect_uri("/callback").is_err());
+    }
+
+    #[tokio::test]
+    async fn registers_clients_dynamically_when_enabled() {
+        let service = service(true).await;
+
+        let info = service.register_dynamic(metadata()).await.unwrap();
+        assert_eq!(info.scope, "read domain:travel");
+        assert_eq!(info.token_endpoint_auth_method, "client_secret_basic");
+        let secret = info.client_secret.unwrap();
+
+        let client = service.active_client(&info.client_id).await.unwrap().unwrap();
+        assert_eq!(client.owner_id, None);
+        assert_eq!(client.client_secret_hash, Some(sha256_base64url(&secret)));
+        assert!(client.allows(&["read".to_string()], &["travel".to_string()]));
+        assert!(!client.allows(&["write".to_string()], &["travel".to_string()]));
+
+        let public = service
+            .register_dynamic(ClientMetadata { token_endpoint_auth_method: Some("none".to_string()), ..metadata() })
+            .await
+            .unwrap();
+        assert!(public.client_secret.is_none());
+
+        let err = service
+            .register_dynamic(ClientMetadata { redirect_uris: vec!["http://friday.example.com".to_string()], ..metadata() })
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), "invalid_redirect_uri");
+
+        let err = service
+            .register_dynamic(ClientMetadata { scope: Some("admin domain:travel".to_string()), ..metadata() })
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), "invalid_client_metadata");
+    }
+
+    fn input(token_endpoint_auth_method: &str, jwks: Option<serde_json::Value>) -> RegisterClientInput {
+        RegisterClientInput {
+            name: "Friday".to_string(),
+            logo_uri: None,
+            homepage_uri: None,
+            redirect_uris: vec!["https://friday.example.com/callback".to_string()],
+            token_endpoint_auth_method: token_endpoint_auth_method.to_string(),
+            jwks,
+            allowed_scopes: vec!["read".to_string()],
+            allowed_domains: vec!["travel".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_clients_with_their_registered_method() {
+        let service = service(false).await;
+
+        let confidential = service.register(None, input("client_secret_post", None)).await.unwrap();
+        let client_id = confidential.client.client_id;
+        let secret = confidential.client_secret.unwrap();
+        let credentials = |secret: &str| ClientCredentials {
+            client_id: Some(client_id.clone()),
+            client_secret: Some(secret.to_string()),
+            ..Default::default()
+        };
+
+        assert!(service.authenticate(credentials(&secret)).await.is_ok());
+        let err = service.authenticate(credentials("guess")).await.unwrap_err();
+        assert_eq!(error_code(err), "invalid_client");
+
+        // A confidential client cannot fall back to acting as a public one
+        let err = service
+            .authenticate(ClientCredentials { client_id: Some(client_id.clone()), ..Default::default() })
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), "invalid_client");
+
+        // Nor use another method than the one it registered
+        let err = service
+            .authenticate(ClientCredentials { basic: Some((client_id.clone(), secret)), ..Default::default() })
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), "invalid_client");
+    }
+
+    #[tokio::test]
+    async fn authenticates_private_key_jwt_assertions_once() {
+        let service = service(false).await;
+        let keys = JwtKeys::generate();
+
+        let err = service.register(None, input("private_key_jwt", None)).await.unwrap_err();
+        assert_eq!(error_code(err), "invalid_client_metadata");
+
+        let jwks = serde_json::to_value(keys.jwks()).unwrap();
+        let registered = service.register(None, input("private_key_jwt", Some(jwks))).await.unwrap();
+        assert!(registered.client_secret.is_none());
+        let client_id = registered.client.client_id;
+
+        let assertion = keys
+            .encode(&serde_json::json!({
+                "iss": client_id,
+                "sub": client_id,
+                "aud": format!("{}/oauth/token", ISSUER),
+                "jti": Uuid::new_v4().to_string(),
+                "exp": chrono::Utc::now().timestamp() + 60,
+            }))
+            .unwrap();
+        let credentials = ClientCredentials {
+            client_assertion_type: Some(JWT_BEARER.to_string()),
+            client_assertion: Some(assertion),
+            ..Default::default()
+        };
+
+        let client = service.authenticate(credentials.clone()).await.unwrap();
+        assert_eq!(client.client_id, client_id);
+
+        let err = service.authenticate(credentials).await.unwrap_err();
+        assert_eq!(error_code(err), "invalid_client");
+    }
+
+    #[tokio::test]
+    async fn rejects_dynamic_registration_when_disabled() {
+        let service = service(false).await;
+
+        let err = service.register_dynamic(metadata()).await.unwrap_err();
+        assert_eq!(error_code(err), "access_denied");
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::models::{Client, RegisterClientInput, UpdateClientInput};
+use super::repository::ClientRepository;
+
+/// Client row as stored in SQLite, with arrays kept as JSON text
+#[derive(sqlx::FromRow)]
+struct ClientRow {
+    client_id: String,
+    owner_id: Option<Uuid>,
+    name: String,
+    logo_uri: Option<String>,
+    homepage_uri: Option<String>,
+    redirect_uris: Json<Vec<String>>,
+    token_endpoint_auth_method: String,
+    client_secret_hash: Option<String>,
+    jwks: Option<Json<serde_json::Value>>,
+    allowed_scopes: Json<Vec<String>>,
+    allowed_domains: Json<Vec<String>>,
+    enabled: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<ClientRow> for Client {
+    fn from(row: ClientRow) -> Self {
+        Self {
+            client_id: row.client_id,
+            owner_id: row.owner_id,
+            name: row.name,
+            logo_uri: row.logo_uri,
+            homepage_uri: row.homepage_uri,
+            redirect_uris: row.redirect_uris.0,
+            token_endpoint_auth_method: row.token_endpoint_auth_method,
+            client_secret_hash: row.client_secret_hash,
+            jwks: row.jwks.map(|jwks| jwks.0),
+            allowed_scopes: row.allowed_scopes.0,
+            allowed_domains: row.allowed_domains.0,
+            enabled: row.enabled,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// SQLite repository for registered clients
+pub struct SqliteClientRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteClientRepository {
+    /// Create a new client repository
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ClientRepository for SqliteClientRepository {
+    async fn create_client(
+        &self,
+        client_id: &str,
+        owner_id: Option<Uuid>,
+        input: RegisterClientInput,
+        client_secret_hash: Option<String>,
+    ) -> Result<Client> {
+        let now = Utc::now();
+        let row = sqlx::query_as::<_, ClientRow>(
+            r#"
+            INSERT INTO clients (
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $12)
+            RETURNING
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .bind(input.name)
+        .bind(input.logo_uri)
+        .bind(input.homepage_uri)
+        .bind(Json(&input.redirect_uris))
+        .bind(input.token_endpoint_auth_method)
+        .bind(client_secret_hash)
+        .bind(input.jwks.map(Json))
+        .bind(Json(&input.allowed_scopes))
+        .bind(Json(&input.allowed_domains))
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<Option<Client>> {
+        let row = sqlx::query_as::<_, ClientRow>(
+            r#"
+            SELECT
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            FROM clients
+            WHERE client_id = $1
+            "#,
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn list_clients(&self, owner_id: Uuid) -> Result<Vec<Client>> {
+        let rows = sqlx::query_as::<_, ClientRow>(
+            r#"
+            SELECT
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            FROM clients
+            WHERE owner_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn update_client(
+        &self,
+        owner_id: Uuid,
+        client_id: &str,
+        input: UpdateClientInput,
+    ) -> Result<Option<Client>> {
+        let row = sqlx::query_as::<_, ClientRow>(
+            r#"
+            UPDATE clients
+            SET
+                name = COALESCE($3, name),
+                logo_uri = COALESCE($4, logo_uri),
+                homepage_uri = COALESCE($5, homepage_uri),
+                redirect_uris = COALESCE($6, redirect_uris),
+                allowed_scopes = COALESCE($7, allowed_scopes),
+                allowed_domains = COALESCE($8, allowed_domains),
+                enabled = COALESCE($9, enabled),
+                jwks = COALESCE($10, jwks),
+                updated_at = $11
+            WHERE client_id = $1 AND owner_id = $2
+            RETURNING
+                client_id, owner_id, name, logo_uri, homepage_uri, redirect_uris,
+                token_endpoint_auth_method, client_secret_hash, jwks, allowed_scopes, allowed_domains,
+                enabled, created_at, updated_at
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .bind(input.name)
+        .bind(input.logo_uri)
+        .bind(input.homepage_uri)
+        .bind(input.redirect_uris.map(Json))
+        .bind(input.allowed_scopes.map(Json))
+        .bind(input.allowed_domains.map(Json))
+        .bind(input.enabled)
+        .bind(input.jwks.map(Json))
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn set_client_secret(&self, owner_id: Uuid, client_id: &str, client_secret_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE clients
+            SET client_secret_hash = $3, updated_at = $4
+            WHERE client_id = $1 AND owner_id = $2 AND client_secret_hash IS NOT NULL
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .bind(client_secret_hash)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_client(&self, owner_id: Uuid, client_id: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM clients
+            WHERE client_id = $1 AND owner_id = $2
+            "#,
+        )
+        .bind(client_id)
+        .bind(owner_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn disable_clients(&self, owner_id: Uuid) -> Result<Vec<String>> {
+        let client_ids = sqlx::query_scalar(
+            r#"
+            UPDATE clients
+            SET enabled = FALSE, updated_at = $2
+            WHERE owner_id = $1
+            RETURNING client_id
+            "#,
+        )
+        .bind(owner_id)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(client_ids)
+    }
+
+    async fn record_assertion(&self, client_id: &str, jti: &str, expires_at: DateTime<Utc>) -> Result<bool> {
+        sqlx::query(
+            r#"
+            DELETE FROM client_assertions
+            WHERE client_id = $1 AND expires_at < $2
+            "#,
+        )
+        .bind(client_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO client_assertions (client_id, jti, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(client_id)
+        .bind(jti)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::repository::IdentityRepository;
+    use crate::identity::sqlite::SqliteIdentityRepository;
+    use crate::identity::CreateUserInput;
+    use crate::storage::{migrations, Database};
+
+    async fn setup() -> (SqliteClientRepository, Uuid) {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let Database::Sqlite(pool) = db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+
+        let user = SqliteIdentityRepository::new(pool.clone())
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        (SqliteClientRepository::new(pool), user.id)
+    }
+
+    fn input() -> RegisterClientInput {
+        RegisterClientInput {
+            name: "Friday".to_string(),
+            logo_uri: None,
+            homepage_uri: Some("https://friday.example.com".to_string()),
+            redirect_uris: vec!["https://friday.example.com/callback".to_string()],
+            token_endpoint_auth_method: "client_secret_basic".to_string(),
+            jwks: None,
+            allowed_scopes: vec!["read".to_string()],
+            allowed_domains: vec!["travel".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_changes_a_client() {
+        let (repo, owner) = setup().await;
+        let stranger = Uuid::new_v4();
+
+        let client = repo.create_client("friday", Some(owner), input(), Some("hash".to_string())).await.unwrap();
+        assert!(client.enabled);
+        assert_eq!(client.redirect_uris, ["https://friday.example.com/callback"]);
+
+        let changes = UpdateClientInput {
+            allowed_domains: Some(vec!["travel".to_string(), "work".to_string()]),
+            enabled: Some(false),
+            ..Default::default()
+        };
+        assert!(repo.update_client(stranger, "friday", changes.clone()).await.unwrap().is_none());
+        let updated = repo.update_client(owner, "friday", changes).await.unwrap().unwrap();
+        assert_eq!(updated.allowed_domains, ["travel", "work"]);
+        assert_eq!(updated.allowed_scopes, ["read"]);
+        assert!(!updated.enabled);
+
+        assert!(!repo.set_client_secret(stranger, "friday", "other").await.unwrap());
+        assert!(repo.set_client_secret(owner, "friday", "other").await.unwrap());
+        let stored = repo.get_client("friday").await.unwrap().unwrap();
+        assert_eq!(stored.client_secret_hash.as_deref(), Some("other"));
+
+        assert_eq!(repo.list_clients(owner).await.unwrap().len(), 1);
+        assert!(!repo.delete_client(stranger, "friday").await.unwrap());
+        assert!(repo.delete_client(owner, "friday").await.unwrap());
+        assert!(repo.get_client("friday").await.unwrap().is_none());
+    }
+}
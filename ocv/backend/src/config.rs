@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use chrono::Duration;
+use std::env;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::encryption::KeyBackend;
+use crate::identity::signing::{JwtConfig, DEFAULT_ISSUER};
+use crate::identity::throttle::LoginLimits;
+use crate::mailer::MailBackend;
+
+/// Where context shards are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryBackend {
+    /// The `context_shards` table of the configured database
+    Database,
+    /// Basic's hosted mem0 service
+    Mem0,
+    /// Process memory, lost on restart
+    InMemory,
+}
+
+impl FromStr for MemoryBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "database" | "postgres" | "sqlite" => Ok(Self::Database),
+            "mem0" => Ok(Self::Mem0),
+            "memory" => Ok(Self::InMemory),
+            other => Err(anyhow::anyhow!("Unknown memory backend: {}", other)),
+        }
+    }
+}
+
+/// Runtime configuration loaded from the environment
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Database connection string (`postgres://` or `sqlite:`)
+    pub database_url: String,
+    /// Maximum number of pooled database connections
+    pub database_max_connections: u32,
+    /// Address the HTTP server binds to
+    pub bind_address: String,
+    /// Seconds to wait for in-flight requests during shutdown
+    pub shutdown_timeout: u64,
+    /// Storage backend for context shards
+    pub memory_backend: MemoryBackend,
+    /// Apply pending migrations before serving requests
+    pub run_migrations: bool,
+    /// Where the key wrapping per-user data keys lives
+    pub key_backend: KeyBackend,
+    /// Token signing keys
+    pub jwt: JwtConfig,
+    /// Consent UI page that OAuth authorization requests are sent to
+    pub consent_url: String,
+    /// Accept RFC 7591 dynamic client registrations, subject to the `client_registration` policy
+    pub dynamic_client_registration: bool,
+    /// How verification and password reset emails are sent
+    pub mail_backend: MailBackend,
+    /// Base URL of the web UI that emailed links open
+    pub ui_url: String,
+    /// Context domains that only users with two-factor authentication can grant access to
+    pub sensitive_domains: Vec<String>,
+    /// How failed logins are slowed down and locked out
+    pub login_limits: LoginLimits,
+    /// Email addresses of the users who can manage policies
+    pub admin_emails: Vec<String>,
+    /// Addresses of the reverse proxies whose forwarded client addresses are believed
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Config {
+    /// Load configuration from environment variables
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            database_url: env::var("DATABASE_URL").context("DATABASE_URL must be set")?,
+            database_max_connections: parse_var("DATABASE_MAX_CONNECTIONS", 10)?,
+            bind_address: env::var("BIND_ADDRESS").unwrap_or_else(|_| "0.0.0.0:8000".to_string()),
+            shutdown_timeout: parse_var("SHUTDOWN_TIMEOUT_SECS", 30)?,
+            memory_backend: parse_var("MEMORY_BACKEND", MemoryBackend::Mem0)?,
+            run_migrations: parse_var("RUN_MIGRATIONS", false)?,
+            key_backend: parse_var("KEY_BACKEND", KeyBackend::MasterKey)?,
+            jwt: JwtConfig {
+                issuer: env::var("JWT_ISSUER").unwrap_or_else(|_| DEFAULT_ISSUER.to_string()),
+                signing_key_file: env::var("JWT_SIGNING_KEY_FILE").ok(),
+                verification_jwks_file: env::var("JWT_VERIFICATION_JWKS_FILE").ok(),
+            },
+            consent_url: env::var("CONSENT_UI_URL")
+                .unwrap_or_else(|_| "http://localhost:3000/consent-request".to_string()),
+            dynamic_client_registration: parse_var("DYNAMIC_CLIENT_REGISTRATION", false)?,
+            mail_backend: parse_var("MAIL_BACKEND", MailBackend::Stdout)?,
+            ui_url: env::var("UI_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            sensitive_domains: list_var("SENSITIVE_DOMAINS"),
+            login_limits: login_limits()?,
+            admin_emails: list_var("ADMIN_EMAILS"),
+            trusted_proxies: list_var("TRUSTED_PROXIES")
+                .iter()
+                .map(|addr| addr.parse().with_context(|| format!("Invalid address in TRUSTED_PROXIES: {}", addr)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Login limits from the `LOGIN_*` variables, defaulting to `LoginLimits::default()`
+fn login_limits() -> Result<LoginLimits> {
+    let defaults = LoginLimits::default();
+    let seconds = |name: &str, default: Duration| parse_var(name, default.num_seconds()).map(Duration::seconds);
+
+    Ok(LoginLimits {
+        max_failures: parse_var("LOGIN_MAX_FAILURES", defaults.max_failures)?,
+        max_failures_per_ip: parse_var("LOGIN_MAX_FAILURES_PER_IP", defaults.max_failures_per_ip)?,
+        failure_window: seconds("LOGIN_FAILURE_WINDOW_SECS", defaults.failure_window)?,
+        lockout: seconds("LOGIN_LOCKOUT_SECS", defaults.lockout)?,
+        base_delay: seconds("LOGIN_DELAY_SECS", defaults.base_delay)?,
+        max_delay: seconds("LOGIN_MAX_DELAY_SECS", defaults.max_delay)?,
+    })
+}
+
+/// Comma-separated values of an optional environment variable
+fn list_var(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|values| values.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Parse an optional environment variable, falling back to a default
+fn parse_var<T>(name: &str, default: T) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(Into::into)
+            .with_context(|| format!("Invalid value for {}", name)),
+        Err(_) => Ok(default),
+    }
+}
@@ -1,11 +1,16 @@
-use async_graphql::{Context, Object, ID, InputObject};
+use async_graphql::{Context, ErrorExtensions, Object, ID, InputObject};
 use uuid::Uuid;
 use std::sync::Arc;
 use chrono::{DateTime, Utc};
 
-use super::models::{AccessGrant, GrantAccessInput, ConsentAuditLog};
-use super::service::ConsentManager;
+use super::models::{
+    AccessApproval, AccessGrant, GrantAccessInput, ConsentAuditLog, ConsentError, ConsentRule, CreateConsentRuleInput,
+    RuleEffect,
+};
+use crate::api::auth::{account_owner, acting_user, principal};
 use crate::api::AppState;
+use crate::identity::graphql::identity_error;
+use crate::utils::errors::AppError;
 
 /// GraphQL representation of an access grant
 #[derive(async_graphql::SimpleObject)]
@@ -21,9 +26,9 @@ pub struct GraphQLAccessGrant {
     /// Granted context domains
     pub context_domains: Vec<String>,
     /// Expiration time (if any)
-    pub expires_at: Option<async_graphql::DateTime>,
+    pub expires_at: Option<DateTime<Utc>>,
     /// Creation timestamp
-    pub created_at: async_graphql::DateTime,
+    pub created_at: DateTime<Utc>,
 }
 
 impl From<AccessGrant> for GraphQLAccessGrant {
@@ -34,8 +39,8 @@ impl From<AccessGrant> for GraphQLAccessGrant {
             client_id: grant.client_id,
             scopes: grant.scopes,
             context_domains: grant.context_domains,
-            expires_at: grant.expires_at.map(Into::into),
-            created_at: grant.created_at.into(),
+            expires_at: grant.expires_at,
+            created_at: grant.created_at,
         }
     }
 }
@@ -43,8 +48,8 @@ impl From<AccessGrant> for GraphQLAccessGrant {
 /// GraphQL input for granting access
 #[derive(InputObject)]
 pub struct GraphQLGrantAccessInput {
-    /// User granting access
-    pub user_id: ID,
+    /// User granting access, defaults to the authenticated user
+    pub user_id: Option<ID>,
     /// Client receiving access
     pub client_id: String,
     /// Scopes to grant
@@ -52,17 +57,18 @@ pub struct GraphQLGrantAccessInput {
     /// Context domains to grant access to
     pub context_domains: Vec<String>,
     /// Optional expiration time
-    pub expires_at: Option<async_graphql::DateTime>,
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
-impl From<GraphQLGrantAccessInput> for GrantAccessInput {
-    fn from(input: GraphQLGrantAccessInput) -> Self {
-        Self {
-            user_id: Uuid::parse_str(&input.user_id.0).unwrap(),
-            client_id: input.client_id,
-            scopes: input.scopes,
-            context_domains: input.context_domains,
-            expires_at: input.expires_at.map(|dt| dt.into()),
+impl GraphQLGrantAccessInput {
+    /// Convert into a grant from `user_id`
+    fn into_input(self, user_id: Uuid) -> GrantAccessInput {
+        GrantAccessInput {
+            user_id,
+            client_id: self.client_id,
+            scopes: self.scopes,
+            context_domains: self.context_domains,
+            expires_at: self.expires_at,
         }
     }
 }
@@ -81,7 +87,7 @@ pub struct GraphQLConsentAuditLog {
     /// Additional details
     pub details: async_graphql::Json<serde_json::Value>,
     /// Timestamp
-    pub timestamp: async_graphql::DateTime,
+    pub timestamp: DateTime<Utc>,
 }
 
 impl From<ConsentAuditLog> for GraphQLConsentAuditLog {
@@ -92,7 +98,108 @@ impl From<ConsentAuditLog> for GraphQLConsentAuditLog {
             client_id: log.client_id,
             action: log.action,
             details: async_graphql::Json(log.details),
-            timestamp: log.timestamp.into(),
+            timestamp: log.timestamp,
+        }
+    }
+}
+
+/// GraphQL representation of a consent rule
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLConsentRule {
+    /// Unique identifier
+    pub id: ID,
+    /// Name shown when the rule blocks a request
+    pub name: String,
+    /// `deny` or `require_approval`
+    pub effect: String,
+    /// Client the rule covers; every client if null
+    pub client_id: Option<String>,
+    /// Domain patterns the rule covers, where `*` matches anything; every domain if empty
+    pub domains: Vec<String>,
+    /// Scopes the rule covers; every scope if empty
+    pub scopes: Vec<String>,
+    /// Cover only clients that have not accessed the context for this many days
+    pub inactive_days: Option<i32>,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ConsentRule> for GraphQLConsentRule {
+    fn from(rule: ConsentRule) -> Self {
+        Self {
+            id: ID(rule.id.to_string()),
+            name: rule.name,
+            effect: rule.effect.as_str().to_string(),
+            client_id: rule.client_id,
+            domains: rule.domains,
+            scopes: rule.scopes,
+            inactive_days: rule.inactive_days,
+            created_at: rule.created_at,
+        }
+    }
+}
+
+/// GraphQL input for creating a consent rule
+#[derive(InputObject)]
+pub struct GraphQLCreateConsentRuleInput {
+    /// Name shown when the rule blocks a request
+    pub name: String,
+    /// `deny` or `require_approval`
+    pub effect: String,
+    /// Client the rule covers, defaults to every client
+    pub client_id: Option<String>,
+    /// Domain patterns the rule covers, where `*` matches anything; defaults to every domain
+    pub domains: Option<Vec<String>>,
+    /// Scopes the rule covers, defaults to every scope
+    pub scopes: Option<Vec<String>>,
+    /// Cover only clients that have not accessed the context for this many days
+    pub inactive_days: Option<i32>,
+}
+
+impl GraphQLCreateConsentRuleInput {
+    /// Convert into a rule for `user_id`
+    fn into_input(self, user_id: Uuid) -> Result<CreateConsentRuleInput, ConsentError> {
+        Ok(CreateConsentRuleInput {
+            user_id,
+            name: self.name,
+            effect: RuleEffect::try_from(self.effect)?,
+            client_id: self.client_id,
+            domains: self.domains.unwrap_or_default(),
+            scopes: self.scopes.unwrap_or_default(),
+            inactive_days: self.inactive_days,
+        })
+    }
+}
+
+/// GraphQL representation of an access waiting for the user's approval
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLAccessApproval {
+    /// Unique identifier
+    pub id: ID,
+    /// Rule that held the access back
+    pub rule_id: ID,
+    /// Client asking for access
+    pub client_id: String,
+    /// Domain the client asked for
+    pub domain: String,
+    /// Scope the client asked for
+    pub scope: String,
+    /// When the client first asked
+    pub created_at: DateTime<Utc>,
+    /// When the request lapses
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<AccessApproval> for GraphQLAccessApproval {
+    fn from(approval: AccessApproval) -> Self {
+        Self {
+            id: ID(approval.id.to_string()),
+            rule_id: ID(approval.rule_id.to_string()),
+            client_id: approval.client_id,
+            domain: approval.domain,
+            scope: approval.scope,
+            created_at: approval.created_at,
+            expires_at: approval.expires_at,
         }
     }
 }
@@ -107,10 +214,10 @@ impl ConsentQuery {
     async fn active_grants(
         &self,
         ctx: &Context<'_>,
-        user_id: ID,
+        user_id: Option<ID>,
     ) -> async_graphql::Result<Vec<GraphQLAccessGrant>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&user_id.0)?;
+        let user_uuid = account_owner(ctx, user_id.as_ref())?;
         
         let grants = state.consent_manager.get_active_grants(user_uuid).await?;
         
@@ -118,23 +225,48 @@ impl ConsentQuery {
     }
     
     /// Check if a client has access to a specific domain
+    ///
+    /// A client calling with its own token checks its own access and may not
+    /// name another client, and a personal access token checks its own limits;
+    /// users must name the client to check.
     async fn check_access(
         &self,
         ctx: &Context<'_>,
-        user_id: ID,
-        client_id: String,
+        user_id: Option<ID>,
+        client_id: Option<String>,
         domain: String,
         scope: String,
     ) -> async_graphql::Result<bool> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&user_id.0)?;
+        let user_uuid = acting_user(ctx, user_id.as_ref())?;
+        let principal = principal(ctx)?;
         
-        let has_access = state.consent_manager.check_access(
-            user_uuid,
-            &client_id,
-            &domain,
-            &scope,
-        ).await?;
+        let has_access = match (principal.client_id(), client_id) {
+            (Some(caller), Some(client_id)) if caller != client_id => {
+                return Err(AppError::Unauthorized(format!(
+                    "Client {} cannot check the access of client {}",
+                    caller, client_id
+                ))
+                .extend());
+            }
+            (Some(_), _) => state.consent_manager.check_access(principal, &domain, &scope).await?,
+            (None, Some(client_id)) if principal.is_restricted() => {
+                return Err(AppError::Unauthorized(format!(
+                    "Personal access tokens cannot check the access of client {}",
+                    client_id
+                ))
+                .extend());
+            }
+            (None, None) if principal.is_restricted() => {
+                state.consent_manager.check_access(principal, &domain, &scope).await?
+            }
+            (None, Some(client_id)) => {
+                state.consent_manager.has_access(user_uuid, &client_id, &domain, &scope).await?
+            }
+            (None, None) => {
+                return Err(AppError::ValidationError("clientId is required".to_string()).extend());
+            }
+        };
         
         Ok(has_access)
     }
@@ -143,12 +275,12 @@ impl ConsentQuery {
     async fn audit_logs(
         &self,
         ctx: &Context<'_>,
-        user_id: ID,
+        user_id: Option<ID>,
         limit: Option<i32>,
         offset: Option<i32>,
     ) -> async_graphql::Result<Vec<GraphQLConsentAuditLog>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&user_id.0)?;
+        let user_uuid = account_owner(ctx, user_id.as_ref())?;
         
         let logs = state.consent_manager.get_audit_logs(
             user_uuid,
@@ -158,6 +290,26 @@ impl ConsentQuery {
         
         Ok(logs.into_iter().map(GraphQLConsentAuditLog::from).collect())
     }
+    
+    /// Get the authenticated user's consent rules
+    async fn consent_rules(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GraphQLConsentRule>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        let rules = state.consent_manager.get_rules(user_uuid).await?;
+        
+        Ok(rules.into_iter().map(GraphQLConsentRule::from).collect())
+    }
+    
+    /// Get the client accesses waiting for the authenticated user's approval
+    async fn pending_approvals(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GraphQLAccessApproval>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        
+        let approvals = state.consent_manager.pending_approvals(user_uuid).await?;
+        
+        Ok(approvals.into_iter().map(GraphQLAccessApproval::from).collect())
+    }
 }
 
 /// Consent mutation root
@@ -173,8 +325,9 @@ impl ConsentMutation {
         input: GraphQLGrantAccessInput,
     ) -> async_graphql::Result<GraphQLAccessGrant> {
         let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, input.user_id.as_ref())?;
         
-        let grant = state.consent_manager.grant_access(input.into()).await?;
+        let grant = state.consent_manager.grant_access(input.into_input(user_uuid)).await.map_err(consent_error)?;
         
         Ok(GraphQLAccessGrant::from(grant))
     }
@@ -184,19 +337,276 @@ impl ConsentMutation {
         &self,
         ctx: &Context<'_>,
         grant_id: ID,
-        user_id: ID,
+        user_id: Option<ID>,
         client_id: String,
     ) -> async_graphql::Result<bool> {
         let state = ctx.data::<Arc<AppState>>()?;
         let grant_uuid = Uuid::parse_str(&grant_id.0)?;
-        let user_uuid = Uuid::parse_str(&user_id.0)?;
+        let user_uuid = account_owner(ctx, user_id.as_ref())?;
         
         let result = state.consent_manager.revoke_grant(
             grant_uuid,
             user_uuid,
             &client_id,
-        ).await?;
+        ).await.map_err(consent_error)?;
         
         Ok(result)
     }
+    
+    /// Add a standing rule for the clients the user shares context with
+    ///
+    /// Rules are checked after the global consent policy, when access is
+    /// granted and each time a client uses it.
+    async fn create_consent_rule(
+        &self,
+        ctx: &Context<'_>,
+        input: GraphQLCreateConsentRuleInput,
+    ) -> async_graphql::Result<GraphQLConsentRule> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        let input = input.into_input(user_uuid).map_err(|err| consent_error(err.into()))?;
+        
+        let rule = state.consent_manager.create_rule(input).await.map_err(consent_error)?;
+        
+        Ok(GraphQLConsentRule::from(rule))
+    }
+    
+    /// Delete one of the user's consent rules
+    async fn delete_consent_rule(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        let uuid = Uuid::parse_str(&id.0)?;
+        
+        Ok(state.consent_manager.delete_rule(user_uuid, uuid).await?)
+    }
+    
+    /// Let a waiting client access through once
+    async fn approve_access(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<GraphQLAccessApproval> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        let uuid = Uuid::parse_str(&id.0)?;
+        
+        state.consent_manager.approve_access(user_uuid, uuid).await?
+            .map(GraphQLAccessApproval::from)
+            .ok_or_else(|| AppError::NotFound(format!("No pending approval {}", id.0)).extend())
+    }
+    
+    /// Refuse a waiting client access
+    async fn deny_access(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        let uuid = Uuid::parse_str(&id.0)?;
+        
+        Ok(state.consent_manager.deny_access(user_uuid, uuid).await?)
+    }
+}
+
+/// Map consent errors to GraphQL errors, with the decision behind a denial
+pub(crate) fn consent_error(err: anyhow::Error) -> async_graphql::Error {
+    match err.downcast_ref::<ConsentError>() {
+        Some(ConsentError::Denied(decision)) => AppError::AccessDenied(decision.clone()).extend(),
+        Some(ConsentError::InvalidRule(_)) => AppError::ValidationError(err.to_string()).extend(),
+        None => identity_error(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::testing::{error_code, graphql, login, state, state_with_clock};
+    use crate::clients::models::RegisterClientInput;
+    use crate::identity::Principal;
+    use crate::storage::Database;
+    use crate::utils::clock::ManualClock;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    /// Client allowed to read and write `health-records` and `travel`
+    async fn register_client(state: &AppState) -> String {
+        state.client_service
+            .register(None, RegisterClientInput {
+                name: "Jarvis".to_string(),
+                logo_uri: None,
+                homepage_uri: None,
+                redirect_uris: vec!["https://jarvis.example.com/callback".to_string()],
+                token_endpoint_auth_method: "none".to_string(),
+                jwks: None,
+                allowed_scopes: vec!["read".to_string(), "write".to_string()],
+                allowed_domains: vec!["health-records".to_string(), "travel".to_string()],
+            })
+            .await
+            .unwrap()
+            .client
+            .client_id
+    }
+
+    #[actix_web::test]
+    async fn consent_rules_block_grants_and_hold_accesses() {
+        let state = state().await;
+        let client_id = register_client(&state).await;
+        let (ada, token) = login(&state, "ada").await;
+        let client = Principal::Client { client_id: client_id.clone(), user_id: ada };
+        let access = |scope: &'static str| {
+            let (state, client) = (state.clone(), client.clone());
+            async move { state.consent_manager.check_access(&client, "travel", scope).await.unwrap() }
+        };
+        let rule = |name: &str, fields: &str| {
+            format!(r#"mutation {{ createConsentRule(input: {{ name: "{}", {} }}) {{ id }} }}"#, name, fields)
+        };
+
+        let (_, body) = graphql(state.clone(), Some(&token), &rule("No health", r#"effect: "deny", domains: ["health-*"]"#)).await;
+        let health_rule = body["data"]["createConsentRule"]["id"].as_str().unwrap().to_string();
+        let (_, body) = graphql(state.clone(), Some(&token), &rule("Typo", r#"effect: "allow""#)).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        let grant = |domain: &str| {
+            format!(
+                r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: ["read", "write"], contextDomains: ["{}"] }}) {{ id }} }}"#,
+                client_id, domain
+            )
+        };
+        let (_, body) = graphql(state.clone(), Some(&token), &grant("health-records")).await;
+        assert_eq!(error_code(&body), "ACCESS_DENIED");
+        assert_eq!(body["errors"][0]["extensions"]["rules"], serde_json::json!([health_rule]));
+        assert_eq!(
+            body["errors"][0]["extensions"]["reasons"],
+            serde_json::json!(["Your consent rule \"No health\" does not allow this"])
+        );
+        let (_, body) = graphql(state.clone(), Some(&token), &grant("travel")).await;
+        assert!(body["data"]["grantAccess"]["id"].is_string());
+
+        // Clients that have not been used for a while only get read access, and reading does not lift the rule
+        let Database::Sqlite(pool) = &state.db else {
+            unreachable!("test state uses SQLite");
+        };
+        sqlx::query("UPDATE consent_audit_logs SET timestamp = $1 WHERE action = 'grant'")
+            .bind(Utc::now() - Duration::days(31))
+            .execute(pool)
+            .await
+            .unwrap();
+        let idle = rule("Read only when idle", r#"effect: "deny", scopes: ["write"], inactiveDays: 30"#);
+        graphql(state.clone(), Some(&token), &idle).await;
+        assert!(!access("write").await);
+        assert!(access("read").await);
+        assert!(!access("write").await);
+
+        // Granting the client access again does
+        let (_, body) = graphql(state.clone(), Some(&token), &grant("travel")).await;
+        assert_eq!(error_code(&body), "ACCESS_DENIED");
+        let read_grant = format!(
+            r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: ["read"], contextDomains: ["travel"] }}) {{ id }} }}"#,
+            client_id
+        );
+        let (_, body) = graphql(state.clone(), Some(&token), &read_grant).await;
+        assert!(body["data"]["grantAccess"]["id"].is_string());
+        assert!(access("write").await);
+
+        // Each write then waits for the user, and an approval lets one through
+        let approval = rule("Ask before writes", r#"effect: "require_approval", scopes: ["write"]"#);
+        graphql(state.clone(), Some(&token), &approval).await;
+        assert!(!access("write").await);
+        assert!(!access("write").await);
+        let (_, body) = graphql(state.clone(), Some(&token), "{ pendingApprovals { id domain scope } }").await;
+        let pending = body["data"]["pendingApprovals"].as_array().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!((&pending[0]["domain"], &pending[0]["scope"]), (&"travel".into(), &"write".into()));
+
+        let approve = format!(r#"mutation {{ approveAccess(id: "{}") {{ id }} }}"#, pending[0]["id"].as_str().unwrap());
+        let (_, body) = graphql(state.clone(), Some(&token), &approve).await;
+        assert_eq!(body["data"]["approveAccess"]["id"], pending[0]["id"]);
+        assert!(access("write").await);
+        assert!(!access("write").await);
+        assert!(access("read").await);
+
+        // The user sees which rule blocked the client
+        let (_, body) = graphql(state, Some(&token), "{ auditLogs(limit: 3) { action details } }").await;
+        let logs = body["data"]["auditLogs"].as_array().unwrap();
+        assert_eq!(logs[1]["action"], "access_blocked");
+        assert_eq!(
+            logs[1]["details"]["decision"]["reasons"],
+            serde_json::json!(["Your consent rule \"Ask before writes\" asks you to approve this first"])
+        );
+        assert!(logs[1]["details"]["approval_id"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn inactivity_rules_start_after_their_days() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let state = state_with_clock(clock.clone()).await;
+        let client_id = register_client(&state).await;
+        let (ada, token) = login(&state, "ada").await;
+        let client = Principal::Client { client_id: client_id.clone(), user_id: ada };
+        let grant = format!(
+            r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: ["read", "write"], contextDomains: ["travel"] }}) {{ id }} }}"#,
+            client_id
+        );
+        let (_, body) = graphql(state.clone(), Some(&token), &grant).await;
+        assert!(body["data"]["grantAccess"]["id"].is_string());
+        let idle = r#"mutation { createConsentRule(input: { name: "Read only when idle", effect: "deny", scopes: ["write"], inactiveDays: 30 }) { id } }"#;
+        let (_, body) = graphql(state.clone(), Some(&token), idle).await;
+        assert!(body["data"]["createConsentRule"]["id"].is_string());
+
+        clock.advance(Duration::days(29));
+        assert!(state.consent_manager.check_access(&client, "travel", "write").await.unwrap());
+
+        clock.advance(Duration::days(31));
+        assert!(!state.consent_manager.check_access(&client, "travel", "write").await.unwrap());
+        assert!(state.consent_manager.check_access(&client, "travel", "read").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn grants_follow_the_policy_decision() {
+        let state = state().await;
+        let client_id = register_client(&state).await;
+        let (ada, token) = login(&state, "ada").await;
+        state.policy_engine
+            .update_policy(ada, "consent", r#"
+                package consent
+                import rego.v1
+
+                default allow := false
+                allow if input.action == "grant"
+                reasons contains "Write access lasts a week" if "write" in input.scopes
+                obligations contains {"type": "expire", "days": 7} if "write" in input.scopes
+                obligations contains {"type": "redact", "fields": ["notes"]} if "health-records" in input.domains
+            "#)
+            .await
+            .unwrap();
+        let grant = |scopes: &str, domain: &str| {
+            format!(
+                r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: {}, contextDomains: ["{}"] }}) {{ expiresAt }} }}"#,
+                client_id, scopes, domain
+            )
+        };
+
+        // Obligations the vault can meet shape the grant
+        let (_, body) = graphql(state.clone(), Some(&token), &grant(r#"["read", "write"]"#, "travel")).await;
+        let expires_at: DateTime<Utc> = body["data"]["grantAccess"]["expiresAt"].as_str().unwrap().parse().unwrap();
+        assert!(expires_at <= Utc::now() + Duration::days(7));
+        let (_, body) = graphql(state.clone(), Some(&token), &grant(r#"["read"]"#, "travel")).await;
+        assert!(body["data"]["grantAccess"]["expiresAt"].is_null());
+
+        // Others refuse it, and the user is told why
+        let (_, body) = graphql(state.clone(), Some(&token), &grant(r#"["read"]"#, "health-records")).await;
+        assert_eq!(error_code(&body), "ACCESS_DENIED");
+        assert_eq!(body["errors"][0]["extensions"]["rules"], serde_json::json!(["consent.allow"]));
+        assert_eq!(
+            body["errors"][0]["extensions"]["reasons"],
+            serde_json::json!(["The vault cannot fulfil the redact obligation"])
+        );
+
+        let (_, body) = graphql(state, Some(&token), "{ auditLogs(limit: 3) { action details } }").await;
+        let logs = body["data"]["auditLogs"].as_array().unwrap();
+        let actions: Vec<&str> = logs.iter().map(|log| log["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, ["grant_denied", "grant", "grant"]);
+        assert_eq!(
+            logs[2]["details"]["decision"],
+            serde_json::json!({
+                "allow": true,
+                "rules": ["consent.allow"],
+                "reasons": ["Write access lasts a week"],
+                "obligations": [{ "type": "expire", "days": 7 }]
+            })
+        );
+    }
 }
@@ -1,6 +1,7 @@
 pub mod models;
 pub mod repository;
 pub mod service;
+pub mod sqlite;
 pub mod graphql;
 
 // Re-export key types
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+use crate::policy_engine::Decision;
+
 /// Represents an access grant given by a user to a client application
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct AccessGrant {
     /// Unique identifier for the grant
     pub id: Uuid,
@@ -47,7 +50,7 @@ pub struct GrantAccessInput {
 }
 
 /// Audit log entry for consent-related actions
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ConsentAuditLog {
     /// Unique identifier for the log entry
     pub id: Uuid,
@@ -83,3 +86,202 @@ pub struct CreateAuditLogInput {
     /// Additional details about the action
     pub details: serde_json::Value,
 }
+
+/// Anonymised record of a deleted account, kept after the user is gone
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AccountDeletion {
+    /// Unique identifier for the record, given to the user as a receipt
+    pub id: Uuid,
+    
+    /// What was erased, without anything that identifies the user
+    pub details: serde_json::Value,
+    
+    /// When the account was deleted
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// What a consent rule does to the requests it covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleEffect {
+    /// Refuse matching grants and accesses
+    Deny,
+    /// Let each matching access through only once the user approves it
+    RequireApproval,
+}
+
+impl RuleEffect {
+    /// Name stored with the rule
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Deny => "deny",
+            Self::RequireApproval => "require_approval",
+        }
+    }
+}
+
+impl TryFrom<String> for RuleEffect {
+    type Error = ConsentError;
+
+    fn try_from(effect: String) -> Result<Self, Self::Error> {
+        match effect.as_str() {
+            "deny" => Ok(Self::Deny),
+            "require_approval" => Ok(Self::RequireApproval),
+            _ => Err(ConsentError::InvalidRule(format!("Unknown effect {}", effect))),
+        }
+    }
+}
+
+/// A standing rule a user sets for the clients they share context with
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConsentRule {
+    /// Unique identifier for the rule
+    pub id: Uuid,
+    
+    /// User who set the rule
+    pub user_id: Uuid,
+    
+    /// Name shown to the user when the rule blocks a request
+    pub name: String,
+    
+    /// What the rule does to the requests it covers
+    #[sqlx(try_from = "String")]
+    pub effect: RuleEffect,
+    
+    /// Client the rule covers; `None` for every client
+    pub client_id: Option<String>,
+    
+    /// Domain patterns the rule covers, where `*` matches anything; empty for every domain
+    pub domains: Vec<String>,
+    
+    /// Scopes the rule covers; empty for every scope
+    pub scopes: Vec<String>,
+    
+    /// Cover only clients that have not accessed the user's context for this many days
+    pub inactive_days: Option<i32>,
+    
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl ConsentRule {
+    /// Whether the rule covers a client asking for any of `scopes` on any of `domains`
+    ///
+    /// Does not look at `inactive_days`, which depends on the client's history.
+    pub fn covers(&self, client_id: &str, domains: &[String], scopes: &[String]) -> bool {
+        self.applies_to(client_id)
+            && (self.domains.is_empty()
+                || domains.iter().any(|domain| self.domains.iter().any(|pattern| matches_pattern(pattern, domain))))
+            && (self.scopes.is_empty() || scopes.iter().any(|scope| self.scopes.contains(scope)))
+    }
+    
+    /// Whether the rule is about a client, whatever it asks for
+    pub fn applies_to(&self, client_id: &str) -> bool {
+        self.client_id.as_ref().is_none_or(|id| id == client_id)
+    }
+}
+
+/// Whether a domain matches a pattern in which `*` stands for any characters
+fn matches_pattern(pattern: &str, domain: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = domain.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard, so the pattern must be the whole domain
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Input for creating a consent rule
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateConsentRuleInput {
+    /// User setting the rule
+    pub user_id: Uuid,
+    
+    /// Name shown when the rule blocks a request
+    pub name: String,
+    
+    /// What the rule does to the requests it covers
+    pub effect: RuleEffect,
+    
+    /// Client the rule covers; `None` for every client
+    pub client_id: Option<String>,
+    
+    /// Domain patterns the rule covers; empty for every domain
+    pub domains: Vec<String>,
+    
+    /// Scopes the rule covers; empty for every scope
+    pub scopes: Vec<String>,
+    
+    /// Cover only clients that have not accessed the user's context for this many days
+    pub inactive_days: Option<i32>,
+}
+
+/// A client's access held back by a rule until the user approves it
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AccessApproval {
+    /// Unique identifier for the approval
+    pub id: Uuid,
+    
+    /// User whose context the client asked for
+    pub user_id: Uuid,
+    
+    /// Rule that held the access back
+    pub rule_id: Uuid,
+    
+    /// Client asking for access
+    pub client_id: String,
+    
+    /// Domain the client asked for
+    pub domain: String,
+    
+    /// Scope the client asked for
+    pub scope: String,
+    
+    /// When the user approved the access; `None` while it waits
+    pub approved_at: Option<DateTime<Utc>>,
+    
+    /// When the client first asked
+    pub created_at: DateTime<Utc>,
+    
+    /// When the request, or an unused approval, lapses
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Errors in granting and revoking access and managing consent rules
+#[derive(Error, Debug)]
+pub enum ConsentError {
+    #[error("Access denied: {}", .0.explanation())]
+    Denied(Decision),
+
+    #[error("Invalid consent rule: {0}")]
+    InvalidRule(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_domain_patterns() {
+        assert!(matches_pattern("health-*", "health-records"));
+        assert!(matches_pattern("health-*", "health-"));
+        assert!(!matches_pattern("health-*", "mental-health-notes"));
+        assert!(matches_pattern("*-notes", "mental-health-notes"));
+        assert!(matches_pattern("a*b*c", "axxbyyc"));
+        assert!(!matches_pattern("a*b*c", "axxcyyb"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(matches_pattern("work", "work"));
+        assert!(!matches_pattern("work", "workshop"));
+    }
+}
@@ -1,25 +1,112 @@
 use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
 
-use super::models::{AccessGrant, GrantAccessInput, ConsentAuditLog, CreateAuditLogInput};
+use super::models::{
+    AccessApproval, AccessGrant, AccountDeletion, GrantAccessInput, ConsentAuditLog, ConsentRule,
+    CreateAuditLogInput, CreateConsentRuleInput,
+};
 
-/// Repository for consent-related data storage and retrieval
-pub struct ConsentRepository {
+/// Storage for access grants and the consent audit trail
+#[async_trait]
+pub trait ConsentRepository: Send + Sync {
+    /// Create a new access grant
+    async fn create_grant(&self, input: GrantAccessInput) -> Result<AccessGrant>;
+    
+    /// Get all active grants for a user
+    async fn get_active_grants(&self, user_id: Uuid) -> Result<Vec<AccessGrant>>;
+    
+    /// Revoke an access grant
+    async fn revoke_grant(&self, grant_id: Uuid) -> Result<bool>;
+    
+    /// Revoke every grant of a user, returning how many there were
+    async fn revoke_all_grants(&self, user_id: Uuid) -> Result<u64>;
+    
+    /// Revoke every grant made to a client, returning the revoked grants
+    async fn revoke_client_grants(&self, client_id: &str) -> Result<Vec<AccessGrant>>;
+    
+    /// Check if a client has access to a specific domain for a user
+    async fn check_access(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        domain: &str,
+        required_scope: &str,
+    ) -> Result<bool>;
+    
+    /// Create an audit log entry at `timestamp`
+    async fn create_audit_log(&self, input: CreateAuditLogInput, timestamp: DateTime<Utc>) -> Result<ConsentAuditLog>;
+    
+    /// Get audit logs for a user
+    async fn get_audit_logs(
+        &self,
+        user_id: Uuid,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<ConsentAuditLog>>;
+    
+    /// Record that an account was deleted
+    async fn record_account_deletion(&self, details: serde_json::Value) -> Result<AccountDeletion>;
+    
+    /// Create a consent rule
+    async fn create_rule(&self, input: CreateConsentRuleInput) -> Result<ConsentRule>;
+    
+    /// Get a user's consent rules, oldest first
+    async fn get_rules(&self, user_id: Uuid) -> Result<Vec<ConsentRule>>;
+    
+    /// Delete one of a user's consent rules
+    async fn delete_rule(&self, user_id: Uuid, rule_id: Uuid) -> Result<bool>;
+    
+    /// When a client was last active for a user, from the audit log
+    ///
+    /// Activity is an access made while no inactivity rule restricted the
+    /// client, or the user granting it access or approving one of its accesses.
+    async fn last_activity(&self, user_id: Uuid, client_id: &str) -> Result<Option<DateTime<Utc>>>;
+    
+    /// Ask the user to approve an access, reusing a request that is still waiting
+    async fn request_approval(
+        &self,
+        user_id: Uuid,
+        rule_id: Uuid,
+        client_id: &str,
+        domain: &str,
+        scope: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AccessApproval>;
+    
+    /// Get a user's unexpired approval requests that are still waiting, oldest first
+    async fn get_pending_approvals(&self, user_id: Uuid) -> Result<Vec<AccessApproval>>;
+    
+    /// Approve a waiting request, returning `None` if it is not waiting
+    async fn approve_access(&self, user_id: Uuid, approval_id: Uuid) -> Result<Option<AccessApproval>>;
+    
+    /// Delete one of a user's approval requests
+    async fn delete_approval(&self, user_id: Uuid, approval_id: Uuid) -> Result<bool>;
+    
+    /// Use up an unexpired approval of an access, returning whether there was one
+    async fn use_approval(&self, user_id: Uuid, client_id: &str, domain: &str, scope: &str) -> Result<bool>;
+}
+
+/// Postgres repository for consent-related data storage and retrieval
+pub struct PgConsentRepository {
     pool: PgPool,
 }
 
-impl ConsentRepository {
+impl PgConsentRepository {
     /// Create a new consent repository
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
+
+#[async_trait]
+impl ConsentRepository for PgConsentRepository {
     
     /// Create a new access grant
-    pub async fn create_grant(&self, input: GrantAccessInput) -> Result<AccessGrant> {
-        let grant = sqlx::query_as!(
-            AccessGrant,
+    async fn create_grant(&self, input: GrantAccessInput) -> Result<AccessGrant> {
+        let grant = sqlx::query_as::<_, AccessGrant>(
             r#"
             INSERT INTO access_grants (
                 user_id, client_id, scopes, context_domains, expires_at
@@ -29,12 +116,12 @@ impl ConsentRepository {
                 id, user_id, client_id, scopes, context_domains,
                 expires_at, created_at
             "#,
-            input.user_id,
-            input.client_id,
-            &input.scopes as &[String],
-            &input.context_domains as &[String],
-            input.expires_at,
         )
+        .bind(input.user_id)
+        .bind(input.client_id)
+        .bind(&input.scopes)
+        .bind(&input.context_domains)
+        .bind(input.expires_at)
         .fetch_one(&self.pool)
         .await?;
         
@@ -42,9 +129,8 @@ impl ConsentRepository {
     }
     
     /// Get all active grants for a user
-    pub async fn get_active_grants(&self, user_id: Uuid) -> Result<Vec<AccessGrant>> {
-        let grants = sqlx::query_as!(
-            AccessGrant,
+    async fn get_active_grants(&self, user_id: Uuid) -> Result<Vec<AccessGrant>> {
+        let grants = sqlx::query_as::<_, AccessGrant>(
             r#"
             SELECT 
                 id, user_id, client_id, scopes, context_domains,
@@ -53,8 +139,8 @@ impl ConsentRepository {
             WHERE user_id = $1
               AND (expires_at IS NULL OR expires_at > NOW())
             "#,
-            user_id
         )
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
         
@@ -62,29 +148,62 @@ impl ConsentRepository {
     }
     
     /// Revoke an access grant
-    pub async fn revoke_grant(&self, grant_id: Uuid) -> Result<bool> {
-        let result = sqlx::query!(
+    async fn revoke_grant(&self, grant_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
             r#"
             DELETE FROM access_grants
             WHERE id = $1
             "#,
-            grant_id
         )
+        .bind(grant_id)
         .execute(&self.pool)
         .await?;
         
         Ok(result.rows_affected() > 0)
     }
     
+    /// Revoke every grant of a user
+    async fn revoke_all_grants(&self, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_grants
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Revoke every grant made to a client
+    async fn revoke_client_grants(&self, client_id: &str) -> Result<Vec<AccessGrant>> {
+        let grants = sqlx::query_as::<_, AccessGrant>(
+            r#"
+            DELETE FROM access_grants
+            WHERE client_id = $1
+            RETURNING 
+                id, user_id, client_id, scopes, context_domains,
+                expires_at, created_at
+            "#,
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(grants)
+    }
+    
     /// Check if a client has access to a specific domain for a user
-    pub async fn check_access(
+    async fn check_access(
         &self,
         user_id: Uuid,
         client_id: &str,
         domain: &str,
         required_scope: &str,
     ) -> Result<bool> {
-        let count = sqlx::query!(
+        let count: i64 = sqlx::query_scalar(
             r#"
             SELECT COUNT(*)
             FROM access_grants
@@ -94,36 +213,34 @@ impl ConsentRepository {
               AND $4 = ANY(scopes)
               AND (expires_at IS NULL OR expires_at > NOW())
             "#,
-            user_id,
-            client_id,
-            domain,
-            required_scope
         )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(required_scope)
         .fetch_one(&self.pool)
         .await?;
         
-        Ok(count.count.unwrap_or(0) > 0)
+        Ok(count > 0)
     }
     
     /// Create an audit log entry
-    pub async fn create_audit_log(&self, input: CreateAuditLogInput) -> Result<ConsentAuditLog> {
-        let log = sqlx::query_as!(
-            ConsentAuditLog,
+    async fn create_audit_log(&self, input: CreateAuditLogInput, timestamp: DateTime<Utc>) -> Result<ConsentAuditLog> {
+        let log = sqlx::query_as::<_, ConsentAuditLog>(
             r#"
             INSERT INTO consent_audit_logs (
-                user_id, client_id, action, details
+                user_id, client_id, action, details, timestamp
             )
-            VALUES ($1, $2, $3, $4)
+            VALUES ($1, $2, $3, $4, $5)
             RETURNING 
-                id, user_id, client_id, action, 
-                details as "details: serde_json::Value",
-                timestamp
+                id, user_id, client_id, action, details, timestamp
             "#,
-            input.user_id,
-            input.client_id,
-            input.action,
-            input.details as serde_json::Value,
         )
+        .bind(input.user_id)
+        .bind(input.client_id)
+        .bind(input.action)
+        .bind(input.details)
+        .bind(timestamp)
         .fetch_one(&self.pool)
         .await?;
         
@@ -131,7 +248,7 @@ impl ConsentRepository {
     }
     
     /// Get audit logs for a user
-    pub async fn get_audit_logs(
+    async fn get_audit_logs(
         &self,
         user_id: Uuid,
         limit: Option<i64>,
@@ -140,25 +257,261 @@ impl ConsentRepository {
         let limit = limit.unwrap_or(50);
         let offset = offset.unwrap_or(0);
         
-        let logs = sqlx::query_as!(
-            ConsentAuditLog,
+        let logs = sqlx::query_as::<_, ConsentAuditLog>(
             r#"
             SELECT 
-                id, user_id, client_id, action, 
-                details as "details: serde_json::Value",
-                timestamp
+                id, user_id, client_id, action, details, timestamp
             FROM consent_audit_logs
             WHERE user_id = $1
             ORDER BY timestamp DESC
             LIMIT $2 OFFSET $3
             "#,
-            user_id,
-            limit,
-            offset
         )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
         .fetch_all(&self.pool)
         .await?;
         
         Ok(logs)
     }
+    
+    /// Record that an account was deleted
+    async fn record_account_deletion(&self, details: serde_json::Value) -> Result<AccountDeletion> {
+        let deletion = sqlx::query_as::<_, AccountDeletion>(
+            r#"
+            INSERT INTO account_deletions (details)
+            VALUES ($1)
+            RETURNING id, details, deleted_at
+            "#,
+        )
+        .bind(details)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(deletion)
+    }
+    
+    /// Create a consent rule
+    async fn create_rule(&self, input: CreateConsentRuleInput) -> Result<ConsentRule> {
+        let rule = sqlx::query_as::<_, ConsentRule>(
+            r#"
+            INSERT INTO consent_rules (
+                user_id, name, effect, client_id, domains, scopes, inactive_days
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING
+                id, user_id, name, effect, client_id, domains, scopes,
+                inactive_days, created_at
+            "#,
+        )
+        .bind(input.user_id)
+        .bind(input.name)
+        .bind(input.effect.as_str())
+        .bind(input.client_id)
+        .bind(input.domains)
+        .bind(input.scopes)
+        .bind(input.inactive_days)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(rule)
+    }
+    
+    /// Get a user's consent rules, oldest first
+    async fn get_rules(&self, user_id: Uuid) -> Result<Vec<ConsentRule>> {
+        let rules = sqlx::query_as::<_, ConsentRule>(
+            r#"
+            SELECT
+                id, user_id, name, effect, client_id, domains, scopes,
+                inactive_days, created_at
+            FROM consent_rules
+            WHERE user_id = $1
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rules)
+    }
+    
+    /// Delete one of a user's consent rules
+    async fn delete_rule(&self, user_id: Uuid, rule_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM consent_rules
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(rule_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// When a client was last active for a user
+    async fn last_activity(&self, user_id: Uuid, client_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let timestamp = sqlx::query_scalar(
+            r#"
+            SELECT timestamp
+            FROM consent_audit_logs
+            WHERE user_id = $1 AND client_id = $2
+              AND (action IN ('grant', 'access_approved')
+                OR (action = 'access' AND NOT COALESCE((details->>'idle')::boolean, false)))
+            ORDER BY timestamp DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(timestamp)
+    }
+    
+    /// Ask the user to approve an access, reusing a request that is still waiting
+    async fn request_approval(
+        &self,
+        user_id: Uuid,
+        rule_id: Uuid,
+        client_id: &str,
+        domain: &str,
+        scope: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AccessApproval> {
+        let waiting = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            SELECT
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            FROM access_approvals
+            WHERE user_id = $1 AND client_id = $2 AND domain = $3 AND scope = $4
+              AND approved_at IS NULL
+              AND expires_at > NOW()
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(scope)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        if let Some(approval) = waiting {
+            return Ok(approval);
+        }
+        
+        let approval = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            INSERT INTO access_approvals (
+                user_id, rule_id, client_id, domain, scope, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(rule_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(scope)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(approval)
+    }
+    
+    /// Get a user's unexpired approval requests that are still waiting
+    async fn get_pending_approvals(&self, user_id: Uuid) -> Result<Vec<AccessApproval>> {
+        let approvals = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            SELECT
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            FROM access_approvals
+            WHERE user_id = $1
+              AND approved_at IS NULL
+              AND expires_at > NOW()
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(approvals)
+    }
+    
+    /// Approve a waiting request
+    async fn approve_access(&self, user_id: Uuid, approval_id: Uuid) -> Result<Option<AccessApproval>> {
+        let approval = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            UPDATE access_approvals
+            SET approved_at = NOW()
+            WHERE id = $1 AND user_id = $2
+              AND approved_at IS NULL
+              AND expires_at > NOW()
+            RETURNING
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            "#,
+        )
+        .bind(approval_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(approval)
+    }
+    
+    /// Delete one of a user's approval requests
+    async fn delete_approval(&self, user_id: Uuid, approval_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_approvals
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(approval_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Use up an unexpired approval of an access
+    async fn use_approval(&self, user_id: Uuid, client_id: &str, domain: &str, scope: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_approvals
+            WHERE id = (
+                SELECT id
+                FROM access_approvals
+                WHERE user_id = $1 AND client_id = $2 AND domain = $3 AND scope = $4
+                  AND approved_at IS NOT NULL
+                  AND expires_at > NOW()
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(scope)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
 }
@@ -1,44 +1,131 @@
 use anyhow::Result;
-use sqlx::PgPool;
+use chrono::{DateTime, Duration, Utc};
 use std::sync::Arc;
 use uuid::Uuid;
 
 use super::{
-    models::{AccessGrant, GrantAccessInput, ConsentAuditLog, CreateAuditLogInput},
-    repository::ConsentRepository,
+    models::{
+        AccessApproval, AccessGrant, AccountDeletion, GrantAccessInput, ConsentAuditLog, ConsentError, ConsentRule,
+        CreateAuditLogInput, CreateConsentRuleInput, RuleEffect,
+    },
+    repository::{ConsentRepository, PgConsentRepository},
+    sqlite::SqliteConsentRepository,
 };
-use crate::policy_engine::service::PolicyEngine;
+use crate::clients::service::{ClientService, SCOPES};
+use crate::identity::{models::IdentityError, IdentityService, Principal};
+use crate::policy_engine::{service::PolicyEngine, Decision, Obligation};
+use crate::storage::Database;
+use crate::utils::clock::Clock;
+
+/// Client id recorded on audit entries written by the vault itself
+pub const VAULT_CLIENT_ID: &str = "vault";
+
+/// How long an access waits for the user's approval, and an approval waits to be used
+const APPROVAL_TTL: Duration = Duration::days(1);
+
+/// Longest inactivity a consent rule can be limited to
+const MAX_INACTIVE_DAYS: i32 = 3650;
 
 /// Service for managing consent and access grants
 pub struct ConsentManager {
-    repository: ConsentRepository,
+    repository: Arc<dyn ConsentRepository>,
     policy_engine: Arc<PolicyEngine>,
+    client_service: Arc<ClientService>,
+    identity_service: Arc<IdentityService>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ConsentManager {
-    /// Create a new consent manager
-    pub fn new(pool: PgPool, policy_engine: Arc<PolicyEngine>) -> Arc<Self> {
+    /// Create a new consent manager that tells the time from `clock`
+    pub fn new(
+        db: &Database,
+        policy_engine: Arc<PolicyEngine>,
+        client_service: Arc<ClientService>,
+        identity_service: Arc<IdentityService>,
+        clock: Arc<dyn Clock>,
+    ) -> Arc<Self> {
         Arc::new(Self {
-            repository: ConsentRepository::new(pool),
+            repository: match db {
+                Database::Postgres(pool) => Arc::new(PgConsentRepository::new(pool.clone())),
+                Database::Sqlite(pool) => Arc::new(SqliteConsentRepository::new(pool.clone())),
+            },
             policy_engine,
+            client_service,
+            identity_service,
+            clock,
         })
     }
     
     /// Grant access to a client
-    pub async fn grant_access(&self, input: GrantAccessInput) -> Result<AccessGrant> {
+    ///
+    /// The user must have verified their email address, and the client must
+    /// be registered and enabled, and allowed to request every scope and
+    /// domain in the grant. Sensitive domains also need the user to have
+    /// two-factor authentication enabled. Once the `consent` policy allows
+    /// the grant, the user's own deny rules can still refuse it, and the grant
+    /// is held to the policy's obligations. The decision is written to the
+    /// audit log either way.
+    pub async fn grant_access(&self, mut input: GrantAccessInput) -> Result<AccessGrant> {
+        let verified = self.identity_service
+            .get_user(input.user_id)
+            .await?
+            .is_some_and(|user| user.is_verified());
+        if !verified {
+            return Err(IdentityError::EmailNotVerified.into());
+        }
+        let two_factor = self.identity_service.has_two_factor(input.user_id).await?;
+        
+        let client = self.client_service
+            .active_client(&input.client_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Unknown or disabled client: {}", input.client_id))?;
+        
+        if !client.allows(&input.scopes, &input.context_domains) {
+            return Err(anyhow::anyhow!(
+                "Client {} is not registered for these scopes or domains",
+                input.client_id
+            ));
+        }
+        
         // First, check if the policy allows this grant
         let policy_input = serde_json::json!({
             "user": input.user_id.to_string(),
             "action": "grant",
             "client": input.client_id,
             "domains": input.context_domains,
-            "scopes": input.scopes
+            "scopes": input.scopes,
+            "two_factor": two_factor
         });
         
-        let allowed = self.policy_engine.evaluate("consent", policy_input).await?;
+        let mut decision = self.policy_engine.evaluate("consent", policy_input).await?;
+        let policy_allowed = decision.allow;
+        
+        // Then the user's own rules, and the obligations the policy attached
+        if decision.allow {
+            let (rules, _) = self.covering_rules(input.user_id, &input.client_id, &input.context_domains, &input.scopes).await?;
+            if let Some(rule) = rules.iter().find(|rule| rule.effect == RuleEffect::Deny) {
+                decision = rule_decision(rule);
+            }
+        }
+        if decision.allow {
+            match fulfil_obligations(&decision.obligations, input.expires_at, self.clock.now()) {
+                Ok(expires_at) => input.expires_at = expires_at,
+                Err(reason) => decision = Decision { rules: decision.rules, ..Decision::deny(reason) },
+            }
+        }
         
-        if !allowed {
-            return Err(anyhow::anyhow!("Policy denied this access grant"));
+        if !decision.allow {
+            self.record_event(input.user_id, &input.client_id, "grant_denied", serde_json::json!({
+                "scopes": input.scopes,
+                "domains": input.context_domains,
+                "decision": decision
+            })).await?;
+            
+            let sensitive = input.context_domains.iter().find(|domain| self.policy_engine.is_sensitive(domain));
+            if let (false, false, Some(domain)) = (policy_allowed, two_factor, sensitive) {
+                return Err(IdentityError::SensitiveDomain(domain.clone()).into());
+            }
+            return Err(ConsentError::Denied(decision).into());
         }
         
         // Create the grant
@@ -53,11 +140,12 @@ impl ConsentManager {
                 "grant_id": grant.id.to_string(),
                 "scopes": input.scopes,
                 "domains": input.context_domains,
-                "expires_at": input.expires_at
+                "expires_at": input.expires_at,
+                "decision": decision
             }),
         };
         
-        self.repository.create_audit_log(audit_input).await?;
+        self.repository.create_audit_log(audit_input, self.clock.now()).await?;
         
         Ok(grant)
     }
@@ -85,10 +173,14 @@ impl ConsentManager {
             "grant_id": grant_id.to_string()
         });
         
-        let allowed = self.policy_engine.evaluate("consent", policy_input).await?;
+        let decision = self.policy_engine.evaluate("consent", policy_input).await?;
         
-        if !allowed {
-            return Err(anyhow::anyhow!("Policy denied this revocation"));
+        if !decision.allow {
+            self.record_event(user_id, client_id, "revoke_denied", serde_json::json!({
+                "grant_id": grant_id.to_string(),
+                "decision": decision
+            })).await?;
+            return Err(ConsentError::Denied(decision).into());
         }
         
         // Revoke the grant
@@ -103,45 +195,130 @@ impl ConsentManager {
                 details: serde_json::json!({
                     "grant_id": grant_id.to_string(),
                     "scopes": grant.scopes,
-                    "domains": grant.context_domains
+                    "domains": grant.context_domains,
+                    "decision": decision
                 }),
             };
             
-            self.repository.create_audit_log(audit_input).await?;
+            self.repository.create_audit_log(audit_input, self.clock.now()).await?;
         }
         
         Ok(result)
     }
     
-    /// Check if a client has access to a specific domain for a user
+    /// Revoke all of a user's grants at once, returning how many there were
+    ///
+    /// Used when the account is deleted, so it is neither put to the policy
+    /// nor logged per grant.
+    pub async fn revoke_all_grants(&self, user_id: Uuid) -> Result<u64> {
+        self.repository.revoke_all_grants(user_id).await
+    }
+    
+    /// Revoke every grant made to a client whose developer deleted their account
+    ///
+    /// Each user who had granted the client access finds the revocation in
+    /// their audit log. Returns how many grants there were.
+    pub async fn revoke_client_grants(&self, client_id: &str) -> Result<u64> {
+        let grants = self.repository.revoke_client_grants(client_id).await?;
+        
+        for grant in &grants {
+            let audit_input = CreateAuditLogInput {
+                user_id: grant.user_id,
+                client_id: client_id.to_string(),
+                action: "revoke".to_string(),
+                details: serde_json::json!({
+                    "grant_id": grant.id.to_string(),
+                    "scopes": grant.scopes,
+                    "domains": grant.context_domains,
+                    "reason": "client_owner_deleted"
+                }),
+            };
+            
+            self.repository.create_audit_log(audit_input, self.clock.now()).await?;
+        }
+        
+        Ok(grants.len() as u64)
+    }
+    
+    /// Check if the caller has access to a specific domain
+    ///
+    /// Users have full access to their own context. A client is identified by
+    /// the credentials it authenticated with, never by an ID it passes, and
+    /// each access it is permitted is written to the user's audit log.
+    /// A personal access token reaches only its own scopes and domains, and
+    /// its accesses are logged as `personal_access_token:<id>`.
+    ///
+    /// A client's grants were put to the `consent` policy when they were made;
+    /// each access is then held to the user's consent rules.
     pub async fn check_access(
         &self,
-        user_id: Uuid,
-        client_id: &str,
+        principal: &Principal,
         domain: &str,
         required_scope: &str,
     ) -> Result<bool> {
-        let has_access = self.repository.check_access(user_id, client_id, domain, required_scope).await?;
+        let mut idle = false;
+        let (has_access, client_id) = match principal {
+            Principal::User(_) => return Ok(true),
+            Principal::Client { client_id, user_id } => {
+                let has_access = self.has_access(*user_id, client_id, domain, required_scope).await? && {
+                    let (passed, restricted) = self.passes_rules(*user_id, client_id, domain, required_scope).await?;
+                    idle = restricted;
+                    passed
+                };
+                (has_access, client_id.to_string())
+            }
+            Principal::Token { token_id, scopes, domains, .. } => (
+                scopes.iter().any(|scope| scope == required_scope) && domains.iter().any(|d| d == domain),
+                format!("personal_access_token:{}", token_id),
+            ),
+        };
         
         if has_access {
-            // Log the access
+            // Log the access, marking one an inactivity rule restricted so it does not lift the rule
+            let mut details = serde_json::json!({
+                "domain": domain,
+                "scope": required_scope,
+                "success": true
+            });
+            if idle {
+                details["idle"] = serde_json::json!(true);
+            }
             let audit_input = CreateAuditLogInput {
-                user_id,
-                client_id: client_id.to_string(),
+                user_id: principal.user_id(),
+                client_id,
                 action: "access".to_string(),
-                details: serde_json::json!({
-                    "domain": domain,
-                    "scope": required_scope,
-                    "success": true
-                }),
+                details,
             };
             
-            self.repository.create_audit_log(audit_input).await?;
+            self.repository.create_audit_log(audit_input, self.clock.now()).await?;
         }
         
         Ok(has_access)
     }
     
+    /// Check if a user's grants give a client access to a specific domain
+    ///
+    /// Grants stop giving access once their client is disabled, deleted, or
+    /// no longer registered for the domain or scope.
+    pub async fn has_access(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        domain: &str,
+        required_scope: &str,
+    ) -> Result<bool> {
+        let registered = self.client_service
+            .active_client(client_id)
+            .await?
+            .is_some_and(|client| client.allows(&[required_scope.to_string()], &[domain.to_string()]));
+        
+        if !registered {
+            return Ok(false);
+        }
+        
+        self.repository.check_access(user_id, client_id, domain, required_scope).await
+    }
+    
     /// Get audit logs for a user
     pub async fn get_audit_logs(
         &self,
@@ -151,4 +328,212 @@ impl ConsentManager {
     ) -> Result<Vec<ConsentAuditLog>> {
         self.repository.get_audit_logs(user_id, limit, offset).await
     }
+    
+    /// Record a vault event in a user's audit log
+    pub async fn record_event(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        action: &str,
+        details: serde_json::Value,
+    ) -> Result<ConsentAuditLog> {
+        let audit_input = CreateAuditLogInput {
+            user_id,
+            client_id: client_id.to_string(),
+            action: action.to_string(),
+            details,
+        };
+        
+        self.repository.create_audit_log(audit_input, self.clock.now()).await
+    }
+    
+    /// Record that an account was deleted
+    ///
+    /// The record outlives the user's audit log, so `details` must not
+    /// identify them.
+    pub async fn record_account_deletion(&self, details: serde_json::Value) -> Result<AccountDeletion> {
+        self.repository.record_account_deletion(details).await
+    }
+    
+    /// Add a consent rule for a user
+    pub async fn create_rule(&self, input: CreateConsentRuleInput) -> Result<ConsentRule> {
+        validate_rule(&input)?;
+        
+        let rule = self.repository.create_rule(input).await?;
+        self.record_event(rule.user_id, VAULT_CLIENT_ID, "consent_rule_created", serde_json::json!({
+            "rule_id": rule.id.to_string(),
+            "name": rule.name,
+            "effect": rule.effect.as_str()
+        })).await?;
+        
+        Ok(rule)
+    }
+    
+    /// Get a user's consent rules
+    pub async fn get_rules(&self, user_id: Uuid) -> Result<Vec<ConsentRule>> {
+        self.repository.get_rules(user_id).await
+    }
+    
+    /// Delete one of a user's consent rules
+    pub async fn delete_rule(&self, user_id: Uuid, rule_id: Uuid) -> Result<bool> {
+        let deleted = self.repository.delete_rule(user_id, rule_id).await?;
+        if deleted {
+            self.record_event(user_id, VAULT_CLIENT_ID, "consent_rule_deleted", serde_json::json!({
+                "rule_id": rule_id.to_string()
+            })).await?;
+        }
+        
+        Ok(deleted)
+    }
+    
+    /// Get the accesses waiting for a user's approval
+    pub async fn pending_approvals(&self, user_id: Uuid) -> Result<Vec<AccessApproval>> {
+        self.repository.get_pending_approvals(user_id).await
+    }
+    
+    /// Approve a waiting access, letting the client through the next time it asks
+    pub async fn approve_access(&self, user_id: Uuid, approval_id: Uuid) -> Result<Option<AccessApproval>> {
+        let approval = self.repository.approve_access(user_id, approval_id).await?;
+        if let Some(approval) = &approval {
+            self.record_event(user_id, &approval.client_id, "access_approved", serde_json::json!({
+                "approval_id": approval.id.to_string(),
+                "domain": approval.domain,
+                "scope": approval.scope
+            })).await?;
+        }
+        
+        Ok(approval)
+    }
+    
+    /// Refuse a waiting access
+    pub async fn deny_access(&self, user_id: Uuid, approval_id: Uuid) -> Result<bool> {
+        self.repository.delete_approval(user_id, approval_id).await
+    }
+    
+    /// The user's rules that cover a client asking for `scopes` on `domains`,
+    /// deny rules first, and whether any inactivity rule restricts the client
+    ///
+    /// A rule limited to inactive clients covers a client only if it has not
+    /// been active for the user within that many days. Accesses made while
+    /// such a rule restricted the client are not activity, so only the user
+    /// can lift the rule, by granting the client access or approving one.
+    async fn covering_rules(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        domains: &[String],
+        scopes: &[String],
+    ) -> Result<(Vec<ConsentRule>, bool)> {
+        let mut rules = Vec::new();
+        let mut idle = false;
+        let mut last_activity: Option<Option<DateTime<Utc>>> = None;
+        
+        for rule in self.repository.get_rules(user_id).await? {
+            if let Some(days) = rule.inactive_days {
+                if !rule.applies_to(client_id) {
+                    continue;
+                }
+                let last = match last_activity {
+                    Some(last) => last,
+                    None => *last_activity.insert(self.repository.last_activity(user_id, client_id).await?),
+                };
+                if last.is_some_and(|at| at > self.clock.now() - Duration::days(days.into())) {
+                    continue;
+                }
+                idle = true;
+            }
+            if rule.covers(client_id, domains, scopes) {
+                rules.push(rule);
+            }
+        }
+        
+        rules.sort_by_key(|rule| rule.effect != RuleEffect::Deny);
+        Ok((rules, idle))
+    }
+    
+    /// Hold a client access the user's grants allow to their consent rules
+    ///
+    /// An access a rule blocks is written to the audit log with the decision. One
+    /// a rule holds for approval waits for the user, and goes through once
+    /// when they approve it. Also returns whether an inactivity rule restricts
+    /// the client.
+    async fn passes_rules(&self, user_id: Uuid, client_id: &str, domain: &str, scope: &str) -> Result<(bool, bool)> {
+        let (rules, idle) = self.covering_rules(user_id, client_id, &[domain.to_string()], &[scope.to_string()]).await?;
+        let Some(rule) = rules.first() else {
+            return Ok((true, idle));
+        };
+        
+        let mut details = serde_json::json!({
+            "domain": domain,
+            "scope": scope,
+            "decision": rule_decision(rule)
+        });
+        
+        if rule.effect == RuleEffect::RequireApproval {
+            if self.repository.use_approval(user_id, client_id, domain, scope).await? {
+                return Ok((true, idle));
+            }
+            let approval = self.repository
+                .request_approval(user_id, rule.id, client_id, domain, scope, self.clock.now() + APPROVAL_TTL)
+                .await?;
+            details["approval_id"] = serde_json::json!(approval.id.to_string());
+        }
+        
+        self.record_event(user_id, client_id, "access_blocked", details).await?;
+        Ok((false, idle))
+    }
+}
+
+/// Decision to deny a request a user's rule covers
+fn rule_decision(rule: &ConsentRule) -> Decision {
+    let reason = match rule.effect {
+        RuleEffect::Deny => format!("Your consent rule \"{}\" does not allow this", rule.name),
+        RuleEffect::RequireApproval => format!("Your consent rule \"{}\" asks you to approve this first", rule.name),
+    };
+    Decision {
+        rules: vec![rule.id.to_string()],
+        ..Decision::deny(reason)
+    }
+}
+
+/// Hold a grant to a policy's obligations, returning when it must expire
+///
+/// `expire` obligations with a number of `days` shorten the grant. Any other
+/// obligation cannot be met, so the grant is refused with the returned reason.
+fn fulfil_obligations(
+    obligations: &[Obligation],
+    mut expires_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    for obligation in obligations {
+        if obligation.kind != "expire" {
+            return Err(format!("The vault cannot fulfil the {} obligation", obligation.kind));
+        }
+        let latest = obligation.parameters.get("days")
+            .and_then(serde_json::Value::as_i64)
+            .filter(|days| *days > 0)
+            .and_then(Duration::try_days)
+            .and_then(|days| now.checked_add_signed(days))
+            .ok_or_else(|| "The expire obligation needs a positive number of days".to_string())?;
+        expires_at = Some(expires_at.map_or(latest, |expires_at| expires_at.min(latest)));
+    }
+    Ok(expires_at)
+}
+
+fn validate_rule(input: &CreateConsentRuleInput) -> Result<()> {
+    let invalid = |message: &str| Err(ConsentError::InvalidRule(message.to_string()).into());
+    
+    if input.name.trim().is_empty() {
+        return invalid("A rule needs a name");
+    }
+    if let Some(scope) = input.scopes.iter().find(|scope| !SCOPES.contains(&scope.as_str())) {
+        return invalid(&format!("Unknown scope {}", scope));
+    }
+    if input.domains.iter().any(|domain| domain.is_empty() || domain.contains(char::is_whitespace)) {
+        return invalid("Domain patterns must be non-empty and contain no spaces");
+    }
+    if input.inactive_days.is_some_and(|days| !(1..=MAX_INACTIVE_DAYS).contains(&days)) {
+        return invalid(&format!("Inactivity must be between 1 and {} days", MAX_INACTIVE_DAYS));
+    }
+    Ok(())
 }
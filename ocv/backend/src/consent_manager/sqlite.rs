@@ -0,0 +1,622 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::models::{
+    AccessApproval, AccessGrant, AccountDeletion, GrantAccessInput, ConsentAuditLog, ConsentError, ConsentRule,
+    CreateAuditLogInput, CreateConsentRuleInput, RuleEffect,
+};
+use super::repository::ConsentRepository;
+
+/// Access grant row as stored in SQLite, with arrays kept as JSON text
+#[derive(sqlx::FromRow)]
+struct AccessGrantRow {
+    id: Uuid,
+    user_id: Uuid,
+    client_id: String,
+    scopes: Json<Vec<String>>,
+    context_domains: Json<Vec<String>>,
+    expires_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AccessGrantRow> for AccessGrant {
+    fn from(row: AccessGrantRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            client_id: row.client_id,
+            scopes: row.scopes.0,
+            context_domains: row.context_domains.0,
+            expires_at: row.expires_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Consent rule row as stored in SQLite, with arrays kept as JSON text
+#[derive(sqlx::FromRow)]
+struct ConsentRuleRow {
+    id: Uuid,
+    user_id: Uuid,
+    name: String,
+    effect: String,
+    client_id: Option<String>,
+    domains: Json<Vec<String>>,
+    scopes: Json<Vec<String>>,
+    inactive_days: Option<i32>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<ConsentRuleRow> for ConsentRule {
+    type Error = ConsentError;
+
+    fn try_from(row: ConsentRuleRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            effect: RuleEffect::try_from(row.effect)?,
+            client_id: row.client_id,
+            domains: row.domains.0,
+            scopes: row.scopes.0,
+            inactive_days: row.inactive_days,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// SQLite repository for consent-related data storage and retrieval
+pub struct SqliteConsentRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteConsentRepository {
+    /// Create a new consent repository
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ConsentRepository for SqliteConsentRepository {
+    async fn create_grant(&self, input: GrantAccessInput) -> Result<AccessGrant> {
+        let row = sqlx::query_as::<_, AccessGrantRow>(
+            r#"
+            INSERT INTO access_grants (
+                id, user_id, client_id, scopes, context_domains, expires_at, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING
+                id, user_id, client_id, scopes, context_domains,
+                expires_at, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(input.user_id)
+        .bind(input.client_id)
+        .bind(Json(&input.scopes))
+        .bind(Json(&input.context_domains))
+        .bind(input.expires_at)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    async fn get_active_grants(&self, user_id: Uuid) -> Result<Vec<AccessGrant>> {
+        let rows = sqlx::query_as::<_, AccessGrantRow>(
+            r#"
+            SELECT
+                id, user_id, client_id, scopes, context_domains,
+                expires_at, created_at
+            FROM access_grants
+            WHERE user_id = $1
+              AND (expires_at IS NULL OR expires_at > $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn revoke_grant(&self, grant_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_grants
+            WHERE id = $1
+            "#,
+        )
+        .bind(grant_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn revoke_all_grants(&self, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_grants
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn revoke_client_grants(&self, client_id: &str) -> Result<Vec<AccessGrant>> {
+        let rows = sqlx::query_as::<_, AccessGrantRow>(
+            r#"
+            DELETE FROM access_grants
+            WHERE client_id = $1
+            RETURNING
+                id, user_id, client_id, scopes, context_domains,
+                expires_at, created_at
+            "#,
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn check_access(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        domain: &str,
+        required_scope: &str,
+    ) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM access_grants
+            WHERE user_id = $1
+              AND client_id = $2
+              AND EXISTS (SELECT 1 FROM json_each(context_domains) WHERE value = $3)
+              AND EXISTS (SELECT 1 FROM json_each(scopes) WHERE value = $4)
+              AND (expires_at IS NULL OR expires_at > $5)
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(required_scope)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count > 0)
+    }
+
+    async fn create_audit_log(&self, input: CreateAuditLogInput, timestamp: DateTime<Utc>) -> Result<ConsentAuditLog> {
+        let log = sqlx::query_as::<_, ConsentAuditLog>(
+            r#"
+            INSERT INTO consent_audit_logs (
+                id, user_id, client_id, action, details, timestamp
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING
+                id, user_id, client_id, action, details, timestamp
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(input.user_id)
+        .bind(input.client_id)
+        .bind(input.action)
+        .bind(input.details)
+        .bind(timestamp)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(log)
+    }
+
+    async fn get_audit_logs(
+        &self,
+        user_id: Uuid,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<ConsentAuditLog>> {
+        let limit = limit.unwrap_or(50);
+        let offset = offset.unwrap_or(0);
+
+        let logs = sqlx::query_as::<_, ConsentAuditLog>(
+            r#"
+            SELECT
+                id, user_id, client_id, action, details, timestamp
+            FROM consent_audit_logs
+            WHERE user_id = $1
+            ORDER BY timestamp DESC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(logs)
+    }
+
+    async fn record_account_deletion(&self, details: serde_json::Value) -> Result<AccountDeletion> {
+        let deletion = sqlx::query_as::<_, AccountDeletion>(
+            r#"
+            INSERT INTO account_deletions (id, details, deleted_at)
+            VALUES ($1, $2, $3)
+            RETURNING id, details, deleted_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(details)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(deletion)
+    }
+
+    async fn create_rule(&self, input: CreateConsentRuleInput) -> Result<ConsentRule> {
+        let row = sqlx::query_as::<_, ConsentRuleRow>(
+            r#"
+            INSERT INTO consent_rules (
+                id, user_id, name, effect, client_id, domains, scopes, inactive_days, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING
+                id, user_id, name, effect, client_id, domains, scopes,
+                inactive_days, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(input.user_id)
+        .bind(input.name)
+        .bind(input.effect.as_str())
+        .bind(input.client_id)
+        .bind(Json(&input.domains))
+        .bind(Json(&input.scopes))
+        .bind(input.inactive_days)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.try_into()?)
+    }
+
+    async fn get_rules(&self, user_id: Uuid) -> Result<Vec<ConsentRule>> {
+        let rows = sqlx::query_as::<_, ConsentRuleRow>(
+            r#"
+            SELECT
+                id, user_id, name, effect, client_id, domains, scopes,
+                inactive_days, created_at
+            FROM consent_rules
+            WHERE user_id = $1
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(ConsentRule::try_from).collect::<Result<_, _>>()?)
+    }
+
+    async fn delete_rule(&self, user_id: Uuid, rule_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM consent_rules
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(rule_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn last_activity(&self, user_id: Uuid, client_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let timestamp = sqlx::query_scalar(
+            r#"
+            SELECT timestamp
+            FROM consent_audit_logs
+            WHERE user_id = $1 AND client_id = $2
+              AND (action IN ('grant', 'access_approved')
+                OR (action = 'access' AND COALESCE(json_extract(details, '$.idle'), 0) = 0))
+            ORDER BY timestamp DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(timestamp)
+    }
+
+    async fn request_approval(
+        &self,
+        user_id: Uuid,
+        rule_id: Uuid,
+        client_id: &str,
+        domain: &str,
+        scope: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AccessApproval> {
+        let waiting = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            SELECT
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            FROM access_approvals
+            WHERE user_id = $1 AND client_id = $2 AND domain = $3 AND scope = $4
+              AND approved_at IS NULL
+              AND expires_at > $5
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(scope)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(approval) = waiting {
+            return Ok(approval);
+        }
+
+        let approval = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            INSERT INTO access_approvals (
+                id, user_id, rule_id, client_id, domain, scope, created_at, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(rule_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(scope)
+        .bind(Utc::now())
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(approval)
+    }
+
+    async fn get_pending_approvals(&self, user_id: Uuid) -> Result<Vec<AccessApproval>> {
+        let approvals = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            SELECT
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            FROM access_approvals
+            WHERE user_id = $1
+              AND approved_at IS NULL
+              AND expires_at > $2
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(approvals)
+    }
+
+    async fn approve_access(&self, user_id: Uuid, approval_id: Uuid) -> Result<Option<AccessApproval>> {
+        let now = Utc::now();
+        let approval = sqlx::query_as::<_, AccessApproval>(
+            r#"
+            UPDATE access_approvals
+            SET approved_at = $3
+            WHERE id = $1 AND user_id = $2
+              AND approved_at IS NULL
+              AND expires_at > $3
+            RETURNING
+                id, user_id, rule_id, client_id, domain, scope,
+                approved_at, created_at, expires_at
+            "#,
+        )
+        .bind(approval_id)
+        .bind(user_id)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(approval)
+    }
+
+    async fn delete_approval(&self, user_id: Uuid, approval_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_approvals
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(approval_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_approval(&self, user_id: Uuid, client_id: &str, domain: &str, scope: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM access_approvals
+            WHERE id = (
+                SELECT id
+                FROM access_approvals
+                WHERE user_id = $1 AND client_id = $2 AND domain = $3 AND scope = $4
+                  AND approved_at IS NOT NULL
+                  AND expires_at > $5
+                LIMIT 1
+            )
+            "#,
+        )
+        .bind(user_id)
+        .bind(client_id)
+        .bind(domain)
+        .bind(scope)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::repository::IdentityRepository;
+    use crate::identity::sqlite::SqliteIdentityRepository;
+    use crate::identity::CreateUserInput;
+    use crate::storage::{migrations, Database};
+    use chrono::Duration;
+
+    async fn setup() -> (SqliteConsentRepository, Uuid) {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let Database::Sqlite(pool) = db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+
+        let user = SqliteIdentityRepository::new(pool.clone())
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        (SqliteConsentRepository::new(pool), user.id)
+    }
+
+    fn grant(user_id: Uuid, expires_at: Option<DateTime<Utc>>) -> GrantAccessInput {
+        GrantAccessInput {
+            user_id,
+            client_id: "notes-app".to_string(),
+            scopes: vec!["read".to_string()],
+            context_domains: vec!["health".to_string(), "work".to_string()],
+            expires_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_access_against_json_arrays() {
+        let (repo, user_id) = setup().await;
+
+        let created = repo.create_grant(grant(user_id, None)).await.unwrap();
+        assert_eq!(created.context_domains, vec!["health", "work"]);
+
+        assert!(repo.check_access(user_id, "notes-app", "work", "read").await.unwrap());
+        assert!(!repo.check_access(user_id, "notes-app", "work", "write").await.unwrap());
+        assert!(!repo.check_access(user_id, "notes-app", "finance", "read").await.unwrap());
+        assert!(!repo.check_access(user_id, "other-app", "work", "read").await.unwrap());
+
+        assert!(repo.revoke_grant(created.id).await.unwrap());
+        assert!(!repo.check_access(user_id, "notes-app", "work", "read").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn ignores_expired_grants() {
+        let (repo, user_id) = setup().await;
+
+        repo.create_grant(grant(user_id, Some(Utc::now() - Duration::minutes(1)))).await.unwrap();
+        let live = repo.create_grant(grant(user_id, Some(Utc::now() + Duration::hours(1)))).await.unwrap();
+
+        let active = repo.get_active_grants(user_id).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, live.id);
+    }
+
+    #[tokio::test]
+    async fn pages_audit_logs_newest_first() {
+        let (repo, user_id) = setup().await;
+
+        for action in ["grant", "access", "revoke"] {
+            repo.create_audit_log(CreateAuditLogInput {
+                user_id,
+                client_id: "notes-app".to_string(),
+                action: action.to_string(),
+                details: serde_json::json!({ "action": action }),
+            }, Utc::now())
+            .await
+            .unwrap();
+        }
+
+        let logs = repo.get_audit_logs(user_id, Some(2), None).await.unwrap();
+        assert_eq!(logs.iter().map(|l| l.action.as_str()).collect::<Vec<_>>(), ["revoke", "access"]);
+        assert_eq!(logs[0].details["action"], "revoke");
+
+        let rest = repo.get_audit_logs(user_id, Some(2), Some(2)).await.unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].action, "grant");
+    }
+
+    #[tokio::test]
+    async fn approvals_are_used_once() {
+        let (repo, user_id) = setup().await;
+
+        let rule = repo
+            .create_rule(CreateConsentRuleInput {
+                user_id,
+                name: "Ask before writes".to_string(),
+                effect: RuleEffect::RequireApproval,
+                client_id: None,
+                domains: vec!["health-*".to_string()],
+                scopes: vec!["write".to_string()],
+                inactive_days: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(repo.get_rules(user_id).await.unwrap()[0].domains, vec!["health-*"]);
+
+        let expires_at = Utc::now() + Duration::hours(1);
+        let request = repo.request_approval(user_id, rule.id, "notes-app", "health-log", "write", expires_at).await.unwrap();
+        let again = repo.request_approval(user_id, rule.id, "notes-app", "health-log", "write", expires_at).await.unwrap();
+        assert_eq!(request.id, again.id);
+        assert!(!repo.use_approval(user_id, "notes-app", "health-log", "write").await.unwrap());
+
+        let approved = repo.approve_access(user_id, request.id).await.unwrap().unwrap();
+        assert!(approved.approved_at.is_some());
+        assert!(repo.get_pending_approvals(user_id).await.unwrap().is_empty());
+        assert!(repo.approve_access(user_id, request.id).await.unwrap().is_none());
+
+        assert!(!repo.use_approval(user_id, "other-app", "health-log", "write").await.unwrap());
+        assert!(repo.use_approval(user_id, "notes-app", "health-log", "write").await.unwrap());
+        assert!(!repo.use_approval(user_id, "notes-app", "health-log", "write").await.unwrap());
+
+        assert!(repo.delete_rule(user_id, rule.id).await.unwrap());
+        assert!(repo.get_rules(user_id).await.unwrap().is_empty());
+    }
+}
@@ -1,15 +1,16 @@
-use async_graphql::{Context, Object, ID, InputObject, Json};
+use async_graphql::{Context, ErrorExtensions, Object, ID, InputObject, Json};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use super::models::{ContextShard, CreateShardInput, UpdateShardInput};
-use super::service::ContextService;
+use crate::api::auth::{account_owner, acting_user, ensure_access, ensure_owner, principal};
 use crate::api::AppState;
+use crate::utils::errors::AppError;
 
 /// GraphQL representation of a context shard
 #[derive(async_graphql::SimpleObject)]
-#[graphql(complex)]
 pub struct GraphQLContextShard {
     /// Unique identifier
     pub id: ID,
@@ -24,9 +25,9 @@ pub struct GraphQLContextShard {
     /// Metadata (non-encrypted)
     pub metadata: Json<HashMap<String, serde_json::Value>>,
     /// Creation timestamp
-    pub created_at: async_graphql::DateTime,
+    pub created_at: DateTime<Utc>,
     /// Last update timestamp
-    pub updated_at: async_graphql::DateTime,
+    pub updated_at: DateTime<Utc>,
     /// Version number
     pub version: i32,
 }
@@ -39,9 +40,9 @@ impl From<ContextShard> for GraphQLContextShard {
             domain: shard.domain,
             content_type: shard.content_type,
             vector_representation: shard.vector_representation,
-            metadata: shard.metadata,
-            created_at: shard.created_at.into(),
-            updated_at: shard.updated_at.into(),
+            metadata: Json(shard.metadata.0),
+            created_at: shard.created_at,
+            updated_at: shard.updated_at,
             version: shard.version,
         }
     }
@@ -50,8 +51,8 @@ impl From<ContextShard> for GraphQLContextShard {
 /// GraphQL input for creating a context shard
 #[derive(InputObject)]
 pub struct GraphQLCreateShardInput {
-    /// User ID of the owner
-    pub user_id: ID,
+    /// User ID of the owner, defaults to the authenticated user
+    pub user_id: Option<ID>,
     /// Domain or category
     pub domain: String,
     /// Type of content
@@ -64,15 +65,16 @@ pub struct GraphQLCreateShardInput {
     pub content: Json<serde_json::Value>,
 }
 
-impl From<GraphQLCreateShardInput> for CreateShardInput {
-    fn from(input: GraphQLCreateShardInput) -> Self {
-        Self {
-            user_id: Uuid::parse_str(&input.user_id.0).unwrap(),
-            domain: input.domain,
-            content_type: input.content_type,
-            vector_representation: input.vector_representation,
-            metadata: input.metadata.0,
-            content: input.content.0,
+impl GraphQLCreateShardInput {
+    /// Convert into a shard owned by `user_id`
+    fn into_input(self, user_id: Uuid) -> CreateShardInput {
+        CreateShardInput {
+            user_id,
+            domain: self.domain,
+            content_type: self.content_type,
+            vector_representation: self.vector_representation,
+            metadata: self.metadata.0,
+            content: self.content.0,
         }
     }
 }
@@ -125,9 +127,15 @@ impl ContextQuery {
     /// Get a context shard by ID
     async fn context_shard(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Option<GraphQLContextShard>> {
         let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = acting_user(ctx, None)?;
         let uuid = Uuid::parse_str(&id.0)?;
         
         let shard = state.context_service.get_shard(uuid).await?;
+        if let Some(shard) = &shard {
+            ensure_owner(user_uuid, Some(shard.user_id))?;
+            ensure_access(ctx, &shard.domain, "read").await?;
+        }
+        
         Ok(shard.map(GraphQLContextShard::from))
     }
     
@@ -138,8 +146,18 @@ impl ContextQuery {
         id: ID
     ) -> async_graphql::Result<Option<GraphQLShardWithContent>> {
         let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = acting_user(ctx, None)?;
         let uuid = Uuid::parse_str(&id.0)?;
         
+        // Check ownership before anything is decrypted
+        match state.context_service.get_shard(uuid).await? {
+            Some(shard) => {
+                ensure_owner(user_uuid, Some(shard.user_id))?;
+                ensure_access(ctx, &shard.domain, "read").await?;
+            },
+            None => return Ok(None),
+        }
+        
         let result = state.context_service.get_shard_with_content(uuid).await?;
         
         Ok(result.map(|(shard, content)| GraphQLShardWithContent {
@@ -152,13 +170,24 @@ impl ContextQuery {
     async fn search_shards(
         &self,
         ctx: &Context<'_>,
-        user_id: ID,
+        user_id: Option<ID>,
         query: String,
         domain: Option<String>,
         limit: Option<i32>,
     ) -> async_graphql::Result<Vec<GraphQLContextShard>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&user_id.0)?;
+        let user_uuid = acting_user(ctx, user_id.as_ref())?;
+        
+        // Clients and personal access tokens only see the domains they were granted
+        match &domain {
+            Some(domain) => ensure_access(ctx, domain, "read").await?,
+            None if principal(ctx)?.is_restricted() => {
+                return Err(AppError::ValidationError(
+                    "Clients and personal access tokens must search within a domain".to_string(),
+                ).extend());
+            },
+            None => {},
+        }
         
         let shards = state.context_service.search_shards(
             user_uuid, 
@@ -174,12 +203,13 @@ impl ContextQuery {
     async fn shards_by_domain(
         &self,
         ctx: &Context<'_>,
-        user_id: ID,
+        user_id: Option<ID>,
         domain: String,
         limit: Option<i32>,
     ) -> async_graphql::Result<Vec<GraphQLContextShard>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&user_id.0)?;
+        let user_uuid = acting_user(ctx, user_id.as_ref())?;
+        ensure_access(ctx, &domain, "read").await?;
         
         let shards = state.context_service.get_shards_by_domain(
             user_uuid, 
@@ -204,23 +234,38 @@ impl ContextMutation {
         input: GraphQLCreateShardInput,
     ) -> async_graphql::Result<GraphQLContextShard> {
         let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = acting_user(ctx, input.user_id.as_ref())?;
+        ensure_access(ctx, &input.domain, "write").await?;
         
-        let shard = state.context_service.create_shard(input.into()).await?;
+        let shard = state.context_service.create_shard(input.into_input(user_uuid)).await?;
         
         Ok(GraphQLContextShard::from(shard))
     }
     
-    /// Update an existing context shard
+    /// Update an existing context shard owned by the authenticated user
     async fn update_shard(
         &self,
         ctx: &Context<'_>,
         id: ID,
+        user_id: Option<ID>,
         input: GraphQLUpdateShardInput,
     ) -> async_graphql::Result<Option<GraphQLContextShard>> {
         let state = ctx.data::<Arc<AppState>>()?;
         let uuid = Uuid::parse_str(&id.0)?;
+        let user_uuid = acting_user(ctx, user_id.as_ref())?;
         
-        let shard = state.context_service.update_shard(uuid, input.into()).await?;
+        match state.context_service.get_shard(uuid).await? {
+            Some(shard) => {
+                ensure_owner(user_uuid, Some(shard.user_id))?;
+                ensure_access(ctx, &shard.domain, "write").await?;
+            },
+            None => return Ok(None),
+        }
+        if let Some(domain) = &input.domain {
+            ensure_access(ctx, domain, "write").await?;
+        }
+        
+        let shard = state.context_service.update_shard(uuid, user_uuid, input.into()).await?;
         
         Ok(shard.map(GraphQLContextShard::from))
     }
@@ -232,10 +277,35 @@ impl ContextMutation {
         id: ID,
     ) -> async_graphql::Result<bool> {
         let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = acting_user(ctx, None)?;
         let uuid = Uuid::parse_str(&id.0)?;
         
+        match state.context_service.get_shard(uuid).await? {
+            Some(shard) => {
+                ensure_owner(user_uuid, Some(shard.user_id))?;
+                ensure_access(ctx, &shard.domain, "write").await?;
+            },
+            None => return Ok(false),
+        }
+        
         let result = state.context_service.delete_shard(uuid).await?;
         
         Ok(result)
     }
+    
+    /// Rotate a user's encryption key and re-encrypt their shards in the background
+    ///
+    /// Returns the new key version.
+    async fn rotate_encryption_key(
+        &self,
+        ctx: &Context<'_>,
+        user_id: Option<ID>,
+    ) -> async_graphql::Result<i32> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, user_id.as_ref())?;
+        
+        let version = state.key_rotation.start(user_uuid).await?;
+        
+        Ok(version)
+    }
 }
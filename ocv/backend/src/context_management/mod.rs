@@ -1,8 +1,10 @@
 pub mod models;
 pub mod repository;
+pub mod rotation;
 pub mod service;
 pub mod graphql;
 
 // Re-export key types
 pub use models::{ContextShard, CreateShardInput, UpdateShardInput};
+pub use rotation::KeyRotationJob;
 pub use service::ContextService;
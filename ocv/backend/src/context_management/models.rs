@@ -4,8 +4,10 @@ use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 
+use crate::encryption::AssociatedData;
+
 /// Represents a single context shard in the vault
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ContextShard {
     /// Unique identifier for the shard
     pub id: Uuid,
@@ -39,6 +41,13 @@ pub struct ContextShard {
     pub version: i32,
 }
 
+impl ContextShard {
+    /// Identity the shard's content is sealed against
+    pub fn associated_data(&self) -> AssociatedData<'_> {
+        AssociatedData::new(self.user_id, self.id, &self.content_type)
+    }
+}
+
 /// Input for creating a new context shard
 #[derive(Debug, Clone, Deserialize)]
 pub struct CreateShardInput {
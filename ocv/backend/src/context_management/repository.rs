@@ -4,7 +4,12 @@ use std::sync::Arc;
 
 use super::models::{ContextShard, CreateShardInput, UpdateShardInput};
 use crate::encryption::service::EncryptionService;
-use crate::adapters::mem0::{Mem0Adapter, MemoryAdapter};
+use crate::encryption::AssociatedData;
+use crate::adapters::MemoryAdapter;
+use crate::adapters::in_memory::InMemoryAdapter;
+use crate::adapters::mem0::Mem0Adapter;
+use crate::adapters::postgres::PgMemoryAdapter;
+use crate::adapters::sqlite::SqliteMemoryAdapter;
 
 /// Repository for context shard storage and retrieval
 pub struct ContextRepository {
@@ -21,17 +26,42 @@ impl ContextRepository {
         }
     }
     
+    /// Create a new context repository backed by the local context_shards table
+    pub fn new_with_postgres(pg_adapter: Arc<PgMemoryAdapter>, encryption_service: Arc<EncryptionService>) -> Self {
+        Self {
+            memory_adapter: pg_adapter,
+            encryption_service,
+        }
+    }
+    
+    /// Create a new context repository backed by a local SQLite vault
+    pub fn new_with_sqlite(sqlite_adapter: Arc<SqliteMemoryAdapter>, encryption_service: Arc<EncryptionService>) -> Self {
+        Self {
+            memory_adapter: sqlite_adapter,
+            encryption_service,
+        }
+    }
+    
+    /// Create a new context repository that keeps shards in process memory
+    pub fn new_in_memory(memory_adapter: Arc<InMemoryAdapter>, encryption_service: Arc<EncryptionService>) -> Self {
+        Self {
+            memory_adapter,
+            encryption_service,
+        }
+    }
+    
     /// Create a new context shard
     pub async fn create_shard(&self, input: CreateShardInput) -> Result<ContextShard> {
-        // Encrypt the content
+        // The id is chosen up front so the content can be bound to it
+        let id = Uuid::new_v4();
         let content_json = serde_json::to_vec(&input.content)?;
         let encrypted_content = self.encryption_service.encrypt(
-            &input.user_id.to_string(),
+            &AssociatedData::new(input.user_id, id, &input.content_type),
             &content_json
         ).await?;
         
-        // Store in mem0
-        self.memory_adapter.store_item(input, encrypted_content).await
+        // Store through the configured adapter
+        self.memory_adapter.store_item(id, input, encrypted_content).await
     }
     
     /// Get a context shard by ID
@@ -39,22 +69,36 @@ impl ContextRepository {
         self.memory_adapter.get_item(&id.to_string()).await
     }
     
-    /// Update an existing context shard
-    pub async fn update_shard(&self, id: Uuid, input: UpdateShardInput) -> Result<Option<ContextShard>> {
-        // Encrypt content if provided
-        let encrypted_content = if let Some(content) = &input.content {
-            let content_json = serde_json::to_vec(content)?;
-            Some(self.encryption_service.encrypt(
-                // Since we don't have user_id in the input, we need to get it from the current shard
-                // In a real implementation, you would want to validate that the user_id matches
-                &id.to_string(), // Using ID as key temporarily
-                &content_json
-            ).await?)
-        } else {
-            None
+    /// Update an existing context shard on behalf of its owner
+    ///
+    /// Fails if the shard belongs to a different user.
+    pub async fn update_shard(&self, id: Uuid, user_id: Uuid, input: UpdateShardInput) -> Result<Option<ContextShard>> {
+        let current = match self.memory_adapter.get_item(&id.to_string()).await? {
+            Some(shard) => shard,
+            None => return Ok(None),
+        };
+        
+        if current.user_id != user_id {
+            return Err(anyhow::anyhow!("Shard {} does not belong to user {}", id, user_id));
+        }
+        
+        // Content is sealed under the owner's key and bound to the resulting
+        // content type, so a type change alone still re-seals the old content
+        let content_type = input.content_type.as_deref().unwrap_or(&current.content_type);
+        let aad = AssociatedData::new(current.user_id, current.id, content_type);
+        let encrypted_content = match &input.content {
+            Some(content) => {
+                let content_json = serde_json::to_vec(content)?;
+                Some(self.encryption_service.encrypt(&aad, &content_json).await?)
+            },
+            None if content_type != current.content_type => {
+                let plaintext = self.encryption_service.decrypt(&current.associated_data(), &current.content).await?;
+                Some(self.encryption_service.encrypt(&aad, &plaintext).await?)
+            },
+            None => None,
         };
         
-        // Update in mem0
+        // Update through the configured adapter
         self.memory_adapter.update_item(&id.to_string(), input, encrypted_content).await
     }
     
@@ -63,6 +107,21 @@ impl ContextRepository {
         self.memory_adapter.delete_item(&id.to_string()).await
     }
     
+    /// Delete every shard of a user through the configured adapter
+    ///
+    /// Returns the number of shards deleted.
+    pub async fn delete_user_shards(&self, user_id: Uuid) -> Result<usize> {
+        let mut deleted = 0;
+        
+        for shard in self.memory_adapter.get_items_by_user(&user_id.to_string()).await? {
+            if self.memory_adapter.delete_item(&shard.id.to_string()).await? {
+                deleted += 1;
+            }
+        }
+        
+        Ok(deleted)
+    }
+    
     /// Search for context shards by text query
     pub async fn search_shards(
         &self, 
@@ -92,4 +151,60 @@ impl ContextRepository {
             limit
         ).await
     }
+    
+    /// Re-encrypt every shard of a user sealed under a key version older than `key_version`
+    /// or in a legacy format
+    ///
+    /// Returns the number of shards that were re-encrypted.
+    pub async fn reseal_shards(&self, user_id: Uuid, key_version: i32) -> Result<usize> {
+        let mut resealed = 0;
+        
+        for shard in self.memory_adapter.get_items_by_user(&user_id.to_string()).await? {
+            if self.reseal_shard(shard, key_version).await? {
+                resealed += 1;
+            }
+        }
+        
+        Ok(resealed)
+    }
+    
+    /// Re-encrypt one shard under the user's current key if it is older than `key_version`
+    ///
+    /// Content in a legacy format is re-sealed as well.
+    async fn reseal_shard(&self, mut shard: ContextShard, key_version: i32) -> Result<bool> {
+        let id = shard.id.to_string();
+        
+        // A concurrent write bumps the version, so re-read and try again
+        for _ in 0..3 {
+            if EncryptionService::is_current(&shard.content, key_version) {
+                return Ok(false);
+            }
+            
+            let aad = shard.associated_data();
+            let plaintext = self.encryption_service.decrypt(&aad, &shard.content).await?;
+            let content = self.encryption_service.encrypt(&aad, &plaintext).await?;
+            
+            let update = UpdateShardInput {
+                domain: None,
+                content_type: None,
+                vector_representation: None,
+                metadata: None,
+                content: None,
+                current_version: shard.version,
+            };
+            
+            let err = match self.memory_adapter.update_item(&id, update, Some(content)).await {
+                Ok(updated) => return Ok(updated.is_some()),
+                Err(err) => err,
+            };
+            
+            match self.memory_adapter.get_item(&id).await? {
+                Some(current) if current.version != shard.version => shard = current,
+                Some(_) => return Err(err),
+                None => return Ok(false),
+            }
+        }
+        
+        Err(anyhow::anyhow!("Shard {} kept changing while being re-encrypted", id))
+    }
 }
@@ -0,0 +1,227 @@
+use anyhow::Result;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::service::ContextService;
+use crate::consent_manager::service::{ConsentManager, VAULT_CLIENT_ID};
+use crate::encryption::service::EncryptionService;
+use crate::identity::IdentityService;
+
+/// Passes over a user's shards before giving up on concurrent writers
+const MAX_PASSES: usize = 5;
+
+/// Rotates a user's data key and re-encrypts their shards and TOTP secret under it
+///
+/// The new key is current as soon as rotation starts, so fresh writes use it.
+/// Existing shards are re-sealed in the background; the old key versions are
+/// only retired once a full pass finds nothing left to re-seal.
+pub struct KeyRotationJob {
+    context_service: Arc<ContextService>,
+    encryption_service: Arc<EncryptionService>,
+    consent_manager: Arc<ConsentManager>,
+    identity_service: Arc<IdentityService>,
+}
+
+impl KeyRotationJob {
+    /// Create a new key rotation job
+    pub fn new(
+        context_service: Arc<ContextService>,
+        encryption_service: Arc<EncryptionService>,
+        consent_manager: Arc<ConsentManager>,
+        identity_service: Arc<IdentityService>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            context_service,
+            encryption_service,
+            consent_manager,
+            identity_service,
+        })
+    }
+
+    /// Create a new key version and re-encrypt the user's shards in the background
+    ///
+    /// Returns the new key version.
+    pub async fn start(self: &Arc<Self>, user_id: Uuid) -> Result<i32> {
+        let version = self.encryption_service.rotate_key(user_id).await?;
+
+        self.consent_manager.record_event(
+            user_id,
+            VAULT_CLIENT_ID,
+            "key_rotation_started",
+            serde_json::json!({ "key_version": version }),
+        ).await?;
+
+        let job = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = job.run(user_id, version).await {
+                log::error!("Key rotation for user {} failed: {}", user_id, err);
+            }
+        });
+
+        Ok(version)
+    }
+
+    /// Re-encrypt the user's shards under `version`, then retire older versions
+    ///
+    /// Progress and the outcome are recorded in the user's audit log.
+    pub async fn run(&self, user_id: Uuid, version: i32) -> Result<()> {
+        match self.reseal_all(user_id, version).await {
+            Ok(resealed) => {
+                let retired = self.encryption_service.retire_keys_before(user_id, version).await?;
+
+                self.consent_manager.record_event(
+                    user_id,
+                    VAULT_CLIENT_ID,
+                    "key_rotation_completed",
+                    serde_json::json!({
+                        "key_version": version,
+                        "resealed": resealed,
+                        "retired_versions": retired
+                    }),
+                ).await?;
+
+                Ok(())
+            },
+            Err(err) => {
+                // Older versions are kept so nothing becomes unreadable
+                self.consent_manager.record_event(
+                    user_id,
+                    VAULT_CLIENT_ID,
+                    "key_rotation_failed",
+                    serde_json::json!({
+                        "key_version": version,
+                        "error": err.to_string()
+                    }),
+                ).await?;
+
+                Err(err)
+            },
+        }
+    }
+
+    /// Re-seal shards until a pass finds none left under an older version
+    ///
+    /// Returns the total number of shards re-sealed.
+    async fn reseal_all(&self, user_id: Uuid, version: i32) -> Result<usize> {
+        // The TOTP secret is sealed with the same key, and rarely changes
+        self.identity_service.reseal_totp_secret(user_id, version).await?;
+        
+        let mut total = 0;
+
+        for pass in 1..=MAX_PASSES {
+            let resealed = self.context_service.reseal_shards(user_id, version).await?;
+            if resealed == 0 {
+                return Ok(total);
+            }
+            total += resealed;
+
+            self.consent_manager.record_event(
+                user_id,
+                VAULT_CLIENT_ID,
+                "key_rotation_progress",
+                serde_json::json!({
+                    "key_version": version,
+                    "pass": pass,
+                    "resealed": resealed
+                }),
+            ).await?;
+        }
+
+        Err(anyhow::anyhow!("Shards were still being written under an old key after {} passes", MAX_PASSES))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ClientService;
+    use crate::context_management::models::CreateShardInput;
+    use crate::encryption::MasterKey;
+    use crate::identity::{signing::DEFAULT_ISSUER, throttle::LoginLimits, totp, CreateUserInput, IdentityService, JwtKeys};
+    use crate::mailer::FileMailer;
+    use crate::policy_engine::service::PolicyEngine;
+    use crate::storage::{migrations, Database};
+    use crate::utils::clock::SystemClock;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn reseals_shards_and_retires_the_old_key() {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let encryption_service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        let context_service = ContextService::new_with_database(&db, encryption_service.clone());
+        let policy_engine = PolicyEngine::new(&db);
+        let client_service = ClientService::new(&db, policy_engine.clone(), false, DEFAULT_ISSUER);
+        let identity_service = IdentityService::new(
+            &db,
+            encryption_service.clone(),
+            JwtKeys::generate(),
+            Arc::new(FileMailer::stdout()),
+            "http://localhost:3000",
+            LoginLimits::default(),
+            Arc::new(SystemClock),
+        );
+        let consent_manager = ConsentManager::new(&db, policy_engine, client_service, identity_service.clone(), Arc::new(SystemClock));
+        let user = identity_service
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let mut shards = Vec::new();
+        for n in 0..3 {
+            let shard = context_service
+                .create_shard(CreateShardInput {
+                    user_id: user.id,
+                    domain: "notes".to_string(),
+                    content_type: "note".to_string(),
+                    vector_representation: None,
+                    metadata: HashMap::new(),
+                    content: serde_json::json!({ "n": n }),
+                })
+                .await
+                .unwrap();
+            assert_eq!(EncryptionService::key_version(&shard.content), 1);
+            shards.push(shard);
+        }
+
+        let enrollment = identity_service.enroll_two_factor(user.id).await.unwrap();
+
+        let job = KeyRotationJob::new(
+            context_service.clone(),
+            encryption_service.clone(),
+            consent_manager.clone(),
+            identity_service.clone(),
+        );
+        let version = encryption_service.rotate_key(user.id).await.unwrap();
+        job.run(user.id, version).await.unwrap();
+
+        // The TOTP secret was re-sealed too
+        let secret = totp_rs::Secret::Encoded(enrollment.secret).to_bytes().unwrap();
+        let code = totp::code_at(&secret, chrono::Utc::now());
+        assert_eq!(identity_service.confirm_two_factor(user.id, &code).await.unwrap().len(), 10);
+
+        for (n, shard) in shards.iter().enumerate() {
+            let (current, content) = context_service.get_shard_with_content(shard.id).await.unwrap().unwrap();
+            assert_eq!(EncryptionService::key_version(&current.content), 2);
+            assert_eq!(content, serde_json::json!({ "n": n }));
+
+            // The old ciphertext died with the old key
+            assert!(encryption_service.decrypt(&shard.associated_data(), &shard.content).await.is_err());
+        }
+
+        let actions: Vec<String> = consent_manager
+            .get_audit_logs(user.id, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|log| log.action)
+            .collect();
+        assert!(actions.contains(&"key_rotation_progress".to_string()));
+        assert!(actions.contains(&"key_rotation_completed".to_string()));
+    }
+}
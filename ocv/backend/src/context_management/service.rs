@@ -8,7 +8,11 @@ use super::{
     repository::ContextRepository,
 };
 use crate::encryption::service::EncryptionService;
+use crate::adapters::in_memory::InMemoryAdapter;
 use crate::adapters::mem0::{Mem0Adapter, Mem0Config};
+use crate::adapters::postgres::PgMemoryAdapter;
+use crate::adapters::sqlite::SqliteMemoryAdapter;
+use crate::storage::Database;
 
 /// Service for managing context shards
 pub struct ContextService {
@@ -29,6 +33,31 @@ impl ContextService {
         })
     }
     
+    /// Create a new context service storing shards in the application database
+    pub fn new_with_database(db: &Database, encryption_service: Arc<EncryptionService>) -> Arc<Self> {
+        let repository = match db {
+            Database::Postgres(pool) => {
+                ContextRepository::new_with_postgres(PgMemoryAdapter::new(pool.clone()), encryption_service.clone())
+            },
+            Database::Sqlite(pool) => {
+                ContextRepository::new_with_sqlite(SqliteMemoryAdapter::new(pool.clone()), encryption_service.clone())
+            },
+        };
+        
+        Arc::new(Self {
+            repository,
+            encryption_service,
+        })
+    }
+    
+    /// Create a new context service that keeps shards in process memory
+    pub fn new_in_memory(encryption_service: Arc<EncryptionService>) -> Arc<Self> {
+        Arc::new(Self {
+            repository: ContextRepository::new_in_memory(InMemoryAdapter::new(), encryption_service.clone()),
+            encryption_service,
+        })
+    }
+    
     /// Create a new context shard
     pub async fn create_shard(&self, input: CreateShardInput) -> Result<ContextShard> {
         self.repository.create_shard(input).await
@@ -46,9 +75,9 @@ impl ContextService {
             None => return Ok(None),
         };
         
-        // Decrypt the content
+        // Decrypt the content; it only opens for this shard's own identity
         let decrypted = self.encryption_service.decrypt(
-            &shard.user_id.to_string(),
+            &shard.associated_data(),
             &shard.content
         ).await?;
         
@@ -57,9 +86,9 @@ impl ContextService {
         Ok(Some((shard, content)))
     }
     
-    /// Update an existing context shard
-    pub async fn update_shard(&self, id: Uuid, input: UpdateShardInput) -> Result<Option<ContextShard>> {
-        self.repository.update_shard(id, input).await
+    /// Update an existing context shard on behalf of its owner
+    pub async fn update_shard(&self, id: Uuid, user_id: Uuid, input: UpdateShardInput) -> Result<Option<ContextShard>> {
+        self.repository.update_shard(id, user_id, input).await
     }
     
     /// Delete a context shard
@@ -67,6 +96,11 @@ impl ContextService {
         self.repository.delete_shard(id).await
     }
     
+    /// Delete all of a user's shards, returning how many there were
+    pub async fn delete_user_shards(&self, user_id: Uuid) -> Result<usize> {
+        self.repository.delete_user_shards(user_id).await
+    }
+    
     /// Search for context shards
     pub async fn search_shards(
         &self,
@@ -87,4 +121,151 @@ impl ContextService {
     ) -> Result<Vec<ContextShard>> {
         self.repository.get_shards_by_domain(user_id, domain, limit).await
     }
+    
+    /// Re-encrypt every shard of a user still sealed under a key version older than `key_version`
+    pub async fn reseal_shards(&self, user_id: Uuid, key_version: i32) -> Result<usize> {
+        self.repository.reseal_shards(user_id, key_version).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::MasterKey;
+    use crate::identity::{throttle::LoginLimits, CreateUserInput, IdentityService, JwtKeys};
+    use crate::mailer::FileMailer;
+    use crate::storage::{migrations, Database};
+    use crate::utils::clock::SystemClock;
+    use std::collections::HashMap;
+
+    async fn setup() -> (Arc<ContextService>, Uuid, Uuid) {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let encryption_service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        let identity_service = IdentityService::new(
+            &db,
+            encryption_service.clone(),
+            JwtKeys::generate(),
+            Arc::new(FileMailer::stdout()),
+            "http://localhost:3000",
+            LoginLimits::default(),
+            Arc::new(SystemClock),
+        );
+
+        let mut users = Vec::new();
+        for name in ["ada", "bob"] {
+            let user = identity_service
+                .create_user(CreateUserInput {
+                    email: format!("{}@example.com", name),
+                    display_name: name.to_string(),
+                    password: "correct horse".to_string(),
+                })
+                .await
+                .unwrap();
+            users.push(user.id);
+        }
+
+        (ContextService::new_with_database(&db, encryption_service), users[0], users[1])
+    }
+
+    fn shard_input(user_id: Uuid, content: Value) -> CreateShardInput {
+        CreateShardInput {
+            user_id,
+            domain: "notes".to_string(),
+            content_type: "note".to_string(),
+            vector_representation: None,
+            metadata: HashMap::new(),
+            content,
+        }
+    }
+
+    fn content_update(current_version: i32, content: Value) -> UpdateShardInput {
+        UpdateShardInput {
+            domain: None,
+            content_type: None,
+            vector_representation: None,
+            metadata: None,
+            content: Some(content),
+            current_version,
+        }
+    }
+
+    #[tokio::test]
+    async fn updated_content_reads_back() {
+        let (service, owner, _) = setup().await;
+
+        let shard = service.create_shard(shard_input(owner, serde_json::json!({ "text": "draft" }))).await.unwrap();
+        let (_, content) = service.get_shard_with_content(shard.id).await.unwrap().unwrap();
+        assert_eq!(content, serde_json::json!({ "text": "draft" }));
+
+        let updated = service
+            .update_shard(shard.id, owner, content_update(shard.version, serde_json::json!({ "text": "final" })))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.version, shard.version + 1);
+
+        let (read, content) = service.get_shard_with_content(shard.id).await.unwrap().unwrap();
+        assert_eq!(read.version, updated.version);
+        assert_eq!(content, serde_json::json!({ "text": "final" }));
+    }
+
+    #[tokio::test]
+    async fn rejects_updates_from_another_user() {
+        let (service, owner, intruder) = setup().await;
+
+        let shard = service.create_shard(shard_input(owner, serde_json::json!({ "text": "mine" }))).await.unwrap();
+
+        let result = service
+            .update_shard(shard.id, intruder, content_update(shard.version, serde_json::json!({ "text": "yours" })))
+            .await;
+        assert!(result.is_err());
+
+        let (read, content) = service.get_shard_with_content(shard.id).await.unwrap().unwrap();
+        assert_eq!(read.version, shard.version);
+        assert_eq!(content, serde_json::json!({ "text": "mine" }));
+    }
+
+    #[tokio::test]
+    async fn content_does_not_open_under_another_shard() {
+        let (service, owner, _) = setup().await;
+
+        let first = service.create_shard(shard_input(owner, serde_json::json!({ "text": "first" }))).await.unwrap();
+        let second = service.create_shard(shard_input(owner, serde_json::json!({ "text": "second" }))).await.unwrap();
+
+        let mut pasted = second.clone();
+        pasted.content = first.content.clone();
+        assert!(service.encryption_service.decrypt(&pasted.associated_data(), &pasted.content).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn changing_content_type_reseals_content() {
+        let (service, owner, _) = setup().await;
+
+        let shard = service.create_shard(shard_input(owner, serde_json::json!({ "text": "draft" }))).await.unwrap();
+
+        let update = UpdateShardInput {
+            content_type: Some("profile".to_string()),
+            content: None,
+            ..content_update(shard.version, Value::Null)
+        };
+        let updated = service.update_shard(shard.id, owner, update).await.unwrap().unwrap();
+        assert_eq!(updated.content_type, "profile");
+        assert_ne!(updated.content, shard.content);
+
+        let (_, content) = service.get_shard_with_content(shard.id).await.unwrap().unwrap();
+        assert_eq!(content, serde_json::json!({ "text": "draft" }));
+    }
+
+    #[tokio::test]
+    async fn updating_a_missing_shard_returns_none() {
+        let (service, owner, _) = setup().await;
+
+        let result = service
+            .update_shard(Uuid::new_v4(), owner, content_update(1, serde_json::json!({})))
+            .await
+            .unwrap();
+        assert!(result.is_none());
+    }
 }
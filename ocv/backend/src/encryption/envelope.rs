@@ -0,0 +1,137 @@
+//! Layout of encrypted content
+//!
+//! Current envelopes are `"OCV" | format | key version (u32, big-endian) | nonce | ciphertext`.
+//! Content written before headers existed is a bare secretbox `nonce | ciphertext`.
+
+use uuid::Uuid;
+
+/// Prefix of every envelope header
+const MAGIC: &[u8] = b"OCV";
+
+/// Magic, format byte and key version
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+/// Key version of content written before headers existed
+const LEGACY_KEY_VERSION: i32 = 1;
+
+/// Cipher used to seal an envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Headerless secretbox, readable only
+    LegacySecretbox,
+    /// Secretbox behind a versioned header, readable only
+    Secretbox,
+    /// XChaCha20-Poly1305 with associated data, used for all new content
+    XChaCha20Poly1305,
+}
+
+impl Format {
+    /// Header byte identifying the format
+    fn tag(self) -> Option<u8> {
+        match self {
+            Self::LegacySecretbox => None,
+            Self::Secretbox => Some(1),
+            Self::XChaCha20Poly1305 => Some(2),
+        }
+    }
+
+    /// Format for a header byte
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Self::Secretbox),
+            2 => Some(Self::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed envelope borrowing from the encrypted bytes
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope<'a> {
+    /// Cipher the content was sealed with
+    pub format: Format,
+    /// Version of the user's key the content was sealed with
+    pub key_version: i32,
+    /// Header bytes, empty for legacy content
+    pub header: &'a [u8],
+    /// Nonce followed by the ciphertext
+    pub body: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Split encrypted bytes into header and body
+    ///
+    /// Anything without a recognisable header is treated as legacy content.
+    pub fn parse(data: &'a [u8]) -> Self {
+        Self::parse_header(data).unwrap_or(Self::legacy(data))
+    }
+
+    /// Read the same bytes as headerless legacy content
+    pub fn legacy(data: &'a [u8]) -> Self {
+        Self {
+            format: Format::LegacySecretbox,
+            key_version: LEGACY_KEY_VERSION,
+            header: &[],
+            body: data,
+        }
+    }
+
+    /// Parse a versioned header, if there is one
+    fn parse_header(data: &'a [u8]) -> Option<Self> {
+        let rest = data.strip_prefix(MAGIC)?;
+        let (&tag, rest) = rest.split_first()?;
+        let format = Format::from_tag(tag)?;
+
+        if rest.len() < 4 {
+            return None;
+        }
+        let key_version = i32::from_be_bytes(rest[..4].try_into().ok()?);
+
+        Some(Self {
+            format,
+            key_version,
+            header: &data[..HEADER_LEN],
+            body: &data[HEADER_LEN..],
+        })
+    }
+}
+
+/// Build the header for new content
+pub fn header(format: Format, key_version: i32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend(format.tag());
+    header.extend_from_slice(&key_version.to_be_bytes());
+    header
+}
+
+/// Identity that a shard's ciphertext is bound to
+///
+/// Content only decrypts under the same user, shard and content type it was
+/// sealed for, so ciphertext cannot be moved between records.
+#[derive(Debug, Clone, Copy)]
+pub struct AssociatedData<'a> {
+    /// Owner of the shard
+    pub user_id: Uuid,
+    /// Shard the content belongs to
+    pub shard_id: Uuid,
+    /// Type of the content
+    pub content_type: &'a str,
+}
+
+impl<'a> AssociatedData<'a> {
+    /// Bind content to a user, shard and content type
+    pub fn new(user_id: Uuid, shard_id: Uuid, content_type: &'a str) -> Self {
+        Self { user_id, shard_id, content_type }
+    }
+
+    /// Bytes authenticated alongside the ciphertext, header included
+    pub(crate) fn to_bytes(self, header: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(header.len() + 32 + self.content_type.len());
+        bytes.extend_from_slice(header);
+        bytes.extend_from_slice(self.user_id.as_bytes());
+        bytes.extend_from_slice(self.shard_id.as_bytes());
+        bytes.extend_from_slice(self.content_type.as_bytes());
+        bytes
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use argon2::Argon2;
+use async_trait::async_trait;
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::randombytes;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::KeyProvider;
+use crate::encryption::master_key::MasterKey;
+
+/// Prefix of every keyfile
+const MAGIC: &[u8] = b"OCVK";
+
+/// Current keyfile layout
+const FORMAT_VERSION: u8 = 1;
+
+/// Length of the Argon2 salt
+const SALT_LEN: usize = 16;
+
+/// Master key stored in a local file, sealed under a passphrase
+///
+/// The file is `"OCVK" | version | salt | nonce | secretbox(master key)`,
+/// with the sealing key derived from the passphrase with Argon2id.
+#[derive(Debug)]
+pub struct KeyfileProvider {
+    path: PathBuf,
+    master_key: MasterKey,
+}
+
+impl KeyfileProvider {
+    /// Open the keyfile named by `KEYFILE_PATH` with `KEYFILE_PASSPHRASE`
+    ///
+    /// A new keyfile with a fresh master key is written if none exists yet.
+    pub fn from_env() -> Result<Self> {
+        let path = env::var("KEYFILE_PATH").context("KEYFILE_PATH must be set")?;
+        let passphrase = env::var("KEYFILE_PASSPHRASE").context("KEYFILE_PASSPHRASE must be set")?;
+
+        match Self::open(&path, &passphrase) {
+            Err(err) if is_not_found(&err) => {
+                log::warn!("No keyfile at {}, creating one with a new master key", path);
+                Self::create(&path, &passphrase)
+            },
+            result => result,
+        }
+    }
+
+    /// Unlock an existing keyfile
+    pub fn open(path: impl AsRef<Path>, passphrase: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("Failed to read keyfile {}", path.display()))?;
+
+        let rest = bytes.strip_prefix(MAGIC)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a keyfile", path.display()))?;
+        let (&version, rest) = rest.split_first()
+            .ok_or_else(|| anyhow::anyhow!("Keyfile {} is truncated", path.display()))?;
+        if version != FORMAT_VERSION {
+            return Err(anyhow::anyhow!("Unsupported keyfile version {}", version));
+        }
+        if rest.len() < SALT_LEN + secretbox::NONCEBYTES {
+            return Err(anyhow::anyhow!("Keyfile {} is truncated", path.display()));
+        }
+
+        let (salt, rest) = rest.split_at(SALT_LEN);
+        let (nonce_bytes, sealed) = rest.split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(nonce_bytes)
+            .ok_or_else(|| anyhow::anyhow!("Invalid nonce"))?;
+
+        let key = secretbox::open(sealed, &nonce, &derive_key(passphrase, salt)?)
+            .map_err(|_| anyhow::anyhow!("Wrong passphrase for keyfile {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            master_key: MasterKey::from_bytes(&key)?,
+        })
+    }
+
+    /// Write a new keyfile holding a freshly generated master key
+    ///
+    /// Fails rather than overwrite an existing file.
+    pub fn create(path: impl AsRef<Path>, passphrase: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let master_key = MasterKey::generate();
+
+        let salt = randombytes::randombytes(SALT_LEN);
+        let nonce = secretbox::gen_nonce();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&salt);
+        bytes.extend_from_slice(nonce.as_ref());
+        bytes.extend_from_slice(&secretbox::seal(master_key.as_bytes(), &nonce, &derive_key(passphrase, &salt)?));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(&bytes))
+            .with_context(|| format!("Failed to write keyfile {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            master_key,
+        })
+    }
+
+    /// Location of the keyfile
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[async_trait]
+impl KeyProvider for KeyfileProvider {
+    fn name(&self) -> &'static str {
+        "keyfile"
+    }
+
+    async fn wrap(&self, key: &[u8]) -> Result<Vec<u8>> {
+        self.master_key.wrap(key).await
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.master_key.unwrap(wrapped).await
+    }
+}
+
+/// Derive the key sealing the keyfile from its passphrase
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<secretbox::Key> {
+    let mut key = [0u8; secretbox::KEYBYTES];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow::anyhow!("Failed to derive keyfile key: {}", err))?;
+
+    Ok(secretbox::Key(key))
+}
+
+/// Whether an error came from the keyfile not existing
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == ErrorKind::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyfile_path() -> PathBuf {
+        env::temp_dir().join(format!("ocv-keyfile-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn reopened_keyfile_unwraps_keys() {
+        sodiumoxide::init().unwrap();
+        let path = keyfile_path();
+
+        let created = KeyfileProvider::create(&path, "hunter2").unwrap();
+        let wrapped = created.wrap(b"data key").await.unwrap();
+
+        let reopened = KeyfileProvider::open(&path, "hunter2").unwrap();
+        assert_eq!(reopened.unwrap(&wrapped).await.unwrap(), b"data key");
+
+        assert!(KeyfileProvider::open(&path, "hunter3").is_err());
+        assert!(KeyfileProvider::create(&path, "hunter2").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_keyfile_is_reported_as_not_found() {
+        let err = KeyfileProvider::open(keyfile_path(), "hunter2").unwrap_err();
+        assert!(is_not_found(&err));
+    }
+}
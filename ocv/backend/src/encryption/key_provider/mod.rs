@@ -0,0 +1,69 @@
+pub mod keyfile;
+pub mod pkcs11;
+pub mod transit;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::master_key::MasterKey;
+
+pub use keyfile::KeyfileProvider;
+pub use pkcs11::Pkcs11Provider;
+pub use transit::TransitProvider;
+
+/// Wraps and unwraps per-user data keys
+///
+/// Implementations decide where the key-encrypting key lives. Wrapped keys
+/// are opaque bytes stored alongside the user.
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Wrap a data key for storage
+    async fn wrap(&self, key: &[u8]) -> Result<Vec<u8>>;
+
+    /// Unwrap a stored data key
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Which key provider a deployment uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBackend {
+    /// Master key from `MASTER_KEY` or `MASTER_KEY_FILE`
+    MasterKey,
+    /// Master key in a passphrase-protected keyfile
+    Keyfile,
+    /// Key held in a PKCS#11 token
+    Pkcs11,
+    /// Vault transit secrets engine
+    Transit,
+}
+
+impl FromStr for KeyBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "master-key" | "env" => Ok(Self::MasterKey),
+            "keyfile" => Ok(Self::Keyfile),
+            "pkcs11" | "hsm" => Ok(Self::Pkcs11),
+            "transit" | "vault" => Ok(Self::Transit),
+            other => Err(anyhow::anyhow!("Unknown key backend: {}", other)),
+        }
+    }
+}
+
+impl KeyBackend {
+    /// Build the provider, reading its settings from the environment
+    pub fn provider_from_env(self) -> Result<Arc<dyn KeyProvider>> {
+        Ok(match self {
+            Self::MasterKey => Arc::new(MasterKey::from_env()?),
+            Self::Keyfile => Arc::new(KeyfileProvider::from_env()?),
+            Self::Pkcs11 => Arc::new(Pkcs11Provider::from_env()?),
+            Self::Transit => Arc::new(TransitProvider::from_env()?),
+        })
+    }
+}
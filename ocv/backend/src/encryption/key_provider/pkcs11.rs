@@ -0,0 +1,400 @@
+//! PKCS#11 key provider
+//!
+//! Talks to the token through the module's exported `C_*` functions, so any
+//! PKCS#11 library works, SoftHSM included. Data keys are wrapped with
+//! AES-GCM by the token and the wrapping key never leaves it.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use libloading::Library;
+use sodiumoxide::randombytes;
+use std::env;
+use std::ffi::c_void;
+use std::os::raw::c_ulong;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use super::KeyProvider;
+
+type CkUlong = c_ulong;
+type CkRv = CkUlong;
+type CkSlotId = CkUlong;
+type CkSessionHandle = CkUlong;
+type CkObjectHandle = CkUlong;
+
+const CKR_OK: CkRv = 0;
+const CKR_USER_ALREADY_LOGGED_IN: CkRv = 0x100;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CkRv = 0x191;
+
+const CKF_OS_LOCKING_OK: CkUlong = 0x2;
+const CKF_RW_SESSION: CkUlong = 0x2;
+const CKF_SERIAL_SESSION: CkUlong = 0x4;
+
+const CKU_USER: CkUlong = 1;
+
+const CKA_CLASS: CkUlong = 0x0;
+const CKA_LABEL: CkUlong = 0x3;
+const CKO_SECRET_KEY: CkUlong = 0x4;
+
+const CKM_AES_GCM: CkUlong = 0x1087;
+
+/// Length of the GCM IV stored in front of each wrapped key
+const IV_LEN: usize = 12;
+
+/// Length of the GCM tag
+const TAG_BITS: CkUlong = 128;
+
+#[repr(C)]
+struct CkInitializeArgs {
+    create_mutex: *mut c_void,
+    destroy_mutex: *mut c_void,
+    lock_mutex: *mut c_void,
+    unlock_mutex: *mut c_void,
+    flags: CkUlong,
+    reserved: *mut c_void,
+}
+
+#[repr(C)]
+struct CkTokenInfo {
+    label: [u8; 32],
+    manufacturer_id: [u8; 32],
+    model: [u8; 16],
+    serial_number: [u8; 16],
+    flags: CkUlong,
+    counters: [CkUlong; 10],
+    hardware_version: [u8; 2],
+    firmware_version: [u8; 2],
+    utc_time: [u8; 16],
+}
+
+#[repr(C)]
+struct CkAttribute {
+    kind: CkUlong,
+    value: *mut c_void,
+    value_len: CkUlong,
+}
+
+#[repr(C)]
+struct CkMechanism {
+    mechanism: CkUlong,
+    parameter: *mut c_void,
+    parameter_len: CkUlong,
+}
+
+#[repr(C)]
+struct CkGcmParams {
+    iv: *mut u8,
+    iv_len: CkUlong,
+    iv_bits: CkUlong,
+    aad: *mut u8,
+    aad_len: CkUlong,
+    tag_bits: CkUlong,
+}
+
+type InitializeFn = unsafe extern "C" fn(*mut c_void) -> CkRv;
+type FinalizeFn = unsafe extern "C" fn(*mut c_void) -> CkRv;
+type GetSlotListFn = unsafe extern "C" fn(u8, *mut CkSlotId, *mut CkUlong) -> CkRv;
+type GetTokenInfoFn = unsafe extern "C" fn(CkSlotId, *mut CkTokenInfo) -> CkRv;
+type OpenSessionFn = unsafe extern "C" fn(CkSlotId, CkUlong, *mut c_void, *mut c_void, *mut CkSessionHandle) -> CkRv;
+type CloseSessionFn = unsafe extern "C" fn(CkSessionHandle) -> CkRv;
+type LoginFn = unsafe extern "C" fn(CkSessionHandle, CkUlong, *const u8, CkUlong) -> CkRv;
+type FindObjectsInitFn = unsafe extern "C" fn(CkSessionHandle, *mut CkAttribute, CkUlong) -> CkRv;
+type FindObjectsFn = unsafe extern "C" fn(CkSessionHandle, *mut CkObjectHandle, CkUlong, *mut CkUlong) -> CkRv;
+type FindObjectsFinalFn = unsafe extern "C" fn(CkSessionHandle) -> CkRv;
+type CryptInitFn = unsafe extern "C" fn(CkSessionHandle, *mut CkMechanism, CkObjectHandle) -> CkRv;
+type CryptFn = unsafe extern "C" fn(CkSessionHandle, *const u8, CkUlong, *mut u8, *mut CkUlong) -> CkRv;
+
+/// Entry points used from the module
+struct Functions {
+    finalize: FinalizeFn,
+    close_session: CloseSessionFn,
+    encrypt_init: CryptInitFn,
+    encrypt: CryptFn,
+    decrypt_init: CryptInitFn,
+    decrypt: CryptFn,
+}
+
+/// Turn a return value into an error naming the call
+fn check(call: &str, rv: CkRv) -> Result<()> {
+    if rv == CKR_OK {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("PKCS#11 {} failed with CKR 0x{:x}", call, rv))
+    }
+}
+
+/// Look up an exported function
+///
+/// # Safety
+/// `T` must match the C signature of `name`.
+unsafe fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T> {
+    let symbol = library
+        .get::<T>(name.as_bytes())
+        .with_context(|| format!("PKCS#11 module does not export {}", name))?;
+    Ok(*symbol)
+}
+
+/// Token-side state shared between blocking calls
+struct Token {
+    functions: Functions,
+    /// PKCS#11 sessions are not safe for concurrent operations
+    session: Mutex<CkSessionHandle>,
+    key: CkObjectHandle,
+    /// Keeps the module loaded for as long as the function pointers are used
+    _library: Library,
+}
+
+impl Token {
+    /// Load the module, log in to the token and find the wrapping key
+    fn open(module: &str, token_label: &str, pin: &str, key_label: &str) -> Result<Self> {
+        // SAFETY: the module is a PKCS#11 library and every symbol is used with
+        // the signature from the PKCS#11 specification
+        unsafe {
+            let library = Library::new(module).with_context(|| format!("Failed to load PKCS#11 module {}", module))?;
+
+            let initialize: InitializeFn = symbol(&library, "C_Initialize")?;
+            let get_slot_list: GetSlotListFn = symbol(&library, "C_GetSlotList")?;
+            let get_token_info: GetTokenInfoFn = symbol(&library, "C_GetTokenInfo")?;
+            let open_session: OpenSessionFn = symbol(&library, "C_OpenSession")?;
+            let login: LoginFn = symbol(&library, "C_Login")?;
+            let find_objects_init: FindObjectsInitFn = symbol(&library, "C_FindObjectsInit")?;
+            let find_objects: FindObjectsFn = symbol(&library, "C_FindObjects")?;
+            let find_objects_final: FindObjectsFinalFn = symbol(&library, "C_FindObjectsFinal")?;
+
+            let functions = Functions {
+                finalize: symbol(&library, "C_Finalize")?,
+                close_session: symbol(&library, "C_CloseSession")?,
+                encrypt_init: symbol(&library, "C_EncryptInit")?,
+                encrypt: symbol(&library, "C_Encrypt")?,
+                decrypt_init: symbol(&library, "C_DecryptInit")?,
+                decrypt: symbol(&library, "C_Decrypt")?,
+            };
+
+            let mut args = CkInitializeArgs {
+                create_mutex: ptr::null_mut(),
+                destroy_mutex: ptr::null_mut(),
+                lock_mutex: ptr::null_mut(),
+                unlock_mutex: ptr::null_mut(),
+                flags: CKF_OS_LOCKING_OK,
+                reserved: ptr::null_mut(),
+            };
+            match initialize(&mut args as *mut _ as *mut c_void) {
+                CKR_OK | CKR_CRYPTOKI_ALREADY_INITIALIZED => {},
+                rv => check("C_Initialize", rv)?,
+            }
+
+            // Find the slot holding the token with the configured label
+            let mut count = 0;
+            check("C_GetSlotList", get_slot_list(1, ptr::null_mut(), &mut count))?;
+            let mut slots = vec![0; count as usize];
+            check("C_GetSlotList", get_slot_list(1, slots.as_mut_ptr(), &mut count))?;
+            slots.truncate(count as usize);
+
+            let mut slot = None;
+            for id in slots {
+                let mut info: CkTokenInfo = std::mem::zeroed();
+                check("C_GetTokenInfo", get_token_info(id, &mut info))?;
+                if String::from_utf8_lossy(&info.label).trim_end() == token_label {
+                    slot = Some(id);
+                    break;
+                }
+            }
+            let slot = slot.ok_or_else(|| anyhow::anyhow!("No PKCS#11 token labelled {}", token_label))?;
+
+            let mut session = 0;
+            check(
+                "C_OpenSession",
+                open_session(slot, CKF_SERIAL_SESSION | CKF_RW_SESSION, ptr::null_mut(), ptr::null_mut(), &mut session),
+            )?;
+
+            match login(session, CKU_USER, pin.as_ptr(), pin.len() as CkUlong) {
+                CKR_OK | CKR_USER_ALREADY_LOGGED_IN => {},
+                rv => {
+                    (functions.close_session)(session);
+                    check("C_Login", rv)?;
+                },
+            }
+
+            // Find the wrapping key by label
+            let mut class = CKO_SECRET_KEY;
+            let mut template = [
+                CkAttribute {
+                    kind: CKA_CLASS,
+                    value: &mut class as *mut _ as *mut c_void,
+                    value_len: std::mem::size_of::<CkUlong>() as CkUlong,
+                },
+                CkAttribute {
+                    kind: CKA_LABEL,
+                    value: key_label.as_ptr() as *mut c_void,
+                    value_len: key_label.len() as CkUlong,
+                },
+            ];
+            check("C_FindObjectsInit", find_objects_init(session, template.as_mut_ptr(), template.len() as CkUlong))?;
+            let mut key = 0;
+            let mut found = 0;
+            let result = check("C_FindObjects", find_objects(session, &mut key, 1, &mut found));
+            check("C_FindObjectsFinal", find_objects_final(session))?;
+            result?;
+
+            if found == 0 {
+                (functions.close_session)(session);
+                return Err(anyhow::anyhow!("No secret key labelled {} on token {}", key_label, token_label));
+            }
+
+            Ok(Self {
+                functions,
+                session: Mutex::new(session),
+                key,
+                _library: library,
+            })
+        }
+    }
+
+    /// Run a single-part AES-GCM encrypt or decrypt on the token
+    fn crypt(&self, encrypt: bool, iv: &[u8], input: &[u8]) -> Result<Vec<u8>> {
+        let (init, crypt, name) = if encrypt {
+            (self.functions.encrypt_init, self.functions.encrypt, "C_Encrypt")
+        } else {
+            (self.functions.decrypt_init, self.functions.decrypt, "C_Decrypt")
+        };
+
+        let mut iv = iv.to_vec();
+        let mut params = CkGcmParams {
+            iv: iv.as_mut_ptr(),
+            iv_len: iv.len() as CkUlong,
+            iv_bits: (iv.len() * 8) as CkUlong,
+            aad: ptr::null_mut(),
+            aad_len: 0,
+            tag_bits: TAG_BITS,
+        };
+        let mut mechanism = CkMechanism {
+            mechanism: CKM_AES_GCM,
+            parameter: &mut params as *mut _ as *mut c_void,
+            parameter_len: std::mem::size_of::<CkGcmParams>() as CkUlong,
+        };
+
+        let guard = self.session.lock().unwrap();
+        let session = *guard;
+
+        // SAFETY: the session is held exclusively while the operation runs and
+        // every buffer outlives the calls that use it
+        unsafe {
+            check(&format!("{}Init", name), init(session, &mut mechanism, self.key))?;
+
+            // Room for the input plus the tag covers both directions
+            let mut output = vec![0u8; input.len() + (TAG_BITS / 8) as usize];
+            let mut output_len = output.len() as CkUlong;
+            check(name, crypt(session, input.as_ptr(), input.len() as CkUlong, output.as_mut_ptr(), &mut output_len))?;
+            output.truncate(output_len as usize);
+
+            Ok(output)
+        }
+    }
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        // SAFETY: the session and module are not used after this point
+        unsafe {
+            (self.functions.close_session)(*self.session.get_mut().unwrap());
+            (self.functions.finalize)(ptr::null_mut());
+        }
+    }
+}
+
+// SAFETY: the session handle is only used behind the mutex and the module was
+// initialised with CKF_OS_LOCKING_OK
+unsafe impl Send for Token {}
+unsafe impl Sync for Token {}
+
+/// Wraps data keys with an AES key held in a PKCS#11 token
+///
+/// Wrapped keys are `IV | AES-GCM ciphertext and tag`. The wrapping key is
+/// found by label and is never read out of the token.
+pub struct Pkcs11Provider {
+    token: Arc<Token>,
+}
+
+impl Pkcs11Provider {
+    /// Open a token and find the wrapping key
+    pub fn new(module: &str, token_label: &str, pin: &str, key_label: &str) -> Result<Self> {
+        Ok(Self {
+            token: Arc::new(Token::open(module, token_label, pin, key_label)?),
+        })
+    }
+
+    /// Open the token configured by `PKCS11_MODULE`, `PKCS11_TOKEN_LABEL`,
+    /// `PKCS11_PIN` and `PKCS11_KEY_LABEL`
+    pub fn from_env() -> Result<Self> {
+        Self::new(
+            &env::var("PKCS11_MODULE").context("PKCS11_MODULE must be set")?,
+            &env::var("PKCS11_TOKEN_LABEL").context("PKCS11_TOKEN_LABEL must be set")?,
+            &env::var("PKCS11_PIN").context("PKCS11_PIN must be set")?,
+            &env::var("PKCS11_KEY_LABEL").context("PKCS11_KEY_LABEL must be set")?,
+        )
+    }
+}
+
+#[async_trait]
+impl KeyProvider for Pkcs11Provider {
+    fn name(&self) -> &'static str {
+        "pkcs11"
+    }
+
+    async fn wrap(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let token = self.token.clone();
+        let key = key.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            let iv = randombytes::randombytes(IV_LEN);
+            let mut wrapped = iv.clone();
+            wrapped.extend(token.crypt(true, &iv, &key)?);
+            Ok(wrapped)
+        })
+        .await?
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>> {
+        if wrapped.len() < IV_LEN {
+            return Err(anyhow::anyhow!("Invalid wrapped key"));
+        }
+
+        let token = self.token.clone();
+        let wrapped = wrapped.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            let (iv, ciphertext) = wrapped.split_at(IV_LEN);
+            token.crypt(false, iv, ciphertext)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Needs TEST_PKCS11_MODULE to point at a module such as SoftHSM's, with a
+    /// token and AES key set up as described in DEVELOPMENT.md
+    #[tokio::test]
+    #[ignore = "needs TEST_PKCS11_MODULE"]
+    async fn wraps_keys_on_the_token() {
+        let module = env::var("TEST_PKCS11_MODULE").expect("TEST_PKCS11_MODULE must be set");
+        sodiumoxide::init().unwrap();
+
+        let provider = Pkcs11Provider::new(
+            &module,
+            &env::var("TEST_PKCS11_TOKEN_LABEL").unwrap_or_else(|_| "ocv".to_string()),
+            &env::var("TEST_PKCS11_PIN").unwrap_or_else(|_| "1234".to_string()),
+            &env::var("TEST_PKCS11_KEY_LABEL").unwrap_or_else(|_| "ocv-wrap".to_string()),
+        )
+        .unwrap();
+
+        let wrapped = provider.wrap(b"data key").await.unwrap();
+        assert_eq!(provider.unwrap(&wrapped).await.unwrap(), b"data key");
+
+        let mut tampered = wrapped.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(provider.unwrap(&tampered).await.is_err());
+    }
+}
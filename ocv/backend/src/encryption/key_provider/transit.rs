@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use std::env;
+
+use super::KeyProvider;
+
+/// Configuration for a Vault transit secrets engine
+#[derive(Clone, Debug)]
+pub struct TransitConfig {
+    /// Base URL of the Vault server
+    pub address: String,
+    /// Token sent as `X-Vault-Token`
+    pub token: String,
+    /// Path the transit engine is mounted at
+    pub mount: String,
+    /// Name of the transit key that wraps data keys
+    pub key_name: String,
+}
+
+impl TransitConfig {
+    /// Load from `VAULT_ADDR`, `VAULT_TOKEN`, `VAULT_TRANSIT_MOUNT` and `VAULT_TRANSIT_KEY`
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            address: env::var("VAULT_ADDR").context("VAULT_ADDR must be set")?,
+            token: env::var("VAULT_TOKEN").context("VAULT_TOKEN must be set")?,
+            mount: env::var("VAULT_TRANSIT_MOUNT").unwrap_or_else(|_| "transit".to_string()),
+            key_name: env::var("VAULT_TRANSIT_KEY").context("VAULT_TRANSIT_KEY must be set")?,
+        })
+    }
+}
+
+/// Response envelope of every Vault API call
+#[derive(Debug, Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct EncryptData {
+    ciphertext: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DecryptData {
+    plaintext: String,
+}
+
+/// Wraps data keys with a Vault transit key
+///
+/// The key-encrypting key never leaves Vault. Wrapped keys are stored as the
+/// `vault:v<n>:...` ciphertext Vault returns.
+pub struct TransitProvider {
+    config: TransitConfig,
+    client: Client,
+}
+
+impl TransitProvider {
+    /// Create a new transit provider
+    pub fn new(config: TransitConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    /// Create a transit provider configured from the environment
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(TransitConfig::from_env()?))
+    }
+
+    /// Call a transit endpoint for the configured key
+    async fn call<T>(&self, operation: &str, body: serde_json::Value) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let url = format!(
+            "{}/v1/{}/{}/{}",
+            self.config.address.trim_end_matches('/'),
+            self.config.mount,
+            operation,
+            urlencoding::encode(&self.config.key_name)
+        );
+
+        let response = self.client
+            .post(&url)
+            .header("X-Vault-Token", &self.config.token)
+            .json(&body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+            return Err(anyhow::anyhow!("Vault transit {} failed with {}: {}", operation, status, error_text));
+        }
+
+        Ok(response.json::<VaultResponse<T>>().await?.data)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for TransitProvider {
+    fn name(&self) -> &'static str {
+        "transit"
+    }
+
+    async fn wrap(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let data: EncryptData = self
+            .call("encrypt", serde_json::json!({ "plaintext": base64::encode(key) }))
+            .await?;
+
+        Ok(data.ciphertext.into_bytes())
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = std::str::from_utf8(wrapped)
+            .map_err(|_| anyhow::anyhow!("Wrapped key is not a transit ciphertext"))?;
+
+        let data: DecryptData = self
+            .call("decrypt", serde_json::json!({ "ciphertext": ciphertext }))
+            .await?;
+
+        Ok(base64::decode(data.plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use sodiumoxide::crypto::secretbox;
+
+    const TOKEN: &str = "s.test";
+
+    /// Stand-in for Vault's transit engine with a single in-memory key
+    async fn transit(
+        req: HttpRequest,
+        path: web::Path<(String, String)>,
+        body: web::Json<serde_json::Value>,
+        key: web::Data<secretbox::Key>,
+    ) -> HttpResponse {
+        if req.headers().get("X-Vault-Token").and_then(|v| v.to_str().ok()) != Some(TOKEN) {
+            return HttpResponse::Forbidden().json(serde_json::json!({ "errors": ["permission denied"] }));
+        }
+
+        let (operation, key_name) = path.into_inner();
+        if key_name != "ocv" {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "errors": ["no such key"] }));
+        }
+
+        match operation.as_str() {
+            "encrypt" => {
+                let plaintext = base64::decode(body["plaintext"].as_str().unwrap()).unwrap();
+                let nonce = secretbox::gen_nonce();
+                let mut sealed = nonce.as_ref().to_vec();
+                sealed.extend(secretbox::seal(&plaintext, &nonce, &key));
+                let ciphertext = format!("vault:v1:{}", base64::encode(sealed));
+                HttpResponse::Ok().json(serde_json::json!({ "data": { "ciphertext": ciphertext } }))
+            },
+            "decrypt" => {
+                let ciphertext = body["ciphertext"].as_str().unwrap();
+                let sealed = base64::decode(ciphertext.trim_start_matches("vault:v1:")).unwrap();
+                let (nonce, sealed) = sealed.split_at(secretbox::NONCEBYTES);
+                let nonce = secretbox::Nonce::from_slice(nonce).unwrap();
+                match secretbox::open(sealed, &nonce, &key) {
+                    Ok(plaintext) => HttpResponse::Ok()
+                        .json(serde_json::json!({ "data": { "plaintext": base64::encode(plaintext) } })),
+                    Err(_) => HttpResponse::BadRequest().json(serde_json::json!({ "errors": ["cipher: message authentication failed"] })),
+                }
+            },
+            _ => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    /// Start the mock on a free port and return its address
+    fn start_mock() -> String {
+        sodiumoxide::init().unwrap();
+        let key = web::Data::new(secretbox::gen_key());
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(key.clone())
+                .route("/v1/transit/{operation}/{key}", web::post().to(transit))
+        })
+        .workers(1)
+        .bind("127.0.0.1:0")
+        .unwrap();
+
+        let address = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        address
+    }
+
+    fn provider(address: &str, token: &str) -> TransitProvider {
+        TransitProvider::new(TransitConfig {
+            address: address.to_string(),
+            token: token.to_string(),
+            mount: "transit".to_string(),
+            key_name: "ocv".to_string(),
+        })
+    }
+
+    #[actix_web::test]
+    async fn wraps_keys_through_transit() {
+        let address = start_mock();
+        let provider = provider(&address, TOKEN);
+
+        let wrapped = provider.wrap(b"data key").await.unwrap();
+        assert!(wrapped.starts_with(b"vault:v1:"));
+        assert_eq!(provider.unwrap(&wrapped).await.unwrap(), b"data key");
+
+        // Change one character of the nonce
+        let mut tampered = wrapped.clone();
+        let at = b"vault:v1:".len() + 4;
+        tampered[at] = if tampered[at] == b'A' { b'B' } else { b'A' };
+        assert!(provider.unwrap(&tampered).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn surfaces_vault_errors() {
+        let address = start_mock();
+
+        let err = provider(&address, "wrong").wrap(b"data key").await.unwrap_err();
+        assert!(err.to_string().contains("403"));
+    }
+}
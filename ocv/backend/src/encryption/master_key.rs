@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sodiumoxide::crypto::secretbox;
+use std::{env, fmt, fs};
+
+use super::key_provider::KeyProvider;
+
+/// Key that wraps every per-user data key
+///
+/// Loaded from `MASTER_KEY` (base64) or from the file named by
+/// `MASTER_KEY_FILE` (base64 text or 32 raw bytes).
+#[derive(Clone)]
+pub struct MasterKey(secretbox::Key);
+
+impl MasterKey {
+    /// Load the master key from the environment
+    pub fn from_env() -> Result<Self> {
+        if let Ok(encoded) = env::var("MASTER_KEY") {
+            return Self::from_base64(&encoded).context("Invalid MASTER_KEY");
+        }
+
+        let path = env::var("MASTER_KEY_FILE").context("MASTER_KEY or MASTER_KEY_FILE must be set")?;
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read master key file {}", path))?;
+
+        if let Ok(key) = Self::from_bytes(&bytes) {
+            return Ok(key);
+        }
+
+        let encoded = String::from_utf8(bytes).context("Master key file is neither raw nor base64")?;
+        Self::from_base64(&encoded).with_context(|| format!("Invalid master key in {}", path))
+    }
+
+    /// Decode a base64 master key
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        Self::from_bytes(&base64::decode(encoded.trim())?)
+    }
+
+    /// Use raw key bytes as the master key
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        secretbox::Key::from_slice(bytes)
+            .map(Self)
+            .ok_or_else(|| anyhow::anyhow!("Master key must be {} bytes", secretbox::KEYBYTES))
+    }
+
+    /// Raw key bytes
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    /// Generate a random master key
+    pub fn generate() -> Self {
+        Self(secretbox::gen_key())
+    }
+
+    /// Base64 encoding of the key, for writing it to a file or env var
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.0)
+    }
+
+    /// Seal a value under the master key
+    pub fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let nonce = secretbox::gen_nonce();
+
+        let mut result = nonce.as_ref().to_vec();
+        result.extend_from_slice(&secretbox::seal(data, &nonce, &self.0));
+        result
+    }
+
+    /// Open a value sealed under the master key
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < secretbox::NONCEBYTES {
+            return Err(anyhow::anyhow!("Invalid sealed data"));
+        }
+
+        let (nonce_bytes, encrypted) = sealed.split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(nonce_bytes)
+            .ok_or_else(|| anyhow::anyhow!("Invalid nonce"))?;
+
+        secretbox::open(encrypted, &nonce, &self.0)
+            .map_err(|_| anyhow::anyhow!("Data was not sealed with this master key"))
+    }
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterKey(..)")
+    }
+}
+
+/// The master key held in process memory
+#[async_trait]
+impl KeyProvider for MasterKey {
+    fn name(&self) -> &'static str {
+        "master-key"
+    }
+
+    async fn wrap(&self, key: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.seal(key))
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.open(wrapped)
+    }
+}
@@ -1,4 +1,12 @@
+pub mod envelope;
+pub mod key_provider;
+pub mod master_key;
+pub mod repository;
 pub mod service;
+pub mod sqlite;
 
 // Re-export key types
+pub use envelope::AssociatedData;
+pub use key_provider::{KeyBackend, KeyProvider};
+pub use master_key::MasterKey;
 pub use service::EncryptionService;
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Storage for wrapped per-user keys and the master key check value
+#[async_trait]
+pub trait KeyRepository: Send + Sync {
+    /// Get a live version of a user's wrapped data key
+    async fn get_wrapped_key(&self, user_id: Uuid, version: i32) -> Result<Option<Vec<u8>>>;
+
+    /// Get the newest live version of a user's wrapped data key
+    async fn get_current_key(&self, user_id: Uuid) -> Result<Option<(i32, Vec<u8>)>>;
+
+    /// Store a version of a user's wrapped data key unless it already exists
+    ///
+    /// Returns true if the key was inserted.
+    async fn insert_wrapped_key(&self, user_id: Uuid, version: i32, wrapped_key: Vec<u8>) -> Result<bool>;
+
+    /// Destroy the key material of every live version older than `version`
+    ///
+    /// Returns the retired versions.
+    async fn retire_keys_before(&self, user_id: Uuid, version: i32) -> Result<Vec<i32>>;
+
+    /// Get any one wrapped key, used to check the master key against existing data
+    async fn any_wrapped_key(&self) -> Result<Option<Vec<u8>>>;
+
+    /// Get the value sealed under the master key when the vault was set up
+    async fn get_master_key_check(&self) -> Result<Option<Vec<u8>>>;
+
+    /// Record the master key check value
+    async fn set_master_key_check(&self, sealed_value: Vec<u8>) -> Result<()>;
+}
+
+/// Postgres repository for wrapped keys
+pub struct PgKeyRepository {
+    pool: PgPool,
+}
+
+impl PgKeyRepository {
+    /// Create a new key repository
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl KeyRepository for PgKeyRepository {
+    async fn get_wrapped_key(&self, user_id: Uuid, version: i32) -> Result<Option<Vec<u8>>> {
+        let key = sqlx::query_scalar(
+            r#"
+            SELECT wrapped_key
+            FROM user_keys
+            WHERE user_id = $1
+              AND version = $2
+              AND wrapped_key IS NOT NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn get_current_key(&self, user_id: Uuid) -> Result<Option<(i32, Vec<u8>)>> {
+        let key = sqlx::query_as(
+            r#"
+            SELECT version, wrapped_key
+            FROM user_keys
+            WHERE user_id = $1
+              AND wrapped_key IS NOT NULL
+            ORDER BY version DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn insert_wrapped_key(&self, user_id: Uuid, version: i32, wrapped_key: Vec<u8>) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO user_keys (user_id, version, wrapped_key)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, version) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(version)
+        .bind(wrapped_key)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn retire_keys_before(&self, user_id: Uuid, version: i32) -> Result<Vec<i32>> {
+        let retired = sqlx::query_scalar(
+            r#"
+            UPDATE user_keys
+            SET wrapped_key = NULL, retired_at = NOW()
+            WHERE user_id = $1
+              AND version < $2
+              AND wrapped_key IS NOT NULL
+            RETURNING version
+            "#,
+        )
+        .bind(user_id)
+        .bind(version)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(retired)
+    }
+
+    async fn any_wrapped_key(&self) -> Result<Option<Vec<u8>>> {
+        let key = sqlx::query_scalar(
+            r#"
+            SELECT wrapped_key
+            FROM user_keys
+            WHERE wrapped_key IS NOT NULL
+            LIMIT 1
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn get_master_key_check(&self) -> Result<Option<Vec<u8>>> {
+        let value = sqlx::query_scalar(
+            r#"
+            SELECT sealed_value
+            FROM master_key_check
+            WHERE id = 1
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(value)
+    }
+
+    async fn set_master_key_check(&self, sealed_value: Vec<u8>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO master_key_check (id, sealed_value)
+            VALUES (1, $1)
+            ON CONFLICT (id) DO UPDATE SET sealed_value = EXCLUDED.sealed_value
+            "#,
+        )
+        .bind(sealed_value)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,101 +1,459 @@
 use anyhow::Result;
+use sodiumoxide::crypto::aead::xchacha20poly1305_ietf as aead;
 use sodiumoxide::crypto::secretbox;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+use super::{
+    envelope::{self, AssociatedData, Envelope, Format},
+    key_provider::KeyProvider,
+    repository::{KeyRepository, PgKeyRepository},
+    sqlite::SqliteKeyRepository,
+};
+use crate::storage::Database;
+
+/// Plaintext wrapped by the key provider to detect a mismatched provider on startup
+const MASTER_KEY_CHECK: &[u8] = b"open-context-vault master key check";
+
 /// Service for encrypting and decrypting data
+///
+/// Every user has versioned data keys wrapped by the configured key provider
+/// and stored in the database. Content is sealed with XChaCha20-Poly1305 in an envelope
+/// that records the key version, so older versions keep working until they
+/// are retired. Unwrapped keys are cached in memory.
 #[derive(Clone)]
 pub struct EncryptionService {
-    // In a real implementation, keys would be stored securely and possibly fetched from a KMS
-    // This is a simplified version for demonstration purposes
-    user_keys: Arc<RwLock<HashMap<String, secretbox::Key>>>,
+    repository: Arc<dyn KeyRepository>,
+    key_provider: Arc<dyn KeyProvider>,
+    user_keys: Arc<RwLock<HashMap<(Uuid, i32), secretbox::Key>>>,
 }
 
 impl EncryptionService {
     /// Create a new encryption service
-    pub fn new() -> Arc<Self> {
+    pub fn new(db: &Database, key_provider: Arc<dyn KeyProvider>) -> Arc<Self> {
         // Initialize sodiumoxide
         sodiumoxide::init().expect("Failed to initialize sodiumoxide");
-        
+
+        let repository: Arc<dyn KeyRepository> = match db {
+            Database::Postgres(pool) => Arc::new(PgKeyRepository::new(pool.clone())),
+            Database::Sqlite(pool) => Arc::new(SqliteKeyRepository::new(pool.clone())),
+        };
+
         Arc::new(Self {
+            repository,
+            key_provider,
             user_keys: Arc::new(RwLock::new(HashMap::new())),
         })
     }
-    
-    /// Get or create a key for a user
-    fn get_or_create_key(&self, user_id: &str) -> Result<secretbox::Key> {
-        // Check if we already have a key for this user
+
+    /// Check that the key provider can unwrap the stored key material
+    ///
+    /// The first run records a check value wrapped by the provider.
+    pub async fn verify_key_provider(&self) -> Result<()> {
+        let name = self.key_provider.name();
+
+        if let Some(sealed) = self.repository.get_master_key_check().await? {
+            let value = self.key_provider.unwrap(&sealed).await
+                .map_err(|err| anyhow::anyhow!("Key provider {} does not match the one this vault was set up with: {}", name, err))?;
+
+            if value != MASTER_KEY_CHECK {
+                return Err(anyhow::anyhow!("Key provider check value is corrupt"));
+            }
+
+            return Ok(());
+        }
+
+        // Keys may predate the check value, so make sure they still unwrap
+        if let Some(wrapped) = self.repository.any_wrapped_key().await? {
+            self.key_provider.unwrap(&wrapped).await
+                .map_err(|err| anyhow::anyhow!("Key provider {} cannot unwrap the stored user keys: {}", name, err))?;
+        }
+
+        let sealed = self.key_provider.wrap(MASTER_KEY_CHECK).await?;
+        self.repository.set_master_key_check(sealed).await
+    }
+
+    /// Generate and store the first data key for a new user
+    ///
+    /// Does nothing if the user already has a key.
+    pub async fn create_key(&self, user_id: Uuid) -> Result<()> {
+        if self.repository.get_current_key(user_id).await?.is_some() {
+            return Ok(());
+        }
+
+        self.insert_key(user_id, 1).await?;
+
+        Ok(())
+    }
+
+    /// Generate a new key version for a user and make it current
+    ///
+    /// Older versions stay available for decryption until they are retired
+    /// with [`retire_keys_before`](Self::retire_keys_before).
+    pub async fn rotate_key(&self, user_id: Uuid) -> Result<i32> {
+        let (current, _) = self.repository.get_current_key(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("No encryption key for user {}", user_id))?;
+        let version = current + 1;
+
+        if !self.insert_key(user_id, version).await? {
+            return Err(anyhow::anyhow!("Key version {} for user {} already exists", version, user_id));
+        }
+
+        Ok(version)
+    }
+
+    /// Destroy every key version older than `version`
+    ///
+    /// Ciphertext sealed under a retired version can no longer be decrypted.
+    pub async fn retire_keys_before(&self, user_id: Uuid, version: i32) -> Result<Vec<i32>> {
+        let retired = self.repository.retire_keys_before(user_id, version).await?;
+
+        let mut keys = self.user_keys.write().unwrap();
+        keys.retain(|(id, v), _| *id != user_id || *v >= version);
+
+        Ok(retired)
+    }
+
+    /// Destroy every key version of a user
+    ///
+    /// Anything sealed for the user, including copies in backups of the
+    /// shard storage, can no longer be decrypted. Returns the destroyed versions.
+    pub async fn destroy_keys(&self, user_id: Uuid) -> Result<Vec<i32>> {
+        self.retire_keys_before(user_id, i32::MAX).await
+    }
+
+    /// Key version a ciphertext was sealed with
+    pub fn key_version(data: &[u8]) -> i32 {
+        Envelope::parse(data).key_version
+    }
+
+    /// Whether a ciphertext uses the current format and at least `key_version`
+    ///
+    /// Anything else should be re-sealed, which is how legacy content migrates.
+    pub fn is_current(data: &[u8], key_version: i32) -> bool {
+        let envelope = Envelope::parse(data);
+        envelope.format == Format::XChaCha20Poly1305 && envelope.key_version >= key_version
+    }
+
+    /// Generate, wrap and store a key version
+    ///
+    /// Returns false if the version already exists.
+    async fn insert_key(&self, user_id: Uuid, version: i32) -> Result<bool> {
+        let key = secretbox::gen_key();
+        let wrapped = self.key_provider.wrap(key.as_ref()).await?;
+
+        if !self.repository.insert_wrapped_key(user_id, version, wrapped).await? {
+            return Ok(false);
+        }
+
+        let mut keys = self.user_keys.write().unwrap();
+        keys.insert((user_id, version), key);
+
+        Ok(true)
+    }
+
+    /// Unwrap a stored key and cache it
+    async fn unwrap_key(&self, user_id: Uuid, version: i32, wrapped: &[u8]) -> Result<secretbox::Key> {
+        let key = secretbox::Key::from_slice(&self.key_provider.unwrap(wrapped).await?)
+            .ok_or_else(|| anyhow::anyhow!("Stored key for user {} is malformed", user_id))?;
+
+        let mut keys = self.user_keys.write().unwrap();
+        keys.insert((user_id, version), key.clone());
+
+        Ok(key)
+    }
+
+    /// Get a specific version of a user's key
+    async fn get_key(&self, user_id: Uuid, version: i32) -> Result<secretbox::Key> {
+        // Check if we already have this key
         {
             let keys = self.user_keys.read().unwrap();
-            if let Some(key) = keys.get(user_id) {
+            if let Some(key) = keys.get(&(user_id, version)) {
                 return Ok(key.clone());
             }
         }
-        
-        // Create a new key
-        let key = secretbox::gen_key();
-        
-        // Store the key
-        {
-            let mut keys = self.user_keys.write().unwrap();
-            keys.insert(user_id.to_string(), key.clone());
+
+        let wrapped = self.repository.get_wrapped_key(user_id, version).await?
+            .ok_or_else(|| anyhow::anyhow!("No live key version {} for user {}", version, user_id))?;
+
+        self.unwrap_key(user_id, version, &wrapped).await
+    }
+
+    /// Get the version new data for a user is sealed with
+    async fn current_key(&self, user_id: Uuid) -> Result<(i32, secretbox::Key)> {
+        // Always ask storage, as another process may have rotated the key
+        let (version, wrapped) = self.repository.get_current_key(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("No encryption key for user {}", user_id))?;
+
+        let cached = self.user_keys.read().unwrap().get(&(user_id, version)).cloned();
+        match cached {
+            Some(key) => Ok((version, key)),
+            None => Ok((version, self.unwrap_key(user_id, version, &wrapped).await?)),
         }
-        
-        Ok(key)
     }
-    
-    /// Encrypt data for a user
-    pub async fn encrypt(&self, user_id: &str, data: &[u8]) -> Result<Vec<u8>> {
-        let key = self.get_or_create_key(user_id)?;
-        let nonce = secretbox::gen_nonce();
-        
-        // Encrypt the data
-        let encrypted = secretbox::seal(data, &nonce, &key);
-        
-        // Combine nonce and encrypted data
-        let mut result = nonce.as_ref().to_vec();
+
+    /// Open an envelope with the key version it names
+    async fn open(&self, envelope: Envelope<'_>, aad: &AssociatedData<'_>) -> Result<Vec<u8>> {
+        let key = self.get_key(aad.user_id, envelope.key_version).await?;
+
+        match envelope.format {
+            Format::XChaCha20Poly1305 => {
+                if envelope.body.len() < aead::NONCEBYTES {
+                    return Err(anyhow::anyhow!("Invalid encrypted data"));
+                }
+
+                let (nonce_bytes, encrypted_data) = envelope.body.split_at(aead::NONCEBYTES);
+                let nonce = aead::Nonce::from_slice(nonce_bytes)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid nonce"))?;
+                let key = aead::Key::from_slice(key.as_ref())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid key length"))?;
+
+                aead::open(encrypted_data, Some(&aad.to_bytes(envelope.header)), &nonce, &key)
+                    .map_err(|_| anyhow::anyhow!("Decryption failed"))
+            },
+            // Secretbox content predates associated data, so nothing is bound
+            Format::Secretbox | Format::LegacySecretbox => {
+                if envelope.body.len() < secretbox::NONCEBYTES {
+                    return Err(anyhow::anyhow!("Invalid encrypted data"));
+                }
+
+                let (nonce_bytes, encrypted_data) = envelope.body.split_at(secretbox::NONCEBYTES);
+                let nonce = secretbox::Nonce::from_slice(nonce_bytes)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid nonce"))?;
+
+                secretbox::open(encrypted_data, &nonce, &key)
+                    .map_err(|_| anyhow::anyhow!("Decryption failed"))
+            },
+        }
+    }
+
+    /// Encrypt data under the owner's current key, bound to `aad`
+    pub async fn encrypt(&self, aad: &AssociatedData<'_>, data: &[u8]) -> Result<Vec<u8>> {
+        let (version, key) = self.current_key(aad.user_id).await?;
+        let key = aead::Key::from_slice(key.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("Invalid key length"))?;
+        let nonce = aead::gen_nonce();
+
+        let header = envelope::header(Format::XChaCha20Poly1305, version);
+        let encrypted = aead::seal(data, Some(&aad.to_bytes(&header)), &nonce, &key);
+
+        // Header, then nonce and encrypted data
+        let mut result = Vec::with_capacity(header.len() + aead::NONCEBYTES + encrypted.len());
+        result.extend_from_slice(&header);
+        result.extend_from_slice(nonce.as_ref());
         result.extend_from_slice(&encrypted);
-        
+
         Ok(result)
     }
-    
-    /// Decrypt data for a user
-    pub async fn decrypt(&self, user_id: &str, data: &[u8]) -> Result<Vec<u8>> {
-        if data.len() < secretbox::NONCEBYTES {
-            return Err(anyhow::anyhow!("Invalid encrypted data"));
+
+    /// Decrypt data with the key version it was sealed under
+    ///
+    /// Fails if `aad` differs from what the content was sealed for.
+    pub async fn decrypt(&self, aad: &AssociatedData<'_>, data: &[u8]) -> Result<Vec<u8>> {
+        let envelope = Envelope::parse(data);
+        if envelope.format == Format::LegacySecretbox {
+            return self.open(envelope, aad).await;
         }
-        
-        // Extract nonce and encrypted data
-        let nonce_bytes = &data[..secretbox::NONCEBYTES];
-        let encrypted_data = &data[secretbox::NONCEBYTES..];
-        
-        // Convert nonce bytes to a Nonce
-        let nonce = secretbox::Nonce::from_slice(nonce_bytes)
-            .ok_or_else(|| anyhow::anyhow!("Invalid nonce"))?;
-        
-        // Get the key
-        let key = self.get_or_create_key(user_id)?;
-        
-        // Decrypt the data
-        let decrypted = secretbox::open(encrypted_data, &nonce, &key)
-            .map_err(|_| anyhow::anyhow!("Decryption failed"))?;
-        
-        Ok(decrypted)
-    }
-    
-    /// Rotate a user's key
-    pub async fn rotate_key(&self, user_id: &str) -> Result<()> {
-        // In a real implementation, this would involve fetching all encrypted data,
-        // decrypting it with the old key, generating a new key, and re-encrypting
-        // with the new key.
-        let new_key = secretbox::gen_key();
-        
-        {
-            let mut keys = self.user_keys.write().unwrap();
-            keys.insert(user_id.to_string(), new_key);
+
+        // Headerless content can start with the magic by chance, so fall back
+        // to reading it as legacy content before giving up
+        match self.open(envelope, aad).await {
+            Ok(decrypted) => Ok(decrypted),
+            Err(err) => self.open(Envelope::legacy(data), aad).await.map_err(|_| err),
         }
-        
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::MasterKey;
+    use crate::storage::migrations;
+    use chrono::Utc;
+
+    async fn database() -> Database {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+        db
+    }
+
+    /// Insert a throwaway user so keys satisfy the foreign key
+    async fn create_user(db: &Database) -> Uuid {
+        let Database::Sqlite(pool) = db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+
+        let id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, email, display_name, password_hash, created_at, updated_at)
+            VALUES ($1, $2, 'Keys', 'unused', $3, $3)
+            "#,
+        )
+        .bind(id)
+        .bind(format!("{}@keys.test", id))
+        .bind(Utc::now())
+        .execute(pool)
+        .await
+        .unwrap();
+
+        id
+    }
+
+    /// Associated data for a fixed shard
+    fn aad(user_id: Uuid) -> AssociatedData<'static> {
+        AssociatedData::new(user_id, Uuid::nil(), "note")
+    }
+
+    /// Secretbox content as written before associated data existed
+    fn secretbox_content(key: &secretbox::Key, header: &[u8], data: &[u8]) -> Vec<u8> {
+        let nonce = secretbox::gen_nonce();
+        let mut content = header.to_vec();
+        content.extend_from_slice(nonce.as_ref());
+        content.extend_from_slice(&secretbox::seal(data, &nonce, key));
+        content
+    }
+
+    #[tokio::test]
+    async fn keys_survive_a_restart() {
+        let db = database().await;
+        let master_key = Arc::new(MasterKey::generate());
+        let user_id = create_user(&db).await;
+
+        let service = EncryptionService::new(&db, master_key.clone());
+        service.create_key(user_id).await.unwrap();
+        let sealed = service.encrypt(&aad(user_id), b"hello").await.unwrap();
+
+        // A fresh service has an empty cache and must unwrap the stored key
+        let restarted = EncryptionService::new(&db, master_key);
+        restarted.verify_key_provider().await.unwrap();
+        let opened = restarted.decrypt(&aad(user_id), &sealed).await.unwrap();
+
+        assert_eq!(opened, b"hello");
+    }
+
+    #[tokio::test]
+    async fn missing_key_is_an_error() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+        let service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+
+        let err = service.encrypt(&aad(user_id), b"hello").await.unwrap_err();
+        assert!(err.to_string().contains("No encryption key"));
+    }
+
+    #[tokio::test]
+    async fn create_key_keeps_an_existing_key() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+        let service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+
+        service.create_key(user_id).await.unwrap();
+        let sealed = service.encrypt(&aad(user_id), b"hello").await.unwrap();
+        service.create_key(user_id).await.unwrap();
+
+        assert_eq!(service.decrypt(&aad(user_id), &sealed).await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_different_key_provider() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+
+        let service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        service.verify_key_provider().await.unwrap();
+        service.create_key(user_id).await.unwrap();
+
+        let wrong = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        assert!(wrong.verify_key_provider().await.is_err());
+        assert!(wrong.encrypt(&aad(user_id), b"hello").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_secretbox_content() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+        let service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        service.create_key(user_id).await.unwrap();
+        let key = service.get_key(user_id, 1).await.unwrap();
+
+        // Bare nonce and box, and the same behind a versioned header
+        let legacy = secretbox_content(&key, &[], b"hello");
+        let versioned = secretbox_content(&key, &envelope::header(Format::Secretbox, 1), b"hello");
+
+        for content in [legacy, versioned] {
+            assert_eq!(EncryptionService::key_version(&content), 1);
+            assert!(!EncryptionService::is_current(&content, 1));
+            assert_eq!(service.decrypt(&aad(user_id), &content).await.unwrap(), b"hello");
+        }
+    }
+
+    #[tokio::test]
+    async fn binds_content_to_user_shard_and_content_type() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+        let other_user_id = create_user(&db).await;
+        let service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        service.create_key(user_id).await.unwrap();
+        service.create_key(other_user_id).await.unwrap();
+
+        let shard_id = Uuid::new_v4();
+        let bound = AssociatedData::new(user_id, shard_id, "note");
+        let sealed = service.encrypt(&bound, b"hello").await.unwrap();
+        assert!(EncryptionService::is_current(&sealed, 1));
+        assert_eq!(service.decrypt(&bound, &sealed).await.unwrap(), b"hello");
+
+        let moved = [
+            AssociatedData::new(user_id, Uuid::new_v4(), "note"),
+            AssociatedData::new(user_id, shard_id, "profile"),
+            AssociatedData::new(other_user_id, shard_id, "note"),
+        ];
+        for aad in moved {
+            assert!(service.decrypt(&aad, &sealed).await.is_err());
+        }
+
+        // Tampering with the header is caught too
+        let mut tampered = sealed.clone();
+        tampered[envelope::HEADER_LEN - 1] ^= 1;
+        assert!(service.decrypt(&bound, &tampered).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rotation_keeps_old_versions_until_retired() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+        let service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        service.create_key(user_id).await.unwrap();
+
+        let old = service.encrypt(&aad(user_id), b"old").await.unwrap();
+        assert_eq!(EncryptionService::key_version(&old), 1);
+
+        assert_eq!(service.rotate_key(user_id).await.unwrap(), 2);
+        let new = service.encrypt(&aad(user_id), b"new").await.unwrap();
+        assert_eq!(EncryptionService::key_version(&new), 2);
+
+        assert_eq!(service.decrypt(&aad(user_id), &old).await.unwrap(), b"old");
+        assert_eq!(service.decrypt(&aad(user_id), &new).await.unwrap(), b"new");
+
+        assert_eq!(service.retire_keys_before(user_id, 2).await.unwrap(), vec![1]);
+        assert!(service.decrypt(&aad(user_id), &old).await.is_err());
+        assert_eq!(service.decrypt(&aad(user_id), &new).await.unwrap(), b"new");
+
+        // A restarted service sees the same versions
+        let restarted = EncryptionService::new(&db, service.key_provider.clone());
+        assert!(restarted.decrypt(&aad(user_id), &old).await.is_err());
+        assert_eq!(restarted.decrypt(&aad(user_id), &new).await.unwrap(), b"new");
+    }
+
+    #[tokio::test]
+    async fn checks_existing_keys_when_no_check_value_is_stored() {
+        let db = database().await;
+        let user_id = create_user(&db).await;
+
+        EncryptionService::new(&db, Arc::new(MasterKey::generate())).create_key(user_id).await.unwrap();
+
+        let wrong = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        assert!(wrong.verify_key_provider().await.is_err());
     }
 }
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::repository::KeyRepository;
+
+/// SQLite repository for wrapped keys
+pub struct SqliteKeyRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteKeyRepository {
+    /// Create a new key repository
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl KeyRepository for SqliteKeyRepository {
+    async fn get_wrapped_key(&self, user_id: Uuid, version: i32) -> Result<Option<Vec<u8>>> {
+        let key = sqlx::query_scalar(
+            r#"
+            SELECT wrapped_key
+            FROM user_keys
+            WHERE user_id = $1
+              AND version = $2
+              AND wrapped_key IS NOT NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn get_current_key(&self, user_id: Uuid) -> Result<Option<(i32, Vec<u8>)>> {
+        let key = sqlx::query_as(
+            r#"
+            SELECT version, wrapped_key
+            FROM user_keys
+            WHERE user_id = $1
+              AND wrapped_key IS NOT NULL
+            ORDER BY version DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn insert_wrapped_key(&self, user_id: Uuid, version: i32, wrapped_key: Vec<u8>) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO user_keys (user_id, version, wrapped_key, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, version) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(version)
+        .bind(wrapped_key)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn retire_keys_before(&self, user_id: Uuid, version: i32) -> Result<Vec<i32>> {
+        let retired = sqlx::query_scalar(
+            r#"
+            UPDATE user_keys
+            SET wrapped_key = NULL, retired_at = $3
+            WHERE user_id = $1
+              AND version < $2
+              AND wrapped_key IS NOT NULL
+            RETURNING version
+            "#,
+        )
+        .bind(user_id)
+        .bind(version)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(retired)
+    }
+
+    async fn any_wrapped_key(&self) -> Result<Option<Vec<u8>>> {
+        let key = sqlx::query_scalar(
+            r#"
+            SELECT wrapped_key
+            FROM user_keys
+            WHERE wrapped_key IS NOT NULL
+            LIMIT 1
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    async fn get_master_key_check(&self) -> Result<Option<Vec<u8>>> {
+        let value = sqlx::query_scalar(
+            r#"
+            SELECT sealed_value
+            FROM master_key_check
+            WHERE id = 1
+            "#,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(value)
+    }
+
+    async fn set_master_key_check(&self, sealed_value: Vec<u8>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO master_key_check (id, sealed_value, created_at)
+            VALUES (1, $1, $2)
+            ON CONFLICT (id) DO UPDATE SET sealed_value = excluded.sealed_value
+            "#,
+        )
+        .bind(sealed_value)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
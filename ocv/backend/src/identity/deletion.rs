@@ -0,0 +1,76 @@
+use anyhow::Result;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::IdentityService;
+use crate::clients::ClientService;
+use crate::consent_manager::models::AccountDeletion;
+use crate::consent_manager::service::ConsentManager;
+use crate::context_management::service::ContextService;
+use crate::encryption::service::EncryptionService;
+
+/// Erases a user's account and everything stored for them
+///
+/// Grants are revoked first so no client reads the context while it goes.
+/// Clients the user registered are disabled rather than deleted, and the
+/// grants other users made to them are revoked in those users' audit logs.
+/// Shards are deleted through the active memory adapter, which stops the
+/// deletion while the account is still usable if the backend is unreachable.
+/// Destroying the data key then makes any copies left behind unreadable.
+pub struct AccountDeletionJob {
+    identity_service: Arc<IdentityService>,
+    consent_manager: Arc<ConsentManager>,
+    client_service: Arc<ClientService>,
+    context_service: Arc<ContextService>,
+    encryption_service: Arc<EncryptionService>,
+}
+
+impl AccountDeletionJob {
+    /// Create a new account deletion job
+    pub fn new(
+        identity_service: Arc<IdentityService>,
+        consent_manager: Arc<ConsentManager>,
+        client_service: Arc<ClientService>,
+        context_service: Arc<ContextService>,
+        encryption_service: Arc<EncryptionService>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            identity_service,
+            consent_manager,
+            client_service,
+            context_service,
+            encryption_service,
+        })
+    }
+
+    /// Delete the user's account once they have confirmed their password and second factor
+    ///
+    /// Returns the anonymised record of the deletion, which is all that is kept.
+    pub async fn delete_account(&self, user_id: Uuid, password: &str, code: Option<&str>) -> Result<AccountDeletion> {
+        self.identity_service.reauthenticate(user_id, password, code).await?;
+
+        let grants_revoked = self.consent_manager.revoke_all_grants(user_id).await?;
+        let clients = self.client_service.disable_clients(user_id).await?;
+        let mut client_grants_revoked = 0;
+        for client_id in &clients {
+            client_grants_revoked += self.consent_manager.revoke_client_grants(client_id).await?;
+        }
+        let shards_deleted = self.context_service.delete_user_shards(user_id).await?;
+        let key_versions = self.encryption_service.destroy_keys(user_id).await?;
+
+        // Sessions, the audit log and the remaining key rows go with the user
+        self.identity_service.delete_user(user_id).await?;
+
+        let deletion = self.consent_manager.record_account_deletion(serde_json::json!({
+            "grants_revoked": grants_revoked,
+            "clients_disabled": clients.len(),
+            "client_grants_revoked": client_grants_revoked,
+            "shards_deleted": shards_deleted,
+            "key_versions_destroyed": key_versions.len()
+        })).await?;
+        log::info!("Deleted an account, recorded as {}", deletion.id);
+
+        Ok(deletion)
+    }
+}
+
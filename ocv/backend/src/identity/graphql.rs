@@ -1,10 +1,16 @@
-use async_graphql::{Context, Object, ID, InputObject};
-use uuid::Uuid;
+use async_graphql::{Context, ErrorExtensions, Object, ID, InputObject};
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
+use uuid::Uuid;
 
-use super::models::{User, CreateUserInput, Credentials, AuthToken};
-use super::service::IdentityService;
+use super::models::{
+    User, CreateUserInput, Credentials, AuthToken, CreatePersonalAccessTokenInput, CreatedPersonalAccessToken,
+    IdentityError, PersonalAccessToken, SecurityEvent, Session, TwoFactorEnrollment,
+};
+use crate::api::auth::{self, account_owner, acting_user};
 use crate::api::AppState;
+use crate::consent_manager::models::AccountDeletion;
+use crate::utils::errors::AppError;
 
 /// GraphQL representation of a user
 #[derive(async_graphql::SimpleObject)]
@@ -15,20 +21,23 @@ pub struct GraphQLUser {
     pub email: String,
     /// Display name
     pub display_name: String,
+    /// Whether the user confirmed they own the email address
+    pub email_verified: bool,
     /// Creation timestamp
-    pub created_at: async_graphql::DateTime,
+    pub created_at: DateTime<Utc>,
     /// Last update timestamp
-    pub updated_at: async_graphql::DateTime,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl From<User> for GraphQLUser {
     fn from(user: User) -> Self {
         Self {
             id: ID(user.id.to_string()),
+            email_verified: user.is_verified(),
             email: user.email,
             display_name: user.display_name,
-            created_at: user.created_at.into(),
-            updated_at: user.updated_at.into(),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
         }
     }
 }
@@ -36,10 +45,12 @@ impl From<User> for GraphQLUser {
 /// GraphQL representation of an auth token
 #[derive(async_graphql::SimpleObject)]
 pub struct GraphQLAuthToken {
-    /// JWT token
+    /// Short-lived JWT access token
     pub token: String,
-    /// Expiration time
-    pub expires_at: async_graphql::DateTime,
+    /// Access token expiration time
+    pub expires_at: DateTime<Utc>,
+    /// Single-use token for `refreshToken`
+    pub refresh_token: String,
     /// User information
     pub user: GraphQLUser,
 }
@@ -48,12 +59,179 @@ impl From<AuthToken> for GraphQLAuthToken {
     fn from(token: AuthToken) -> Self {
         Self {
             token: token.token,
-            expires_at: token.expires_at.into(),
+            expires_at: token.expires_at,
+            refresh_token: token.refresh_token,
             user: GraphQLUser::from(token.user),
         }
     }
 }
 
+/// GraphQL representation of a login session
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLSession {
+    /// Unique identifier
+    pub id: ID,
+    /// User agent of the device that last used the session
+    pub user_agent: Option<String>,
+    /// IP address the session was last used from
+    pub ip_address: Option<String>,
+    /// Login time
+    pub created_at: DateTime<Utc>,
+    /// Last login or refresh
+    pub last_used_at: DateTime<Utc>,
+    /// When the session ends unless refreshed
+    pub expires_at: DateTime<Utc>,
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+impl GraphQLSession {
+    fn new(session: Session, current: &Session) -> Self {
+        Self {
+            id: ID(session.id.to_string()),
+            current: session.id == current.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Two-factor authentication settings of the authenticated user
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLTwoFactorStatus {
+    /// Whether logins ask for a code
+    pub enabled: bool,
+    /// Unused recovery codes
+    pub recovery_codes_left: i64,
+}
+
+/// TOTP secret to add to an authenticator app
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLTwoFactorEnrollment {
+    /// Base32 secret for manual entry
+    pub secret: String,
+    /// `otpauth://` URI to show as a QR code
+    pub provisioning_uri: String,
+}
+
+impl From<TwoFactorEnrollment> for GraphQLTwoFactorEnrollment {
+    fn from(enrollment: TwoFactorEnrollment) -> Self {
+        Self {
+            secret: enrollment.secret,
+            provisioning_uri: enrollment.provisioning_uri,
+        }
+    }
+}
+
+/// GraphQL representation of a security event
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLSecurityEvent {
+    /// Unique identifier
+    pub id: ID,
+    /// Kind of event: `login_succeeded`, `login_failed`, `account_locked`, `password_changed`,
+    /// `personal_access_token_created` or `personal_access_token_revoked`
+    pub event: String,
+    /// Address the request came from
+    pub ip_address: Option<String>,
+    /// `User-Agent` of the request
+    pub user_agent: Option<String>,
+    /// Additional details
+    pub details: async_graphql::Json<serde_json::Value>,
+    /// When it happened
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<SecurityEvent> for GraphQLSecurityEvent {
+    fn from(event: SecurityEvent) -> Self {
+        Self {
+            id: ID(event.id.to_string()),
+            event: event.event,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            details: async_graphql::Json(event.details),
+            created_at: event.created_at,
+        }
+    }
+}
+
+/// GraphQL representation of a personal access token
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLPersonalAccessToken {
+    /// Unique identifier
+    pub id: ID,
+    /// Name the user gave the token
+    pub name: String,
+    /// Scopes the token may use
+    pub scopes: Vec<String>,
+    /// Context domains the token may reach
+    pub domains: Vec<String>,
+    /// When the token stops working
+    pub expires_at: DateTime<Utc>,
+    /// When the token last authenticated a request
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<PersonalAccessToken> for GraphQLPersonalAccessToken {
+    fn from(token: PersonalAccessToken) -> Self {
+        Self {
+            id: ID(token.id.to_string()),
+            name: token.name,
+            scopes: token.scopes,
+            domains: token.domains,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+/// A new personal access token with its secret
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLCreatedPersonalAccessToken {
+    /// The stored token
+    pub personal_access_token: GraphQLPersonalAccessToken,
+    /// Bearer token to send as `Authorization: Bearer <token>`, shown only once
+    pub token: String,
+}
+
+impl From<CreatedPersonalAccessToken> for GraphQLCreatedPersonalAccessToken {
+    fn from(created: CreatedPersonalAccessToken) -> Self {
+        Self {
+            personal_access_token: created.token.into(),
+            token: created.secret,
+        }
+    }
+}
+
+/// Receipt for a deleted account
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLAccountDeletion {
+    /// ID of the anonymised deletion record
+    pub id: ID,
+    /// Access grants that were revoked
+    pub grants_revoked: i64,
+    /// Context shards that were deleted
+    pub shards_deleted: i64,
+    /// When the account was deleted
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl From<AccountDeletion> for GraphQLAccountDeletion {
+    fn from(deletion: AccountDeletion) -> Self {
+        Self {
+            id: ID(deletion.id.to_string()),
+            grants_revoked: deletion.details["grants_revoked"].as_i64().unwrap_or_default(),
+            shards_deleted: deletion.details["shards_deleted"].as_i64().unwrap_or_default(),
+            deleted_at: deletion.deleted_at,
+        }
+    }
+}
+
 /// GraphQL input for creating a user
 #[derive(InputObject)]
 pub struct GraphQLCreateUserInput {
@@ -93,43 +271,110 @@ impl From<GraphQLCredentials> for Credentials {
     }
 }
 
+/// GraphQL input for creating a personal access token
+#[derive(InputObject)]
+pub struct GraphQLCreatePersonalAccessTokenInput {
+    /// Name to recognise the token by
+    pub name: String,
+    /// Scopes the token may use: `read` and/or `write`
+    pub scopes: Vec<String>,
+    /// Context domains the token may reach
+    pub domains: Vec<String>,
+    /// Days until the token expires, 30 by default and at most 365
+    pub expires_in_days: Option<i32>,
+}
+
+impl From<GraphQLCreatePersonalAccessTokenInput> for CreatePersonalAccessTokenInput {
+    fn from(input: GraphQLCreatePersonalAccessTokenInput) -> Self {
+        Self {
+            name: input.name,
+            scopes: input.scopes,
+            domains: input.domains,
+            expires_in_days: input.expires_in_days.map(i64::from),
+        }
+    }
+}
+
 /// Identity query root
 #[derive(Default)]
 pub struct IdentityQuery;
 
 #[Object]
 impl IdentityQuery {
-    /// Get the current user from JWT token
-    async fn me(
-        &self,
-        ctx: &Context<'_>,
-        token: String,
-    ) -> async_graphql::Result<Option<GraphQLUser>> {
+    /// Get the authenticated user
+    async fn me(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<GraphQLUser>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        
-        let user_id = match state.identity_service.validate_token(&token).await? {
-            Some(id) => id,
-            None => return Ok(None),
-        };
+        let user_id = acting_user(ctx, None)?;
         
         let user = state.identity_service.get_user(user_id).await?;
         
         Ok(user.map(GraphQLUser::from))
     }
     
-    /// Get a user by ID
+    /// Get a user by ID, which must be the authenticated user
     async fn user(
         &self,
         ctx: &Context<'_>,
         id: ID,
     ) -> async_graphql::Result<Option<GraphQLUser>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&id.0)?;
+        let user_uuid = acting_user(ctx, Some(&id))?;
         
         let user = state.identity_service.get_user(user_uuid).await?;
         
         Ok(user.map(GraphQLUser::from))
     }
+    
+    /// List the authenticated user's active sessions
+    async fn sessions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GraphQLSession>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        let current = auth::session(ctx)?;
+        
+        let sessions = state.identity_service.sessions(user_id).await?;
+        
+        Ok(sessions.into_iter().map(|session| GraphQLSession::new(session, current)).collect())
+    }
+    
+    /// Two-factor authentication settings of the authenticated user
+    async fn two_factor(&self, ctx: &Context<'_>) -> async_graphql::Result<GraphQLTwoFactorStatus> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        Ok(GraphQLTwoFactorStatus {
+            enabled: state.identity_service.has_two_factor(user_id).await?,
+            recovery_codes_left: state.identity_service.recovery_codes_left(user_id).await?,
+        })
+    }
+    
+    /// Sign-ins, lockouts and password changes of the authenticated user, newest first
+    async fn security_events(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> async_graphql::Result<Vec<GraphQLSecurityEvent>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        let events = state.identity_service.security_events(
+            user_id,
+            limit.map(|l| l as i64),
+            offset.map(|o| o as i64),
+        ).await?;
+        
+        Ok(events.into_iter().map(GraphQLSecurityEvent::from).collect())
+    }
+    
+    /// Personal access tokens of the authenticated user that were not revoked, newest first
+    async fn personal_access_tokens(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GraphQLPersonalAccessToken>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        let tokens = state.identity_service.personal_access_tokens(user_id).await?;
+        
+        Ok(tokens.into_iter().map(GraphQLPersonalAccessToken::from).collect())
+    }
 }
 
 /// Identity mutation root
@@ -146,12 +391,64 @@ impl IdentityMutation {
     ) -> async_graphql::Result<GraphQLUser> {
         let state = ctx.data::<Arc<AppState>>()?;
         
-        let user = state.identity_service.create_user(input.into()).await?;
+        let user = state.identity_service.create_user(input.into()).await.map_err(identity_error)?;
+        
+        Ok(GraphQLUser::from(user))
+    }
+    
+    /// Verify the user's email address with the token from their verification email
+    async fn verify_email(&self, ctx: &Context<'_>, token: String) -> async_graphql::Result<GraphQLUser> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        
+        let user = state.identity_service.verify_email(&token).await.map_err(identity_error)?;
         
         Ok(GraphQLUser::from(user))
     }
     
+    /// Send the authenticated user a new verification email
+    ///
+    /// Returns `false` if their email address is already verified.
+    async fn resend_verification_email(&self, ctx: &Context<'_>) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        Ok(state.identity_service.resend_verification_email(user_id).await?)
+    }
+    
+    /// Email a password reset link to the account with this address
+    ///
+    /// Always returns `true`, whether or not there is such an account.
+    async fn request_password_reset(&self, ctx: &Context<'_>, email: String) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        
+        state.identity_service.request_password_reset(&email).await?;
+        
+        Ok(true)
+    }
+    
+    /// Choose a new password with the token from a password reset email
+    ///
+    /// Each token works once, and all of the user's sessions are ended.
+    async fn reset_password(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+        new_password: String,
+    ) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        
+        state.identity_service
+            .reset_password(&token, &new_password, auth::device(ctx))
+            .await
+            .map_err(identity_error)?;
+        
+        Ok(true)
+    }
+    
     /// Login with email and password
+    ///
+    /// Accounts with two-factor authentication fail with `TWO_FACTOR_REQUIRED`
+    /// and a `challenge` extension to pass to `completeLogin`.
     async fn login(
         &self,
         ctx: &Context<'_>,
@@ -159,23 +456,581 @@ impl IdentityMutation {
     ) -> async_graphql::Result<Option<GraphQLAuthToken>> {
         let state = ctx.data::<Arc<AppState>>()?;
         
-        let token = state.identity_service.authenticate(credentials.into()).await?;
+        let token = state.identity_service
+            .authenticate(credentials.into(), auth::device(ctx))
+            .await
+            .map_err(identity_error)?;
         
         Ok(token.map(GraphQLAuthToken::from))
     }
     
+    /// Finish a login with the challenge from `login` and a TOTP or recovery code
+    async fn complete_login(
+        &self,
+        ctx: &Context<'_>,
+        challenge: String,
+        code: String,
+    ) -> async_graphql::Result<GraphQLAuthToken> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        
+        let token = state.identity_service
+            .complete_login(&challenge, &code, auth::device(ctx))
+            .await
+            .map_err(identity_error)?;
+        
+        Ok(GraphQLAuthToken::from(token))
+    }
+    
+    /// Start enrolling in two-factor authentication
+    ///
+    /// Add the secret to an authenticator app, then send its first code to
+    /// `confirmTwoFactor`.
+    async fn enroll_two_factor(&self, ctx: &Context<'_>) -> async_graphql::Result<GraphQLTwoFactorEnrollment> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        let enrollment = state.identity_service.enroll_two_factor(user_id).await.map_err(identity_error)?;
+        
+        Ok(GraphQLTwoFactorEnrollment::from(enrollment))
+    }
+    
+    /// Turn on two-factor authentication with a code from the authenticator app
+    ///
+    /// Returns recovery codes, which are only shown this once.
+    async fn confirm_two_factor(&self, ctx: &Context<'_>, code: String) -> async_graphql::Result<Vec<String>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        state.identity_service.confirm_two_factor(user_id, &code).await.map_err(identity_error)
+    }
+    
+    /// Replace the recovery codes, given a current TOTP or recovery code
+    async fn regenerate_recovery_codes(&self, ctx: &Context<'_>, code: String) -> async_graphql::Result<Vec<String>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        state.identity_service.regenerate_recovery_codes(user_id, &code).await.map_err(identity_error)
+    }
+    
+    /// Turn off two-factor authentication, given a current TOTP or recovery code
+    async fn disable_two_factor(&self, ctx: &Context<'_>, code: String) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        state.identity_service.disable_two_factor(user_id, &code).await.map_err(identity_error)?;
+        
+        Ok(true)
+    }
+    
+    /// Exchange a refresh token for new access and refresh tokens
+    ///
+    /// Each refresh token works once. Reusing one ends its session.
+    async fn refresh_token(
+        &self,
+        ctx: &Context<'_>,
+        refresh_token: String,
+    ) -> async_graphql::Result<Option<GraphQLAuthToken>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        
+        let token = state.identity_service.refresh(&refresh_token, None, auth::device(ctx)).await?;
+        
+        Ok(token.map(GraphQLAuthToken::from))
+    }
+    
+    /// End the session making the request
+    async fn logout(&self, ctx: &Context<'_>) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = acting_user(ctx, None)?;
+        let session = auth::session(ctx)?;
+        
+        Ok(state.identity_service.revoke_session(user_id, session.id).await?)
+    }
+    
+    /// End all of the authenticated user's sessions, returning how many were active
+    async fn logout_all_sessions(&self, ctx: &Context<'_>) -> async_graphql::Result<u64> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        Ok(state.identity_service.revoke_all_sessions(user_id).await?)
+    }
+    
+    /// End one of the authenticated user's sessions
+    async fn revoke_session(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        let session_id = Uuid::parse_str(&id)?;
+        
+        Ok(state.identity_service.revoke_session(user_id, session_id).await?)
+    }
+    
+    /// Create a personal access token for scripts and tools
+    ///
+    /// The token only reaches the given scopes and domains, and cannot manage
+    /// the account. Its secret is returned only this once.
+    async fn create_personal_access_token(
+        &self,
+        ctx: &Context<'_>,
+        input: GraphQLCreatePersonalAccessTokenInput,
+    ) -> async_graphql::Result<GraphQLCreatedPersonalAccessToken> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        let created = state.identity_service
+            .create_personal_access_token(user_id, input.into(), auth::device(ctx))
+            .await
+            .map_err(identity_error)?;
+        
+        Ok(created.into())
+    }
+    
+    /// Revoke one of the authenticated user's personal access tokens
+    async fn revoke_personal_access_token(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<bool> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        let token_id = Uuid::parse_str(&id)?;
+        
+        Ok(state.identity_service.revoke_personal_access_token(user_id, token_id, auth::device(ctx)).await?)
+    }
+    
     /// Update user profile
     async fn update_profile(
         &self,
         ctx: &Context<'_>,
-        id: ID,
+        id: Option<ID>,
         display_name: Option<String>,
     ) -> async_graphql::Result<Option<GraphQLUser>> {
         let state = ctx.data::<Arc<AppState>>()?;
-        let user_uuid = Uuid::parse_str(&id.0)?;
+        let user_uuid = account_owner(ctx, id.as_ref())?;
         
         let user = state.identity_service.update_user(user_uuid, display_name).await?;
         
         Ok(user.map(GraphQLUser::from))
     }
+    
+    /// Delete the authenticated user's account and everything stored for them
+    ///
+    /// Needs the password, and a TOTP or recovery code when two-factor
+    /// authentication is on. This cannot be undone.
+    async fn delete_account(
+        &self,
+        ctx: &Context<'_>,
+        password: String,
+        code: Option<String>,
+    ) -> async_graphql::Result<GraphQLAccountDeletion> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_id = account_owner(ctx, None)?;
+        
+        let deletion = state.account_deletion
+            .delete_account(user_id, &password, code.as_deref())
+            .await
+            .map_err(identity_error)?;
+        
+        Ok(deletion.into())
+    }
+}
+
+/// Report registration, recovery, two-factor and throttling errors with their GraphQL code
+pub(crate) fn identity_error(err: anyhow::Error) -> async_graphql::Error {
+    match err.downcast_ref::<IdentityError>() {
+        Some(IdentityError::TwoFactorRequired { challenge }) => {
+            async_graphql::Error::new(err.to_string()).extend_with(|_, e| {
+                e.set("code", "TWO_FACTOR_REQUIRED");
+                e.set("challenge", challenge.as_str());
+            })
+        },
+        Some(IdentityError::TooManyAttempts { retry_after }) => {
+            async_graphql::Error::new(err.to_string()).extend_with(|_, e| {
+                e.set("code", "TOO_MANY_REQUESTS");
+                e.set("retryAfter", *retry_after);
+            })
+        },
+        Some(
+            IdentityError::EmailNotVerified
+            | IdentityError::InvalidPassword
+            | IdentityError::InvalidTwoFactorCode
+            | IdentityError::SensitiveDomain(_),
+        ) => AppError::Unauthorized(err.to_string()).extend(),
+        Some(identity_err) => AppError::ValidationError(identity_err.to_string()).extend(),
+        None => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::testing::{error_code, graphql, login, state, state_with_clock, state_with_mailbox, UI_URL};
+    use crate::clients::models::RegisterClientInput;
+    use crate::context_management::models::CreateShardInput;
+    use crate::identity::totp;
+    use crate::mailer::FileMailer;
+    use crate::storage::Database;
+    use crate::utils::clock::{Clock, ManualClock};
+    use chrono::{Duration, TimeZone};
+    use std::collections::HashMap;
+
+    const REGISTER: &str = r#"mutation {
+        register(input: { email: "ada@example.com", displayName: "Ada", password: "correct horse" }) { id emailVerified }
+    }"#;
+
+    const LOGIN: &str = r#"mutation {
+        login(credentials: { email: "ada@example.com", password: "correct horse" }) { token refreshToken }
+    }"#;
+
+    /// Token in the link of the last email sent
+    fn emailed_token(mailbox: &FileMailer, page: &str) -> String {
+        let email = mailbox.sent().unwrap().pop().unwrap();
+        let prefix = format!("{}/{}?token=", UI_URL, page);
+        let link = email.body.lines().find_map(|line| line.strip_prefix(&prefix)).unwrap();
+        link.to_string()
+    }
+
+    #[actix_web::test]
+    async fn verifies_the_email_with_the_emailed_link() {
+        let (state, mailbox) = state_with_mailbox().await;
+
+        let (_, body) = graphql(state.clone(), None, REGISTER).await;
+        assert_eq!(body["data"]["register"]["emailVerified"], false);
+        assert_eq!(mailbox.sent().unwrap()[0].to, "ada@example.com");
+        let token = emailed_token(&mailbox, "verify-email");
+
+        // Unverified users cannot grant access
+        let (_, body) = graphql(state.clone(), None, LOGIN).await;
+        let access_token = body["data"]["login"]["token"].as_str().unwrap().to_string();
+        let grant = r#"mutation { grantAccess(input: { clientId: "jarvis", scopes: ["read"], contextDomains: ["travel"] }) { id } }"#;
+        let (_, body) = graphql(state.clone(), Some(&access_token), grant).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        let verify = format!(r#"mutation {{ verifyEmail(token: "{}") {{ emailVerified }} }}"#, token);
+        let (_, body) = graphql(state.clone(), None, &verify).await;
+        assert_eq!(body["data"]["verifyEmail"]["emailVerified"], true);
+
+        // Links work once
+        let (_, body) = graphql(state.clone(), None, &verify).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+        let (_, body) = graphql(state, Some(&access_token), "mutation { resendVerificationEmail }").await;
+        assert_eq!(body["data"]["resendVerificationEmail"], false);
+    }
+
+    #[actix_web::test]
+    async fn resets_the_password_once_and_ends_sessions() {
+        let (state, mailbox) = state_with_mailbox().await;
+        graphql(state.clone(), None, REGISTER).await;
+        let (_, body) = graphql(state.clone(), None, LOGIN).await;
+        let access_token = body["data"]["login"]["token"].as_str().unwrap().to_string();
+
+        // Unknown addresses get the same answer but no email
+        let request = r#"mutation { requestPasswordReset(email: "nobody@example.com") }"#;
+        let (_, body) = graphql(state.clone(), None, request).await;
+        assert_eq!(body["data"]["requestPasswordReset"], true);
+        assert_eq!(mailbox.sent().unwrap().len(), 1);
+
+        let request = r#"mutation { requestPasswordReset(email: "ada@example.com") }"#;
+        graphql(state.clone(), None, request).await;
+        let token = emailed_token(&mailbox, "reset-password");
+
+        let weak = format!(r#"mutation {{ resetPassword(token: "{}", newPassword: "short") }}"#, token);
+        let (_, body) = graphql(state.clone(), None, &weak).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        let reset = format!(r#"mutation {{ resetPassword(token: "{}", newPassword: "battery staple") }}"#, token);
+        let (_, body) = graphql(state.clone(), None, &reset).await;
+        assert_eq!(body["data"]["resetPassword"], true);
+        let (_, body) = graphql(state.clone(), None, &reset).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        let (status, _) = graphql(state.clone(), Some(&access_token), "{ me { id } }").await;
+        assert_eq!(status, 401);
+        let (_, body) = graphql(state.clone(), None, LOGIN).await;
+        assert!(body["data"]["login"].is_null());
+        let login = r#"mutation {
+            login(credentials: { email: "ada@example.com", password: "battery staple" }) { token }
+        }"#;
+        let (_, body) = graphql(state.clone(), None, login).await;
+        let token = body["data"]["login"]["token"].as_str().unwrap();
+
+        let (_, body) = graphql(state, Some(token), "{ securityEvents { event } }").await;
+        let events: Vec<&str> = body["data"]["securityEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(events[..3], ["login_succeeded", "login_failed", "password_changed"]);
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_registrations() {
+        let (state, mailbox) = state_with_mailbox().await;
+        graphql(state.clone(), None, REGISTER).await;
+
+        for (email, password) in [
+            ("not-an-email", "correct horse"),
+            ("bob@example.com", "short"),
+            (" ada@example.com ", "correct horse"),
+        ] {
+            let query = format!(
+                r#"mutation {{ register(input: {{ email: "{}", displayName: "Bob", password: "{}" }}) {{ id }} }}"#,
+                email, password
+            );
+            let (_, body) = graphql(state.clone(), None, &query).await;
+            assert_eq!(error_code(&body), "VALIDATION_ERROR", "{}", email);
+        }
+        assert_eq!(mailbox.sent().unwrap().len(), 1);
+    }
+
+    /// Turn on two-factor authentication, returning the secret and recovery codes
+    async fn enable_two_factor(state: &Arc<AppState>, token: &str, now: DateTime<Utc>) -> (Vec<u8>, Vec<String>) {
+        let (_, body) = graphql(state.clone(), Some(token), "mutation { enrollTwoFactor { secret provisioningUri } }").await;
+        let enrollment = &body["data"]["enrollTwoFactor"];
+        assert!(enrollment["provisioningUri"].as_str().unwrap().starts_with("otpauth://totp/"));
+        let secret = totp_rs::Secret::Encoded(enrollment["secret"].as_str().unwrap().to_string()).to_bytes().unwrap();
+
+        let confirm = format!(r#"mutation {{ confirmTwoFactor(code: "{}") }}"#, totp::code_at(&secret, now));
+        let (_, body) = graphql(state.clone(), Some(token), &confirm).await;
+        (secret, serde_json::from_value(body["data"]["confirmTwoFactor"].clone()).unwrap())
+    }
+
+    /// Log in with a password, returning the challenge for the second step
+    async fn login_challenge(state: &Arc<AppState>) -> String {
+        let (_, body) = graphql(state.clone(), None, LOGIN).await;
+        assert_eq!(error_code(&body), "TWO_FACTOR_REQUIRED");
+        assert!(body["data"]["login"].is_null());
+        body["errors"][0]["extensions"]["challenge"].as_str().unwrap().to_string()
+    }
+
+    fn complete_login(challenge: &str, code: &str) -> String {
+        format!(r#"mutation {{ completeLogin(challenge: "{}", code: "{}") {{ token }} }}"#, challenge, code)
+    }
+
+    #[actix_web::test]
+    async fn logins_ask_for_a_second_factor() {
+        // Confirm the code a second before a new time step starts
+        let step = totp::STEP as i64;
+        let start = Utc.timestamp_opt((Utc::now().timestamp() / step + 1) * step - 1, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let state = state_with_clock(clock.clone()).await;
+        let (_, token) = login(&state, "ada").await;
+        let (secret, codes) = enable_two_factor(&state, &token, clock.now()).await;
+        assert_eq!(codes.len(), totp::RECOVERY_CODE_COUNT);
+
+        let (_, body) = graphql(state.clone(), Some(&token), "{ twoFactor { enabled recoveryCodesLeft } }").await;
+        assert_eq!(body["data"]["twoFactor"], serde_json::json!({ "enabled": true, "recoveryCodesLeft": 10 }));
+        let (_, body) = graphql(state.clone(), Some(&token), "mutation { enrollTwoFactor { secret } }").await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        // The code that enabled two-factor authentication cannot be replayed, even in the next step
+        clock.advance(Duration::seconds(2));
+        let challenge = login_challenge(&state).await;
+        let replayed = totp::code_at(&secret, start);
+        let (_, body) = graphql(state.clone(), None, &complete_login(&challenge, &replayed)).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let next = totp::code_at(&secret, clock.now());
+        let (_, body) = graphql(state.clone(), None, &complete_login(&challenge, &next)).await;
+        assert!(body["data"]["completeLogin"]["token"].is_string());
+
+        // Challenges and recovery codes work once
+        let (_, body) = graphql(state.clone(), None, &complete_login(&challenge, &codes[0])).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+        let challenge = login_challenge(&state).await;
+        let (_, body) = graphql(state.clone(), None, &complete_login(&challenge, &codes[0].to_uppercase())).await;
+        assert!(body["data"]["completeLogin"]["token"].is_string());
+        let challenge = login_challenge(&state).await;
+        let (_, body) = graphql(state.clone(), None, &complete_login(&challenge, &codes[0])).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        // Challenges expire on the vault's clock
+        let challenge = login_challenge(&state).await;
+        clock.advance(Duration::minutes(5));
+        let (_, body) = graphql(state.clone(), None, &complete_login(&challenge, &codes[2])).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+
+        let disable = r#"mutation { disableTwoFactor(code: "wrong-guess") }"#;
+        let (_, body) = graphql(state.clone(), Some(&token), disable).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let disable = format!(r#"mutation {{ disableTwoFactor(code: "{}") }}"#, codes[1]);
+        let (_, body) = graphql(state.clone(), Some(&token), &disable).await;
+        assert_eq!(body["data"]["disableTwoFactor"], true);
+        let (_, body) = graphql(state, None, LOGIN).await;
+        assert!(body["data"]["login"]["token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn sensitive_domains_need_two_factor() {
+        let state = state().await;
+        state.policy_engine.require_two_factor_for(["health".to_string()]);
+        let client = state.client_service
+            .register(None, RegisterClientInput {
+                name: "Jarvis".to_string(),
+                logo_uri: None,
+                homepage_uri: None,
+                redirect_uris: vec!["https://jarvis.example.com/callback".to_string()],
+                token_endpoint_auth_method: "none".to_string(),
+                jwks: None,
+                allowed_scopes: vec!["read".to_string()],
+                allowed_domains: vec!["health".to_string(), "travel".to_string()],
+            })
+            .await
+            .unwrap()
+            .client;
+        let (_, token) = login(&state, "ada").await;
+
+        let grant = |domain: &str| {
+            format!(
+                r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: ["read"], contextDomains: ["{}"] }}) {{ id }} }}"#,
+                client.client_id, domain
+            )
+        };
+        let (_, body) = graphql(state.clone(), Some(&token), &grant("travel")).await;
+        assert!(body["data"]["grantAccess"]["id"].is_string());
+        let (_, body) = graphql(state.clone(), Some(&token), &grant("health")).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        enable_two_factor(&state, &token, Utc::now()).await;
+        let (_, body) = graphql(state, Some(&token), &grant("health")).await;
+        assert!(body["data"]["grantAccess"]["id"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn locked_accounts_say_when_to_retry() {
+        let state = state().await;
+        let (_, token) = login(&state, "ada").await;
+
+        let wrong = r#"mutation {
+            login(credentials: { email: "ada@example.com", password: "wrong horse" }) { token }
+        }"#;
+        for _ in 0..5 {
+            let (_, body) = graphql(state.clone(), None, wrong).await;
+            assert!(body["data"]["login"].is_null());
+        }
+        let (_, body) = graphql(state.clone(), None, LOGIN).await;
+        assert_eq!(error_code(&body), "TOO_MANY_REQUESTS");
+        assert_eq!(body["errors"][0]["extensions"]["retryAfter"], 900);
+
+        let (_, body) = graphql(state, Some(&token), "{ securityEvents(limit: 2) { event details } }").await;
+        let events = body["data"]["securityEvents"].as_array().unwrap();
+        assert_eq!(events[0]["event"], "account_locked");
+        assert_eq!(events[0]["details"]["failures"], 5);
+        assert_eq!(events[1]["event"], "login_failed");
+    }
+
+    #[actix_web::test]
+    async fn deleting_a_developer_account_revokes_grants_to_their_clients() {
+        let state = state().await;
+        let (ada, ada_token) = login(&state, "ada").await;
+        let (bob, bob_token) = login(&state, "bob").await;
+        let client = state.client_service
+            .register(Some(ada), RegisterClientInput {
+                name: "Jarvis".to_string(),
+                logo_uri: None,
+                homepage_uri: None,
+                redirect_uris: vec!["https://jarvis.example.com/callback".to_string()],
+                token_endpoint_auth_method: "none".to_string(),
+                jwks: None,
+                allowed_scopes: vec!["read".to_string()],
+                allowed_domains: vec!["travel".to_string()],
+            })
+            .await
+            .unwrap()
+            .client;
+        let grant = format!(
+            r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: ["read"], contextDomains: ["travel"] }}) {{ id }} }}"#,
+            client.client_id
+        );
+        let (_, body) = graphql(state.clone(), Some(&bob_token), &grant).await;
+        let grant_id = body["data"]["grantAccess"]["id"].clone();
+
+        let (_, body) = graphql(state.clone(), Some(&ada_token), r#"mutation { deleteAccount(password: "correct horse") { id } }"#).await;
+        assert!(body["data"]["deleteAccount"]["id"].is_string());
+
+        // The client is kept without an owner, but can no longer be used
+        let kept = state.client_service.get_client(&client.client_id).await.unwrap().unwrap();
+        assert_eq!(kept.owner_id, None);
+        assert!(!kept.enabled);
+
+        // Bob's grant is revoked, and his audit log says why
+        assert!(state.consent_manager.get_active_grants(bob).await.unwrap().is_empty());
+        let (_, body) = graphql(state, Some(&bob_token), "{ auditLogs(limit: 1) { action details } }").await;
+        let log = &body["data"]["auditLogs"][0];
+        assert_eq!(log["action"], "revoke");
+        assert_eq!(log["details"]["grant_id"], grant_id);
+        assert_eq!(log["details"]["reason"], "client_owner_deleted");
+    }
+
+    #[actix_web::test]
+    async fn deleting_an_account_erases_it() {
+        let state = state().await;
+        let client = state.client_service
+            .register(None, RegisterClientInput {
+                name: "Jarvis".to_string(),
+                logo_uri: None,
+                homepage_uri: None,
+                redirect_uris: vec!["https://jarvis.example.com/callback".to_string()],
+                token_endpoint_auth_method: "none".to_string(),
+                jwks: None,
+                allowed_scopes: vec!["read".to_string()],
+                allowed_domains: vec!["travel".to_string()],
+            })
+            .await
+            .unwrap()
+            .client;
+        let (user_id, token) = login(&state, "ada").await;
+        let (_, codes) = enable_two_factor(&state, &token, Utc::now()).await;
+
+        let shard = state.context_service
+            .create_shard(CreateShardInput {
+                user_id,
+                domain: "travel".to_string(),
+                content_type: "note".to_string(),
+                vector_representation: None,
+                metadata: HashMap::new(),
+                content: serde_json::json!({ "text": "Passport number" }),
+            })
+            .await
+            .unwrap();
+        let grant = format!(
+            r#"mutation {{ grantAccess(input: {{ clientId: "{}", scopes: ["read"], contextDomains: ["travel"] }}) {{ id }} }}"#,
+            client.client_id
+        );
+        let (_, body) = graphql(state.clone(), Some(&token), &grant).await;
+        assert!(body["data"]["grantAccess"]["id"].is_string());
+
+        // Both the password and a second factor are needed
+        let delete = |password: &str, code: Option<&str>| {
+            let code = code.map(|code| format!(r#", code: "{}""#, code)).unwrap_or_default();
+            format!(
+                r#"mutation {{ deleteAccount(password: "{}"{}) {{ id grantsRevoked shardsDeleted }} }}"#,
+                password, code
+            )
+        };
+        let (_, body) = graphql(state.clone(), Some(&token), &delete("wrong horse", Some(&codes[0]))).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let (_, body) = graphql(state.clone(), Some(&token), &delete("correct horse", None)).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        assert!(state.identity_service.get_user(user_id).await.unwrap().is_some());
+
+        let (_, body) = graphql(state.clone(), Some(&token), &delete("correct horse", Some(&codes[0]))).await;
+        let deletion = &body["data"]["deleteAccount"];
+        assert_eq!(deletion["grantsRevoked"], 1);
+        assert_eq!(deletion["shardsDeleted"], 1);
+
+        assert!(state.identity_service.get_user(user_id).await.unwrap().is_none());
+        assert!(state.context_service.get_shard(shard.id).await.unwrap().is_none());
+        let (_, body) = graphql(state.clone(), Some(&token), "{ twoFactor { enabled } }").await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        // A copy of the shard, as kept in a backup, can no longer be decrypted
+        assert!(state.encryption_service.decrypt(&shard.associated_data(), &shard.content).await.is_err());
+
+        // Only the anonymised record is left
+        let Database::Sqlite(pool) = &state.db else {
+            unreachable!("test state uses SQLite");
+        };
+        let details: serde_json::Value = sqlx::query_scalar("SELECT details FROM account_deletions WHERE id = $1")
+            .bind(Uuid::parse_str(deletion["id"].as_str().unwrap()).unwrap())
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(details["key_versions_destroyed"], 1);
+        assert!(!details.to_string().contains(&user_id.to_string()));
+    }
 }
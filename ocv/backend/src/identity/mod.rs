@@ -1,8 +1,15 @@
+pub mod deletion;
 pub mod models;
 pub mod repository;
 pub mod service;
+pub mod signing;
+pub mod sqlite;
+pub mod throttle;
+pub mod totp;
 pub mod graphql;
 
 // Re-export key types
-pub use models::{User, CreateUserInput};
+pub use models::{User, CreateUserInput, Principal, Session, DeviceInfo};
+pub use deletion::AccountDeletionJob;
 pub use service::IdentityService;
+pub use signing::{JwtConfig, JwtKeys};
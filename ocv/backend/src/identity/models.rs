@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
 /// Represents a user in the system
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     /// Unique identifier
     pub id: Uuid,
@@ -14,6 +15,9 @@ pub struct User {
     /// Display name
     pub display_name: String,
     
+    /// When the user confirmed they own the email address
+    pub email_verified_at: Option<DateTime<Utc>>,
+    
     /// Creation timestamp
     pub created_at: DateTime<Utc>,
     
@@ -21,6 +25,13 @@ pub struct User {
     pub updated_at: DateTime<Utc>,
 }
 
+impl User {
+    /// Whether the user confirmed they own the email address
+    pub fn is_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+}
+
 /// Input for creating a new user
 #[derive(Debug, Clone, Deserialize)]
 pub struct CreateUserInput {
@@ -47,12 +58,337 @@ pub struct Credentials {
 /// Authentication token
 #[derive(Debug, Clone, Serialize)]
 pub struct AuthToken {
-    /// JWT token
+    /// Short-lived JWT access token
     pub token: String,
     
-    /// Token expiration time
+    /// Access token expiration time
     pub expires_at: DateTime<Utc>,
     
+    /// Single-use token that renews the session
+    pub refresh_token: String,
+    
+    /// Session the tokens belong to
+    pub session_id: Uuid,
+    
     /// User information
     pub user: User,
 }
+
+/// Who an authenticated request acts as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// A user acting for themselves
+    User(Uuid),
+    /// A client application acting on behalf of a user
+    Client {
+        /// Client/application ID
+        client_id: String,
+        /// User the client acts for
+        user_id: Uuid,
+    },
+    /// A script or tool holding one of the user's personal access tokens
+    Token {
+        /// Personal access token ID
+        token_id: Uuid,
+        /// User the token belongs to
+        user_id: Uuid,
+        /// Scopes the token may use
+        scopes: Vec<String>,
+        /// Context domains the token may reach
+        domains: Vec<String>,
+    },
+}
+
+impl Principal {
+    /// Whether a client is acting on the user's behalf
+    pub fn is_client(&self) -> bool {
+        matches!(self, Self::Client { .. })
+    }
+    
+    /// Whether access is limited to some scopes and domains, rather than the user's own
+    pub fn is_restricted(&self) -> bool {
+        !matches!(self, Self::User(_))
+    }
+    
+    /// User whose data the request acts on
+    pub fn user_id(&self) -> Uuid {
+        match self {
+            Self::User(user_id) => *user_id,
+            Self::Client { user_id, .. } => *user_id,
+            Self::Token { user_id, .. } => *user_id,
+        }
+    }
+    
+    /// Client acting on the user's behalf, as authenticated by its token
+    pub fn client_id(&self) -> Option<&str> {
+        match self {
+            Self::Client { client_id, .. } => Some(client_id),
+            Self::User(_) | Self::Token { .. } => None,
+        }
+    }
+}
+
+/// A login session, renewed with a refresh token
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Session {
+    /// Unique identifier, carried in access tokens as `sid`
+    pub id: Uuid,
+    
+    /// User the session belongs to
+    pub user_id: Uuid,
+    
+    /// OAuth client the session was issued to, if any
+    pub client_id: Option<String>,
+    
+    /// Access grant a client session acts under
+    pub grant_id: Option<Uuid>,
+    
+    /// User agent of the device that last used the session
+    pub user_agent: Option<String>,
+    
+    /// IP address the session was last used from
+    pub ip_address: Option<String>,
+    
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    
+    /// Last login or refresh
+    pub last_used_at: DateTime<Utc>,
+    
+    /// When the session ends unless refreshed
+    pub expires_at: DateTime<Utc>,
+    
+    /// When the session was logged out or revoked
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// Whether the session can still be used at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+}
+
+/// Device a request came from
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    /// `User-Agent` header
+    pub user_agent: Option<String>,
+    
+    /// Client IP address
+    pub ip_address: Option<String>,
+}
+
+/// Principal and session behind a valid access token
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    /// Who the request acts as
+    pub principal: Principal,
+    
+    /// Session the token was issued for; personal access tokens have none
+    pub session: Option<Session>,
+}
+
+/// A personal access token, limited to some scopes and domains of its user's context
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PersonalAccessToken {
+    /// Unique identifier
+    pub id: Uuid,
+    
+    /// User the token acts for
+    pub user_id: Uuid,
+    
+    /// Name the user gave the token
+    pub name: String,
+    
+    /// Scopes the token may use, such as `read`
+    pub scopes: Vec<String>,
+    
+    /// Context domains the token may reach
+    pub domains: Vec<String>,
+    
+    /// When the token stops working
+    pub expires_at: DateTime<Utc>,
+    
+    /// When the token last authenticated a request
+    pub last_used_at: Option<DateTime<Utc>>,
+    
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    
+    /// When the user revoked the token
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl PersonalAccessToken {
+    /// Whether the token can still be used at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+    
+    /// Principal requests made with the token act as
+    pub fn principal(&self) -> Principal {
+        Principal::Token {
+            token_id: self.id,
+            user_id: self.user_id,
+            scopes: self.scopes.clone(),
+            domains: self.domains.clone(),
+        }
+    }
+}
+
+/// Input for creating a personal access token
+#[derive(Debug, Clone)]
+pub struct CreatePersonalAccessTokenInput {
+    /// Name to recognise the token by
+    pub name: String,
+    
+    /// Scopes the token may use
+    pub scopes: Vec<String>,
+    
+    /// Context domains the token may reach
+    pub domains: Vec<String>,
+    
+    /// Days until the token expires
+    pub expires_in_days: Option<i64>,
+}
+
+/// A newly created personal access token, with the only copy of its secret
+#[derive(Debug, Clone)]
+pub struct CreatedPersonalAccessToken {
+    /// The stored token
+    pub token: PersonalAccessToken,
+    
+    /// Bearer token to send, shown only once
+    pub secret: String,
+}
+
+/// What an emailed token lets its holder do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    /// Confirm the account's email address
+    VerifyEmail,
+    /// Choose a new password
+    ResetPassword,
+}
+
+impl EmailTokenPurpose {
+    /// Name stored with the token
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::VerifyEmail => "verify_email",
+            Self::ResetPassword => "reset_password",
+        }
+    }
+}
+
+/// A user's TOTP secret
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TotpCredential {
+    /// Owner of the secret
+    pub user_id: Uuid,
+    
+    /// Secret sealed with the user's data key
+    pub secret: Vec<u8>,
+    
+    /// When the user confirmed their first code; until then logins do not ask for one
+    pub enabled_at: Option<DateTime<Utc>>,
+    
+    /// Time step of the last accepted code
+    pub last_used_step: Option<i64>,
+    
+    /// Enrolment timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// Recent failed logins of an account or address
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LoginAttempts {
+    /// Failures in a row, each within the failure window of the one before
+    pub failures: i32,
+    
+    /// Time of the latest failure
+    pub last_failure_at: DateTime<Utc>,
+    
+    /// End of the current lockout, if any
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+/// Sign-in, lockout or credential change on a user's account
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SecurityEvent {
+    /// Unique identifier
+    pub id: Uuid,
+    
+    /// Account the event happened to
+    pub user_id: Uuid,
+    
+    /// Kind of event, such as `login_succeeded` or `password_changed`
+    pub event: String,
+    
+    /// Address the request came from
+    pub ip_address: Option<String>,
+    
+    /// `User-Agent` of the request
+    pub user_agent: Option<String>,
+    
+    /// Additional details about the event
+    pub details: serde_json::Value,
+    
+    /// When it happened
+    pub created_at: DateTime<Utc>,
+}
+
+/// A TOTP secret to add to an authenticator app
+#[derive(Debug, Clone)]
+pub struct TwoFactorEnrollment {
+    /// Base32 secret for manual entry
+    pub secret: String,
+    
+    /// `otpauth://` URI to show as a QR code
+    pub provisioning_uri: String,
+}
+
+/// Errors in account registration, recovery and two-factor authentication
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("{0} is not a valid email address")]
+    InvalidEmail(String),
+
+    #[error("Passwords must be at least {0} characters long")]
+    WeakPassword(usize),
+
+    #[error("An account with this email address already exists")]
+    EmailTaken,
+
+    #[error("The password is incorrect")]
+    InvalidPassword,
+
+    #[error("Too many failed logins; try again in {retry_after} seconds")]
+    TooManyAttempts { retry_after: i64 },
+
+    #[error("The link is invalid or has expired")]
+    InvalidToken,
+
+    #[error("Verify your email address before granting access")]
+    EmailNotVerified,
+
+    /// The password was right; the login completes with a code and this challenge
+    #[error("A two-factor authentication code is required")]
+    TwoFactorRequired { challenge: String },
+
+    #[error("The authentication code is invalid")]
+    InvalidTwoFactorCode,
+
+    #[error("Two-factor authentication is already enabled")]
+    TwoFactorAlreadyEnabled,
+
+    #[error("Two-factor authentication is not enabled")]
+    TwoFactorNotEnabled,
+
+    #[error("Enable two-factor authentication before granting access to {0}")]
+    SensitiveDomain(String),
+
+    #[error("Invalid personal access token: {0}")]
+    InvalidPersonalAccessToken(String),
+}
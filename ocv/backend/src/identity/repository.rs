@@ -1,4 +1,6 @@
 use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 use argon2::{
@@ -9,40 +11,264 @@ use argon2::{
     Argon2
 };
 
-use super::models::{User, CreateUserInput, Credentials};
+use super::models::{
+    User, CreateUserInput, Credentials, CreatePersonalAccessTokenInput, DeviceInfo, EmailTokenPurpose, LoginAttempts,
+    PersonalAccessToken, SecurityEvent, Session, TotpCredential,
+};
+
+/// Row type used when the password hash is needed for verification
+#[derive(sqlx::FromRow)]
+pub(crate) struct UserWithPassword {
+    id: Uuid,
+    email: String,
+    display_name: String,
+    email_verified_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    password_hash: String,
+}
+
+impl UserWithPassword {
+    /// Return the user if the password matches the stored hash
+    pub(crate) fn verify(self, password: &str) -> Result<Option<User>> {
+        let parsed_hash = PasswordHash::new(&self.password_hash)
+            .map_err(|e| anyhow::anyhow!("Invalid password hash: {}", e))?;
+        let password_matches = Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok();
+        
+        if password_matches {
+            Ok(Some(User {
+                id: self.id,
+                email: self.email,
+                display_name: self.display_name,
+                email_verified_at: self.email_verified_at,
+                created_at: self.created_at,
+                updated_at: self.updated_at,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Hash a password for storage
+pub(crate) fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = Argon2::default();
+    let password_hash = argon2.hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?
+        .to_string();
+    
+    Ok(password_hash)
+}
+
+/// Storage for user accounts
+#[async_trait]
+pub trait IdentityRepository: Send + Sync {
+    /// Create a new user
+    async fn create_user(&self, input: CreateUserInput) -> Result<User>;
+    
+    /// Get a user by ID
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>>;
+    
+    /// Get a user by email
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>>;
+    
+    /// Authenticate a user with credentials
+    async fn authenticate(&self, credentials: Credentials) -> Result<Option<User>>;
+    
+    /// Update a user's profile
+    async fn update_user(&self, id: Uuid, display_name: Option<String>) -> Result<Option<User>>;
+    
+    /// Delete a user along with everything that references them
+    async fn delete_user(&self, id: Uuid) -> Result<bool>;
+    
+    /// Record that the user owns their email address
+    async fn mark_email_verified(&self, id: Uuid) -> Result<Option<User>>;
+    
+    /// Replace a user's password, returning whether the user exists
+    async fn set_password(&self, id: Uuid, password: &str) -> Result<bool>;
+    
+    /// Store the hash of an emailed token, replacing the user's earlier ones for the same purpose
+    async fn create_email_token(
+        &self,
+        user_id: Uuid,
+        purpose: EmailTokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()>;
+    
+    /// Use up an emailed token unexpired at `now`, returning the user it was sent to
+    async fn consume_email_token(&self, token_hash: &str, purpose: EmailTokenPurpose, now: DateTime<Utc>) -> Result<Option<Uuid>>;
+    
+    /// Start a session holding the hash of its first refresh token
+    ///
+    /// Sessions for an OAuth client name the client and the grant it acts under.
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        client: Option<(&str, Uuid)>,
+        refresh_token_hash: &str,
+        device: &DeviceInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session>;
+    
+    /// Get a session by ID
+    async fn get_session(&self, id: Uuid) -> Result<Option<Session>>;
+    
+    /// Swap the refresh token hash of a session active at `now` that still holds `current_hash`
+    ///
+    /// Returns `None` if the session is revoked, expired, or was already
+    /// refreshed with `current_hash`.
+    /// A client session's new expiry is capped at its grant's.
+    async fn rotate_session(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+        device: &DeviceInfo,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<Session>>;
+    
+    /// List a user's sessions active at `now`, most recently used first
+    async fn list_sessions(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<Vec<Session>>;
+    
+    /// Revoke one of a user's active sessions, returning whether it was active
+    async fn revoke_session(&self, user_id: Uuid, id: Uuid) -> Result<bool>;
+    
+    /// Revoke all of a user's active sessions, returning how many there were
+    async fn revoke_sessions(&self, user_id: Uuid) -> Result<u64>;
+    
+    /// Get a user's TOTP secret, whether or not it is enabled yet
+    async fn get_totp(&self, user_id: Uuid) -> Result<Option<TotpCredential>>;
+    
+    /// Store a sealed TOTP secret awaiting its first code, replacing an earlier pending one
+    ///
+    /// Returns `false` and stores nothing if two-factor authentication is already enabled.
+    async fn set_pending_totp(&self, user_id: Uuid, secret: &[u8]) -> Result<bool>;
+    
+    /// Replace the sealed secret if it still equals `current`
+    async fn reseal_totp(&self, user_id: Uuid, current: &[u8], secret: &[u8]) -> Result<bool>;
+    
+    /// Enable a pending TOTP secret, recording the confirmed code's step and the recovery codes
+    async fn enable_totp(&self, user_id: Uuid, step: i64, recovery_code_hashes: &[String]) -> Result<bool>;
+    
+    /// Accept a code's time step if it is later than the last accepted one
+    async fn use_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool>;
+    
+    /// Remove a user's TOTP secret and recovery codes
+    async fn delete_totp(&self, user_id: Uuid) -> Result<bool>;
+    
+    /// Replace a user's recovery codes
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()>;
+    
+    /// Use up one of a user's recovery codes
+    async fn consume_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool>;
+    
+    /// Count a user's unused recovery codes
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64>;
+    
+    /// Store the hash of a login challenge awaiting a second factor, dropping ones expired at `now`
+    async fn create_login_challenge(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()>;
+    
+    /// Count an attempt at a login challenge unexpired at `now`
+    ///
+    /// Returns the user logging in, or `None` once `max_attempts` were made.
+    async fn attempt_login_challenge(&self, token_hash: &str, max_attempts: i32, now: DateTime<Utc>) -> Result<Option<Uuid>>;
+    
+    /// Remove an answered login challenge
+    async fn delete_login_challenge(&self, token_hash: &str) -> Result<()>;
+    
+    /// Get the recent failed logins of an account or address
+    async fn get_login_attempts(&self, subject_hash: &str) -> Result<Option<LoginAttempts>>;
+    
+    /// Count a failed login at `now`, starting over if the previous one came before `window_start`
+    ///
+    /// Forgets other subjects whose failures and lockouts are over.
+    async fn record_login_failure(
+        &self,
+        subject_hash: &str,
+        now: DateTime<Utc>,
+        window_start: DateTime<Utc>,
+    ) -> Result<LoginAttempts>;
+    
+    /// Lock an account or address out until `locked_until`
+    async fn lock_login(&self, subject_hash: &str, locked_until: DateTime<Utc>) -> Result<()>;
+    
+    /// Forget the failed logins of an account or address
+    async fn clear_login_failures(&self, subject_hash: &str) -> Result<()>;
+    
+    /// Record a security event on a user's account
+    async fn create_security_event(
+        &self,
+        user_id: Uuid,
+        event: &str,
+        device: &DeviceInfo,
+        details: serde_json::Value,
+        created_at: DateTime<Utc>,
+    ) -> Result<SecurityEvent>;
+    
+    /// List a user's security events, newest first
+    async fn list_security_events(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<SecurityEvent>>;
+    
+    /// Store a personal access token by the hash of its secret
+    async fn create_personal_access_token(
+        &self,
+        user_id: Uuid,
+        input: &CreatePersonalAccessTokenInput,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PersonalAccessToken>;
+    
+    /// Record a use of the active personal access token with this hash, returning the token
+    async fn use_personal_access_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<PersonalAccessToken>>;
+    
+    /// List a user's personal access tokens that were not revoked, newest first
+    async fn list_personal_access_tokens(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>>;
+    
+    /// Revoke one of a user's personal access tokens, returning whether it was not revoked yet
+    async fn revoke_personal_access_token(&self, user_id: Uuid, id: Uuid, now: DateTime<Utc>) -> Result<bool>;
+}
 
-/// Repository for user-related operations
-pub struct IdentityRepository {
+/// Postgres repository for user-related operations
+pub struct PgIdentityRepository {
     pool: PgPool,
 }
 
-impl IdentityRepository {
+impl PgIdentityRepository {
     /// Create a new identity repository
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
-    
+}
+
+#[async_trait]
+impl IdentityRepository for PgIdentityRepository {
     /// Create a new user
-    pub async fn create_user(&self, input: CreateUserInput) -> Result<User> {
+    async fn create_user(&self, input: CreateUserInput) -> Result<User> {
         // Hash the password
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2.hash_password(input.password.as_bytes(), &salt)?
-            .to_string();
+        let password_hash = hash_password(&input.password)?;
         
         // Insert the user
-        let user = sqlx::query_as!(
-            User,
+        let user = sqlx::query_as::<_, User>(
             r#"
             INSERT INTO users (email, display_name, password_hash)
             VALUES ($1, $2, $3)
             RETURNING 
-                id, email, display_name, created_at, updated_at
+                id, email, display_name, email_verified_at, created_at, updated_at
             "#,
-            input.email,
-            input.display_name,
-            password_hash,
         )
+        .bind(input.email)
+        .bind(input.display_name)
+        .bind(password_hash)
         .fetch_one(&self.pool)
         .await?;
         
@@ -50,17 +276,16 @@ impl IdentityRepository {
     }
     
     /// Get a user by ID
-    pub async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
-        let user = sqlx::query_as!(
-            User,
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
             r#"
             SELECT 
-                id, email, display_name, created_at, updated_at
+                id, email, display_name, email_verified_at, created_at, updated_at
             FROM users
             WHERE id = $1
             "#,
-            id
         )
+        .bind(id)
         .fetch_optional(&self.pool)
         .await?;
         
@@ -68,17 +293,16 @@ impl IdentityRepository {
     }
     
     /// Get a user by email
-    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
-        let user = sqlx::query_as!(
-            User,
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
             r#"
             SELECT 
-                id, email, display_name, created_at, updated_at
+                id, email, display_name, email_verified_at, created_at, updated_at
             FROM users
             WHERE email = $1
             "#,
-            email
         )
+        .bind(email)
         .fetch_optional(&self.pool)
         .await?;
         
@@ -86,17 +310,17 @@ impl IdentityRepository {
     }
     
     /// Authenticate a user with credentials
-    pub async fn authenticate(&self, credentials: Credentials) -> Result<Option<User>> {
+    async fn authenticate(&self, credentials: Credentials) -> Result<Option<User>> {
         // Get the user and password hash
-        let result = sqlx::query!(
+        let result = sqlx::query_as::<_, UserWithPassword>(
             r#"
             SELECT 
-                id, email, display_name, created_at, updated_at, password_hash
+                id, email, display_name, email_verified_at, created_at, updated_at, password_hash
             FROM users
             WHERE email = $1
             "#,
-            credentials.email
         )
+        .bind(&credentials.email)
         .fetch_optional(&self.pool)
         .await?;
         
@@ -105,32 +329,16 @@ impl IdentityRepository {
         };
         
         // Verify the password
-        let parsed_hash = PasswordHash::new(&user_data.password_hash)?;
-        let password_matches = Argon2::default()
-            .verify_password(credentials.password.as_bytes(), &parsed_hash)
-            .is_ok();
-        
-        if password_matches {
-            Ok(Some(User {
-                id: user_data.id,
-                email: user_data.email,
-                display_name: user_data.display_name,
-                created_at: user_data.created_at,
-                updated_at: user_data.updated_at,
-            }))
-        } else {
-            Ok(None)
-        }
+        user_data.verify(&credentials.password)
     }
     
     /// Update a user's profile
-    pub async fn update_user(
+    async fn update_user(
         &self,
         id: Uuid,
         display_name: Option<String>,
     ) -> Result<Option<User>> {
-        let user = sqlx::query_as!(
-            User,
+        let user = sqlx::query_as::<_, User>(
             r#"
             UPDATE users
             SET 
@@ -138,14 +346,687 @@ impl IdentityRepository {
                 updated_at = NOW()
             WHERE id = $1
             RETURNING 
-                id, email, display_name, created_at, updated_at
+                id, email, display_name, email_verified_at, created_at, updated_at
             "#,
-            id,
-            display_name,
         )
+        .bind(id)
+        .bind(display_name)
         .fetch_optional(&self.pool)
         .await?;
         
         Ok(user)
     }
+    
+    /// Delete a user along with everything that references them
+    async fn delete_user(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Record that the user owns their email address
+    async fn mark_email_verified(&self, id: Uuid) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET
+                email_verified_at = COALESCE(email_verified_at, NOW()),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING 
+                id, email, display_name, email_verified_at, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(user)
+    }
+    
+    /// Replace a user's password
+    async fn set_password(&self, id: Uuid, password: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(hash_password(password)?)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Store the hash of an emailed token, replacing the user's earlier ones for the same purpose
+    async fn create_email_token(
+        &self,
+        user_id: Uuid,
+        purpose: EmailTokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query("DELETE FROM email_tokens WHERE user_id = $1 AND purpose = $2")
+            .bind(user_id)
+            .bind(purpose.as_str())
+            .execute(&mut *tx)
+            .await?;
+        
+        sqlx::query(
+            r#"
+            INSERT INTO email_tokens (token_hash, user_id, purpose, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        Ok(())
+    }
+    
+    /// Use up an unexpired emailed token
+    async fn consume_email_token(&self, token_hash: &str, purpose: EmailTokenPurpose, now: DateTime<Utc>) -> Result<Option<Uuid>> {
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            DELETE FROM email_tokens
+            WHERE token_hash = $1 AND purpose = $2 AND expires_at > $3
+            RETURNING user_id
+            "#,
+        )
+        .bind(token_hash)
+        .bind(purpose.as_str())
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(user_id)
+    }
+    
+    /// Start a session holding the hash of its first refresh token
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        client: Option<(&str, Uuid)>,
+        refresh_token_hash: &str,
+        device: &DeviceInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            INSERT INTO sessions (
+                user_id, client_id, grant_id, refresh_token_hash, user_agent, ip_address, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(client.map(|(client_id, _)| client_id))
+        .bind(client.map(|(_, grant_id)| grant_id))
+        .bind(refresh_token_hash)
+        .bind(&device.user_agent)
+        .bind(&device.ip_address)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(session)
+    }
+    
+    /// Get a session by ID
+    async fn get_session(&self, id: Uuid) -> Result<Option<Session>> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            FROM sessions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(session)
+    }
+    
+    /// Swap the refresh token hash of an active session that still holds `current_hash`
+    async fn rotate_session(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+        device: &DeviceInfo,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<Session>> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET
+                refresh_token_hash = $3,
+                user_agent = COALESCE($4, user_agent),
+                ip_address = COALESCE($5, ip_address),
+                last_used_at = $6,
+                expires_at = LEAST($7, (SELECT expires_at FROM access_grants WHERE id = sessions.grant_id))
+            WHERE id = $1
+              AND refresh_token_hash = $2
+              AND revoked_at IS NULL
+              AND expires_at > $6
+            RETURNING
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            "#,
+        )
+        .bind(id)
+        .bind(current_hash)
+        .bind(new_hash)
+        .bind(&device.user_agent)
+        .bind(&device.ip_address)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(session)
+    }
+    
+    /// List a user's active sessions, most recently used first
+    async fn list_sessions(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<Vec<Session>> {
+        let sessions = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            FROM sessions
+            WHERE user_id = $1
+              AND revoked_at IS NULL
+              AND expires_at > $2
+            ORDER BY last_used_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(sessions)
+    }
+    
+    /// Revoke one of a user's active sessions
+    async fn revoke_session(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Revoke all of a user's active sessions
+    async fn revoke_sessions(&self, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Get a user's TOTP secret
+    async fn get_totp(&self, user_id: Uuid) -> Result<Option<TotpCredential>> {
+        let credential = sqlx::query_as::<_, TotpCredential>(
+            r#"
+            SELECT user_id, secret, enabled_at, last_used_step, created_at
+            FROM totp_credentials
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(credential)
+    }
+    
+    /// Store a sealed TOTP secret awaiting its first code
+    async fn set_pending_totp(&self, user_id: Uuid, secret: &[u8]) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO totp_credentials (user_id, secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+            WHERE totp_credentials.enabled_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(secret)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Replace the sealed secret if it still equals `current`
+    async fn reseal_totp(&self, user_id: Uuid, current: &[u8], secret: &[u8]) -> Result<bool> {
+        let result = sqlx::query("UPDATE totp_credentials SET secret = $3 WHERE user_id = $1 AND secret = $2")
+            .bind(user_id)
+            .bind(current)
+            .bind(secret)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Enable a pending TOTP secret
+    async fn enable_totp(&self, user_id: Uuid, step: i64, recovery_code_hashes: &[String]) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        
+        let enabled = sqlx::query(
+            r#"
+            UPDATE totp_credentials
+            SET enabled_at = NOW(), last_used_step = $2
+            WHERE user_id = $1 AND enabled_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() > 0;
+        if !enabled {
+            return Ok(false);
+        }
+        
+        insert_recovery_codes(&mut tx, user_id, recovery_code_hashes).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+    
+    /// Accept a code's time step if it is later than the last accepted one
+    async fn use_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE totp_credentials
+            SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Remove a user's TOTP secret and recovery codes
+    async fn delete_totp(&self, user_id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM totp_credentials WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+        
+        tx.commit().await?;
+        Ok(deleted)
+    }
+    
+    /// Replace a user's recovery codes
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_recovery_codes(&mut tx, user_id, code_hashes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    
+    /// Use up one of a user's recovery codes
+    async fn consume_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Count a user's unused recovery codes
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        
+        Ok(count)
+    }
+    
+    /// Store the hash of a login challenge awaiting a second factor
+    async fn create_login_challenge(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query("DELETE FROM login_challenges WHERE expires_at < $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        
+        sqlx::query(
+            r#"
+            INSERT INTO login_challenges (token_hash, user_id, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Count an attempt at an unexpired login challenge
+    async fn attempt_login_challenge(&self, token_hash: &str, max_attempts: i32, now: DateTime<Utc>) -> Result<Option<Uuid>> {
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            UPDATE login_challenges
+            SET attempts = attempts + 1
+            WHERE token_hash = $1 AND expires_at > $3 AND attempts < $2
+            RETURNING user_id
+            "#,
+        )
+        .bind(token_hash)
+        .bind(max_attempts)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(user_id)
+    }
+    
+    /// Remove an answered login challenge
+    async fn delete_login_challenge(&self, token_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_challenges WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
+    /// Get the recent failed logins of an account or address
+    async fn get_login_attempts(&self, subject_hash: &str) -> Result<Option<LoginAttempts>> {
+        let attempts = sqlx::query_as::<_, LoginAttempts>(
+            r#"
+            SELECT failures, last_failure_at, locked_until
+            FROM login_attempts
+            WHERE subject_hash = $1
+            "#,
+        )
+        .bind(subject_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(attempts)
+    }
+    
+    /// Count a failed login, starting over if the previous one is outside the window
+    async fn record_login_failure(
+        &self,
+        subject_hash: &str,
+        now: DateTime<Utc>,
+        window_start: DateTime<Utc>,
+    ) -> Result<LoginAttempts> {
+        sqlx::query(
+            r#"
+            DELETE FROM login_attempts
+            WHERE last_failure_at < $1
+              AND (locked_until IS NULL OR locked_until < $2)
+            "#,
+        )
+        .bind(window_start)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        
+        let attempts = sqlx::query_as::<_, LoginAttempts>(
+            r#"
+            INSERT INTO login_attempts (subject_hash, failures, last_failure_at)
+            VALUES ($1, 1, $2)
+            ON CONFLICT (subject_hash) DO UPDATE
+            SET
+                failures = CASE
+                    WHEN login_attempts.last_failure_at < $3 THEN 1
+                    ELSE login_attempts.failures + 1
+                END,
+                last_failure_at = $2
+            RETURNING failures, last_failure_at, locked_until
+            "#,
+        )
+        .bind(subject_hash)
+        .bind(now)
+        .bind(window_start)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(attempts)
+    }
+    
+    /// Lock an account or address out until `locked_until`
+    async fn lock_login(&self, subject_hash: &str, locked_until: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE login_attempts SET locked_until = $2 WHERE subject_hash = $1")
+            .bind(subject_hash)
+            .bind(locked_until)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
+    /// Forget the failed logins of an account or address
+    async fn clear_login_failures(&self, subject_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_attempts WHERE subject_hash = $1")
+            .bind(subject_hash)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
+    /// Record a security event on a user's account
+    async fn create_security_event(
+        &self,
+        user_id: Uuid,
+        event: &str,
+        device: &DeviceInfo,
+        details: serde_json::Value,
+        created_at: DateTime<Utc>,
+    ) -> Result<SecurityEvent> {
+        let event = sqlx::query_as::<_, SecurityEvent>(
+            r#"
+            INSERT INTO security_events (id, user_id, event, ip_address, user_agent, details, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, user_id, event, ip_address, user_agent, details, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(event)
+        .bind(&device.ip_address)
+        .bind(&device.user_agent)
+        .bind(details)
+        .bind(created_at)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(event)
+    }
+    
+    /// List a user's security events, newest first
+    async fn list_security_events(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<SecurityEvent>> {
+        let events = sqlx::query_as::<_, SecurityEvent>(
+            r#"
+            SELECT id, user_id, event, ip_address, user_agent, details, created_at
+            FROM security_events
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(events)
+    }
+    
+    /// Store a personal access token by the hash of its secret
+    async fn create_personal_access_token(
+        &self,
+        user_id: Uuid,
+        input: &CreatePersonalAccessTokenInput,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PersonalAccessToken> {
+        let token = sqlx::query_as::<_, PersonalAccessToken>(
+            r#"
+            INSERT INTO personal_access_tokens (user_id, name, token_hash, scopes, domains, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, name, scopes, domains, expires_at, last_used_at, created_at, revoked_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(&input.name)
+        .bind(token_hash)
+        .bind(&input.scopes)
+        .bind(&input.domains)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(token)
+    }
+    
+    /// Record a use of the active personal access token with this hash
+    async fn use_personal_access_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<PersonalAccessToken>> {
+        let token = sqlx::query_as::<_, PersonalAccessToken>(
+            r#"
+            UPDATE personal_access_tokens
+            SET last_used_at = $2
+            WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > $2
+            RETURNING id, user_id, name, scopes, domains, expires_at, last_used_at, created_at, revoked_at
+            "#,
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(token)
+    }
+    
+    /// List a user's personal access tokens that were not revoked, newest first
+    async fn list_personal_access_tokens(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>> {
+        let tokens = sqlx::query_as::<_, PersonalAccessToken>(
+            r#"
+            SELECT id, user_id, name, scopes, domains, expires_at, last_used_at, created_at, revoked_at
+            FROM personal_access_tokens
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(tokens)
+    }
+    
+    /// Revoke one of a user's personal access tokens
+    async fn revoke_personal_access_token(&self, user_id: Uuid, id: Uuid, now: DateTime<Utc>) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE personal_access_tokens
+            SET revoked_at = $3
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Replace a user's recovery codes within a transaction
+async fn insert_recovery_codes(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> Result<()> {
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+    
+    for code_hash in code_hashes {
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&mut **tx)
+            .await?;
+    }
+    
+    Ok(())
 }
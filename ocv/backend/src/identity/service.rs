@@ -1,48 +1,264 @@
 use anyhow::Result;
-use sqlx::PgPool;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 use chrono::{DateTime, Duration, Utc};
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
+use jsonwebtoken::jwk::JwkSet;
 use serde::{Deserialize, Serialize};
 
 use super::{
-    models::{User, CreateUserInput, Credentials, AuthToken},
-    repository::IdentityRepository,
+    models::{
+        User, CreateUserInput, Credentials, AuthToken, Authenticated, CreatePersonalAccessTokenInput,
+        CreatedPersonalAccessToken, DeviceInfo, EmailTokenPurpose, IdentityError, PersonalAccessToken, Principal,
+        SecurityEvent, Session, TotpCredential, TwoFactorEnrollment,
+    },
+    repository::{IdentityRepository, PgIdentityRepository},
+    signing::JwtKeys,
+    sqlite::SqliteIdentityRepository,
+    throttle::{LoginLimits, LoginSubject},
+    totp,
 };
+use crate::clients::service::SCOPES;
+use crate::encryption::{service::EncryptionService, AssociatedData};
+use crate::mailer::{Email, Mailer};
+use crate::utils::clock::Clock;
+use crate::utils::tokens::{random_token, sha256_base64url};
+use crate::storage::Database;
+
+/// Lifetime of access tokens
+const ACCESS_TOKEN_TTL: Duration = Duration::minutes(15);
+
+/// Sessions end after this long without a refresh
+const SESSION_TTL: Duration = Duration::days(30);
+
+/// How long an email verification link works
+const VERIFY_EMAIL_TTL: Duration = Duration::days(1);
+
+/// How long a password reset link works
+const RESET_PASSWORD_TTL: Duration = Duration::hours(1);
+
+/// Shortest password accepted
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// How long a login may wait for its second factor
+const LOGIN_CHALLENGE_TTL: Duration = Duration::minutes(5);
+
+/// Wrong codes allowed per login challenge before the login must start over
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// Prefix that tells personal access tokens apart from JWTs
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "ocv_pat_";
+
+/// Lifetime of personal access tokens created without one
+const PERSONAL_ACCESS_TOKEN_DAYS: i64 = 30;
+
+/// Longest lifetime of a personal access token
+const MAX_PERSONAL_ACCESS_TOKEN_DAYS: i64 = 365;
+
+/// Content type the TOTP secret is sealed for, with the user ID in place of a shard ID
+const TOTP_CONTENT_TYPE: &str = "totp_secret";
 
 /// Claims for JWT tokens
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
     /// Subject (user ID)
     sub: String,
+    /// Issuer
+    iss: String,
     /// Expiration time
     exp: i64,
     /// Issued at
     iat: i64,
+    /// Session the token belongs to
+    sid: String,
+    /// Client the token was issued to, if it acts on the user's behalf
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
 }
 
 /// Service for managing users and authentication
 pub struct IdentityService {
-    repository: IdentityRepository,
-    jwt_secret: String,
+    repository: Arc<dyn IdentityRepository>,
+    encryption_service: Arc<EncryptionService>,
+    jwt_keys: JwtKeys,
+    mailer: Arc<dyn Mailer>,
+    ui_url: String,
+    login_limits: LoginLimits,
+    clock: Arc<dyn Clock>,
+    /// Lowercased email addresses of administrators
+    admins: RwLock<HashSet<String>>,
 }
 
 impl IdentityService {
     /// Create a new identity service
-    pub fn new(pool: PgPool) -> Arc<Self> {
-        // In a real app, this would be loaded from environment variables
-        let jwt_secret = "supersecret123".to_string();
-        
+    ///
+    /// Verification and password reset emails link to pages under `ui_url`.
+    /// Failed logins are limited by `login_limits`, timed with `clock`.
+    pub fn new(
+        db: &Database,
+        encryption_service: Arc<EncryptionService>,
+        jwt_keys: JwtKeys,
+        mailer: Arc<dyn Mailer>,
+        ui_url: &str,
+        login_limits: LoginLimits,
+        clock: Arc<dyn Clock>,
+    ) -> Arc<Self> {
         Arc::new(Self {
-            repository: IdentityRepository::new(pool),
-            jwt_secret,
+            repository: match db {
+                Database::Postgres(pool) => Arc::new(PgIdentityRepository::new(pool.clone())),
+                Database::Sqlite(pool) => Arc::new(SqliteIdentityRepository::new(pool.clone())),
+            },
+            encryption_service,
+            jwt_keys,
+            mailer,
+            ui_url: ui_url.trim_end_matches('/').to_string(),
+            login_limits,
+            clock,
+            admins: RwLock::new(HashSet::new()),
         })
     }
     
-    /// Create a new user
+    /// Make the users with these email addresses administrators
+    pub fn set_admins(&self, emails: impl IntoIterator<Item = String>) {
+        let mut admins = self.admins.write().unwrap();
+        *admins = emails.into_iter().map(|email| email.trim().to_lowercase()).collect();
+    }
+    
+    /// Whether a user is an administrator
+    ///
+    /// The user must have verified their email address, so nobody becomes an
+    /// administrator by registering an administrator's address first.
+    pub async fn is_admin(&self, user_id: Uuid) -> Result<bool> {
+        let Some(user) = self.repository.get_user_by_id(user_id).await? else {
+            return Ok(false);
+        };
+        
+        Ok(user.is_verified() && self.admins.read().unwrap().contains(&user.email.to_lowercase()))
+    }
+    
+    /// Create a new user along with their data encryption key
+    ///
+    /// The user is sent a link to verify their email address, and cannot
+    /// grant access to clients until they follow it.
     pub async fn create_user(&self, input: CreateUserInput) -> Result<User> {
-        self.repository.create_user(input).await
+        let email = input.email.trim().to_string();
+        validate_email(&email)?;
+        validate_password(&input.password)?;
+        if self.repository.get_user_by_email(&email).await?.is_some() {
+            return Err(IdentityError::EmailTaken.into());
+        }
+        
+        let user = self.repository.create_user(CreateUserInput { email, ..input }).await?;
+        self.encryption_service.create_key(user.id).await?;
+        
+        // The user can ask for another link, so a mail failure does not fail registration
+        if let Err(err) = self.send_verification_email(&user).await {
+            log::error!("Failed to send verification email to user {}: {:#}", user.id, err);
+        }
+        
+        Ok(user)
+    }
+    
+    /// Send a new verification link, returning `false` if the email is already verified
+    pub async fn resend_verification_email(&self, user_id: Uuid) -> Result<bool> {
+        let user = self.repository.get_user_by_id(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+        if user.is_verified() {
+            return Ok(false);
+        }
+        
+        self.send_verification_email(&user).await?;
+        Ok(true)
+    }
+    
+    /// Mark the email address a verification token was sent to as verified
+    pub async fn verify_email(&self, token: &str) -> Result<User> {
+        let user_id = self.repository
+            .consume_email_token(&sha256_base64url(token), EmailTokenPurpose::VerifyEmail, self.clock.now())
+            .await?
+            .ok_or(IdentityError::InvalidToken)?;
+        
+        self.repository.mark_email_verified(user_id).await?.ok_or_else(|| IdentityError::InvalidToken.into())
+    }
+    
+    /// Email a password reset link to the account with this address, if there is one
+    ///
+    /// Succeeds either way, so the response does not reveal which addresses
+    /// have accounts.
+    pub async fn request_password_reset(&self, email: &str) -> Result<()> {
+        let Some(user) = self.repository.get_user_by_email(email.trim()).await? else {
+            log::info!("Password reset requested for an unknown email address");
+            return Ok(());
+        };
+        
+        let token = self.email_token(user.id, EmailTokenPurpose::ResetPassword, RESET_PASSWORD_TTL).await?;
+        self.mailer
+            .send(Email {
+                to: user.email,
+                subject: "Reset your Open Context Vault password".to_string(),
+                body: format!(
+                    "Hi {},\n\nSomeone asked to reset the password of your Open Context Vault account. \
+                     To choose a new password, open this link within an hour:\n\n\
+                     {}/reset-password?token={}\n\n\
+                     If it was not you, ignore this email and your password stays the same.",
+                    user.display_name, self.ui_url, token
+                ),
+            })
+            .await
+    }
+    
+    /// Set a new password with a token from a reset email
+    ///
+    /// The token works once. All of the user's sessions are ended, and since
+    /// the link reached their inbox, their email address counts as verified.
+    pub async fn reset_password(&self, token: &str, new_password: &str, device: DeviceInfo) -> Result<()> {
+        validate_password(new_password)?;
+        
+        let user_id = self.repository
+            .consume_email_token(&sha256_base64url(token), EmailTokenPurpose::ResetPassword, self.clock.now())
+            .await?
+            .ok_or(IdentityError::InvalidToken)?;
+        
+        self.repository.set_password(user_id, new_password).await?;
+        let user = self.repository.mark_email_verified(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+        let revoked = self.repository.revoke_sessions(user_id).await?;
+        log::info!("Password of user {} reset, {} sessions ended", user_id, revoked);
+        
+        // Proving access to the inbox lifts a lockout of the account
+        self.repository.clear_login_failures(&LoginSubject::Account(user.email).hash()).await?;
+        self.record_security_event(user_id, "password_changed", &device, serde_json::json!({
+            "sessions_ended": revoked
+        })).await?;
+        
+        Ok(())
+    }
+    
+    /// Email the user a link that verifies their address
+    async fn send_verification_email(&self, user: &User) -> Result<()> {
+        let token = self.email_token(user.id, EmailTokenPurpose::VerifyEmail, VERIFY_EMAIL_TTL).await?;
+        self.mailer
+            .send(Email {
+                to: user.email.clone(),
+                subject: "Verify your email address".to_string(),
+                body: format!(
+                    "Hi {},\n\nConfirm that this is your email address by opening this link within 24 hours:\n\n\
+                     {}/verify-email?token={}\n\n\
+                     If you did not create an Open Context Vault account, you can ignore this email.",
+                    user.display_name, self.ui_url, token
+                ),
+            })
+            .await
+    }
+    
+    /// Create a single-use token for an email link, replacing earlier ones for the same purpose
+    async fn email_token(&self, user_id: Uuid, purpose: EmailTokenPurpose, ttl: Duration) -> Result<String> {
+        let token = random_token();
+        self.repository
+            .create_email_token(user_id, purpose, &sha256_base64url(&token), self.clock.now() + ttl)
+            .await?;
+        
+        Ok(token)
     }
     
     /// Get a user by ID
@@ -55,51 +271,512 @@ impl IdentityService {
         self.repository.get_user_by_email(email).await
     }
     
-    /// Authenticate a user with credentials
-    pub async fn authenticate(&self, credentials: Credentials) -> Result<Option<AuthToken>> {
+    /// Authenticate a user with credentials and start a session
+    ///
+    /// If the user has two-factor authentication enabled, fails with
+    /// `IdentityError::TwoFactorRequired` instead, holding a challenge that
+    /// `complete_login` exchanges for a session along with a code.
+    ///
+    /// Failed logins are counted against the email address and the client's
+    /// address. Each failure delays the next attempt at the account further,
+    /// until either is locked out for a while; until then logins fail with
+    /// `IdentityError::TooManyAttempts` without checking the password.
+    pub async fn authenticate(&self, credentials: Credentials, device: DeviceInfo) -> Result<Option<AuthToken>> {
+        let subjects = login_subjects(&credentials.email, &device);
+        self.check_login_throttle(&subjects).await?;
+        
+        let email = credentials.email.clone();
         let user = match self.repository.authenticate(credentials).await? {
             Some(u) => u,
-            None => return Ok(None),
+            None => {
+                self.record_login_failure(&email, &subjects, &device, "password").await?;
+                return Ok(None);
+            },
+        };
+        self.repository.clear_login_failures(&subjects[0].hash()).await?;
+        
+        if self.has_two_factor(user.id).await? {
+            let challenge = random_token();
+            let now = self.clock.now();
+            self.repository
+                .create_login_challenge(user.id, &sha256_base64url(&challenge), now, now + LOGIN_CHALLENGE_TTL)
+                .await?;
+            return Err(IdentityError::TwoFactorRequired { challenge }.into());
+        }
+        
+        Ok(Some(self.start_session(user, device).await?))
+    }
+    
+    /// Confirm that the signed-in user is present before an irreversible change
+    ///
+    /// Needs the password and, with two-factor authentication on, a TOTP or
+    /// recovery code as well.
+    pub async fn reauthenticate(&self, user_id: Uuid, password: &str, code: Option<&str>) -> Result<()> {
+        let user = self.repository.get_user_by_id(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+        
+        // Guesses here count against the same limit as logins
+        let subjects = [LoginSubject::Account(user.email.clone())];
+        self.check_login_throttle(&subjects).await?;
+        let credentials = Credentials { email: user.email.clone(), password: password.to_string() };
+        if self.repository.authenticate(credentials).await?.is_none() {
+            self.record_login_failure(&user.email, &subjects, &DeviceInfo::default(), "password").await?;
+            return Err(IdentityError::InvalidPassword.into());
+        }
+        
+        if self.has_two_factor(user_id).await? {
+            let Some(code) = code else {
+                return Err(IdentityError::InvalidTwoFactorCode.into());
+            };
+            if !self.check_two_factor_code(user_id, code).await? {
+                self.record_login_failure(&user.email, &subjects, &DeviceInfo::default(), "two_factor_code").await?;
+                return Err(IdentityError::InvalidTwoFactorCode.into());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Finish a login that needs a second factor, with a TOTP or recovery code
+    ///
+    /// A challenge works once and only allows a few wrong codes.
+    pub async fn complete_login(&self, challenge: &str, code: &str, device: DeviceInfo) -> Result<AuthToken> {
+        let challenge_hash = sha256_base64url(challenge);
+        let user_id = self.repository
+            .attempt_login_challenge(&challenge_hash, MAX_CHALLENGE_ATTEMPTS, self.clock.now())
+            .await?
+            .ok_or(IdentityError::InvalidToken)?;
+        
+        let user = self.repository.get_user_by_id(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+        
+        // Wrong codes count as failed logins, so challenges cannot be farmed for guesses
+        let subjects = login_subjects(&user.email, &device);
+        self.check_login_throttle(&subjects).await?;
+        if !self.check_two_factor_code(user_id, code).await? {
+            self.record_login_failure(&user.email, &subjects, &device, "two_factor_code").await?;
+            return Err(IdentityError::InvalidTwoFactorCode.into());
+        }
+        self.repository.delete_login_challenge(&challenge_hash).await?;
+        self.repository.clear_login_failures(&subjects[0].hash()).await?;
+        
+        self.start_session(user, device).await
+    }
+    
+    /// Start a user's own session
+    async fn start_session(&self, user: User, device: DeviceInfo) -> Result<AuthToken> {
+        let secret = random_token();
+        let session = self.repository
+            .create_session(user.id, None, &sha256_base64url(&secret), &device, self.clock.now() + SESSION_TTL)
+            .await?;
+        self.record_security_event(user.id, "login_succeeded", &device, serde_json::json!({
+            "session_id": session.id
+        })).await?;
+        
+        self.issue(user, &session, &secret)
+    }
+    
+    /// Refuse a login while any of its subjects must wait or is locked out
+    async fn check_login_throttle(&self, subjects: &[LoginSubject]) -> Result<()> {
+        let now = self.clock.now();
+        
+        for subject in subjects {
+            let Some(attempts) = self.repository.get_login_attempts(&subject.hash()).await? else {
+                continue;
+            };
+            if let Some(wait) = self.login_limits.retry_after(subject, &attempts, now) {
+                let retry_after = (wait.num_milliseconds() + 999) / 1000;
+                return Err(IdentityError::TooManyAttempts { retry_after }.into());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Count a failed login against its subjects, locking out any that reached their limit
+    ///
+    /// The failure and any lockout of an existing account go to its security events.
+    async fn record_login_failure(
+        &self,
+        email: &str,
+        subjects: &[LoginSubject],
+        device: &DeviceInfo,
+        reason: &str,
+    ) -> Result<()> {
+        let now = self.clock.now();
+        let user = self.repository.get_user_by_email(email).await?;
+        
+        if let Some(user) = &user {
+            self.record_security_event(user.id, "login_failed", device, serde_json::json!({
+                "reason": reason
+            })).await?;
+        }
+        
+        for subject in subjects {
+            let hash = subject.hash();
+            let attempts = self.repository
+                .record_login_failure(&hash, now, self.login_limits.window_start(now))
+                .await?;
+            let Some(locked_until) = self.login_limits.lockout_after(subject, attempts.failures, now) else {
+                continue;
+            };
+            self.repository.lock_login(&hash, locked_until).await?;
+            
+            match (subject, &user) {
+                (LoginSubject::Account(_), Some(user)) => {
+                    log::warn!("Locked user {} out after {} failed logins", user.id, attempts.failures);
+                    self.record_security_event(user.id, "account_locked", device, serde_json::json!({
+                        "failures": attempts.failures,
+                        "locked_until": locked_until
+                    })).await?;
+                },
+                (LoginSubject::Account(_), None) => {},
+                (LoginSubject::Address(ip), _) => {
+                    log::warn!("Locked {} out after {} failed logins", ip, attempts.failures);
+                },
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Record a security event on a user's account
+    async fn record_security_event(
+        &self,
+        user_id: Uuid,
+        event: &str,
+        device: &DeviceInfo,
+        details: serde_json::Value,
+    ) -> Result<()> {
+        self.repository.create_security_event(user_id, event, device, details, self.clock.now()).await?;
+        Ok(())
+    }
+    
+    /// List a user's sign-ins, lockouts and password changes, newest first
+    pub async fn security_events(
+        &self,
+        user_id: Uuid,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<SecurityEvent>> {
+        self.repository
+            .list_security_events(user_id, limit.unwrap_or(50), offset.unwrap_or(0))
+            .await
+    }
+    
+    /// Whether logins to the account need a second factor
+    pub async fn has_two_factor(&self, user_id: Uuid) -> Result<bool> {
+        Ok(self.repository.get_totp(user_id).await?.is_some_and(|totp| totp.enabled_at.is_some()))
+    }
+    
+    /// Number of unused recovery codes
+    pub async fn recovery_codes_left(&self, user_id: Uuid) -> Result<i64> {
+        self.repository.count_recovery_codes(user_id).await
+    }
+    
+    /// Create a TOTP secret for the user to add to their authenticator app
+    ///
+    /// Two-factor authentication stays off until `confirm_two_factor` receives
+    /// a code generated from it. Enrolling again replaces an unconfirmed secret.
+    pub async fn enroll_two_factor(&self, user_id: Uuid) -> Result<TwoFactorEnrollment> {
+        let user = self.repository.get_user_by_id(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+        
+        let secret = totp::generate_secret();
+        let sealed = self.encryption_service.encrypt(&totp_aad(user_id), &secret).await?;
+        if !self.repository.set_pending_totp(user_id, &sealed).await? {
+            return Err(IdentityError::TwoFactorAlreadyEnabled.into());
+        }
+        
+        Ok(TwoFactorEnrollment {
+            secret: totp::secret_base32(&secret),
+            provisioning_uri: totp::provisioning_uri(&secret, &user.email),
+        })
+    }
+    
+    /// Turn on two-factor authentication with a first code from the authenticator app
+    ///
+    /// Returns the recovery codes, which are only shown this once.
+    pub async fn confirm_two_factor(&self, user_id: Uuid, code: &str) -> Result<Vec<String>> {
+        let credential = match self.repository.get_totp(user_id).await? {
+            Some(credential) if credential.enabled_at.is_none() => credential,
+            Some(_) => return Err(IdentityError::TwoFactorAlreadyEnabled.into()),
+            None => return Err(IdentityError::TwoFactorNotEnabled.into()),
+        };
+        
+        let secret = self.totp_secret(&credential).await?;
+        let step = totp::matching_step(&secret, code, self.clock.now()).ok_or(IdentityError::InvalidTwoFactorCode)?;
+        
+        let codes = totp::generate_recovery_codes();
+        if !self.repository.enable_totp(user_id, step, &recovery_code_hashes(&codes)).await? {
+            return Err(IdentityError::TwoFactorAlreadyEnabled.into());
+        }
+        log::info!("Two-factor authentication enabled for user {}", user_id);
+        
+        Ok(codes)
+    }
+    
+    /// Replace the recovery codes, given a current TOTP or recovery code
+    pub async fn regenerate_recovery_codes(&self, user_id: Uuid, code: &str) -> Result<Vec<String>> {
+        if !self.check_two_factor_code(user_id, code).await? {
+            return Err(IdentityError::InvalidTwoFactorCode.into());
+        }
+        
+        let codes = totp::generate_recovery_codes();
+        self.repository.replace_recovery_codes(user_id, &recovery_code_hashes(&codes)).await?;
+        Ok(codes)
+    }
+    
+    /// Turn off two-factor authentication, given a current TOTP or recovery code
+    pub async fn disable_two_factor(&self, user_id: Uuid, code: &str) -> Result<()> {
+        if !self.check_two_factor_code(user_id, code).await? {
+            return Err(IdentityError::InvalidTwoFactorCode.into());
+        }
+        
+        self.repository.delete_totp(user_id).await?;
+        log::info!("Two-factor authentication disabled for user {}", user_id);
+        Ok(())
+    }
+    
+    /// Re-encrypt the TOTP secret if it is sealed under a key version older than `key_version`
+    pub async fn reseal_totp_secret(&self, user_id: Uuid, key_version: i32) -> Result<bool> {
+        let Some(credential) = self.repository.get_totp(user_id).await? else {
+            return Ok(false);
+        };
+        if EncryptionService::is_current(&credential.secret, key_version) {
+            return Ok(false);
+        }
+        
+        let secret = self.totp_secret(&credential).await?;
+        let sealed = self.encryption_service.encrypt(&totp_aad(user_id), &secret).await?;
+        self.repository.reseal_totp(user_id, &credential.secret, &sealed).await
+    }
+    
+    /// Check a TOTP or recovery code against an enabled second factor, using it up
+    ///
+    /// TOTP codes are refused if a code from the same or a later time step
+    /// was already accepted.
+    async fn check_two_factor_code(&self, user_id: Uuid, code: &str) -> Result<bool> {
+        let credential = match self.repository.get_totp(user_id).await? {
+            Some(credential) if credential.enabled_at.is_some() => credential,
+            _ => return Err(IdentityError::TwoFactorNotEnabled.into()),
+        };
+        
+        if !totp::is_totp_code(code) {
+            let code_hash = sha256_base64url(&totp::normalize_recovery_code(code));
+            return self.repository.consume_recovery_code(user_id, &code_hash).await;
+        }
+        
+        let secret = self.totp_secret(&credential).await?;
+        match totp::matching_step(&secret, code, self.clock.now()) {
+            Some(step) => self.repository.use_totp_step(user_id, step).await,
+            None => Ok(false),
+        }
+    }
+    
+    /// Decrypt a stored TOTP secret
+    async fn totp_secret(&self, credential: &TotpCredential) -> Result<Vec<u8>> {
+        self.encryption_service.decrypt(&totp_aad(credential.user_id), &credential.secret).await
+    }
+    
+    /// Start a session for a client acting under one of the user's grants
+    ///
+    /// The session ends when the grant is revoked, and no later than the
+    /// grant's own expiry.
+    pub async fn start_client_session(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        grant_id: Uuid,
+        grant_expires_at: Option<DateTime<Utc>>,
+        device: DeviceInfo,
+    ) -> Result<AuthToken> {
+        let user = self.repository.get_user_by_id(user_id).await?
+            .ok_or_else(|| anyhow::anyhow!("User {} not found", user_id))?;
+        
+        let expires_at = grant_expires_at.map_or(self.clock.now() + SESSION_TTL, |at| at.min(self.clock.now() + SESSION_TTL));
+        let secret = random_token();
+        let session = self.repository
+            .create_session(user_id, Some((client_id, grant_id)), &sha256_base64url(&secret), &device, expires_at)
+            .await?;
+        
+        self.issue(user, &session, &secret)
+    }
+    
+    /// Exchange a refresh token for new access and refresh tokens
+    ///
+    /// `client_id` must name the client the session was issued to, or be `None`
+    /// for a user's own session. Refresh tokens are single-use. Presenting one
+    /// that was already used means it leaked, so the whole session is revoked.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        client_id: Option<&str>,
+        device: DeviceInfo,
+    ) -> Result<Option<AuthToken>> {
+        let Some((session_id, secret)) = parse_refresh_token(refresh_token) else {
+            return Ok(None);
+        };
+        
+        let session = match self.repository.get_session(session_id).await? {
+            Some(session) if session.client_id.as_deref() == client_id => session,
+            _ => return Ok(None),
+        };
+        
+        let now = self.clock.now();
+        let new_secret = random_token();
+        let rotated = self.repository
+            .rotate_session(session_id, &sha256_base64url(secret), &sha256_base64url(&new_secret), &device, now, now + SESSION_TTL)
+            .await?;
+        
+        let Some(session) = rotated else {
+            if session.is_active(now) {
+                log::warn!("Refresh token reused for session {}, revoking it", session_id);
+                self.repository.revoke_session(session.user_id, session_id).await?;
+            }
+            return Ok(None);
         };
         
-        // Generate a JWT token
-        let expiration = Utc::now() + Duration::hours(24);
+        let Some(user) = self.repository.get_user_by_id(session.user_id).await? else {
+            return Ok(None);
+        };
+        
+        Ok(Some(self.issue(user, &session, &new_secret)?))
+    }
+    
+    /// Sign an access token for a session and pair it with the refresh token
+    fn issue(&self, user: User, session: &Session, secret: &str) -> Result<AuthToken> {
+        let now = self.clock.now();
+        let expiration = now + ACCESS_TOKEN_TTL;
         
         let claims = Claims {
             sub: user.id.to_string(),
+            iss: self.jwt_keys.issuer().to_string(),
             exp: expiration.timestamp(),
-            iat: Utc::now().timestamp(),
+            iat: now.timestamp(),
+            sid: session.id.to_string(),
+            client_id: session.client_id.clone(),
         };
         
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
-        )?;
-        
-        Ok(Some(AuthToken {
-            token,
+        Ok(AuthToken {
+            token: self.jwt_keys.encode(&claims)?,
             expires_at: expiration,
+            refresh_token: format!("{}.{}", session.id, secret),
+            session_id: session.id,
             user,
-        }))
+        })
     }
     
-    /// Validate a JWT token
-    pub async fn validate_token(&self, token: &str) -> Result<Option<Uuid>> {
-        let validation = Validation::default();
+    /// Validate an access token and return the principal it authenticates
+    ///
+    /// Tokens stop validating as soon as their session is logged out or revoked.
+    pub async fn validate_token(&self, token: &str) -> Result<Option<Authenticated>> {
+        if token.starts_with(PERSONAL_ACCESS_TOKEN_PREFIX) {
+            return self.validate_personal_access_token(token).await;
+        }
         
-        let token_data = match decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &validation,
-        ) {
-            Ok(data) => data,
+        let claims = match self.jwt_keys.decode::<Claims>(token) {
+            Ok(claims) => claims,
             Err(_) => return Ok(None),
         };
         
-        let user_id = Uuid::parse_str(&token_data.claims.sub)?;
+        let user_id = Uuid::parse_str(&claims.sub)?;
+        let session_id = Uuid::parse_str(&claims.sid)?;
         
-        Ok(Some(user_id))
+        let session = match self.repository.get_session(session_id).await? {
+            Some(session)
+                if session.user_id == user_id
+                    && session.client_id == claims.client_id
+                    && session.is_active(self.clock.now()) => session,
+            _ => return Ok(None),
+        };
+        
+        let principal = match claims.client_id {
+            Some(client_id) => Principal::Client { client_id, user_id },
+            None => Principal::User(user_id),
+        };
+        
+        Ok(Some(Authenticated { principal, session: Some(session) }))
+    }
+    
+    /// Validate a personal access token, recording that it was used
+    async fn validate_personal_access_token(&self, token: &str) -> Result<Option<Authenticated>> {
+        let token = self.repository
+            .use_personal_access_token(&sha256_base64url(token), self.clock.now())
+            .await?;
+        
+        Ok(token.map(|token| Authenticated { principal: token.principal(), session: None }))
+    }
+    
+    /// Create a personal access token limited to some scopes and domains
+    ///
+    /// The secret is returned only here; the vault keeps its hash.
+    pub async fn create_personal_access_token(
+        &self,
+        user_id: Uuid,
+        input: CreatePersonalAccessTokenInput,
+        device: DeviceInfo,
+    ) -> Result<CreatedPersonalAccessToken> {
+        let input = CreatePersonalAccessTokenInput { name: input.name.trim().to_string(), ..input };
+        let days = input.expires_in_days.unwrap_or(PERSONAL_ACCESS_TOKEN_DAYS);
+        validate_personal_access_token(&input, days)?;
+        
+        let secret = format!("{}{}", PERSONAL_ACCESS_TOKEN_PREFIX, random_token());
+        let expires_at = self.clock.now() + Duration::days(days);
+        let token = self.repository
+            .create_personal_access_token(user_id, &input, &sha256_base64url(&secret), expires_at)
+            .await?;
+        
+        self.record_security_event(user_id, "personal_access_token_created", &device, serde_json::json!({
+            "token_id": token.id,
+            "name": token.name,
+        })).await?;
+        
+        Ok(CreatedPersonalAccessToken { token, secret })
+    }
+    
+    /// List a user's personal access tokens that were not revoked, newest first
+    pub async fn personal_access_tokens(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>> {
+        self.repository.list_personal_access_tokens(user_id).await
+    }
+    
+    /// Revoke one of a user's personal access tokens, returning whether it was not revoked yet
+    pub async fn revoke_personal_access_token(&self, user_id: Uuid, id: Uuid, device: DeviceInfo) -> Result<bool> {
+        let revoked = self.repository.revoke_personal_access_token(user_id, id, self.clock.now()).await?;
+        
+        if revoked {
+            self.record_security_event(user_id, "personal_access_token_revoked", &device, serde_json::json!({
+                "token_id": id,
+            })).await?;
+        }
+        
+        Ok(revoked)
+    }
+    
+    /// List a user's active sessions
+    pub async fn sessions(&self, user_id: Uuid) -> Result<Vec<Session>> {
+        self.repository.list_sessions(user_id, self.clock.now()).await
+    }
+    
+    /// End one of a user's sessions, returning whether it was active
+    pub async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool> {
+        self.repository.revoke_session(user_id, session_id).await
+    }
+    
+    /// End all of a user's sessions, returning how many were active
+    pub async fn revoke_all_sessions(&self, user_id: Uuid) -> Result<u64> {
+        self.repository.revoke_sessions(user_id).await
+    }
+    
+    /// Delete a user, ending their sessions and removing everything stored for them
+    /// in the vault's database
+    pub async fn delete_user(&self, user_id: Uuid) -> Result<bool> {
+        self.repository.delete_user(user_id).await
+    }
+    
+    /// Public keys that verify tokens issued by this vault
+    pub fn jwks(&self) -> &JwkSet {
+        self.jwt_keys.jwks()
     }
     
     /// Update a user's profile
@@ -111,3 +788,293 @@ impl IdentityService {
         self.repository.update_user(id, display_name).await
     }
 }
+
+/// What a login from `device` to `email` counts against, the account first
+fn login_subjects(email: &str, device: &DeviceInfo) -> Vec<LoginSubject> {
+    let mut subjects = vec![LoginSubject::Account(email.to_string())];
+    subjects.extend(device.ip_address.clone().map(LoginSubject::Address));
+    subjects
+}
+
+/// Accept addresses with a single `@`, a non-empty local part and a dotted domain
+fn validate_email(email: &str) -> Result<()> {
+    let valid = email.len() <= 254
+        && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        && email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty()
+                && local.len() <= 64
+                && domain.contains('.')
+                && domain.split('.').all(|label| {
+                    !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+        });
+    
+    if valid {
+        Ok(())
+    } else {
+        Err(IdentityError::InvalidEmail(email.to_string()).into())
+    }
+}
+
+fn validate_password(password: &str) -> Result<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(IdentityError::WeakPassword(MIN_PASSWORD_LENGTH).into());
+    }
+    Ok(())
+}
+
+fn validate_personal_access_token(input: &CreatePersonalAccessTokenInput, days: i64) -> Result<()> {
+    let invalid = |message: &str| IdentityError::InvalidPersonalAccessToken(message.to_string());
+    
+    if input.name.is_empty() {
+        return Err(invalid("name is required").into());
+    }
+    if input.scopes.is_empty() {
+        return Err(invalid("at least one scope is required").into());
+    }
+    if let Some(scope) = input.scopes.iter().find(|scope| !SCOPES.contains(&scope.as_str())) {
+        return Err(invalid(&format!("unknown scope {}", scope)).into());
+    }
+    if input.domains.is_empty() {
+        return Err(invalid("at least one domain is required").into());
+    }
+    if input.domains.iter().any(|domain| domain.is_empty() || domain.contains(char::is_whitespace)) {
+        return Err(invalid("domain names must be non-empty and contain no spaces").into());
+    }
+    if !(1..=MAX_PERSONAL_ACCESS_TOKEN_DAYS).contains(&days) {
+        return Err(invalid(&format!("tokens must expire within 1 to {} days", MAX_PERSONAL_ACCESS_TOKEN_DAYS)).into());
+    }
+    Ok(())
+}
+
+/// What a user's TOTP secret is sealed for
+fn totp_aad(user_id: Uuid) -> AssociatedData<'static> {
+    AssociatedData::new(user_id, user_id, TOTP_CONTENT_TYPE)
+}
+
+/// Hashes recovery codes are stored under
+fn recovery_code_hashes(codes: &[String]) -> Vec<String> {
+    codes.iter().map(|code| sha256_base64url(&totp::normalize_recovery_code(code))).collect()
+}
+
+/// Split a refresh token into its session ID and secret
+fn parse_refresh_token(token: &str) -> Option<(Uuid, &str)> {
+    let (session_id, secret) = token.split_once('.')?;
+    Some((Uuid::parse_str(session_id).ok()?, secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::MasterKey;
+    use crate::mailer::FileMailer;
+    use crate::storage::migrations;
+    use crate::utils::clock::{ManualClock, SystemClock};
+
+    async fn service() -> (Arc<IdentityService>, User) {
+        service_with_clock(Arc::new(SystemClock)).await
+    }
+
+    async fn service_with_clock(clock: Arc<dyn Clock>) -> (Arc<IdentityService>, User) {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let encryption_service = EncryptionService::new(&db, Arc::new(MasterKey::generate()));
+        let service = IdentityService::new(
+            &db,
+            encryption_service,
+            JwtKeys::generate(),
+            Arc::new(FileMailer::stdout()),
+            "http://localhost:3000",
+            LoginLimits::default(),
+            clock,
+        );
+        let user = service
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        (service, user)
+    }
+
+    async fn login(service: &IdentityService) -> AuthToken {
+        service
+            .authenticate(Credentials {
+                email: "ada@example.com".to_string(),
+                password: "correct horse".to_string(),
+            }, DeviceInfo::default())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn personal_access_tokens_expire() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let (service, user) = service_with_clock(clock.clone()).await;
+        let input = |scopes: &[&str], expires_in_days: Option<i64>| CreatePersonalAccessTokenInput {
+            name: " backup ".to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            domains: vec!["travel".to_string()],
+            expires_in_days,
+        };
+
+        for invalid in [input(&[], None), input(&["admin"], None), input(&["read"], Some(0)), input(&["read"], Some(366))] {
+            let err = service.create_personal_access_token(user.id, invalid, DeviceInfo::default()).await.unwrap_err();
+            assert!(matches!(err.downcast_ref(), Some(IdentityError::InvalidPersonalAccessToken(_))));
+        }
+
+        let created = service
+            .create_personal_access_token(user.id, input(&["read"], Some(7)), DeviceInfo::default())
+            .await
+            .unwrap();
+        assert_eq!(created.token.name, "backup");
+
+        let authenticated = service.validate_token(&created.secret).await.unwrap().unwrap();
+        assert_eq!(authenticated.principal, created.token.principal());
+        assert!(authenticated.session.is_none());
+
+        clock.advance(Duration::days(7));
+        assert!(service.validate_token(&created.secret).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_rotate() {
+        let (service, user) = service().await;
+        let first = login(&service).await;
+
+        let second = service.refresh(&first.refresh_token, None, DeviceInfo::default()).await.unwrap().unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        assert_eq!(second.user.id, user.id);
+
+        let authenticated = service.validate_token(&second.token).await.unwrap().unwrap();
+        assert_eq!(authenticated.principal, Principal::User(user.id));
+
+        let third = service.refresh(&second.refresh_token, None, DeviceInfo::default()).await.unwrap();
+        assert!(third.is_some());
+    }
+
+    #[tokio::test]
+    async fn sessions_expire_on_the_clock() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let (service, user) = service_with_clock(clock.clone()).await;
+        let first = login(&service).await;
+
+        // Refreshing keeps the session going
+        clock.advance(SESSION_TTL - Duration::minutes(1));
+        let second = service.refresh(&first.refresh_token, None, DeviceInfo::default()).await.unwrap().unwrap();
+        clock.advance(SESSION_TTL - Duration::minutes(1));
+        assert_eq!(service.sessions(user.id).await.unwrap().len(), 1);
+
+        clock.advance(Duration::minutes(1));
+        assert!(service.sessions(user.id).await.unwrap().is_empty());
+        assert!(service.refresh(&second.refresh_token, None, DeviceInfo::default()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn reusing_a_refresh_token_revokes_the_session() {
+        let (service, user) = service().await;
+        let first = login(&service).await;
+        let other = login(&service).await;
+
+        let second = service.refresh(&first.refresh_token, None, DeviceInfo::default()).await.unwrap().unwrap();
+        assert!(service.refresh(&first.refresh_token, None, DeviceInfo::default()).await.unwrap().is_none());
+
+        // Neither the stolen nor the legitimate chain works any more
+        assert!(service.refresh(&second.refresh_token, None, DeviceInfo::default()).await.unwrap().is_none());
+        assert!(service.validate_token(&second.token).await.unwrap().is_none());
+
+        // Other sessions keep working
+        assert!(service.validate_token(&other.token).await.unwrap().is_some());
+        assert_eq!(service.sessions(user.id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn logging_out_everywhere_ends_all_sessions() {
+        let (service, user) = service().await;
+        let first = login(&service).await;
+        let second = login(&service).await;
+
+        assert_eq!(service.revoke_all_sessions(user.id).await.unwrap(), 2);
+        assert!(service.validate_token(&first.token).await.unwrap().is_none());
+        assert!(service.refresh(&second.refresh_token, None, DeviceInfo::default()).await.unwrap().is_none());
+        assert!(service.refresh("garbage", None, DeviceInfo::default()).await.unwrap().is_none());
+    }
+
+    fn retry_after(result: Result<Option<AuthToken>>) -> i64 {
+        match result.unwrap_err().downcast_ref() {
+            Some(IdentityError::TooManyAttempts { retry_after }) => *retry_after,
+            other => panic!("expected the login to be throttled, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_logins_slow_down_then_lock_the_account() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let (service, user) = service_with_clock(clock.clone()).await;
+        let device = DeviceInfo { ip_address: Some("192.0.2.1".to_string()), ..Default::default() };
+        let attempt = |password: &str| {
+            service.authenticate(Credentials {
+                email: "ada@example.com".to_string(),
+                password: password.to_string(),
+            }, device.clone())
+        };
+
+        // Each failure doubles the wait, even for the right password
+        for wait in [1, 2, 4, 8] {
+            assert!(attempt("wrong horse").await.unwrap().is_none());
+            assert_eq!(retry_after(attempt("correct horse").await), wait);
+            clock.advance(Duration::seconds(wait));
+        }
+
+        // The fifth failure locks the account
+        assert!(attempt("wrong horse").await.unwrap().is_none());
+        clock.advance(Duration::minutes(10));
+        assert_eq!(retry_after(attempt("correct horse").await), 300);
+        clock.advance(Duration::minutes(5));
+        assert!(attempt("correct horse").await.unwrap().is_some());
+
+        // Success starts the count over
+        assert!(attempt("wrong horse").await.unwrap().is_none());
+        assert_eq!(retry_after(attempt("correct horse").await), 1);
+
+        let events: Vec<String> = service
+            .security_events(user.id, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| event.event)
+            .collect();
+        assert_eq!(events[..3], ["login_failed", "login_succeeded", "account_locked"]);
+        assert_eq!(events.iter().filter(|event| *event == "login_failed").count(), 6);
+    }
+
+    #[tokio::test]
+    async fn failed_logins_from_one_address_lock_it_out() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let (service, _) = service_with_clock(clock.clone()).await;
+        let from = |ip: &str| DeviceInfo { ip_address: Some(ip.to_string()), ..Default::default() };
+        let credentials = |email: &str| Credentials {
+            email: email.to_string(),
+            password: "correct horse".to_string(),
+        };
+
+        // Guesses spread over many accounts, known or not
+        for n in 0..20 {
+            let email = format!("user{}@example.com", n);
+            assert!(service.authenticate(credentials(&email), from("192.0.2.1")).await.unwrap().is_none());
+        }
+
+        let locked_out = service.authenticate(credentials("ada@example.com"), from("192.0.2.1")).await;
+        assert_eq!(retry_after(locked_out), 900);
+        assert!(service.authenticate(credentials("ada@example.com"), from("192.0.2.2")).await.unwrap().is_some());
+
+        // Failures older than the window are forgotten
+        clock.advance(Duration::minutes(16));
+        assert!(service.authenticate(credentials("ada@example.com"), from("192.0.2.1")).await.unwrap().is_some());
+    }
+}
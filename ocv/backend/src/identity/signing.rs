@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// Issuer used when none is configured
+pub const DEFAULT_ISSUER: &str = "open-context-vault";
+
+/// Where token signing keys come from
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    /// `iss` claim of issued tokens
+    pub issuer: String,
+    /// PEM file with the Ed25519 or RSA private key that signs new tokens
+    pub signing_key_file: Option<String>,
+    /// JWKS file with retired public keys that are still accepted
+    pub verification_jwks_file: Option<String>,
+}
+
+/// Public key accepted when verifying tokens
+struct VerificationKey {
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+}
+
+/// Keys for issuing and verifying vault tokens
+///
+/// New tokens are signed with a single current key and carry its `kid`. Tokens
+/// signed by any key in the published JWKS verify, so a rotated-out key keeps
+/// working until its tokens expire. Key IDs are RFC 7638 thumbprints.
+pub struct JwtKeys {
+    issuer: String,
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    verification_keys: HashMap<String, VerificationKey>,
+    jwks: JwkSet,
+}
+
+impl JwtKeys {
+    /// Load keys from configuration
+    ///
+    /// Without a signing key file a throwaway Ed25519 key is generated, so
+    /// tokens stop verifying when the process restarts.
+    pub fn from_config(config: &JwtConfig) -> Result<Self> {
+        let keys = match &config.signing_key_file {
+            Some(path) => {
+                let pem = fs::read(path).with_context(|| format!("Failed to read JWT signing key {}", path))?;
+                Self::from_pem(&pem).with_context(|| format!("Invalid JWT signing key in {}", path))?
+            },
+            None => {
+                log::warn!("JWT_SIGNING_KEY_FILE is not set, signing tokens with a throwaway key");
+                Self::generate()
+            },
+        };
+
+        let keys = match &config.verification_jwks_file {
+            Some(path) => {
+                let json = fs::read(path).with_context(|| format!("Failed to read JWKS {}", path))?;
+                let jwks: JwkSet = serde_json::from_slice(&json).with_context(|| format!("Invalid JWKS in {}", path))?;
+                keys.with_verification_keys(jwks)?
+            },
+            None => keys,
+        };
+
+        Ok(keys.with_issuer(&config.issuer))
+    }
+
+    /// Sign with a PEM-encoded Ed25519 (PKCS#8) or RSA (PKCS#8 or PKCS#1) private key
+    pub fn from_pem(pem: &[u8]) -> Result<Self> {
+        let parsed = pem::parse(pem)?;
+        let der = parsed.contents();
+
+        if parsed.tag() == "PRIVATE KEY" {
+            if let Ok(pair) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der) {
+                return Self::ed25519(&pair, EncodingKey::from_ed_der(der));
+            }
+        }
+
+        let pair = match parsed.tag() {
+            "PRIVATE KEY" => RsaKeyPair::from_pkcs8(der),
+            "RSA PRIVATE KEY" => RsaKeyPair::from_der(der),
+            tag => return Err(anyhow::anyhow!("Unsupported PEM block {}", tag)),
+        }
+        .map_err(|err| anyhow::anyhow!("Not an Ed25519 or RSA private key: {}", err))?;
+
+        Self::rsa(&pair, EncodingKey::from_rsa_pem(pem)?)
+    }
+
+    /// Sign with a freshly generated Ed25519 key
+    pub fn generate() -> Self {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).expect("Failed to generate Ed25519 key");
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("Generated key is valid");
+
+        Self::ed25519(&pair, EncodingKey::from_ed_der(pkcs8.as_ref())).expect("Generated key is valid")
+    }
+
+    /// Keys signing with an Ed25519 pair
+    fn ed25519(pair: &Ed25519KeyPair, encoding_key: EncodingKey) -> Result<Self> {
+        let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: base64_url(pair.public_key().as_ref()),
+        });
+
+        Self::signing(Algorithm::EdDSA, params, encoding_key)
+    }
+
+    /// Keys signing with an RSA pair
+    fn rsa(pair: &RsaKeyPair, encoding_key: EncodingKey) -> Result<Self> {
+        let public = ring::rsa::PublicKeyComponents::<Vec<u8>>::from(pair.public());
+        let params = AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: base64_url(&public.n),
+            e: base64_url(&public.e),
+        });
+
+        Self::signing(Algorithm::RS256, params, encoding_key)
+    }
+
+    /// Keys signing with `encoding_key`, whose public half is `params`
+    fn signing(algorithm: Algorithm, params: AlgorithmParameters, encoding_key: EncodingKey) -> Result<Self> {
+        let jwk = public_jwk(algorithm, params)?;
+        let kid = jwk.common.key_id.clone().expect("Key ID is set");
+
+        let mut keys = Self {
+            issuer: DEFAULT_ISSUER.to_string(),
+            kid,
+            algorithm,
+            encoding_key,
+            verification_keys: HashMap::new(),
+            jwks: JwkSet { keys: Vec::new() },
+        };
+        keys.add_verification_key(jwk)?;
+
+        Ok(keys)
+    }
+
+    /// Also accept tokens signed by the keys in `jwks`
+    ///
+    /// Keys without a `kid` get their thumbprint; keys without an `alg` get
+    /// the algorithm for their key type.
+    pub fn with_verification_keys(mut self, jwks: JwkSet) -> Result<Self> {
+        for jwk in jwks.keys {
+            let algorithm = match (&jwk.common.key_algorithm, &jwk.algorithm) {
+                (Some(KeyAlgorithm::EdDSA), _) | (None, AlgorithmParameters::OctetKeyPair(_)) => Algorithm::EdDSA,
+                (Some(KeyAlgorithm::RS256), _) | (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+                (alg, _) => return Err(anyhow::anyhow!("Unsupported verification key algorithm {:?}", alg)),
+            };
+
+            let mut public = public_jwk(algorithm, jwk.algorithm)?;
+            if let Some(kid) = jwk.common.key_id {
+                public.common.key_id = Some(kid);
+            }
+
+            if !self.verification_keys.contains_key(public.common.key_id.as_deref().unwrap_or_default()) {
+                self.add_verification_key(public)?;
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Set the `iss` claim issued and required
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.issuer = issuer.to_string();
+        self
+    }
+
+    /// Publish a public key and accept tokens signed with it
+    fn add_verification_key(&mut self, jwk: Jwk) -> Result<()> {
+        let kid = jwk.common.key_id.clone().expect("Key ID is set");
+        let algorithm = match jwk.common.key_algorithm {
+            Some(KeyAlgorithm::RS256) => Algorithm::RS256,
+            _ => Algorithm::EdDSA,
+        };
+
+        self.verification_keys.insert(kid, VerificationKey {
+            algorithm,
+            decoding_key: DecodingKey::from_jwk(&jwk)?,
+        });
+        self.jwks.keys.push(jwk);
+
+        Ok(())
+    }
+
+    /// Issuer of vault tokens
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Key ID new tokens are signed with
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    /// Public keys for verifying vault tokens
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+
+    /// Sign claims with the current key
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+
+        Ok(encode(&header, claims, &self.encoding_key)?)
+    }
+
+    /// Verify a token's signature, expiry and issuer
+    ///
+    /// The token must name a published key with its `kid`.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T> {
+        let header = decode_header(token)?;
+        let kid = header.kid.ok_or_else(|| anyhow::anyhow!("Token has no key ID"))?;
+        let key = self.verification_keys.get(&kid)
+            .ok_or_else(|| anyhow::anyhow!("Token signed by unknown key {}", kid))?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+
+        Ok(decode::<T>(token, &key.decoding_key, &validation)?.claims)
+    }
+}
+
+/// Public JWK for signature verification, with its thumbprint as the key ID
+fn public_jwk(algorithm: Algorithm, params: AlgorithmParameters) -> Result<Jwk> {
+    let (key_algorithm, thumbprint_input) = match (algorithm, &params) {
+        (Algorithm::EdDSA, AlgorithmParameters::OctetKeyPair(okp)) if okp.curve == EllipticCurve::Ed25519 => {
+            (KeyAlgorithm::EdDSA, format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x))
+        },
+        (Algorithm::RS256, AlgorithmParameters::RSA(rsa)) => {
+            (KeyAlgorithm::RS256, format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n))
+        },
+        _ => return Err(anyhow::anyhow!("Key does not match algorithm {:?}", algorithm)),
+    };
+
+    let thumbprint = ring::digest::digest(&ring::digest::SHA256, thumbprint_input.as_bytes());
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(base64_url(thumbprint.as_ref())),
+            ..Default::default()
+        },
+        algorithm: params,
+    })
+}
+
+/// Unpadded base64url, as used throughout JOSE
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Claims {
+        sub: String,
+        iss: String,
+        exp: i64,
+    }
+
+    fn claims(keys: &JwtKeys) -> Claims {
+        Claims {
+            sub: "ada".to_string(),
+            iss: keys.issuer().to_string(),
+            exp: Utc::now().timestamp() + 60,
+        }
+    }
+
+    /// PKCS#1 RSA key generated with `openssl genrsa 2048`
+    const RSA_PEM: &str = include_str!("testdata/rsa.pem");
+
+    /// PKCS#8 Ed25519 key generated with `openssl genpkey -algorithm ed25519`
+    const ED25519_PEM: &str = include_str!("testdata/ed25519.pem");
+
+    #[test]
+    fn signs_with_a_kid_from_the_jwks() {
+        for (pem, algorithm) in [(RSA_PEM, Algorithm::RS256), (ED25519_PEM, Algorithm::EdDSA)] {
+            let keys = JwtKeys::from_pem(pem.as_bytes()).unwrap();
+            let token = keys.encode(&claims(&keys)).unwrap();
+
+            let header = decode_header(&token).unwrap();
+            assert_eq!(header.alg, algorithm);
+            assert_eq!(header.kid.as_deref(), Some(keys.kid()));
+            assert!(keys.jwks().find(keys.kid()).is_some());
+
+            // A client holding only the JWKS can verify the token
+            let jwk = keys.jwks().find(keys.kid()).unwrap();
+            let mut validation = Validation::new(algorithm);
+            validation.set_issuer(&[DEFAULT_ISSUER]);
+            let verified = decode::<Claims>(&token, &DecodingKey::from_jwk(jwk).unwrap(), &validation).unwrap();
+            assert_eq!(verified.claims, claims(&keys));
+        }
+    }
+
+    #[test]
+    fn accepts_tokens_from_a_rotated_out_key() {
+        let old = JwtKeys::from_pem(RSA_PEM.as_bytes()).unwrap();
+        let token = old.encode(&claims(&old)).unwrap();
+
+        let current = JwtKeys::from_pem(ED25519_PEM.as_bytes()).unwrap();
+        assert!(current.decode::<Claims>(&token).is_err());
+
+        let current = current.with_verification_keys(old.jwks().clone()).unwrap();
+        assert_eq!(current.decode::<Claims>(&token).unwrap(), claims(&old));
+        assert_eq!(current.jwks().keys.len(), 2);
+
+        // New tokens still come from the current key
+        let header = decode_header(&current.encode(&claims(&current)).unwrap()).unwrap();
+        assert_eq!(header.kid.as_deref(), Some(current.kid()));
+    }
+
+    #[test]
+    fn rejects_other_issuers_and_keys() {
+        let keys = JwtKeys::generate();
+        let token = keys.encode(&claims(&keys)).unwrap();
+
+        assert!(JwtKeys::generate().decode::<Claims>(&token).is_err());
+
+        let renamed = JwtKeys::generate().with_issuer("someone-else");
+        let token = renamed.encode(&claims(&keys)).unwrap();
+        assert!(renamed.decode::<Claims>(&token).is_err());
+    }
+}
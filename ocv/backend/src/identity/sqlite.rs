@@ -0,0 +1,1024 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::models::{
+    User, CreateUserInput, Credentials, CreatePersonalAccessTokenInput, DeviceInfo, EmailTokenPurpose, LoginAttempts,
+    PersonalAccessToken, SecurityEvent, Session, TotpCredential,
+};
+use super::repository::{hash_password, IdentityRepository, UserWithPassword};
+
+/// Personal access token row as stored in SQLite, with arrays kept as JSON text
+#[derive(sqlx::FromRow)]
+struct PersonalAccessTokenRow {
+    id: Uuid,
+    user_id: Uuid,
+    name: String,
+    scopes: Json<Vec<String>>,
+    domains: Json<Vec<String>>,
+    expires_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<PersonalAccessTokenRow> for PersonalAccessToken {
+    fn from(row: PersonalAccessTokenRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            scopes: row.scopes.0,
+            domains: row.domains.0,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+            created_at: row.created_at,
+            revoked_at: row.revoked_at,
+        }
+    }
+}
+
+/// SQLite repository for user-related operations
+pub struct SqliteIdentityRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteIdentityRepository {
+    /// Create a new identity repository
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for SqliteIdentityRepository {
+    async fn create_user(&self, input: CreateUserInput) -> Result<User> {
+        let password_hash = hash_password(&input.password)?;
+        let now = Utc::now();
+
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            INSERT INTO users (id, email, display_name, password_hash, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $5)
+            RETURNING
+                id, email, display_name, email_verified_at, created_at, updated_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(input.email)
+        .bind(input.display_name)
+        .bind(password_hash)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT
+                id, email, display_name, email_verified_at, created_at, updated_at
+            FROM users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT
+                id, email, display_name, email_verified_at, created_at, updated_at
+            FROM users
+            WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn authenticate(&self, credentials: Credentials) -> Result<Option<User>> {
+        let result = sqlx::query_as::<_, UserWithPassword>(
+            r#"
+            SELECT
+                id, email, display_name, email_verified_at, created_at, updated_at, password_hash
+            FROM users
+            WHERE email = $1
+            "#,
+        )
+        .bind(&credentials.email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(user_data) = result else {
+            return Ok(None);
+        };
+
+        user_data.verify(&credentials.password)
+    }
+
+    async fn update_user(&self, id: Uuid, display_name: Option<String>) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET
+                display_name = COALESCE($2, display_name),
+                updated_at = $3
+            WHERE id = $1
+            RETURNING
+                id, email, display_name, email_verified_at, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(display_name)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn mark_email_verified(&self, id: Uuid) -> Result<Option<User>> {
+        let now = Utc::now();
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET
+                email_verified_at = COALESCE(email_verified_at, $2),
+                updated_at = $2
+            WHERE id = $1
+            RETURNING
+                id, email, display_name, email_verified_at, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn set_password(&self, id: Uuid, password: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = $2, updated_at = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(hash_password(password)?)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_email_token(
+        &self,
+        user_id: Uuid,
+        purpose: EmailTokenPurpose,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM email_tokens WHERE user_id = $1 AND purpose = $2")
+            .bind(user_id)
+            .bind(purpose.as_str())
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO email_tokens (token_hash, user_id, purpose, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .bind(Utc::now())
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn consume_email_token(&self, token_hash: &str, purpose: EmailTokenPurpose, now: DateTime<Utc>) -> Result<Option<Uuid>> {
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            DELETE FROM email_tokens
+            WHERE token_hash = $1 AND purpose = $2 AND expires_at > $3
+            RETURNING user_id
+            "#,
+        )
+        .bind(token_hash)
+        .bind(purpose.as_str())
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        client: Option<(&str, Uuid)>,
+        refresh_token_hash: &str,
+        device: &DeviceInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Session> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            INSERT INTO sessions (
+                id, user_id, client_id, grant_id, refresh_token_hash, user_agent, ip_address,
+                created_at, last_used_at, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, $9)
+            RETURNING
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(client.map(|(client_id, _)| client_id))
+        .bind(client.map(|(_, grant_id)| grant_id))
+        .bind(refresh_token_hash)
+        .bind(&device.user_agent)
+        .bind(&device.ip_address)
+        .bind(Utc::now())
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn get_session(&self, id: Uuid) -> Result<Option<Session>> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            FROM sessions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn rotate_session(
+        &self,
+        id: Uuid,
+        current_hash: &str,
+        new_hash: &str,
+        device: &DeviceInfo,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<Session>> {
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET
+                refresh_token_hash = $3,
+                user_agent = COALESCE($4, user_agent),
+                ip_address = COALESCE($5, ip_address),
+                last_used_at = $6,
+                expires_at = MIN($7, COALESCE((SELECT expires_at FROM access_grants WHERE id = sessions.grant_id), $7))
+            WHERE id = $1
+              AND refresh_token_hash = $2
+              AND revoked_at IS NULL
+              AND expires_at > $6
+            RETURNING
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            "#,
+        )
+        .bind(id)
+        .bind(current_hash)
+        .bind(new_hash)
+        .bind(&device.user_agent)
+        .bind(&device.ip_address)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn list_sessions(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<Vec<Session>> {
+        let sessions = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT
+                id, user_id, client_id, grant_id, user_agent, ip_address,
+                created_at, last_used_at, expires_at, revoked_at
+            FROM sessions
+            WHERE user_id = $1
+              AND revoked_at IS NULL
+              AND expires_at > $2
+            ORDER BY last_used_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn revoke_session(&self, user_id: Uuid, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = $3
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn revoke_sessions(&self, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET revoked_at = $2
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn get_totp(&self, user_id: Uuid) -> Result<Option<TotpCredential>> {
+        let credential = sqlx::query_as::<_, TotpCredential>(
+            r#"
+            SELECT user_id, secret, enabled_at, last_used_step, created_at
+            FROM totp_credentials
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn set_pending_totp(&self, user_id: Uuid, secret: &[u8]) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO totp_credentials (user_id, secret, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = excluded.secret, last_used_step = NULL, created_at = excluded.created_at
+            WHERE totp_credentials.enabled_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(secret)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn reseal_totp(&self, user_id: Uuid, current: &[u8], secret: &[u8]) -> Result<bool> {
+        let result = sqlx::query("UPDATE totp_credentials SET secret = $3 WHERE user_id = $1 AND secret = $2")
+            .bind(user_id)
+            .bind(current)
+            .bind(secret)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn enable_totp(&self, user_id: Uuid, step: i64, recovery_code_hashes: &[String]) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let enabled = sqlx::query(
+            r#"
+            UPDATE totp_credentials
+            SET enabled_at = $3, last_used_step = $2
+            WHERE user_id = $1 AND enabled_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() > 0;
+        if !enabled {
+            return Ok(false);
+        }
+
+        insert_recovery_codes(&mut tx, user_id, recovery_code_hashes).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn use_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE totp_credentials
+            SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_totp(&self, user_id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM totp_credentials WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() > 0;
+
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_recovery_codes(&mut tx, user_id, code_hashes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn consume_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn create_login_challenge(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query("DELETE FROM login_challenges WHERE expires_at < $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO login_challenges (token_hash, user_id, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn attempt_login_challenge(&self, token_hash: &str, max_attempts: i32, now: DateTime<Utc>) -> Result<Option<Uuid>> {
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            UPDATE login_challenges
+            SET attempts = attempts + 1
+            WHERE token_hash = $1 AND expires_at > $3 AND attempts < $2
+            RETURNING user_id
+            "#,
+        )
+        .bind(token_hash)
+        .bind(max_attempts)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    async fn delete_login_challenge(&self, token_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_challenges WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_login_attempts(&self, subject_hash: &str) -> Result<Option<LoginAttempts>> {
+        let attempts = sqlx::query_as::<_, LoginAttempts>(
+            r#"
+            SELECT failures, last_failure_at, locked_until
+            FROM login_attempts
+            WHERE subject_hash = $1
+            "#,
+        )
+        .bind(subject_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(attempts)
+    }
+
+    async fn record_login_failure(
+        &self,
+        subject_hash: &str,
+        now: DateTime<Utc>,
+        window_start: DateTime<Utc>,
+    ) -> Result<LoginAttempts> {
+        sqlx::query(
+            r#"
+            DELETE FROM login_attempts
+            WHERE last_failure_at < $1
+              AND (locked_until IS NULL OR locked_until < $2)
+            "#,
+        )
+        .bind(window_start)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        let attempts = sqlx::query_as::<_, LoginAttempts>(
+            r#"
+            INSERT INTO login_attempts (subject_hash, failures, last_failure_at)
+            VALUES ($1, 1, $2)
+            ON CONFLICT (subject_hash) DO UPDATE
+            SET
+                failures = CASE
+                    WHEN login_attempts.last_failure_at < $3 THEN 1
+                    ELSE login_attempts.failures + 1
+                END,
+                last_failure_at = $2
+            RETURNING failures, last_failure_at, locked_until
+            "#,
+        )
+        .bind(subject_hash)
+        .bind(now)
+        .bind(window_start)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(attempts)
+    }
+
+    async fn lock_login(&self, subject_hash: &str, locked_until: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE login_attempts SET locked_until = $2 WHERE subject_hash = $1")
+            .bind(subject_hash)
+            .bind(locked_until)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, subject_hash: &str) -> Result<()> {
+        sqlx::query("DELETE FROM login_attempts WHERE subject_hash = $1")
+            .bind(subject_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_security_event(
+        &self,
+        user_id: Uuid,
+        event: &str,
+        device: &DeviceInfo,
+        details: serde_json::Value,
+        created_at: DateTime<Utc>,
+    ) -> Result<SecurityEvent> {
+        let event = sqlx::query_as::<_, SecurityEvent>(
+            r#"
+            INSERT INTO security_events (id, user_id, event, ip_address, user_agent, details, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, user_id, event, ip_address, user_agent, details, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(event)
+        .bind(&device.ip_address)
+        .bind(&device.user_agent)
+        .bind(details)
+        .bind(created_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(event)
+    }
+
+    async fn list_security_events(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<SecurityEvent>> {
+        let events = sqlx::query_as::<_, SecurityEvent>(
+            r#"
+            SELECT id, user_id, event, ip_address, user_agent, details, created_at
+            FROM security_events
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    async fn create_personal_access_token(
+        &self,
+        user_id: Uuid,
+        input: &CreatePersonalAccessTokenInput,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<PersonalAccessToken> {
+        let row = sqlx::query_as::<_, PersonalAccessTokenRow>(
+            r#"
+            INSERT INTO personal_access_tokens (
+                id, user_id, name, token_hash, scopes, domains, expires_at, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, user_id, name, scopes, domains, expires_at, last_used_at, created_at, revoked_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(&input.name)
+        .bind(token_hash)
+        .bind(Json(&input.scopes))
+        .bind(Json(&input.domains))
+        .bind(expires_at)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    async fn use_personal_access_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<PersonalAccessToken>> {
+        let row = sqlx::query_as::<_, PersonalAccessTokenRow>(
+            r#"
+            UPDATE personal_access_tokens
+            SET last_used_at = $2
+            WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > $2
+            RETURNING id, user_id, name, scopes, domains, expires_at, last_used_at, created_at, revoked_at
+            "#,
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(PersonalAccessToken::from))
+    }
+
+    async fn list_personal_access_tokens(&self, user_id: Uuid) -> Result<Vec<PersonalAccessToken>> {
+        let rows = sqlx::query_as::<_, PersonalAccessTokenRow>(
+            r#"
+            SELECT id, user_id, name, scopes, domains, expires_at, last_used_at, created_at, revoked_at
+            FROM personal_access_tokens
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(PersonalAccessToken::from).collect())
+    }
+
+    async fn revoke_personal_access_token(&self, user_id: Uuid, id: Uuid, now: DateTime<Utc>) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE personal_access_tokens
+            SET revoked_at = $3
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Replace a user's recovery codes within a transaction
+async fn insert_recovery_codes(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> Result<()> {
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    for code_hash in code_hashes {
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{migrations, Database};
+
+    async fn repository() -> SqliteIdentityRepository {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+
+        let Database::Sqlite(pool) = db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+        SqliteIdentityRepository::new(pool)
+    }
+
+    #[tokio::test]
+    async fn creates_and_authenticates_users() {
+        let repo = repository().await;
+
+        let user = repo
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let by_email = repo.get_user_by_email("ada@example.com").await.unwrap().unwrap();
+        assert_eq!(by_email.id, user.id);
+
+        let good = repo
+            .authenticate(Credentials {
+                email: "ada@example.com".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(good.map(|u| u.id), Some(user.id));
+
+        let bad = repo
+            .authenticate(Credentials {
+                email: "ada@example.com".to_string(),
+                password: "wrong".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(bad.is_none());
+
+        let updated = repo.update_user(user.id, Some("Lovelace".to_string())).await.unwrap().unwrap();
+        assert_eq!(updated.display_name, "Lovelace");
+        assert!(updated.updated_at >= user.updated_at);
+    }
+
+    #[tokio::test]
+    async fn email_tokens_work_once() {
+        let repo = repository().await;
+        let user = repo
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(!user.is_verified());
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::hours(1);
+        let reset = EmailTokenPurpose::ResetPassword;
+
+        repo.create_email_token(user.id, reset, "first", expires_at).await.unwrap();
+        repo.create_email_token(user.id, reset, "second", expires_at).await.unwrap();
+        repo.create_email_token(user.id, EmailTokenPurpose::VerifyEmail, "verify", expires_at).await.unwrap();
+
+        // A newer token replaces the older one, and tokens only serve their purpose
+        assert!(repo.consume_email_token("first", reset, now).await.unwrap().is_none());
+        assert!(repo.consume_email_token("verify", reset, now).await.unwrap().is_none());
+        assert_eq!(repo.consume_email_token("second", reset, now).await.unwrap(), Some(user.id));
+        assert!(repo.consume_email_token("second", reset, now).await.unwrap().is_none());
+
+        repo.create_email_token(user.id, reset, "stale", expires_at).await.unwrap();
+        assert!(repo.consume_email_token("stale", reset, expires_at).await.unwrap().is_none());
+
+        assert!(repo.set_password(user.id, "battery staple").await.unwrap());
+        let credentials = Credentials {
+            email: "ada@example.com".to_string(),
+            password: "battery staple".to_string(),
+        };
+        assert!(repo.authenticate(credentials).await.unwrap().is_some());
+
+        let verified = repo.mark_email_verified(user.id).await.unwrap().unwrap();
+        assert!(verified.is_verified());
+    }
+
+    #[tokio::test]
+    async fn rotates_and_revokes_sessions() {
+        let repo = repository().await;
+        let user = repo
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+        let device = DeviceInfo {
+            user_agent: Some("curl/8".to_string()),
+            ip_address: Some("192.0.2.1".to_string()),
+        };
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::days(1);
+
+        let session = repo.create_session(user.id, None, "first", &device, expires_at).await.unwrap();
+        assert_eq!(session.user_agent.as_deref(), Some("curl/8"));
+
+        let moved = DeviceInfo {
+            user_agent: None,
+            ip_address: Some("192.0.2.2".to_string()),
+        };
+        let rotated = repo.rotate_session(session.id, "first", "second", &moved, now, expires_at).await.unwrap().unwrap();
+        assert_eq!(rotated.user_agent.as_deref(), Some("curl/8"));
+        assert_eq!(rotated.ip_address.as_deref(), Some("192.0.2.2"));
+
+        // The old hash no longer matches
+        assert!(repo.rotate_session(session.id, "first", "third", &moved, now, expires_at).await.unwrap().is_none());
+        assert_eq!(repo.list_sessions(user.id, now).await.unwrap().len(), 1);
+        assert!(repo.list_sessions(user.id, expires_at).await.unwrap().is_empty());
+
+        assert!(repo.revoke_session(user.id, session.id).await.unwrap());
+        assert!(!repo.revoke_session(user.id, session.id).await.unwrap());
+        assert!(repo.rotate_session(session.id, "second", "third", &moved, now, expires_at).await.unwrap().is_none());
+        assert!(repo.list_sessions(user.id, now).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn totp_steps_and_recovery_codes_work_once() {
+        let repo = repository().await;
+        let user = repo
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert!(repo.set_pending_totp(user.id, b"first").await.unwrap());
+        assert!(repo.set_pending_totp(user.id, b"second").await.unwrap());
+        let codes = ["a".to_string(), "b".to_string()];
+        assert!(repo.enable_totp(user.id, 10, &codes).await.unwrap());
+        assert!(!repo.enable_totp(user.id, 11, &codes).await.unwrap());
+
+        // An enabled secret is only replaced by disabling it first
+        assert!(!repo.set_pending_totp(user.id, b"third").await.unwrap());
+        let credential = repo.get_totp(user.id).await.unwrap().unwrap();
+        assert_eq!(credential.secret, b"second");
+        assert!(credential.enabled_at.is_some());
+
+        assert!(!repo.use_totp_step(user.id, 10).await.unwrap());
+        assert!(repo.use_totp_step(user.id, 11).await.unwrap());
+        assert!(!repo.use_totp_step(user.id, 11).await.unwrap());
+
+        assert!(repo.consume_recovery_code(user.id, "a").await.unwrap());
+        assert!(!repo.consume_recovery_code(user.id, "a").await.unwrap());
+        assert_eq!(repo.count_recovery_codes(user.id).await.unwrap(), 1);
+
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::minutes(5);
+        repo.create_login_challenge(user.id, "challenge", now, expires_at).await.unwrap();
+        assert_eq!(repo.attempt_login_challenge("challenge", 2, expires_at).await.unwrap(), None);
+        assert_eq!(repo.attempt_login_challenge("challenge", 2, now).await.unwrap(), Some(user.id));
+        assert_eq!(repo.attempt_login_challenge("challenge", 2, now).await.unwrap(), Some(user.id));
+        assert_eq!(repo.attempt_login_challenge("challenge", 2, now).await.unwrap(), None);
+
+        assert!(repo.delete_totp(user.id).await.unwrap());
+        assert!(repo.get_totp(user.id).await.unwrap().is_none());
+        assert_eq!(repo.count_recovery_codes(user.id).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn personal_access_tokens_work_until_revoked_or_expired() {
+        let repo = repository().await;
+        let user = repo
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+        let input = CreatePersonalAccessTokenInput {
+            name: "backup script".to_string(),
+            scopes: vec!["read".to_string()],
+            domains: vec!["travel".to_string(), "health".to_string()],
+            expires_in_days: None,
+        };
+        let now = Utc::now();
+
+        let token = repo.create_personal_access_token(user.id, &input, "hash", now + chrono::Duration::days(1)).await.unwrap();
+        assert_eq!(token.domains, ["travel", "health"]);
+        assert!(token.last_used_at.is_none());
+
+        let used = repo.use_personal_access_token("hash", now).await.unwrap().unwrap();
+        assert_eq!(used.id, token.id);
+        assert_eq!(used.last_used_at, Some(now));
+        assert!(repo.use_personal_access_token("other", now).await.unwrap().is_none());
+        assert!(repo.use_personal_access_token("hash", now + chrono::Duration::days(2)).await.unwrap().is_none());
+
+        assert_eq!(repo.list_personal_access_tokens(user.id).await.unwrap().len(), 1);
+        assert!(repo.revoke_personal_access_token(user.id, token.id, now).await.unwrap());
+        assert!(!repo.revoke_personal_access_token(user.id, token.id, now).await.unwrap());
+        assert!(repo.use_personal_access_token("hash", now).await.unwrap().is_none());
+        assert!(repo.list_personal_access_tokens(user.id).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Limits on failed logins, per account and per source address
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::models::LoginAttempts;
+use crate::utils::tokens::sha256_base64url;
+
+/// How failed logins are slowed down and locked out
+#[derive(Debug, Clone)]
+pub struct LoginLimits {
+    /// Failed logins to an account before it is locked
+    pub max_failures: i32,
+    /// Failed logins from an address, to any account, before it is locked out
+    pub max_failures_per_ip: i32,
+    /// How long a failure counts after the one before it
+    pub failure_window: Duration,
+    /// How long a lockout lasts
+    pub lockout: Duration,
+    /// Wait after the first failed login to an account, doubled after each further one
+    pub base_delay: Duration,
+    /// Longest wait between failed logins before the lockout
+    pub max_delay: Duration,
+}
+
+impl Default for LoginLimits {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            max_failures_per_ip: 20,
+            failure_window: Duration::minutes(15),
+            lockout: Duration::minutes(15),
+            base_delay: Duration::seconds(1),
+            max_delay: Duration::seconds(30),
+        }
+    }
+}
+
+/// What failed logins count against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginSubject {
+    /// The email address logged in with, whether or not an account has it
+    Account(String),
+    /// The address the login came from
+    Address(String),
+}
+
+impl LoginSubject {
+    /// Hash failures are stored under, so addresses and unknown emails are not kept
+    pub fn hash(&self) -> String {
+        match self {
+            Self::Account(email) => sha256_base64url(&format!("account:{}", email.trim().to_lowercase())),
+            Self::Address(ip) => sha256_base64url(&format!("ip:{}", ip)),
+        }
+    }
+}
+
+impl LoginLimits {
+    /// When failures that came before `now` stop counting
+    pub fn window_start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - self.failure_window
+    }
+
+    /// End of the lockout started by the latest failure, if it reached the limit
+    pub fn lockout_after(&self, subject: &LoginSubject, failures: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let max_failures = match subject {
+            LoginSubject::Account(_) => self.max_failures,
+            LoginSubject::Address(_) => self.max_failures_per_ip,
+        };
+
+        (failures >= max_failures).then(|| now + self.lockout)
+    }
+
+    /// Wait required after a number of failures
+    ///
+    /// Only accounts are slowed down; an address may be shared by many users.
+    pub fn delay(&self, subject: &LoginSubject, failures: i32) -> Duration {
+        if failures <= 0 || matches!(subject, LoginSubject::Address(_)) {
+            return Duration::zero();
+        }
+
+        let doublings = (failures - 1).min(30) as u32;
+        self.base_delay
+            .checked_mul(2i32.saturating_pow(doublings))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    /// How long the subject must wait before its next login, if at all
+    pub fn retry_after(&self, subject: &LoginSubject, attempts: &LoginAttempts, now: DateTime<Utc>) -> Option<Duration> {
+        if let Some(locked_until) = attempts.locked_until.filter(|until| *until > now) {
+            return Some(locked_until - now);
+        }
+        if attempts.last_failure_at < self.window_start(now) {
+            return None;
+        }
+
+        let ready_at = attempts.last_failure_at + self.delay(subject, attempts.failures);
+        (ready_at > now).then(|| ready_at - now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempts(failures: i32, last_failure_at: DateTime<Utc>, locked_until: Option<DateTime<Utc>>) -> LoginAttempts {
+        LoginAttempts { failures, last_failure_at, locked_until }
+    }
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        let limits = LoginLimits::default();
+        let account = LoginSubject::Account("ada@example.com".to_string());
+        let address = LoginSubject::Address("192.0.2.1".to_string());
+
+        let delays: Vec<i64> = (0..8).map(|n| limits.delay(&account, n).num_seconds()).collect();
+        assert_eq!(delays, [0, 1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(limits.delay(&account, i32::MAX), limits.max_delay);
+        assert_eq!(limits.delay(&address, 3), Duration::zero());
+    }
+
+    #[test]
+    fn waits_out_delays_lockouts_and_the_window() {
+        let limits = LoginLimits::default();
+        let account = LoginSubject::Account("ada@example.com".to_string());
+        let now = Utc::now();
+
+        let recent = attempts(3, now - Duration::seconds(1), None);
+        assert_eq!(limits.retry_after(&account, &recent, now), Some(Duration::seconds(3)));
+        assert_eq!(limits.retry_after(&account, &recent, now + Duration::seconds(3)), None);
+
+        let locked = attempts(5, now, limits.lockout_after(&account, 5, now));
+        assert_eq!(limits.retry_after(&account, &locked, now), Some(limits.lockout));
+        assert_eq!(limits.retry_after(&account, &locked, now + limits.lockout), None);
+        assert_eq!(limits.lockout_after(&account, 4, now), None);
+
+        let address = LoginSubject::Address("192.0.2.1".to_string());
+        assert_eq!(limits.lockout_after(&address, 5, now), None);
+        assert!(limits.lockout_after(&address, 20, now).is_some());
+    }
+
+    #[test]
+    fn accounts_are_counted_case_insensitively() {
+        let lower = LoginSubject::Account("ada@example.com".to_string());
+        let upper = LoginSubject::Account(" Ada@Example.com".to_string());
+        assert_eq!(lower.hash(), upper.hash());
+        assert_ne!(lower.hash(), LoginSubject::Address("ada@example.com".to_string()).hash());
+    }
+}
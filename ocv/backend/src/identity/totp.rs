@@ -0,0 +1,127 @@
+//! Time-based one-time passwords (RFC 6238) and recovery codes
+
+use chrono::{DateTime, Utc};
+use totp_rs::{Algorithm, TOTP};
+
+/// Issuer shown in authenticator apps
+const ISSUER: &str = "Open Context Vault";
+
+/// Length of generated secrets; RFC 4226 recommends 160 bits
+const SECRET_BYTES: usize = 20;
+
+/// Seconds each code is valid for
+pub const STEP: u64 = 30;
+
+/// Steps either side of the current one that are accepted, for clock drift
+const SKEW_STEPS: i64 = 1;
+
+/// Number of recovery codes issued at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Alphabet of recovery codes, without easily confused letters
+const RECOVERY_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// Generate a new TOTP secret
+pub fn generate_secret() -> Vec<u8> {
+    sodiumoxide::randombytes::randombytes(SECRET_BYTES)
+}
+
+fn totp(secret: &[u8], account: &str) -> TOTP {
+    TOTP::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        0,
+        STEP,
+        secret.to_vec(),
+        Some(ISSUER.to_string()),
+        account.to_string(),
+    )
+}
+
+/// Base32 form of a secret, for typing into an authenticator app
+pub fn secret_base32(secret: &[u8]) -> String {
+    totp(secret, "").get_secret_base32()
+}
+
+/// `otpauth://` URI that authenticator apps import from a QR code
+pub fn provisioning_uri(secret: &[u8], account: &str) -> String {
+    totp(secret, account).get_url()
+}
+
+/// Code for the time step containing `at`
+pub fn code_at(secret: &[u8], at: DateTime<Utc>) -> String {
+    totp(secret, "").generate(at.timestamp() as u64)
+}
+
+/// Time step of the code, if it is valid at `now`
+pub fn matching_step(secret: &[u8], code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let totp = totp(secret, "");
+    let current = now.timestamp() / STEP as i64;
+
+    (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .find(|step| totp.check(code.trim(), (step * STEP as i64) as u64))
+}
+
+/// Whether a code looks like a TOTP code rather than a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+    let code = code.trim();
+    code.len() == 6 && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Generate a fresh set of recovery codes, formatted `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = sodiumoxide::randombytes::randombytes(10)
+                .iter()
+                .map(|b| RECOVERY_ALPHABET[(b % 32) as usize] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+/// Recovery code as it is hashed, ignoring case, spaces and dashes
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn accepts_codes_within_one_step_of_now() {
+        let secret = generate_secret();
+        let now = Utc::now();
+        let step = now.timestamp() / STEP as i64;
+
+        assert_eq!(matching_step(&secret, &code_at(&secret, now), now), Some(step));
+        let late = code_at(&secret, now - Duration::seconds(30));
+        assert_eq!(matching_step(&secret, &late, now), Some(step - 1));
+        let stale = code_at(&secret, now - Duration::seconds(90));
+        assert_eq!(matching_step(&secret, &stale, now), None);
+        assert_eq!(matching_step(&generate_secret(), &code_at(&secret, now), now), None);
+    }
+
+    #[test]
+    fn provisioning_uri_names_the_account() {
+        let secret = generate_secret();
+        let uri = provisioning_uri(&secret, "ada@example.com");
+
+        assert!(uri.starts_with("otpauth://totp/Open%20Context%20Vault:ada%40example.com?"));
+        assert!(uri.contains(&format!("secret={}", secret_base32(&secret))));
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|code| code.len() == 11 && !is_totp_code(code)));
+        assert_eq!(normalize_recovery_code(" AB12c-DEf34 "), "ab12cdef34");
+    }
+}
@@ -0,0 +1,13 @@
+pub mod adapters;
+pub mod api;
+pub mod clients;
+pub mod config;
+pub mod consent_manager;
+pub mod context_management;
+pub mod encryption;
+pub mod identity;
+pub mod mailer;
+pub mod oauth;
+pub mod policy_engine;
+pub mod storage;
+pub mod utils;
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use std::env;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use super::{Email, Mailer};
+
+/// Writes emails to files or standard output instead of delivering them
+///
+/// Meant for local development and tests: each email is written as a small
+/// RFC 5322 message, so links in it can be followed by hand.
+pub struct FileMailer {
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    /// Write each email to its own `.eml` file in `dir`, creating it if needed
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create mail directory {}", dir.display()))?;
+
+        Ok(Self { dir: Some(dir) })
+    }
+
+    /// Print emails to standard output
+    pub fn stdout() -> Self {
+        Self { dir: None }
+    }
+
+    /// Create a file mailer writing to `MAIL_DIR`
+    pub fn from_env() -> Result<Self> {
+        Self::new(env::var("MAIL_DIR").context("MAIL_DIR must be set")?)
+    }
+
+    /// Emails written so far, oldest first
+    pub fn sent(&self) -> Result<Vec<Email>> {
+        let Some(dir) = &self.dir else {
+            return Ok(Vec::new());
+        };
+
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "eml"));
+        paths.sort();
+
+        paths.iter().map(|path| parse(&std::fs::read_to_string(path)?)).collect()
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    fn name(&self) -> &'static str {
+        if self.dir.is_some() { "file" } else { "stdout" }
+    }
+
+    async fn send(&self, email: Email) -> Result<()> {
+        let message = format!("To: {}\nSubject: {}\n\n{}\n", email.to, email.subject, email.body);
+
+        match &self.dir {
+            Some(dir) => {
+                // Timestamps keep the files in sending order
+                let name = format!("{}-{}.eml", Utc::now().format("%Y%m%dT%H%M%S%.6f"), Uuid::new_v4());
+                std::fs::write(dir.join(name), message)?;
+            }
+            None => println!("{}", message),
+        }
+
+        Ok(())
+    }
+}
+
+/// Read back a message written by `send`
+fn parse(message: &str) -> Result<Email> {
+    let (headers, body) = message.split_once("\n\n").context("Malformed email file")?;
+    let header = |name: &str| {
+        headers.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(str::to_string)
+            .with_context(|| format!("Email file has no {} header", name))
+    };
+
+    Ok(Email {
+        to: header("To: ")?,
+        subject: header("Subject: ")?,
+        body: body.trim_end_matches('\n').to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn writes_emails_that_can_be_read_back() {
+        let dir = env::temp_dir().join(format!("ocv-mail-{}", Uuid::new_v4()));
+        let mailer = FileMailer::new(&dir).unwrap();
+
+        for subject in ["First", "Second"] {
+            mailer
+                .send(Email {
+                    to: "ada@example.com".to_string(),
+                    subject: subject.to_string(),
+                    body: "Hello\n\nhttps://vault.example.com/verify-email?token=abc".to_string(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let sent = mailer.sent().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].subject, "First");
+        assert_eq!(sent[1].to, "ada@example.com");
+        assert!(sent[1].body.ends_with("token=abc"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
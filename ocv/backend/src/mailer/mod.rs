@@ -0,0 +1,66 @@
+pub mod file;
+pub mod smtp;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub use file::FileMailer;
+pub use smtp::SmtpMailer;
+
+/// A plain-text email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    /// Recipient address
+    pub to: String,
+    /// Subject line
+    pub subject: String,
+    /// Plain-text body
+    pub body: String,
+}
+
+/// Sends account emails such as verification and password reset links
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Send an email
+    async fn send(&self, email: Email) -> Result<()>;
+}
+
+/// Which mailer a deployment uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailBackend {
+    /// SMTP relay configured with `SMTP_*`
+    Smtp,
+    /// One file per email in `MAIL_DIR`
+    File,
+    /// Print emails to standard output
+    Stdout,
+}
+
+impl FromStr for MailBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "smtp" => Ok(Self::Smtp),
+            "file" => Ok(Self::File),
+            "stdout" | "console" => Ok(Self::Stdout),
+            other => Err(anyhow::anyhow!("Unknown mail backend: {}", other)),
+        }
+    }
+}
+
+impl MailBackend {
+    /// Build the mailer, reading its settings from the environment
+    pub fn mailer_from_env(self) -> Result<Arc<dyn Mailer>> {
+        Ok(match self {
+            Self::Smtp => Arc::new(SmtpMailer::from_env()?),
+            Self::File => Arc::new(FileMailer::from_env()?),
+            Self::Stdout => Arc::new(FileMailer::stdout()),
+        })
+    }
+}
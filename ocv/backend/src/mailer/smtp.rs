@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::env;
+use std::str::FromStr;
+
+use super::{Email, Mailer};
+
+/// How the SMTP connection is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS, usually on port 587
+    Starttls,
+    /// Implicit TLS, usually on port 465
+    Tls,
+    /// No encryption, only for local relays
+    None,
+}
+
+impl FromStr for SmtpTls {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "starttls" => Ok(Self::Starttls),
+            "tls" | "smtps" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            other => Err(anyhow::anyhow!("Unknown SMTP TLS mode: {}", other)),
+        }
+    }
+}
+
+/// Configuration for an SMTP relay
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    /// Relay host name
+    pub host: String,
+    /// Relay port
+    pub port: u16,
+    /// How the connection is secured
+    pub tls: SmtpTls,
+    /// Username and password, if the relay requires them
+    pub credentials: Option<(String, String)>,
+    /// Sender address, e.g. `Open Context Vault <vault@example.com>`
+    pub from: String,
+}
+
+impl SmtpConfig {
+    /// Load from `SMTP_HOST`, `SMTP_PORT`, `SMTP_TLS`, `SMTP_USERNAME`, `SMTP_PASSWORD` and `MAIL_FROM`
+    pub fn from_env() -> Result<Self> {
+        let tls = match env::var("SMTP_TLS") {
+            Ok(tls) => tls.parse()?,
+            Err(_) => SmtpTls::Starttls,
+        };
+        let default_port = match tls {
+            SmtpTls::Tls => 465,
+            SmtpTls::Starttls | SmtpTls::None => 587,
+        };
+
+        Ok(Self {
+            host: env::var("SMTP_HOST").context("SMTP_HOST must be set")?,
+            port: match env::var("SMTP_PORT") {
+                Ok(port) => port.parse().context("Invalid value for SMTP_PORT")?,
+                Err(_) => default_port,
+            },
+            tls,
+            credentials: match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+                (Ok(username), Ok(password)) => Some((username, password)),
+                _ => None,
+            },
+            from: env::var("MAIL_FROM").context("MAIL_FROM must be set")?,
+        })
+    }
+}
+
+/// Delivers emails through an SMTP relay
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// Create a new SMTP mailer
+    ///
+    /// No connection is made until the first email is sent.
+    pub fn new(config: SmtpConfig) -> Result<Self> {
+        let builder = match config.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+        };
+        let builder = builder.port(config.port);
+        let builder = match config.credentials {
+            Some((username, password)) => builder.credentials(Credentials::new(username, password)),
+            None => builder,
+        };
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse().context("Invalid MAIL_FROM address")?,
+        })
+    }
+
+    /// Create an SMTP mailer configured from the environment
+    pub fn from_env() -> Result<Self> {
+        Self::new(SmtpConfig::from_env()?)
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, email: Email) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse().with_context(|| format!("Invalid recipient {}", email.to))?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
@@ -1,43 +1,161 @@
-use tiny_http::{Server, Response, Header};
-use serde::{Serialize, Deserialize};
-use std::str::FromStr;
-
-#[derive(Serialize, Deserialize)]
-struct HealthResponse {
-    status: String,
-    version: String,
+use actix_web::{middleware::Logger, web, App, HttpServer};
+use std::sync::Arc;
+
+use open_context_vault::{
+    api::{self, schema, AppState, TrustedProxies},
+    clients::ClientService,
+    config::{Config, MemoryBackend},
+    consent_manager::ConsentManager,
+    context_management::{ContextService, KeyRotationJob},
+    encryption::EncryptionService,
+    identity::{AccountDeletionJob, IdentityService, JwtKeys},
+    oauth::OAuthService,
+    policy_engine::PolicyEngine,
+    storage::{migrations, Database},
+    utils::clock::{Clock, SystemClock},
+};
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let config = Config::from_env()?;
+
+    // Connect to the database
+    let db = Database::connect(&config.database_url, config.database_max_connections).await?;
+
+    if config.run_migrations {
+        log::info!("Applying pending migrations");
+        migrations::run_migrations(&db).await?;
+    }
+
+    // Build services
+    let key_provider = config.key_backend.provider_from_env()?;
+    log::info!("Wrapping data keys with the {} key provider", key_provider.name());
+    let encryption_service = EncryptionService::new(&db, key_provider);
+    encryption_service.verify_key_provider().await?;
+    let policy_engine = PolicyEngine::new(&db);
+    policy_engine.install_default_policies().await?;
+    policy_engine.require_two_factor_for(config.sensitive_domains.clone());
+    let context_service = match config.memory_backend {
+        MemoryBackend::Database => ContextService::new_with_database(&db, encryption_service.clone()),
+        MemoryBackend::Mem0 => ContextService::new_with_mem0(encryption_service.clone()),
+        MemoryBackend::InMemory => ContextService::new_in_memory(encryption_service.clone()),
+    };
+    let client_service = ClientService::new(
+        &db,
+        policy_engine.clone(),
+        config.dynamic_client_registration,
+        &config.jwt.issuer,
+    );
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let mailer = config.mail_backend.mailer_from_env()?;
+    log::info!("Sending account emails with the {} mailer", mailer.name());
+    let identity_service = IdentityService::new(
+        &db,
+        encryption_service.clone(),
+        JwtKeys::from_config(&config.jwt)?,
+        mailer,
+        &config.ui_url,
+        config.login_limits.clone(),
+        clock.clone(),
+    );
+    identity_service.set_admins(config.admin_emails.clone());
+    let consent_manager = ConsentManager::new(
+        &db,
+        policy_engine.clone(),
+        client_service.clone(),
+        identity_service.clone(),
+        clock,
+    );
+    let key_rotation = KeyRotationJob::new(
+        context_service.clone(),
+        encryption_service.clone(),
+        consent_manager.clone(),
+        identity_service.clone(),
+    );
+    let account_deletion = AccountDeletionJob::new(
+        identity_service.clone(),
+        consent_manager.clone(),
+        client_service.clone(),
+        context_service.clone(),
+        encryption_service.clone(),
+    );
+    let oauth_service = OAuthService::new(
+        &db,
+        consent_manager.clone(),
+        identity_service.clone(),
+        client_service.clone(),
+        &config.consent_url,
+    );
+
+    let state = Arc::new(AppState {
+        db: db.clone(),
+        context_service,
+        consent_manager,
+        encryption_service,
+        policy_engine,
+        identity_service,
+        key_rotation,
+        account_deletion,
+        oauth_service,
+        client_service,
+    });
+
+    let schema = schema::schema_builder().data(state.clone()).finish();
+    let trusted_proxies = web::Data::new(TrustedProxies::new(config.trusted_proxies.clone()));
+
+    log::info!("Server running at http://{}/", config.bind_address);
+
+    // Signals are handled below so the database is only closed once in-flight requests finish
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(Logger::default())
+            .app_data(web::Data::from(state.clone()))
+            .app_data(web::Data::new(schema.clone()))
+            .app_data(trusted_proxies.clone())
+            .configure(api::configure)
+    })
+    .bind(&config.bind_address)?
+    .shutdown_timeout(config.shutdown_timeout)
+    .disable_signals()
+    .run();
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutdown signal received, draining connections");
+        handle.stop(true).await;
+    });
+
+    server.await?;
+    db.close().await;
+
+    Ok(())
 }
 
-fn main() {
-    // Create a server listening on port 8000
-    let server = Server::http("0.0.0.0:8000").unwrap();
-    println!("Server running at http://0.0.0.0:8000/");
-
-    for request in server.incoming_requests() {
-        let url = request.url();
-        
-        match url {
-            "/" => {
-                let response = Response::from_string("Open Context Vault API")
-                    .with_header(Header::from_str("Content-Type: text/plain").unwrap());
-                request.respond(response).unwrap();
-            },
-            "/api/health" => {
-                let health = HealthResponse {
-                    status: "ok".to_string(),
-                    version: env!("CARGO_PKG_VERSION").to_string(),
-                };
-                
-                let json = serde_json::to_string(&health).unwrap();
-                let response = Response::from_string(json)
-                    .with_header(Header::from_str("Content-Type: application/json").unwrap());
-                request.respond(response).unwrap();
-            },
-            _ => {
-                let response = Response::from_string("Not Found")
-                    .with_status_code(404);
-                request.respond(response).unwrap();
-            }
-        }
+/// Wait for SIGTERM or Ctrl+C
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
 }
@@ -0,0 +1,107 @@
+use async_graphql::{Context, ErrorExtensions, Object, ID};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::models::AuthorizationRequest;
+use crate::api::auth::account_owner;
+use crate::clients::graphql::GraphQLClient;
+use crate::api::AppState;
+use crate::consent_manager::graphql::consent_error;
+use crate::utils::errors::AppError;
+
+/// GraphQL representation of an authorization request awaiting consent
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLAuthorizationRequest {
+    /// Unique identifier
+    pub id: ID,
+    /// Client asking for access
+    pub client_id: String,
+    /// Registered details of the client, to show on the consent page
+    pub client: Option<GraphQLClient>,
+    /// Where the user is sent back to
+    pub redirect_uri: String,
+    /// Requested scopes
+    pub scopes: Vec<String>,
+    /// Requested context domains
+    pub context_domains: Vec<String>,
+    /// Deadline for approval
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<AuthorizationRequest> for GraphQLAuthorizationRequest {
+    fn from(request: AuthorizationRequest) -> Self {
+        Self {
+            id: ID(request.id.to_string()),
+            client_id: request.client_id,
+            client: None,
+            redirect_uri: request.redirect_uri,
+            scopes: request.scopes,
+            context_domains: request.context_domains,
+            expires_at: request.expires_at,
+        }
+    }
+}
+
+/// OAuth query root
+#[derive(Default)]
+pub struct OAuthQuery;
+
+#[Object]
+impl OAuthQuery {
+    /// Get an authorization request that is waiting for consent
+    async fn authorization_request(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<Option<GraphQLAuthorizationRequest>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        account_owner(ctx, None)?;
+        let uuid = Uuid::parse_str(&id.0)?;
+        
+        let Some(request) = state.oauth_service.pending_request(uuid).await? else {
+            return Ok(None);
+        };
+        let client = state.client_service.get_client(&request.client_id).await?;
+        
+        Ok(Some(GraphQLAuthorizationRequest {
+            client: client.map(GraphQLClient::from),
+            ..request.into()
+        }))
+    }
+}
+
+/// OAuth mutation root
+#[derive(Default)]
+pub struct OAuthMutation;
+
+#[Object]
+impl OAuthMutation {
+    /// Approve an authorization request, granting the client the access it asked for
+    ///
+    /// Returns the URL to send the user back to the client with.
+    async fn approve_authorization(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<String> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let user_uuid = account_owner(ctx, None)?;
+        let uuid = Uuid::parse_str(&id.0)?;
+        
+        state.oauth_service.approve(uuid, user_uuid).await.map_err(consent_error)?
+            .ok_or_else(|| not_pending(&id))
+    }
+    
+    /// Deny an authorization request
+    ///
+    /// Returns the URL to send the user back to the client with.
+    async fn deny_authorization(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<String> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        account_owner(ctx, None)?;
+        let uuid = Uuid::parse_str(&id.0)?;
+        
+        state.oauth_service.deny(uuid).await?
+            .ok_or_else(|| not_pending(&id))
+    }
+}
+
+fn not_pending(id: &ID) -> async_graphql::Error {
+    AppError::NotFound(format!("No pending authorization request {}", id.0)).extend()
+}
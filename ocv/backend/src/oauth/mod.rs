@@ -0,0 +1,9 @@
+pub mod models;
+pub mod repository;
+pub mod service;
+pub mod sqlite;
+pub mod graphql;
+
+// Re-export key types
+pub use models::{AuthorizationRequest, OAuthError};
+pub use service::OAuthService;
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+/// A client's request for access, from the authorize redirect to the code exchange
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuthorizationRequest {
+    /// Unique identifier, used by the consent UI
+    pub id: Uuid,
+
+    /// Client asking for access
+    pub client_id: String,
+
+    /// Where the user is sent back to
+    pub redirect_uri: String,
+
+    /// Requested scopes (e.g., "read", "write")
+    pub scopes: Vec<String>,
+
+    /// Requested context domains
+    pub context_domains: Vec<String>,
+
+    /// Opaque value returned to the client unchanged
+    pub state: Option<String>,
+
+    /// PKCE S256 challenge the code verifier must match
+    pub code_challenge: String,
+
+    /// User who approved the request
+    pub user_id: Option<Uuid>,
+
+    /// Grant created on approval
+    pub grant_id: Option<Uuid>,
+
+    /// Session the code was exchanged for
+    pub session_id: Option<Uuid>,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Deadline for approval, then for exchanging the code
+    pub expires_at: DateTime<Utc>,
+
+    /// When the code was exchanged for tokens
+    pub exchanged_at: Option<DateTime<Utc>>,
+}
+
+/// Input for recording a new authorization request
+#[derive(Debug, Clone)]
+pub struct NewAuthorizationRequest {
+    /// Client asking for access
+    pub client_id: String,
+
+    /// Where the user is sent back to
+    pub redirect_uri: String,
+
+    /// Requested scopes
+    pub scopes: Vec<String>,
+
+    /// Requested context domains
+    pub context_domains: Vec<String>,
+
+    /// Opaque value returned to the client unchanged
+    pub state: Option<String>,
+
+    /// PKCE S256 challenge
+    pub code_challenge: String,
+}
+
+/// Query parameters of the authorization endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthorizeParams {
+    /// Must be `code`
+    pub response_type: Option<String>,
+    /// Client asking for access
+    pub client_id: Option<String>,
+    /// Where the user is sent back to
+    pub redirect_uri: Option<String>,
+    /// Space-separated scopes and `domain:<name>` entries
+    pub scope: Option<String>,
+    /// Opaque value returned to the client unchanged
+    pub state: Option<String>,
+    /// Base64url SHA-256 of the code verifier
+    pub code_challenge: Option<String>,
+    /// Must be `S256`
+    pub code_challenge_method: Option<String>,
+}
+
+/// Form parameters of the token endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TokenParams {
+    /// `authorization_code` or `refresh_token`
+    pub grant_type: Option<String>,
+    /// Code from the authorization redirect
+    pub code: Option<String>,
+    /// Redirect URI the code was requested with
+    pub redirect_uri: Option<String>,
+    /// Client the code or refresh token was issued to
+    pub client_id: Option<String>,
+    /// Secret of `client_secret_post` clients
+    pub client_secret: Option<String>,
+    /// Type of `client_assertion`, for `private_key_jwt` clients
+    pub client_assertion_type: Option<String>,
+    /// JWT signed with one of the client's registered keys
+    pub client_assertion: Option<String>,
+    /// PKCE verifier matching the request's challenge
+    pub code_verifier: Option<String>,
+    /// Refresh token to rotate
+    pub refresh_token: Option<String>,
+}
+
+/// Successful token endpoint response (RFC 6749 section 5.1)
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenResponse {
+    /// Access token for the vault API
+    pub access_token: String,
+
+    /// Always `Bearer`
+    pub token_type: &'static str,
+
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+
+    /// Single-use token for the `refresh_token` grant
+    pub refresh_token: String,
+
+    /// Granted scope, when issued from an authorization code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+/// Errors reported to OAuth clients (RFC 6749 sections 4.1.2.1 and 5.2)
+#[derive(Error, Debug)]
+pub enum OAuthError {
+    #[error("{0}")]
+    InvalidRequest(String),
+
+    #[error("{0}")]
+    InvalidGrant(String),
+
+    #[error("{0}")]
+    InvalidScope(String),
+
+    #[error("Unsupported grant type {0}")]
+    UnsupportedGrantType(String),
+
+    #[error("Unsupported response type {0}")]
+    UnsupportedResponseType(String),
+}
+
+impl OAuthError {
+    /// Value of the `error` parameter
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidGrant(_) => "invalid_grant",
+            OAuthError::InvalidScope(_) => "invalid_scope",
+            OAuthError::UnsupportedGrantType(_) => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType(_) => "unsupported_response_type",
+        }
+    }
+}
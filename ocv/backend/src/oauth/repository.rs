@@ -0,0 +1,215 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::models::{AuthorizationRequest, NewAuthorizationRequest};
+
+/// Storage for OAuth authorization requests and their codes
+#[async_trait]
+pub trait OAuthRepository: Send + Sync {
+    /// Record a request awaiting the user's consent
+    async fn create_request(
+        &self,
+        input: NewAuthorizationRequest,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthorizationRequest>;
+
+    /// Get a request by ID
+    async fn get_request(&self, id: Uuid) -> Result<Option<AuthorizationRequest>>;
+
+    /// Record a user's approval of a pending request and the hash of its code
+    ///
+    /// Returns `None` if the request expired or was already approved.
+    async fn approve_request(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        grant_id: Uuid,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<AuthorizationRequest>>;
+
+    /// Delete a request
+    async fn delete_request(&self, id: Uuid) -> Result<bool>;
+
+    /// Mark the code with this hash exchanged, if it is unexpired and unused
+    async fn exchange_code(&self, code_hash: &str) -> Result<Option<AuthorizationRequest>>;
+
+    /// Get the request a code was issued for, whether or not it was exchanged
+    async fn get_request_by_code(&self, code_hash: &str) -> Result<Option<AuthorizationRequest>>;
+
+    /// Remember the session a code was exchanged for
+    async fn set_session(&self, id: Uuid, session_id: Uuid) -> Result<()>;
+}
+
+/// Postgres repository for OAuth authorization requests
+pub struct PgOAuthRepository {
+    pool: PgPool,
+}
+
+impl PgOAuthRepository {
+    /// Create a new OAuth repository
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OAuthRepository for PgOAuthRepository {
+    /// Record a request awaiting the user's consent
+    async fn create_request(
+        &self,
+        input: NewAuthorizationRequest,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthorizationRequest> {
+        let request = sqlx::query_as::<_, AuthorizationRequest>(
+            r#"
+            INSERT INTO authorization_requests (
+                client_id, redirect_uri, scopes, context_domains, state, code_challenge, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            "#,
+        )
+        .bind(input.client_id)
+        .bind(input.redirect_uri)
+        .bind(&input.scopes)
+        .bind(&input.context_domains)
+        .bind(input.state)
+        .bind(input.code_challenge)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Get a request by ID
+    async fn get_request(&self, id: Uuid) -> Result<Option<AuthorizationRequest>> {
+        let request = sqlx::query_as::<_, AuthorizationRequest>(
+            r#"
+            SELECT
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            FROM authorization_requests
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Record a user's approval of a pending request
+    async fn approve_request(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        grant_id: Uuid,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<AuthorizationRequest>> {
+        let request = sqlx::query_as::<_, AuthorizationRequest>(
+            r#"
+            UPDATE authorization_requests
+            SET
+                user_id = $2,
+                grant_id = $3,
+                code_hash = $4,
+                expires_at = $5
+            WHERE id = $1
+              AND user_id IS NULL
+              AND expires_at > NOW()
+            RETURNING
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(grant_id)
+        .bind(code_hash)
+        .bind(expires_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Delete a request
+    async fn delete_request(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM authorization_requests
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Mark the code with this hash exchanged, if it is unexpired and unused
+    async fn exchange_code(&self, code_hash: &str) -> Result<Option<AuthorizationRequest>> {
+        let request = sqlx::query_as::<_, AuthorizationRequest>(
+            r#"
+            UPDATE authorization_requests
+            SET exchanged_at = NOW()
+            WHERE code_hash = $1
+              AND exchanged_at IS NULL
+              AND expires_at > NOW()
+            RETURNING
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            "#,
+        )
+        .bind(code_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Get the request a code was issued for
+    async fn get_request_by_code(&self, code_hash: &str) -> Result<Option<AuthorizationRequest>> {
+        let request = sqlx::query_as::<_, AuthorizationRequest>(
+            r#"
+            SELECT
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            FROM authorization_requests
+            WHERE code_hash = $1
+            "#,
+        )
+        .bind(code_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Remember the session a code was exchanged for
+    async fn set_session(&self, id: Uuid, session_id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE authorization_requests
+            SET session_id = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use url::Url;
+use uuid::Uuid;
+
+use super::{
+    models::{AuthorizationRequest, AuthorizeParams, NewAuthorizationRequest, OAuthError, TokenParams, TokenResponse},
+    repository::{OAuthRepository, PgOAuthRepository},
+    sqlite::SqliteOAuthRepository,
+};
+use crate::clients::{models::ClientCredentials, ClientService};
+use crate::consent_manager::{models::GrantAccessInput, ConsentManager};
+use crate::identity::{models::AuthToken, DeviceInfo, IdentityService};
+use crate::storage::Database;
+use crate::utils::tokens::{random_token, sha256_base64url};
+
+/// How long the user has to approve a request
+const REQUEST_TTL: Duration = Duration::minutes(10);
+
+/// How long the client has to exchange its code
+const CODE_TTL: Duration = Duration::minutes(1);
+
+/// Prefix of the scope entries that name context domains
+const DOMAIN_PREFIX: &str = "domain:";
+
+/// OAuth 2.0 authorization server for client applications
+///
+/// Implements the authorization code flow with PKCE (RFC 6749, RFC 7636).
+/// Approving a request creates an access grant, and the tokens a client gets
+/// for its code act under that grant until it is revoked.
+pub struct OAuthService {
+    repository: Arc<dyn OAuthRepository>,
+    consent_manager: Arc<ConsentManager>,
+    identity_service: Arc<IdentityService>,
+    client_service: Arc<ClientService>,
+    consent_url: String,
+}
+
+impl OAuthService {
+    /// Create a new OAuth service that sends users to `consent_url` to approve requests
+    pub fn new(
+        db: &Database,
+        consent_manager: Arc<ConsentManager>,
+        identity_service: Arc<IdentityService>,
+        client_service: Arc<ClientService>,
+        consent_url: &str,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            repository: match db {
+                Database::Postgres(pool) => Arc::new(PgOAuthRepository::new(pool.clone())),
+                Database::Sqlite(pool) => Arc::new(SqliteOAuthRepository::new(pool.clone())),
+            },
+            consent_manager,
+            identity_service,
+            client_service,
+            consent_url: consent_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Validate an authorization request and record it for the user to approve
+    ///
+    /// Returns the consent page the user agent should be sent to. Invalid
+    /// requests are never redirected, since the redirect URI can't be trusted.
+    pub async fn authorize(&self, params: AuthorizeParams) -> Result<String> {
+        match params.response_type.as_deref() {
+            Some("code") => {},
+            Some(other) => return Err(OAuthError::UnsupportedResponseType(other.to_string()).into()),
+            None => return Err(invalid_request("response_type is required")),
+        }
+
+        let client_id = required(params.client_id, "client_id")?;
+        let redirect_uri = required(params.redirect_uri, "redirect_uri")?;
+
+        let client = self.client_service
+            .active_client(&client_id)
+            .await?
+            .ok_or_else(|| invalid_request(&format!("Unknown client {}", client_id)))?;
+        if !client.redirect_uris.contains(&redirect_uri) {
+            return Err(invalid_request("redirect_uri is not registered for this client"));
+        }
+
+        let code_challenge = required(params.code_challenge, "code_challenge")?;
+        if params.code_challenge_method.as_deref() != Some("S256") {
+            return Err(invalid_request("code_challenge_method must be S256"));
+        }
+        if code_challenge.len() != 43 || !code_challenge.chars().all(is_base64url) {
+            return Err(invalid_request("code_challenge must be a base64url SHA-256 digest"));
+        }
+
+        let (scopes, context_domains) = parse_scope(&required(params.scope, "scope")?)?;
+        if !client.allows(&scopes, &context_domains) {
+            return Err(OAuthError::InvalidScope(format!("Client {} may not request this scope", client_id)).into());
+        }
+
+        let request = self.repository
+            .create_request(NewAuthorizationRequest {
+                client_id,
+                redirect_uri,
+                scopes,
+                context_domains,
+                state: params.state,
+                code_challenge,
+            }, Utc::now() + REQUEST_TTL)
+            .await?;
+
+        Ok(format!("{}/{}", self.consent_url, request.id))
+    }
+
+    /// Get a request that is still waiting for the user's approval
+    pub async fn pending_request(&self, id: Uuid) -> Result<Option<AuthorizationRequest>> {
+        Ok(self.repository.get_request(id).await?.filter(is_pending))
+    }
+
+    /// Approve a pending request for a user
+    ///
+    /// Grants the client the requested access and returns the redirect that
+    /// hands the client its code, or `None` if the request is not pending.
+    pub async fn approve(&self, id: Uuid, user_id: Uuid) -> Result<Option<String>> {
+        let Some(request) = self.pending_request(id).await? else {
+            return Ok(None);
+        };
+
+        let grant = self.consent_manager
+            .grant_access(GrantAccessInput {
+                user_id,
+                client_id: request.client_id.clone(),
+                scopes: request.scopes.clone(),
+                context_domains: request.context_domains.clone(),
+                expires_at: None,
+            })
+            .await?;
+
+        let code = random_token();
+        let approved = self.repository
+            .approve_request(id, user_id, grant.id, &sha256_base64url(&code), Utc::now() + CODE_TTL)
+            .await?;
+
+        let Some(request) = approved else {
+            // Approved concurrently or expired since it was read
+            self.consent_manager.revoke_grant(grant.id, user_id, &request.client_id).await?;
+            return Ok(None);
+        };
+
+        let mut redirect = Url::parse(&request.redirect_uri)?;
+        redirect.query_pairs_mut().append_pair("code", &code);
+        if let Some(state) = &request.state {
+            redirect.query_pairs_mut().append_pair("state", state);
+        }
+
+        Ok(Some(redirect.into()))
+    }
+
+    /// Deny a pending request, returning the redirect that tells the client
+    pub async fn deny(&self, id: Uuid) -> Result<Option<String>> {
+        let Some(request) = self.pending_request(id).await? else {
+            return Ok(None);
+        };
+
+        self.repository.delete_request(id).await?;
+
+        let mut redirect = Url::parse(&request.redirect_uri)?;
+        redirect.query_pairs_mut().append_pair("error", "access_denied");
+        if let Some(state) = &request.state {
+            redirect.query_pairs_mut().append_pair("state", state);
+        }
+
+        Ok(Some(redirect.into()))
+    }
+
+    /// Handle a token endpoint request
+    ///
+    /// The client authenticates first, with the `basic` credentials from the
+    /// `Authorization` header or the ones in `params`.
+    pub async fn token(
+        &self,
+        params: TokenParams,
+        basic: Option<(String, String)>,
+        device: DeviceInfo,
+    ) -> Result<TokenResponse> {
+        match params.grant_type.as_deref() {
+            Some("authorization_code" | "refresh_token") => {}
+            Some(other) => return Err(OAuthError::UnsupportedGrantType(other.to_string()).into()),
+            None => return Err(invalid_request("grant_type is required")),
+        }
+
+        let client = self.client_service
+            .authenticate(ClientCredentials {
+                client_id: params.client_id.clone(),
+                basic,
+                client_secret: params.client_secret.clone(),
+                client_assertion_type: params.client_assertion_type.clone(),
+                client_assertion: params.client_assertion.clone(),
+            })
+            .await?;
+
+        if params.grant_type.as_deref() == Some("authorization_code") {
+            self.exchange_code(params, &client.client_id, device).await
+        } else {
+            self.refresh(params, &client.client_id, device).await
+        }
+    }
+
+    /// Exchange an authorization code for tokens acting under its grant
+    ///
+    /// Codes work once. A code presented again has leaked, so the session it
+    /// was first exchanged for is revoked. The client, redirect URI and code
+    /// verifier are checked before the code is used up, so a stolen code
+    /// presented without them cannot be burned before the client redeems it.
+    async fn exchange_code(&self, params: TokenParams, client_id: &str, device: DeviceInfo) -> Result<TokenResponse> {
+        let code = required(params.code, "code")?;
+        let redirect_uri = required(params.redirect_uri, "redirect_uri")?;
+        let code_verifier = required(params.code_verifier, "code_verifier")?;
+
+        let code_hash = sha256_base64url(&code);
+        let invalid = || OAuthError::InvalidGrant("Authorization code is invalid or expired".to_string());
+        let request = self.repository.get_request_by_code(&code_hash).await?.ok_or_else(invalid)?;
+        if request.exchanged_at.is_some() {
+            if let (Some(user_id), Some(session_id)) = (request.user_id, request.session_id) {
+                log::warn!("Authorization code for request {} reused, revoking its session", request.id);
+                self.identity_service.revoke_session(user_id, session_id).await?;
+            }
+            return Err(invalid().into());
+        }
+
+        if request.client_id != client_id || request.redirect_uri != redirect_uri {
+            return Err(OAuthError::InvalidGrant("Authorization code was issued to another client".to_string()).into());
+        }
+        if !is_code_verifier(&code_verifier) || sha256_base64url(&code_verifier) != request.code_challenge {
+            return Err(OAuthError::InvalidGrant("code_verifier does not match the code_challenge".to_string()).into());
+        }
+
+        // Only one exchange can use the code up, and only before it expires
+        let request = self.repository.exchange_code(&code_hash).await?.ok_or_else(invalid)?;
+
+        let (Some(user_id), Some(grant_id)) = (request.user_id, request.grant_id) else {
+            return Err(OAuthError::InvalidGrant("Authorization code was not approved".to_string()).into());
+        };
+
+        // The session lasts no longer than the grant, which may have been revoked or expired since
+        let grant = self.consent_manager
+            .get_active_grants(user_id)
+            .await?
+            .into_iter()
+            .find(|grant| grant.id == grant_id)
+            .ok_or_else(|| OAuthError::InvalidGrant("The access grant was revoked or has expired".to_string()))?;
+
+        let token = self.identity_service
+            .start_client_session(user_id, client_id, grant_id, grant.expires_at, device)
+            .await?;
+        self.repository.set_session(request.id, token.session_id).await?;
+
+        let scope = format_scope(&request.scopes, &request.context_domains);
+        Ok(token_response(token, Some(scope)))
+    }
+
+    /// Rotate a client's refresh token
+    async fn refresh(&self, params: TokenParams, client_id: &str, device: DeviceInfo) -> Result<TokenResponse> {
+        let refresh_token = required(params.refresh_token, "refresh_token")?;
+
+        let token = self.identity_service
+            .refresh(&refresh_token, Some(client_id), device)
+            .await?
+            .ok_or_else(|| OAuthError::InvalidGrant("Refresh token is invalid or expired".to_string()))?;
+
+        Ok(token_response(token, None))
+    }
+}
+
+/// Whether a request is waiting for the user's approval
+fn is_pending(request: &AuthorizationRequest) -> bool {
+    request.user_id.is_none() && request.expires_at > Utc::now()
+}
+
+/// Token endpoint response for freshly issued tokens
+fn token_response(token: AuthToken, scope: Option<String>) -> TokenResponse {
+    TokenResponse {
+        access_token: token.token,
+        token_type: "Bearer",
+        expires_in: (token.expires_at - Utc::now()).num_seconds(),
+        refresh_token: token.refresh_token,
+        scope,
+    }
+}
+
+/// Split an OAuth scope into grant scopes and `domain:` context domains
+///
+/// `"read domain:travel"` grants the `read` scope on the `travel` domain.
+pub fn parse_scope(scope: &str) -> Result<(Vec<String>, Vec<String>)> {
+    let mut scopes: Vec<String> = Vec::new();
+    let mut domains: Vec<String> = Vec::new();
+
+    for entry in scope.split_whitespace() {
+        let (list, value) = match entry.strip_prefix(DOMAIN_PREFIX) {
+            Some(domain) => (&mut domains, domain),
+            None => (&mut scopes, entry),
+        };
+        if !value.is_empty() && !list.iter().any(|existing| existing == value) {
+            list.push(value.to_string());
+        }
+    }
+
+    if scopes.is_empty() || domains.is_empty() {
+        return Err(OAuthError::InvalidScope("scope must name at least one scope and one domain:<name>".to_string()).into());
+    }
+
+    Ok((scopes, domains))
+}
+
+/// Join grant scopes and context domains into an OAuth scope
+pub fn format_scope(scopes: &[String], domains: &[String]) -> String {
+    scopes
+        .iter()
+        .cloned()
+        .chain(domains.iter().map(|domain| format!("{}{}", DOMAIN_PREFIX, domain)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether a PKCE code verifier is well formed (RFC 7636 section 4.1)
+fn is_code_verifier(verifier: &str) -> bool {
+    (43..=128).contains(&verifier.len())
+        && verifier.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+}
+
+fn is_base64url(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Value of a parameter the request must include
+fn required(value: Option<String>, name: &str) -> Result<String> {
+    value
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| invalid_request(&format!("{} is required", name)))
+}
+
+fn invalid_request(message: &str) -> anyhow::Error {
+    OAuthError::InvalidRequest(message.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_names_scopes_and_domains() {
+        let (scopes, domains) = parse_scope("read  write domain:travel read domain:health").unwrap();
+        assert_eq!(scopes, ["read", "write"]);
+        assert_eq!(domains, ["travel", "health"]);
+        assert_eq!(format_scope(&scopes, &domains), "read write domain:travel domain:health");
+
+        assert!(parse_scope("read").is_err());
+        assert!(parse_scope("domain:travel").is_err());
+    }
+}
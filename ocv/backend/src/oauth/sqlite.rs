@@ -0,0 +1,215 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::models::{AuthorizationRequest, NewAuthorizationRequest};
+use super::repository::OAuthRepository;
+
+/// Authorization request row as stored in SQLite, with arrays kept as JSON text
+#[derive(sqlx::FromRow)]
+struct AuthorizationRequestRow {
+    id: Uuid,
+    client_id: String,
+    redirect_uri: String,
+    scopes: Json<Vec<String>>,
+    context_domains: Json<Vec<String>>,
+    state: Option<String>,
+    code_challenge: String,
+    user_id: Option<Uuid>,
+    grant_id: Option<Uuid>,
+    session_id: Option<Uuid>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    exchanged_at: Option<DateTime<Utc>>,
+}
+
+impl From<AuthorizationRequestRow> for AuthorizationRequest {
+    fn from(row: AuthorizationRequestRow) -> Self {
+        Self {
+            id: row.id,
+            client_id: row.client_id,
+            redirect_uri: row.redirect_uri,
+            scopes: row.scopes.0,
+            context_domains: row.context_domains.0,
+            state: row.state,
+            code_challenge: row.code_challenge,
+            user_id: row.user_id,
+            grant_id: row.grant_id,
+            session_id: row.session_id,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            exchanged_at: row.exchanged_at,
+        }
+    }
+}
+
+/// SQLite repository for OAuth authorization requests
+pub struct SqliteOAuthRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteOAuthRepository {
+    /// Create a new OAuth repository
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl OAuthRepository for SqliteOAuthRepository {
+    async fn create_request(
+        &self,
+        input: NewAuthorizationRequest,
+        expires_at: DateTime<Utc>,
+    ) -> Result<AuthorizationRequest> {
+        let row = sqlx::query_as::<_, AuthorizationRequestRow>(
+            r#"
+            INSERT INTO authorization_requests (
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                created_at, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(input.client_id)
+        .bind(input.redirect_uri)
+        .bind(Json(&input.scopes))
+        .bind(Json(&input.context_domains))
+        .bind(input.state)
+        .bind(input.code_challenge)
+        .bind(Utc::now())
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    async fn get_request(&self, id: Uuid) -> Result<Option<AuthorizationRequest>> {
+        let row = sqlx::query_as::<_, AuthorizationRequestRow>(
+            r#"
+            SELECT
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            FROM authorization_requests
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn approve_request(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        grant_id: Uuid,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<AuthorizationRequest>> {
+        let row = sqlx::query_as::<_, AuthorizationRequestRow>(
+            r#"
+            UPDATE authorization_requests
+            SET
+                user_id = $2,
+                grant_id = $3,
+                code_hash = $4,
+                expires_at = $5
+            WHERE id = $1
+              AND user_id IS NULL
+              AND expires_at > $6
+            RETURNING
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(grant_id)
+        .bind(code_hash)
+        .bind(expires_at)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn delete_request(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM authorization_requests
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn exchange_code(&self, code_hash: &str) -> Result<Option<AuthorizationRequest>> {
+        let row = sqlx::query_as::<_, AuthorizationRequestRow>(
+            r#"
+            UPDATE authorization_requests
+            SET exchanged_at = $2
+            WHERE code_hash = $1
+              AND exchanged_at IS NULL
+              AND expires_at > $2
+            RETURNING
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            "#,
+        )
+        .bind(code_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn get_request_by_code(&self, code_hash: &str) -> Result<Option<AuthorizationRequest>> {
+        let row = sqlx::query_as::<_, AuthorizationRequestRow>(
+            r#"
+            SELECT
+                id, client_id, redirect_uri, scopes, context_domains, state, code_challenge,
+                user_id, grant_id, session_id, created_at, expires_at, exchanged_at
+            FROM authorization_requests
+            WHERE code_hash = $1
+            "#,
+        )
+        .bind(code_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn set_session(&self, id: Uuid, session_id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE authorization_requests
+            SET session_id = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use async_graphql::{Context, ErrorExtensions, Object, ID};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+use super::models::{Policy, PolicyError, PolicyVersion};
+use crate::api::auth::admin;
+use crate::api::AppState;
+use crate::utils::errors::AppError;
+
+/// GraphQL representation of a policy and its current version
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLPolicy {
+    /// Name the policy is evaluated by
+    pub name: String,
+    /// Current version
+    pub version: i32,
+    /// Rego source of the current version
+    pub source: String,
+    /// Administrator who wrote the current version; null for built-in policies
+    pub author_id: Option<ID>,
+    /// Whether the policy is enforced; a disabled policy denies every request
+    pub enabled: bool,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last time the policy was changed, enabled or disabled
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Policy> for GraphQLPolicy {
+    fn from(policy: Policy) -> Self {
+        Self {
+            name: policy.name,
+            version: policy.version,
+            source: policy.source,
+            author_id: policy.author_id.map(|id| ID(id.to_string())),
+            enabled: policy.enabled,
+            created_at: policy.created_at,
+            updated_at: policy.updated_at,
+        }
+    }
+}
+
+/// GraphQL representation of one version of a policy
+#[derive(async_graphql::SimpleObject)]
+pub struct GraphQLPolicyVersion {
+    /// Version number, starting at 1
+    pub version: i32,
+    /// Rego source
+    pub source: String,
+    /// Administrator who wrote this version; null for built-in policies
+    pub author_id: Option<ID>,
+    /// When this version was written
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<PolicyVersion> for GraphQLPolicyVersion {
+    fn from(version: PolicyVersion) -> Self {
+        Self {
+            version: version.version,
+            source: version.source,
+            author_id: version.author_id.map(|id| ID(id.to_string())),
+            created_at: version.created_at,
+        }
+    }
+}
+
+/// Policy query root
+#[derive(Default)]
+pub struct PolicyQuery;
+
+#[Object]
+impl PolicyQuery {
+    /// List all policies; administrators only
+    async fn policies(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<GraphQLPolicy>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        admin(ctx).await?;
+        
+        let policies = state.policy_engine.policies().await?;
+        
+        Ok(policies.into_iter().map(GraphQLPolicy::from).collect())
+    }
+    
+    /// Get a policy by name; administrators only
+    async fn policy(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<Option<GraphQLPolicy>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        admin(ctx).await?;
+        
+        let policy = state.policy_engine.get_policy(&name).await?;
+        
+        Ok(policy.map(GraphQLPolicy::from))
+    }
+    
+    /// List a policy's versions, newest first; administrators only
+    async fn policy_versions(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<Vec<GraphQLPolicyVersion>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        admin(ctx).await?;
+        
+        let versions = state.policy_engine.policy_versions(&name).await?;
+        
+        Ok(versions.into_iter().map(GraphQLPolicyVersion::from).collect())
+    }
+}
+
+/// Policy mutation root
+#[derive(Default)]
+pub struct PolicyMutation;
+
+#[Object]
+impl PolicyMutation {
+    /// Add a policy written in Rego, which must define `allow`; administrators only
+    async fn create_policy(&self, ctx: &Context<'_>, name: String, source: String) -> async_graphql::Result<GraphQLPolicy> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let admin_id = admin(ctx).await?;
+        
+        let policy = state.policy_engine.create_policy(admin_id, &name, &source).await.map_err(policy_error)?;
+        
+        Ok(GraphQLPolicy::from(policy))
+    }
+    
+    /// Replace a policy with a new version; administrators only
+    async fn update_policy(&self, ctx: &Context<'_>, name: String, source: String) -> async_graphql::Result<GraphQLPolicy> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let admin_id = admin(ctx).await?;
+        
+        let policy = state.policy_engine.update_policy(admin_id, &name, &source).await.map_err(policy_error)?;
+        
+        Ok(GraphQLPolicy::from(policy))
+    }
+    
+    /// Put an earlier version of a policy back in force; administrators only
+    async fn rollback_policy(&self, ctx: &Context<'_>, name: String, version: i32) -> async_graphql::Result<GraphQLPolicy> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let admin_id = admin(ctx).await?;
+        
+        let policy = state.policy_engine.rollback_policy(admin_id, &name, version).await.map_err(policy_error)?;
+        
+        Ok(GraphQLPolicy::from(policy))
+    }
+    
+    /// Stop enforcing a policy, so it denies every request; administrators only
+    async fn disable_policy(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<GraphQLPolicy> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let admin_id = admin(ctx).await?;
+        
+        let policy = state.policy_engine.set_policy_enabled(admin_id, &name, false).await.map_err(policy_error)?;
+        
+        Ok(GraphQLPolicy::from(policy))
+    }
+    
+    /// Enforce a disabled policy again; administrators only
+    async fn enable_policy(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<GraphQLPolicy> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let admin_id = admin(ctx).await?;
+        
+        let policy = state.policy_engine.set_policy_enabled(admin_id, &name, true).await.map_err(policy_error)?;
+        
+        Ok(GraphQLPolicy::from(policy))
+    }
+}
+
+/// Report missing policies as not found and invalid ones as validation errors
+///
+/// Compile errors carry the `line` and `column` of the problem as extensions.
+fn policy_error(err: anyhow::Error) -> async_graphql::Error {
+    match err.downcast_ref::<PolicyError>() {
+        Some(PolicyError::NotFound(_) | PolicyError::VersionNotFound { .. }) => {
+            AppError::NotFound(err.to_string()).extend()
+        },
+        Some(PolicyError::Compile { error, .. }) => AppError::ValidationError(err.to_string())
+            .extend()
+            .extend_with(|_, e| {
+                e.set("line", error.line);
+                e.set("column", error.column);
+            }),
+        Some(_) => AppError::ValidationError(err.to_string()).extend(),
+        None => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::testing::{error_code, graphql, login, state};
+
+    #[actix_web::test]
+    async fn only_administrators_manage_policies() {
+        let state = state().await;
+        let (ada, token) = login(&state, "ada").await;
+        let (_, other) = login(&state, "bob").await;
+        state.identity_service.set_admins(["Ada@Example.com".to_string()]);
+
+        let (_, body) = graphql(state.clone(), Some(&other), "{ policies { name } }").await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+
+        let (_, body) = graphql(state.clone(), Some(&token), "{ policies { name version authorId } }").await;
+        assert_eq!(
+            body["data"]["policies"],
+            serde_json::json!([
+                { "name": "client_registration", "version": 1, "authorId": null },
+                { "name": "consent", "version": 1, "authorId": null },
+            ])
+        );
+
+        let update = r#"mutation { updatePolicy(name: "consent", source: "package consent\nallow {\n  input.action ==\n}") { version } }"#;
+        let (_, body) = graphql(state.clone(), Some(&token), update).await;
+        assert_eq!(error_code(&body), "VALIDATION_ERROR");
+        assert_eq!(body["errors"][0]["extensions"]["line"], 3);
+
+        let update = r#"mutation { updatePolicy(name: "consent", source: "package consent\ndefault allow := false") { version authorId } }"#;
+        let (_, body) = graphql(state.clone(), Some(&token), update).await;
+        assert_eq!(body["data"]["updatePolicy"], serde_json::json!({ "version": 2, "authorId": ada.to_string() }));
+
+        let rollback = r#"mutation { rollbackPolicy(name: "consent", version: 1) { version } }"#;
+        let (_, body) = graphql(state.clone(), Some(&other), rollback).await;
+        assert_eq!(error_code(&body), "UNAUTHORIZED");
+        let (_, body) = graphql(state.clone(), Some(&token), rollback).await;
+        assert_eq!(body["data"]["rollbackPolicy"]["version"], 1);
+
+        let (_, body) = graphql(state.clone(), Some(&token), r#"{ policyVersions(name: "consent") { version } }"#).await;
+        assert_eq!(body["data"]["policyVersions"], serde_json::json!([{ "version": 2 }, { "version": 1 }]));
+
+        let (_, body) = graphql(state.clone(), Some(&token), r#"mutation { disablePolicy(name: "missing") { enabled } }"#).await;
+        assert_eq!(error_code(&body), "NOT_FOUND");
+
+        let (_, body) = graphql(state, Some(&token), r#"{ auditLogs { action } }"#).await;
+        let actions: Vec<&str> = body["data"]["auditLogs"].as_array().unwrap().iter().map(|log| log["action"].as_str().unwrap()).collect();
+        assert_eq!(actions.len(), 2);
+        assert!(actions.contains(&"policy_updated") && actions.contains(&"policy_rolled_back"));
+    }
+}
@@ -1,4 +1,10 @@
+pub mod models;
+pub mod rego;
+pub mod repository;
 pub mod service;
+pub mod sqlite;
+pub mod graphql;
 
 // Re-export key types
+pub use models::{Decision, Obligation, Policy, PolicyError};
 pub use service::PolicyEngine;
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::rego::CompileError;
+
+/// A stored policy and the version of it in force
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Policy {
+    /// Name callers evaluate the policy by, such as `consent`
+    pub name: String,
+
+    /// Current version
+    pub version: i32,
+
+    /// Rego source of the current version
+    pub source: String,
+
+    /// User who wrote the current version; `None` for built-in policies
+    pub author_id: Option<Uuid>,
+
+    /// Whether the policy is enforced; a disabled policy denies every request
+    pub enabled: bool,
+
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Last time the policy was changed, enabled or disabled
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One version in a policy's history
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PolicyVersion {
+    /// Name of the policy
+    pub policy_name: String,
+
+    /// Version number, starting at 1
+    pub version: i32,
+
+    /// Rego source
+    pub source: String,
+
+    /// User who wrote this version; `None` for built-in policies
+    pub author_id: Option<Uuid>,
+
+    /// When this version was written
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of evaluating a policy, with what led to it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    /// Whether the request is allowed
+    pub allow: bool,
+
+    /// Rules that matched: `<policy>.<rule>` for the policy's rules that were
+    /// `true`, and the id of any consent rule of the user's
+    pub rules: Vec<String>,
+
+    /// Why the request was allowed or denied, for people to read
+    pub reasons: Vec<String>,
+
+    /// What an allowed request must be subject to
+    pub obligations: Vec<Obligation>,
+}
+
+impl Decision {
+    /// Deny a request for a reason
+    pub fn deny(reason: impl Into<String>) -> Self {
+        Self {
+            reasons: vec![reason.into()],
+            ..Self::default()
+        }
+    }
+
+    /// The reasons in one line, for error messages
+    pub fn explanation(&self) -> String {
+        if self.reasons.is_empty() {
+            "no reason was given".to_string()
+        } else {
+            self.reasons.join("; ")
+        }
+    }
+}
+
+/// Something a request must be subject to when a policy allows it, such as
+/// `{"type": "expire", "days": 7}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Obligation {
+    /// What kind of obligation it is, such as `expire`
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    /// The rest of the obligation's fields
+    #[serde(flatten)]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Errors in managing and evaluating policies
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Policy not found: {0}")]
+    NotFound(String),
+
+    #[error("Policy {name} has no version {version}")]
+    VersionNotFound { name: String, version: i32 },
+
+    #[error("Policy {0} already exists")]
+    AlreadyExists(String),
+
+    #[error("Policy names may only contain lowercase letters, digits and underscores: {0}")]
+    InvalidName(String),
+
+    #[error("Policy {name} does not compile: {error}")]
+    Compile { name: String, error: CompileError },
+
+    #[error("Policy {0} does not define an allow rule")]
+    MissingAllow(String),
+
+    #[error("Policy {0} returned {1} instead of a boolean")]
+    NotBoolean(String, serde_json::Value),
+
+    #[error("Policy {0} returned reasons that are not strings: {1}")]
+    InvalidReasons(String, serde_json::Value),
+
+    #[error("Policy {0} returned obligations that are not objects with a type: {1}")]
+    InvalidObligations(String, serde_json::Value),
+}
@@ -0,0 +1,117 @@
+//! Syntax tree of a Rego module
+
+use super::value::Value;
+
+/// A parsed policy module
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// Path named by `package`
+    pub package: Vec<String>,
+    /// Rules in source order; a name may have several
+    pub rules: Vec<Rule>,
+}
+
+/// One definition of a rule
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Rule name
+    pub name: String,
+    /// What the rule produces when its body holds
+    pub head: RuleHead,
+    /// Literals that must all hold; empty for constants
+    pub body: Vec<Literal>,
+    /// Line the rule starts on
+    pub line: usize,
+}
+
+/// What a rule definition produces
+#[derive(Debug, Clone)]
+pub enum RuleHead {
+    /// `default name := value`, used when no other definition holds
+    Default(Expr),
+    /// `name := value if { ... }`, or `true` when no value is given
+    Complete(Expr),
+    /// `name contains member if { ... }` or `name[member] { ... }`
+    Set(Expr),
+    /// `name[key] := value if { ... }`
+    Object(Expr, Expr),
+}
+
+/// A statement in a rule or comprehension body
+#[derive(Debug, Clone)]
+pub enum Literal {
+    /// Holds when the expression is defined and not `false`
+    Expr(Expr),
+    /// Holds when the expression never holds
+    Not(Expr),
+    /// `some x, y` declares local variables
+    Some(Vec<String>),
+    /// `some k, v in collection` iterates over a collection
+    SomeIn {
+        key: Option<Expr>,
+        value: Expr,
+        collection: Expr,
+    },
+    /// `x := value`
+    Assign(String, Expr),
+}
+
+/// An expression
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// String, number, boolean or null
+    Scalar(Value),
+    /// Variable, or a reference such as `input.domains[_]`: its head and path
+    Ref(String, Vec<Expr>),
+    Array(Vec<Expr>),
+    Set(Vec<Expr>),
+    Object(Vec<(Expr, Expr)>),
+    ArrayComprehension(Box<Expr>, Vec<Literal>),
+    SetComprehension(Box<Expr>, Vec<Literal>),
+    ObjectComprehension(Box<Expr>, Box<Expr>, Vec<Literal>),
+    /// Built-in function call
+    Call(String, Vec<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+}
+
+impl Expr {
+    /// Name of the variable, if the expression is a bare one
+    pub fn as_var(&self) -> Option<&str> {
+        match self {
+            Self::Ref(name, path) if path.is_empty() => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression refers to nothing but literal values
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Self::Scalar(_) => true,
+            Self::Array(items) | Self::Set(items) => items.iter().all(Self::is_constant),
+            Self::Object(pairs) => pairs.iter().all(|(k, v)| k.is_constant() && v.is_constant()),
+            Self::Neg(expr) => expr.is_constant(),
+            _ => false,
+        }
+    }
+}
+
+/// Infix operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// `=`, which compares or binds an unbound variable
+    Unify,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    /// Membership, `x in collection`
+    In,
+}
@@ -0,0 +1,168 @@
+//! Built-in functions policies can call
+
+use chrono::Utc;
+
+use super::value::Value;
+
+/// Built-in functions and how many arguments each takes
+const BUILTINS: [(&str, usize); 33] = [
+    ("count", 1),
+    ("sum", 1),
+    ("max", 1),
+    ("min", 1),
+    ("sort", 1),
+    ("abs", 1),
+    ("round", 1),
+    ("to_number", 1),
+    ("lower", 1),
+    ("upper", 1),
+    ("trim_space", 1),
+    ("startswith", 2),
+    ("endswith", 2),
+    ("contains", 2),
+    ("concat", 2),
+    ("split", 2),
+    ("sprintf", 2),
+    ("is_string", 1),
+    ("is_number", 1),
+    ("is_boolean", 1),
+    ("is_array", 1),
+    ("is_object", 1),
+    ("is_set", 1),
+    ("is_null", 1),
+    ("object.get", 3),
+    ("object.keys", 1),
+    ("array.concat", 2),
+    ("set", 0),
+    ("time.now_ns", 0),
+    ("union", 1),
+    ("intersection", 1),
+    ("any", 1),
+    ("all", 1),
+];
+
+/// Number of arguments a built-in function takes, `None` if there is no such function
+pub fn arity(name: &str) -> Option<usize> {
+    BUILTINS.iter().find(|(builtin, _)| *builtin == name).map(|(_, arity)| *arity)
+}
+
+/// Call a built-in function
+///
+/// Returns `None` when the result is undefined, such as for arguments of the
+/// wrong type.
+pub fn call(name: &str, args: &[Value]) -> Option<Value> {
+    use Value::*;
+
+    match (name, args) {
+        ("count", [String(s)]) => Some(Number(s.chars().count().into())),
+        ("count", [Object(map)]) => Some(Number(map.len().into())),
+        ("count", [collection]) => Some(Number(members(collection)?.len().into())),
+        ("sum", [collection]) => Value::number(numbers(collection)?.iter().sum()),
+        ("max", [collection]) => members(collection)?.into_iter().max(),
+        ("min", [collection]) => members(collection)?.into_iter().min(),
+        ("sort", [collection]) => {
+            let mut items = members(collection)?;
+            items.sort();
+            Some(Array(items))
+        }
+        ("abs", [n]) => Value::number(n.as_f64()?.abs()),
+        ("round", [n]) => Value::number(n.as_f64()?.round()),
+        ("to_number", [value]) => match value {
+            Number(_) => Some(value.clone()),
+            String(s) => s.trim().parse::<f64>().ok().and_then(Value::number),
+            Bool(b) => Some(Number(u8::from(*b).into())),
+            Null => Some(Number(0.into())),
+            _ => None,
+        },
+        ("lower", [String(s)]) => Some(String(s.to_lowercase())),
+        ("upper", [String(s)]) => Some(String(s.to_uppercase())),
+        ("trim_space", [String(s)]) => Some(String(s.trim().to_string())),
+        ("startswith", [String(s), String(prefix)]) => Some(Bool(s.starts_with(prefix.as_str()))),
+        ("endswith", [String(s), String(suffix)]) => Some(Bool(s.ends_with(suffix.as_str()))),
+        ("contains", [String(s), String(part)]) => Some(Bool(s.contains(part.as_str()))),
+        ("concat", [String(separator), collection]) => {
+            let parts = members(collection)?
+                .iter()
+                .map(|part| part.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()?;
+            Some(String(parts.join(separator)))
+        }
+        ("split", [String(s), String(separator)]) => {
+            Some(Array(s.split(separator.as_str()).map(|part| String(part.to_string())).collect()))
+        }
+        ("sprintf", [String(format), Array(values)]) => Some(String(sprintf(format, values))),
+        ("is_string", [value]) => Some(Bool(matches!(value, String(_)))),
+        ("is_number", [value]) => Some(Bool(matches!(value, Number(_)))),
+        ("is_boolean", [value]) => Some(Bool(matches!(value, Bool(_)))),
+        ("is_array", [value]) => Some(Bool(matches!(value, Array(_)))),
+        ("is_object", [value]) => Some(Bool(matches!(value, Object(_)))),
+        ("is_set", [value]) => Some(Bool(matches!(value, Set(_)))),
+        ("is_null", [value]) => Some(Bool(matches!(value, Null))),
+        ("object.get", [Object(map), key, default]) => {
+            Some(map.get(&key.to_key()).cloned().unwrap_or_else(|| default.clone()))
+        }
+        ("object.keys", [Object(map)]) => Some(Set(map.keys().map(|key| String(key.clone())).collect())),
+        ("array.concat", [Array(a), Array(b)]) => Some(Array(a.iter().chain(b).cloned().collect())),
+        ("set", []) => Some(Set(Default::default())),
+        ("time.now_ns", []) => Some(Number(Utc::now().timestamp_nanos_opt()?.into())),
+        ("union", [collection]) => {
+            let mut union = std::collections::BTreeSet::new();
+            for set in members(collection)? {
+                let Set(set) = set else { return None };
+                union.extend(set);
+            }
+            Some(Set(union))
+        }
+        ("intersection", [collection]) => {
+            let mut sets = members(collection)?.into_iter();
+            let Some(Set(mut intersection)) = sets.next() else {
+                return Some(Set(Default::default()));
+            };
+            for set in sets {
+                let Set(set) = set else { return None };
+                intersection.retain(|value| set.contains(value));
+            }
+            Some(Set(intersection))
+        }
+        ("any", [collection]) => Some(Bool(members(collection)?.contains(&Bool(true)))),
+        ("all", [collection]) => Some(Bool(members(collection)?.iter().all(|value| *value == Bool(true)))),
+        _ => None,
+    }
+}
+
+/// Members of an array or set
+fn members(collection: &Value) -> Option<Vec<Value>> {
+    match collection {
+        Value::Array(items) => Some(items.clone()),
+        Value::Set(set) => Some(set.iter().cloned().collect()),
+        _ => None,
+    }
+}
+
+fn numbers(collection: &Value) -> Option<Vec<f64>> {
+    members(collection)?.iter().map(Value::as_f64).collect()
+}
+
+/// Format with `%v`, `%s` and `%d` verbs, each taking the next value
+fn sprintf(format: &str, values: &[Value]) -> String {
+    let mut output = String::new();
+    let mut values = values.iter();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => output.push('%'),
+            Some(_) => match values.next() {
+                Some(Value::String(s)) => output.push_str(s),
+                Some(value) => output.push_str(&serde_json::Value::from(value.clone()).to_string()),
+                None => output.push_str("%!(MISSING)"),
+            },
+            None => output.push('%'),
+        }
+    }
+    output
+}
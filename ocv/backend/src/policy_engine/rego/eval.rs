@@ -0,0 +1,398 @@
+//! Evaluates the rules of a parsed module
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+
+use super::ast::{BinaryOp, Expr, Literal, Module, RuleHead};
+use super::builtins;
+use super::value::Value;
+
+/// Variables bound so far
+type Env = HashMap<String, Value>;
+
+/// Evaluates rules of one module against an input and a data document
+pub struct Evaluator<'a> {
+    module: &'a Module,
+    input: &'a Value,
+    data: &'a Value,
+    /// Rule values already computed, `None` when undefined
+    cache: RefCell<HashMap<String, Option<Value>>>,
+    /// Rules being computed, to catch rules that depend on themselves
+    evaluating: RefCell<HashSet<String>>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(module: &'a Module, input: &'a Value, data: &'a Value) -> Self {
+        Self {
+            module,
+            input,
+            data,
+            cache: RefCell::new(HashMap::new()),
+            evaluating: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Value of a rule, `None` when it is undefined
+    pub fn rule(&self, name: &str) -> Result<Option<Value>> {
+        if let Some(value) = self.cache.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        if !self.evaluating.borrow_mut().insert(name.to_string()) {
+            return Err(anyhow!("rule {} depends on itself", name));
+        }
+
+        let value = self.compute_rule(name);
+        self.evaluating.borrow_mut().remove(name);
+        let value = value?;
+        self.cache.borrow_mut().insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    fn compute_rule(&self, name: &str) -> Result<Option<Value>> {
+        let mut default = None;
+        let mut values = BTreeSet::new();
+        let mut set: Option<BTreeSet<Value>> = None;
+        let mut object: Option<BTreeMap<String, Value>> = None;
+
+        for rule in self.module.rules.iter().filter(|rule| rule.name == name) {
+            match &rule.head {
+                RuleHead::Default(value) => {
+                    default = self.eval_expr(value, &Env::new())?.into_iter().next().map(|(value, _)| value);
+                }
+                RuleHead::Complete(value) => {
+                    for env in self.solutions(&rule.body, Env::new())? {
+                        values.extend(self.eval_expr(value, &env)?.into_iter().map(|(value, _)| value));
+                    }
+                }
+                RuleHead::Set(member) => {
+                    let set = set.get_or_insert_with(BTreeSet::new);
+                    for env in self.solutions(&rule.body, Env::new())? {
+                        set.extend(self.eval_expr(member, &env)?.into_iter().map(|(value, _)| value));
+                    }
+                }
+                RuleHead::Object(key, value) => {
+                    let object = object.get_or_insert_with(BTreeMap::new);
+                    for env in self.solutions(&rule.body, Env::new())? {
+                        for (key, env) in self.eval_expr(key, &env)? {
+                            for (value, _) in self.eval_expr(value, &env)? {
+                                insert(object, name, key.to_key(), value)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(set) = set {
+            return Ok(Some(Value::Set(set)));
+        }
+        if let Some(object) = object {
+            return Ok(Some(Value::Object(object)));
+        }
+        if values.len() > 1 {
+            return Err(anyhow!("rule {} has conflicting values", name));
+        }
+        Ok(values.into_iter().next().or(default))
+    }
+
+    /// Every binding of variables under which all literals of a body hold
+    fn solutions(&self, body: &[Literal], env: Env) -> Result<Vec<Env>> {
+        let mut solutions = Vec::new();
+        self.eval_body(body, env, &mut solutions)?;
+        Ok(solutions)
+    }
+
+    fn eval_body(&self, body: &[Literal], env: Env, solutions: &mut Vec<Env>) -> Result<()> {
+        let Some((first, rest)) = body.split_first() else {
+            solutions.push(env);
+            return Ok(());
+        };
+
+        for env in self.eval_literal(first, env)? {
+            self.eval_body(rest, env, solutions)?;
+        }
+        Ok(())
+    }
+
+    fn eval_literal(&self, literal: &Literal, mut env: Env) -> Result<Vec<Env>> {
+        match literal {
+            Literal::Expr(expr) => Ok(self
+                .eval_expr(expr, &env)?
+                .into_iter()
+                .filter(|(value, _)| value.is_truthy())
+                .map(|(_, env)| env)
+                .collect()),
+            Literal::Not(expr) => {
+                let holds = self.eval_expr(expr, &env)?.iter().any(|(value, _)| value.is_truthy());
+                Ok(if holds { Vec::new() } else { vec![env] })
+            }
+            Literal::Some(vars) => {
+                for var in vars {
+                    env.remove(var);
+                }
+                Ok(vec![env])
+            }
+            Literal::SomeIn { key, value, collection } => {
+                for pattern in key.iter().chain([value]) {
+                    if let Some(var) = pattern.as_var() {
+                        env.remove(var);
+                    }
+                }
+
+                let mut envs = Vec::new();
+                for (collection, env) in self.eval_expr(collection, &env)? {
+                    for (k, v) in collection.entries() {
+                        let env = match key {
+                            Some(key) => self.bind(key, k, env.clone())?,
+                            None => Some(env.clone()),
+                        };
+                        if let Some(env) = env {
+                            envs.extend(self.bind(value, v, env)?);
+                        }
+                    }
+                }
+                Ok(envs)
+            }
+            Literal::Assign(var, expr) => Ok(self
+                .eval_expr(expr, &env)?
+                .into_iter()
+                .map(|(value, mut env)| {
+                    env.insert(var.clone(), value);
+                    env
+                })
+                .collect()),
+        }
+    }
+
+    /// Bind `pattern` to `value` if it is an unbound variable, or else check it equals `value`
+    fn bind(&self, pattern: &Expr, value: Value, mut env: Env) -> Result<Option<Env>> {
+        if let Some(var) = self.unbound(pattern, &env) {
+            env.insert(var.to_string(), value);
+            return Ok(Some(env));
+        }
+        Ok(self
+            .eval_expr(pattern, &env)?
+            .into_iter()
+            .find(|(v, _)| *v == value)
+            .map(|(_, env)| env))
+    }
+
+    /// Name of the variable, if the expression is a variable that is not bound yet
+    fn unbound<'e>(&self, expr: &'e Expr, env: &Env) -> Option<&'e str> {
+        let var = expr.as_var()?;
+        let global = var == "input" || var == "data" || self.module.defines(var);
+        (!env.contains_key(var) && !global).then_some(var)
+    }
+
+    /// Each value the expression can take, with the bindings that give it
+    fn eval_expr(&self, expr: &Expr, env: &Env) -> Result<Vec<(Value, Env)>> {
+        match expr {
+            Expr::Scalar(value) => Ok(vec![(value.clone(), env.clone())]),
+            Expr::Ref(head, path) => self.eval_ref(head, path, env),
+            Expr::Array(items) => Ok(self
+                .eval_all(items, env)?
+                .into_iter()
+                .map(|(values, env)| (Value::Array(values), env))
+                .collect()),
+            Expr::Set(items) => Ok(self
+                .eval_all(items, env)?
+                .into_iter()
+                .map(|(values, env)| (Value::Set(values.into_iter().collect()), env))
+                .collect()),
+            Expr::Object(pairs) => Ok(self
+                .eval_all(pairs.iter().flat_map(|(key, value)| [key, value]), env)?
+                .into_iter()
+                .map(|(values, env)| {
+                    let object = values.chunks(2).map(|pair| (pair[0].to_key(), pair[1].clone())).collect();
+                    (Value::Object(object), env)
+                })
+                .collect()),
+            Expr::ArrayComprehension(term, body) => {
+                let mut items = Vec::new();
+                for env in self.solutions(body, env.clone())? {
+                    items.extend(self.eval_expr(term, &env)?.into_iter().map(|(value, _)| value));
+                }
+                Ok(vec![(Value::Array(items), env.clone())])
+            }
+            Expr::SetComprehension(term, body) => {
+                let mut items = BTreeSet::new();
+                for env in self.solutions(body, env.clone())? {
+                    items.extend(self.eval_expr(term, &env)?.into_iter().map(|(value, _)| value));
+                }
+                Ok(vec![(Value::Set(items), env.clone())])
+            }
+            Expr::ObjectComprehension(key, value, body) => {
+                let mut object = BTreeMap::new();
+                for env in self.solutions(body, env.clone())? {
+                    for (key, env) in self.eval_expr(key, &env)? {
+                        for (value, _) in self.eval_expr(value, &env)? {
+                            insert(&mut object, "object comprehension", key.to_key(), value)?;
+                        }
+                    }
+                }
+                Ok(vec![(Value::Object(object), env.clone())])
+            }
+            Expr::Call(name, args) => Ok(self
+                .eval_all(args, env)?
+                .into_iter()
+                .filter_map(|(args, env)| builtins::call(name, &args).map(|value| (value, env)))
+                .collect()),
+            Expr::Binary(BinaryOp::Unify, left, right) => self.unify(left, right, env),
+            Expr::Binary(op, left, right) => {
+                let mut results = Vec::new();
+                for (left, env) in self.eval_expr(left, env)? {
+                    for (right, env) in self.eval_expr(right, &env)? {
+                        results.extend(apply(*op, &left, &right).map(|value| (value, env)));
+                    }
+                }
+                Ok(results)
+            }
+            Expr::Neg(operand) => Ok(self
+                .eval_expr(operand, env)?
+                .into_iter()
+                .filter_map(|(value, env)| Value::number(-value.as_f64()?).map(|value| (value, env)))
+                .collect()),
+        }
+    }
+
+    /// Each combination of values a list of expressions can take
+    fn eval_all<'e>(&self, exprs: impl IntoIterator<Item = &'e Expr>, env: &Env) -> Result<Vec<(Vec<Value>, Env)>> {
+        let mut results = vec![(Vec::new(), env.clone())];
+        for expr in exprs {
+            let mut next = Vec::new();
+            for (values, env) in results {
+                for (value, env) in self.eval_expr(expr, &env)? {
+                    let mut values = values.clone();
+                    values.push(value);
+                    next.push((values, env));
+                }
+            }
+            results = next;
+        }
+        Ok(results)
+    }
+
+    /// `left = right`, binding whichever side is an unbound variable
+    fn unify(&self, left: &Expr, right: &Expr, env: &Env) -> Result<Vec<(Value, Env)>> {
+        let (var, other) = match (self.unbound(left, env), self.unbound(right, env)) {
+            (Some(var), _) => (var, right),
+            (None, Some(var)) => (var, left),
+            (None, None) => return self.eval_expr(&Expr::Binary(BinaryOp::Eq, Box::new(left.clone()), Box::new(right.clone())), env),
+        };
+
+        Ok(self
+            .eval_expr(other, env)?
+            .into_iter()
+            .map(|(value, mut env)| {
+                env.insert(var.to_string(), value);
+                (Value::Bool(true), env)
+            })
+            .collect())
+    }
+
+    fn eval_ref(&self, head: &str, path: &[Expr], env: &Env) -> Result<Vec<(Value, Env)>> {
+        let root = if let Some(value) = env.get(head) {
+            value.clone()
+        } else if head == "input" {
+            self.input.clone()
+        } else if head == "data" {
+            return self.eval_data(path, env);
+        } else if self.module.defines(head) {
+            match self.rule(head)? {
+                Some(value) => value,
+                None => return Ok(Vec::new()),
+            }
+        } else {
+            // Unbound variables are undefined
+            return Ok(Vec::new());
+        };
+
+        self.walk(root, path, env.clone())
+    }
+
+    /// `data.<package>.<rule>` refers to this module's rules, and the rest of `data` to the data document
+    fn eval_data(&self, path: &[Expr], env: &Env) -> Result<Vec<(Value, Env)>> {
+        let package = &self.module.package;
+        let in_package = path.len() > package.len()
+            && package
+                .iter()
+                .zip(path)
+                .all(|(name, step)| matches!(step, Expr::Scalar(Value::String(s)) if s == name));
+
+        if in_package {
+            if let Expr::Scalar(Value::String(rule)) = &path[package.len()] {
+                return match self.rule(rule)? {
+                    Some(value) => self.walk(value, &path[package.len() + 1..], env.clone()),
+                    None => Ok(Vec::new()),
+                };
+            }
+        }
+        self.walk(self.data.clone(), path, env.clone())
+    }
+
+    /// Follow a reference's path into a value; unbound variables in it iterate over collections
+    fn walk(&self, value: Value, path: &[Expr], env: Env) -> Result<Vec<(Value, Env)>> {
+        let Some((step, rest)) = path.split_first() else {
+            return Ok(vec![(value, env)]);
+        };
+
+        let mut results = Vec::new();
+        if let Some(var) = self.unbound(step, &env) {
+            for (key, child) in value.entries() {
+                let mut env = env.clone();
+                env.insert(var.to_string(), key);
+                results.extend(self.walk(child, rest, env)?);
+            }
+        } else {
+            for (key, env) in self.eval_expr(step, &env)? {
+                if let Some(child) = value.get(&key) {
+                    results.extend(self.walk(child, rest, env)?);
+                }
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Add a key to an object a rule or comprehension builds, failing if it already has a different value
+fn insert(object: &mut BTreeMap<String, Value>, name: &str, key: String, value: Value) -> Result<()> {
+    match object.get(&key) {
+        Some(existing) if *existing != value => Err(anyhow!("{} has conflicting values for key {}", name, key)),
+        _ => {
+            object.insert(key, value);
+            Ok(())
+        }
+    }
+}
+
+/// Apply an operator, `None` when the result is undefined
+fn apply(op: BinaryOp, left: &Value, right: &Value) -> Option<Value> {
+    let result = match op {
+        BinaryOp::Eq | BinaryOp::Unify => left == right,
+        BinaryOp::Ne => left != right,
+        BinaryOp::Lt => left < right,
+        BinaryOp::Le => left <= right,
+        BinaryOp::Gt => left > right,
+        BinaryOp::Ge => left >= right,
+        BinaryOp::In => right.contains(left),
+        BinaryOp::Sub if matches!((left, right), (Value::Set(_), Value::Set(_))) => {
+            let (Value::Set(left), Value::Set(right)) = (left, right) else { unreachable!() };
+            return Some(Value::Set(left.difference(right).cloned().collect()));
+        }
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            let (a, b) = (left.as_f64()?, right.as_f64()?);
+            let n = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div if b != 0.0 => a / b,
+                BinaryOp::Rem if b != 0.0 => a % b,
+                _ => return None,
+            };
+            return Value::number(n);
+        }
+    };
+    Some(Value::Bool(result))
+}
@@ -0,0 +1,159 @@
+//! Splits Rego source into tokens
+
+use super::value::Value;
+use super::CompileError;
+
+/// A token of Rego source
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// Name or keyword
+    Ident(String),
+    /// String or number literal
+    Literal(Value),
+    /// Operator or punctuation
+    Punct(&'static str),
+    /// End of a line, which separates body literals
+    Newline,
+    Eof,
+}
+
+/// A token and where it starts
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Operators and punctuation, longest first
+const PUNCTUATION: [&str; 25] = [
+    ":=", "==", "!=", "<=", ">=", "{", "}", "[", "]", "(", ")", ".", ",", ";", ":", "=", "<", ">", "+", "-",
+    "*", "/", "%", "|", "&",
+];
+
+/// Tokenize a module, ending with `Eof`
+pub fn tokenize(source: &str) -> Result<Vec<Spanned>, CompileError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut line_start) = (0, 1, 0);
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i - line_start + 1;
+        let error = move |message: String| CompileError { line, column, message };
+
+        match c {
+            '\n' => {
+                tokens.push(Spanned { token: Token::Newline, line, column });
+                i += 1;
+                line += 1;
+                line_start = i;
+            }
+            c if c.is_whitespace() => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '"' => {
+                let (value, end) = string(&chars, i + 1).map_err(error)?;
+                tokens.push(Spanned { token: Token::Literal(Value::String(value)), line, column });
+                i = end;
+            }
+            '`' => {
+                let end = (i + 1..chars.len())
+                    .find(|&j| chars[j] == '`')
+                    .ok_or_else(|| error("unterminated raw string".to_string()))?;
+                let value: String = chars[i + 1..end].iter().collect();
+                tokens.push(Spanned { token: Token::Literal(Value::String(value)), line, column });
+                for j in (i + 1..end).filter(|&j| chars[j] == '\n') {
+                    line += 1;
+                    line_start = j + 1;
+                }
+                i = end + 1;
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' && next_is_digit(&chars, i)) {
+                    i += 1;
+                }
+                if i < chars.len() && matches!(chars[i], 'e' | 'E') {
+                    i += 1;
+                    if i < chars.len() && matches!(chars[i], '+' | '-') {
+                        i += 1;
+                    }
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                let value = text
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(Value::number)
+                    .ok_or_else(|| error(format!("invalid number {}", text)))?;
+                tokens.push(Spanned { token: Token::Literal(value), line, column });
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Spanned { token: Token::Ident(chars[start..i].iter().collect()), line, column });
+            }
+            _ => {
+                let punct = PUNCTUATION
+                    .iter()
+                    .find(|p| p.chars().enumerate().all(|(j, pc)| chars.get(i + j) == Some(&pc)))
+                    .ok_or_else(|| error(format!("unexpected character {:?}", c)))?;
+                tokens.push(Spanned { token: Token::Punct(punct), line, column });
+                i += punct.len();
+            }
+        }
+    }
+
+    let column = i - line_start + 1;
+    tokens.push(Spanned { token: Token::Eof, line, column });
+    Ok(tokens)
+}
+
+fn next_is_digit(chars: &[char], i: usize) -> bool {
+    chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Read a double-quoted string starting after its opening quote, returning it and the index after it
+fn string(chars: &[char], mut i: usize) -> Result<(String, usize), String> {
+    let mut value = String::new();
+
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return Ok((value, i + 1)),
+            '\n' => break,
+            '\\' => {
+                let escaped = chars.get(i + 1).ok_or("unterminated string")?;
+                match escaped {
+                    '"' | '\\' | '/' => value.push(*escaped),
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'u' => {
+                        let hex: String = chars.get(i + 2..i + 6).ok_or("invalid \\u escape")?.iter().collect();
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| "invalid \\u escape")?;
+                        value.push(char::from_u32(code).ok_or("invalid \\u escape")?);
+                        i += 4;
+                    }
+                    other => return Err(format!("invalid escape \\{}", other)),
+                }
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    Err("unterminated string".to_string())
+}
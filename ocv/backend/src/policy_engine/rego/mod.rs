@@ -0,0 +1,152 @@
+//! A Rego interpreter for the policies the engine runs
+//!
+//! It supports the parts of the language policies here are written in:
+//! packages, `default` values, complete, partial set and partial object rules
+//! in both the `name { ... }` and `name if { ... }` forms, `not`, `some`, `:=`,
+//! `=`, `in`, comprehensions, references that iterate with `_` or unbound
+//! variables, comparison and arithmetic operators, and common built-in
+//! functions. Functions, `else`, `with` and `every` are rejected as compile
+//! errors instead of being ignored.
+
+mod ast;
+mod builtins;
+mod eval;
+mod lexer;
+mod parser;
+mod value;
+
+use thiserror::Error;
+
+pub use ast::Module;
+use value::Value;
+
+/// A policy that failed to compile, and where
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("line {line}, column {column}: {message}")]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Parse and check a policy module
+pub fn compile(source: &str) -> Result<Module, CompileError> {
+    parser::parse(source)
+}
+
+impl Module {
+    /// Whether the module defines a rule
+    pub fn defines(&self, rule: &str) -> bool {
+        self.rules.iter().any(|r| r.name == rule)
+    }
+
+    /// Evaluate a rule with `input` and `data` as the input and data documents
+    ///
+    /// Returns `None` when the rule is undefined. Sets are returned as arrays.
+    pub fn evaluate(
+        &self,
+        rule: &str,
+        input: &serde_json::Value,
+        data: &serde_json::Value,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        let (input, data) = (Value::from(input.clone()), Value::from(data.clone()));
+        let value = eval::Evaluator::new(self, &input, &data).rule(rule)?;
+        Ok(value.map(serde_json::Value::from))
+    }
+
+    /// Evaluate every rule the module defines, in source order
+    ///
+    /// Undefined rules are left out.
+    pub fn evaluate_all(
+        &self,
+        input: &serde_json::Value,
+        data: &serde_json::Value,
+    ) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+        let (input, data) = (Value::from(input.clone()), Value::from(data.clone()));
+        let evaluator = eval::Evaluator::new(self, &input, &data);
+
+        let mut values: Vec<(String, serde_json::Value)> = Vec::new();
+        for rule in &self.rules {
+            if values.iter().any(|(name, _)| *name == rule.name) {
+                continue;
+            }
+            if let Some(value) = evaluator.rule(&rule.name)? {
+                values.push((rule.name.clone(), value.into()));
+            }
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn eval(source: &str, rule: &str, input: serde_json::Value) -> Option<serde_json::Value> {
+        compile(source).unwrap().evaluate(rule, &input, &json!({})).unwrap()
+    }
+
+    #[test]
+    fn iterates_with_wildcards_and_variables() {
+        let policy = r#"
+            package p
+
+            shared[domain] {
+                input.domains[_] = domain
+                data.p.allowed[domain]
+            }
+            allowed := {"travel", "work"}
+            positions := [i | input.domains[i] == "work"]
+            pairs := {k: v | some k, v in input.labels; v != ""}
+        "#;
+        let input = json!({"domains": ["travel", "health", "work"], "labels": {"a": "x", "b": ""}});
+
+        assert_eq!(eval(policy, "shared", input.clone()), Some(json!(["travel", "work"])));
+        assert_eq!(eval(policy, "positions", input.clone()), Some(json!([2])));
+        assert_eq!(eval(policy, "pairs", input), Some(json!({"a": "x"})));
+    }
+
+    #[test]
+    fn uses_defaults_only_when_no_definition_holds() {
+        let policy = r#"
+            package p
+            import rego.v1
+
+            default level := "none"
+            level := "high" if input.score >= 10
+            level := "low" if {
+                input.score < 10
+                input.score > 0
+            }
+            reasons contains msg if {
+                some domain in input.domains
+                not startswith(domain, "public")
+                msg := sprintf("%s is private", [domain])
+            }
+        "#;
+
+        assert_eq!(eval(policy, "level", json!({"score": 12})), Some(json!("high")));
+        assert_eq!(eval(policy, "level", json!({"score": 3})), Some(json!("low")));
+        assert_eq!(eval(policy, "level", json!({})), Some(json!("none")));
+        assert_eq!(eval(policy, "missing", json!({})), None);
+
+        let all = compile(policy).unwrap().evaluate_all(&json!({"score": 3}), &json!({})).unwrap();
+        assert_eq!(all, [("level".to_string(), json!("low")), ("reasons".to_string(), json!([]))]);
+        assert_eq!(
+            eval(policy, "reasons", json!({"domains": ["public_docs", "health"]})),
+            Some(json!(["health is private"]))
+        );
+    }
+
+    #[test]
+    fn reports_conflicts_and_recursion() {
+        let module = compile("package p\nvalue := 1 { input.a }\nvalue := 2 { input.b }\nloop { loop }").unwrap();
+
+        let input = json!({"a": true, "b": true});
+        assert!(module.evaluate("value", &input, &json!({})).is_err());
+        assert_eq!(module.evaluate("value", &json!({"a": true}), &json!({})).unwrap(), Some(json!(1)));
+        assert!(module.evaluate("loop", &input, &json!({})).is_err());
+    }
+}
@@ -0,0 +1,685 @@
+//! Parses Rego source into a module
+
+use std::collections::HashMap;
+
+use super::ast::{BinaryOp, Expr, Literal, Module, Rule, RuleHead};
+use super::builtins;
+use super::lexer::{tokenize, Spanned, Token};
+use super::value::Value;
+use super::CompileError;
+
+/// Words that cannot name variables or rules
+const KEYWORDS: [&str; 15] = [
+    "package", "import", "default", "not", "some", "in", "if", "contains", "true", "false", "null", "every", "with",
+    "else", "as",
+];
+
+/// Parse and check a module
+pub fn parse(source: &str) -> Result<Module, CompileError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        nesting: 0,
+        wildcards: 0,
+    };
+    let module = parser.module()?;
+    check(&module)?;
+    Ok(module)
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// Brackets the parser is inside, where line breaks do not end a literal
+    nesting: usize,
+    /// `_` wildcards seen so far, each of which becomes its own variable
+    wildcards: usize,
+}
+
+impl Parser {
+    /// Current token, skipping line breaks inside brackets
+    fn peek(&mut self) -> &Spanned {
+        if self.nesting > 0 {
+            self.skip_newlines();
+        }
+        &self.tokens[self.pos]
+    }
+
+    fn advance(&mut self) -> Spanned {
+        let token = self.peek().clone();
+        if token.token != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.tokens[self.pos].token == Token::Newline {
+            self.pos += 1;
+        }
+    }
+
+    fn is_punct(&mut self, punct: &str) -> bool {
+        matches!(self.peek().token, Token::Punct(p) if p == punct)
+    }
+
+    fn is_keyword(&mut self, keyword: &str) -> bool {
+        matches!(&self.peek().token, Token::Ident(name) if name == keyword)
+    }
+
+    fn eat_punct(&mut self, punct: &str) -> bool {
+        let found = self.is_punct(punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), CompileError> {
+        if self.eat_punct(punct) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>) -> CompileError {
+        let token = self.peek();
+        CompileError {
+            line: token.line,
+            column: token.column,
+            message: message.into(),
+        }
+    }
+
+    fn unexpected(&mut self) -> CompileError {
+        let found = match &self.peek().token {
+            Token::Ident(name) => format!("`{}`", name),
+            Token::Literal(_) => "literal".to_string(),
+            Token::Punct(punct) => format!("`{}`", punct),
+            Token::Newline => "end of line".to_string(),
+            Token::Eof => "end of file".to_string(),
+        };
+        self.error(format!("unexpected {}", found))
+    }
+
+    /// A name that is not a keyword
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match &self.peek().token {
+            Token::Ident(name) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// A name, where keywords are allowed, such as after a `.`
+    fn any_ident(&mut self) -> Result<String, CompileError> {
+        match &self.peek().token {
+            Token::Ident(name) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Require the end of a line
+    fn end_of_statement(&mut self) -> Result<(), CompileError> {
+        match self.tokens[self.pos].token {
+            Token::Newline | Token::Eof => Ok(()),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn module(&mut self) -> Result<Module, CompileError> {
+        self.skip_newlines();
+        if !self.eat_keyword("package") {
+            return Err(self.error("expected `package`"));
+        }
+        let mut package = vec![self.ident()?];
+        while self.eat_punct(".") {
+            package.push(self.ident()?);
+        }
+        self.end_of_statement()?;
+
+        let mut rules = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek().token == Token::Eof {
+                break;
+            }
+            if self.is_keyword("import") {
+                self.import()?;
+            } else {
+                rules.push(self.rule()?);
+            }
+            self.end_of_statement()?;
+        }
+
+        Ok(Module { package, rules })
+    }
+
+    /// Accept the imports that only enable keywords
+    fn import(&mut self) -> Result<(), CompileError> {
+        let start = self.advance();
+        let mut path = vec![self.any_ident()?];
+        while self.eat_punct(".") {
+            path.push(self.any_ident()?);
+        }
+
+        let path = path.join(".");
+        if path == "rego.v1" || path == "future.keywords" || path.starts_with("future.keywords.") {
+            Ok(())
+        } else {
+            Err(CompileError {
+                line: start.line,
+                column: start.column,
+                message: format!("import {} is not supported", path),
+            })
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule, CompileError> {
+        let line = self.peek().line;
+
+        if self.eat_keyword("default") {
+            let name = self.ident()?;
+            if !(self.eat_punct(":=") || self.eat_punct("=")) {
+                return Err(self.unexpected());
+            }
+            let value = self.expr()?;
+            if !value.is_constant() {
+                return Err(CompileError {
+                    line,
+                    column: 1,
+                    message: format!("the default value of {} must be a constant", name),
+                });
+            }
+            return Ok(Rule { name, head: RuleHead::Default(value), body: Vec::new(), line });
+        }
+
+        let name = self.ident()?;
+        if self.is_punct("(") {
+            return Err(self.error("functions are not supported"));
+        }
+
+        let mut valued = true;
+        let head = if self.eat_punct("[") {
+            self.nesting += 1;
+            let key = self.expr()?;
+            self.expect_punct("]")?;
+            self.nesting -= 1;
+            if self.eat_punct(":=") || self.eat_punct("=") {
+                RuleHead::Object(key, self.expr()?)
+            } else {
+                RuleHead::Set(key)
+            }
+        } else if self.eat_keyword("contains") {
+            RuleHead::Set(self.expr()?)
+        } else if self.eat_punct(":=") || self.eat_punct("=") {
+            RuleHead::Complete(self.expr()?)
+        } else {
+            valued = false;
+            RuleHead::Complete(Expr::Scalar(Value::Bool(true)))
+        };
+
+        let body = if self.eat_keyword("if") {
+            if self.eat_punct("{") {
+                self.body("}")?
+            } else {
+                vec![self.literal()?]
+            }
+        } else if self.eat_punct("{") {
+            self.body("}")?
+        } else {
+            Vec::new()
+        };
+
+        if body.is_empty() && !valued {
+            return Err(self.error(format!("rule {} needs a body or a value", name)));
+        }
+        if self.is_keyword("else") {
+            return Err(self.error("`else` is not supported"));
+        }
+
+        Ok(Rule { name, head, body, line })
+    }
+
+    /// Literals up to `terminator`, separated by line breaks or `;`
+    fn body(&mut self, terminator: &str) -> Result<Vec<Literal>, CompileError> {
+        let nesting = std::mem::replace(&mut self.nesting, 0);
+        let mut literals = Vec::new();
+
+        loop {
+            while matches!(self.tokens[self.pos].token, Token::Newline | Token::Punct(";")) {
+                self.pos += 1;
+            }
+            if self.is_punct(terminator) {
+                break;
+            }
+            literals.push(self.literal()?);
+            match self.tokens[self.pos].token {
+                Token::Newline | Token::Punct(";") => {}
+                Token::Punct(punct) if punct == terminator => {}
+                _ => return Err(self.unexpected()),
+            }
+        }
+
+        if literals.is_empty() {
+            return Err(self.error("bodies cannot be empty"));
+        }
+        self.pos += 1;
+        self.nesting = nesting;
+        Ok(literals)
+    }
+
+    fn literal(&mut self) -> Result<Literal, CompileError> {
+        if self.eat_keyword("not") {
+            return Ok(Literal::Not(self.expr()?));
+        }
+        if self.eat_keyword("some") {
+            return self.some();
+        }
+        if self.is_keyword("every") {
+            return Err(self.error("`every` is not supported"));
+        }
+
+        let expr = self.expr()?;
+        let literal = if self.is_punct(":=") {
+            let Some(var) = expr.as_var().filter(|var| !var.starts_with('$')).map(str::to_string) else {
+                return Err(self.error("only variables can be assigned with :="));
+            };
+            self.pos += 1;
+            Literal::Assign(var, self.expr()?)
+        } else if self.eat_punct("=") {
+            Literal::Expr(Expr::Binary(BinaryOp::Unify, Box::new(expr), Box::new(self.expr()?)))
+        } else {
+            Literal::Expr(expr)
+        };
+
+        if self.is_keyword("with") {
+            return Err(self.error("`with` is not supported"));
+        }
+        Ok(literal)
+    }
+
+    /// `some x, y` or `some k, v in collection`, after `some`
+    fn some(&mut self) -> Result<Literal, CompileError> {
+        let mut vars = vec![self.term()?];
+        while self.eat_punct(",") {
+            vars.push(self.term()?);
+        }
+
+        if self.eat_keyword("in") {
+            let collection = self.expr()?;
+            let value = vars.pop().expect("at least one variable");
+            return match vars.pop() {
+                None => Ok(Literal::SomeIn { key: None, value, collection }),
+                Some(key) if vars.is_empty() => Ok(Literal::SomeIn { key: Some(key), value, collection }),
+                Some(_) => Err(self.error("`some ... in` takes one or two variables")),
+            };
+        }
+
+        vars.iter()
+            .map(|var| var.as_var().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .map(Literal::Some)
+            .ok_or_else(|| self.error("`some` declares variables"))
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.comparison()?;
+        while self.eat_keyword("in") {
+            let right = self.comparison()?;
+            left = Expr::Binary(BinaryOp::In, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.additive()?;
+        loop {
+            let op = match self.peek().token {
+                Token::Punct("==") => BinaryOp::Eq,
+                Token::Punct("!=") => BinaryOp::Ne,
+                Token::Punct("<") => BinaryOp::Lt,
+                Token::Punct("<=") => BinaryOp::Le,
+                Token::Punct(">") => BinaryOp::Gt,
+                Token::Punct(">=") => BinaryOp::Ge,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.additive()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek().token {
+                Token::Punct("+") => BinaryOp::Add,
+                Token::Punct("-") => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, CompileError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek().token {
+                Token::Punct("*") => BinaryOp::Mul,
+                Token::Punct("/") => BinaryOp::Div,
+                Token::Punct("%") => BinaryOp::Rem,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if !self.eat_punct("-") {
+            return self.term();
+        }
+
+        match self.unary()? {
+            Expr::Scalar(Value::Number(n)) => Ok(Expr::Scalar(
+                n.as_f64().and_then(|n| Value::number(-n)).ok_or_else(|| self.error("invalid number"))?,
+            )),
+            operand => Ok(Expr::Neg(Box::new(operand))),
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, CompileError> {
+        let token = self.peek().clone();
+        match token.token {
+            Token::Literal(value) => {
+                self.pos += 1;
+                Ok(Expr::Scalar(value))
+            }
+            Token::Ident(name) if matches!(name.as_str(), "true" | "false" | "null") => {
+                self.pos += 1;
+                Ok(Expr::Scalar(match name.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => Value::Null,
+                }))
+            }
+            Token::Punct("(") => {
+                self.pos += 1;
+                self.nesting += 1;
+                let expr = self.expr()?;
+                self.expect_punct(")")?;
+                self.nesting -= 1;
+                Ok(expr)
+            }
+            Token::Punct("[") => self.array(),
+            Token::Punct("{") => self.object_or_set(),
+            // `contains` is also a built-in function
+            Token::Ident(ref name)
+                if !KEYWORDS.contains(&name.as_str())
+                    || name == "contains" && self.tokens[self.pos + 1].token == Token::Punct("(") =>
+            {
+                self.reference(token)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// A variable, reference or function call starting at `start`
+    fn reference(&mut self, start: Spanned) -> Result<Expr, CompileError> {
+        let head = self.any_ident()?;
+        let head = if head == "_" { self.wildcard() } else { head };
+        let mut path = Vec::new();
+        let mut dotted = true;
+
+        loop {
+            if self.eat_punct(".") {
+                path.push(Expr::Scalar(Value::String(self.any_ident()?)));
+            } else if self.eat_punct("[") {
+                self.nesting += 1;
+                path.push(self.expr()?);
+                self.expect_punct("]")?;
+                self.nesting -= 1;
+                dotted = false;
+            } else {
+                break;
+            }
+        }
+
+        if !self.is_punct("(") {
+            return Ok(Expr::Ref(head, path));
+        }
+        if !dotted {
+            return Err(self.unexpected());
+        }
+
+        let name = std::iter::once(head)
+            .chain(path.into_iter().filter_map(|step| match step {
+                Expr::Scalar(Value::String(name)) => Some(name),
+                _ => None,
+            }))
+            .collect::<Vec<_>>()
+            .join(".");
+        let error = |message: String| CompileError { line: start.line, column: start.column, message };
+        let arity = builtins::arity(&name).ok_or_else(|| error(format!("unknown function {}", name)))?;
+
+        self.pos += 1;
+        self.nesting += 1;
+        let args = self.list(")")?;
+        self.nesting -= 1;
+
+        if args.len() != arity {
+            return Err(error(format!("{} takes {} argument(s)", name, arity)));
+        }
+        Ok(Expr::Call(name, args))
+    }
+
+    fn wildcard(&mut self) -> String {
+        self.wildcards += 1;
+        format!("$wildcard{}", self.wildcards)
+    }
+
+    /// Comma-separated expressions up to `close`, inside brackets
+    fn list(&mut self, close: &str) -> Result<Vec<Expr>, CompileError> {
+        let mut items = Vec::new();
+        loop {
+            if self.eat_punct(close) {
+                return Ok(items);
+            }
+            items.push(self.expr()?);
+            if !self.eat_punct(",") {
+                self.expect_punct(close)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    /// Array or array comprehension
+    fn array(&mut self) -> Result<Expr, CompileError> {
+        self.pos += 1;
+        self.nesting += 1;
+
+        let expr = if self.eat_punct("]") {
+            Expr::Array(Vec::new())
+        } else {
+            let first = self.expr()?;
+            if self.eat_punct("|") {
+                Expr::ArrayComprehension(Box::new(first), self.body("]")?)
+            } else {
+                let mut items = vec![first];
+                if self.eat_punct(",") {
+                    items.extend(self.list("]")?);
+                } else {
+                    self.expect_punct("]")?;
+                }
+                Expr::Array(items)
+            }
+        };
+
+        self.nesting -= 1;
+        Ok(expr)
+    }
+
+    /// Object, set, or one of their comprehensions
+    fn object_or_set(&mut self) -> Result<Expr, CompileError> {
+        self.pos += 1;
+        self.nesting += 1;
+
+        let expr = if self.eat_punct("}") {
+            Expr::Object(Vec::new())
+        } else {
+            let first = self.expr()?;
+            if self.eat_punct(":") {
+                let value = self.expr()?;
+                if self.eat_punct("|") {
+                    Expr::ObjectComprehension(Box::new(first), Box::new(value), self.body("}")?)
+                } else {
+                    let mut pairs = vec![(first, value)];
+                    loop {
+                        if self.eat_punct("}") {
+                            break;
+                        }
+                        self.expect_punct(",")?;
+                        if self.eat_punct("}") {
+                            break;
+                        }
+                        let key = self.expr()?;
+                        self.expect_punct(":")?;
+                        pairs.push((key, self.expr()?));
+                    }
+                    Expr::Object(pairs)
+                }
+            } else if self.eat_punct("|") {
+                Expr::SetComprehension(Box::new(first), self.body("}")?)
+            } else {
+                let mut items = vec![first];
+                if self.eat_punct(",") {
+                    items.extend(self.list("}")?);
+                } else {
+                    self.expect_punct("}")?;
+                }
+                Expr::Set(items)
+            }
+        };
+
+        self.nesting -= 1;
+        Ok(expr)
+    }
+}
+
+/// Reject rules whose definitions do not fit together
+fn check(module: &Module) -> Result<(), CompileError> {
+    let mut kinds: HashMap<&str, &str> = HashMap::new();
+    let mut defaults: HashMap<&str, usize> = HashMap::new();
+
+    for rule in &module.rules {
+        let error = |message: String| CompileError { line: rule.line, column: 1, message };
+        if matches!(rule.name.as_str(), "input" | "data") {
+            return Err(error(format!("{} cannot be used as a rule name", rule.name)));
+        }
+
+        let kind = match rule.head {
+            RuleHead::Default(_) => {
+                if defaults.insert(&rule.name, rule.line).is_some() {
+                    return Err(error(format!("{} has more than one default", rule.name)));
+                }
+                "complete"
+            }
+            RuleHead::Complete(_) => "complete",
+            RuleHead::Set(_) => "partial set",
+            RuleHead::Object(..) => "partial object",
+        };
+        match kinds.insert(&rule.name, kind) {
+            Some(previous) if previous != kind => {
+                return Err(error(format!("{} is defined as both a {} and a {} rule", rule.name, previous, kind)));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> CompileError {
+        parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn parses_both_rule_syntaxes() {
+        let module = parse(
+            r#"
+            package vault.consent
+            import rego.v1
+
+            default allow := false
+            allow if input.action == "grant"
+            allow {
+                some domain in input.domains
+                not blocked[domain]; count(input.scopes) > 0
+            }
+            blocked contains "health"
+            reasons[domain] := msg if {
+                domain := input.domains[_]
+                msg := sprintf("%s is requested", [domain])
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(module.package, ["vault", "consent"]);
+        let names: Vec<&str> = module.rules.iter().map(|rule| rule.name.as_str()).collect();
+        assert_eq!(names, ["allow", "allow", "allow", "blocked", "reasons"]);
+        assert_eq!(module.rules[2].body.len(), 3);
+    }
+
+    #[test]
+    fn reports_where_errors_are() {
+        let err = error("package p\n\nallow {\n    input.x == \n}\n");
+        assert_eq!((err.line, err.column), (4, 16));
+
+        assert_eq!(error("allow { true }").message, "expected `package`");
+        assert_eq!(error("package p\nallow { input.x = \"a }").message, "unterminated string");
+        assert_eq!(error("package p\nallow { lookup(input.x) }").message, "unknown function lookup");
+        assert_eq!(error("package p\nallow { count(1, 2) }").message, "count takes 1 argument(s)");
+        assert_eq!(error("package p\nallow {}").message, "bodies cannot be empty");
+    }
+
+    #[test]
+    fn rejects_unsupported_features() {
+        assert_eq!(error("package p\nf(x) { x }").message, "functions are not supported");
+        assert_eq!(error("package p\nallow { input.x } else = false { true }").message, "`else` is not supported");
+        assert_eq!(error("package p\nallow { input.x with input as {} }").message, "`with` is not supported");
+        assert_eq!(error("package p\nimport data.users").message, "import data.users is not supported");
+        assert_eq!(
+            error("package p\ndefault allow := input.x").message,
+            "the default value of allow must be a constant"
+        );
+        assert_eq!(
+            error("package p\nallow { true }\nallow contains 1").message,
+            "allow is defined as both a complete and a partial set rule"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Values policies compute with: JSON plus sets
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A Rego value
+///
+/// Values are ordered by type first (null, booleans, numbers, strings, arrays,
+/// objects, sets) and then by content, and numbers compare by magnitude, so
+/// `1 == 1.0`.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(serde_json::Number),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+    Set(BTreeSet<Value>),
+}
+
+impl Value {
+    /// Number value, kept integral when possible
+    pub fn number(n: f64) -> Option<Self> {
+        if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+            Some(Self::Number((n as i64).into()))
+        } else {
+            serde_json::Number::from_f64(n).map(Self::Number)
+        }
+    }
+
+    /// The number, if this is one
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => n.as_f64(),
+            _ => None,
+        }
+    }
+
+    /// The string, if this is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Whether a body literal with this value holds
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Bool(false))
+    }
+
+    /// Key this value is stored under in an object
+    pub fn to_key(&self) -> String {
+        match self {
+            Self::String(s) => s.clone(),
+            other => serde_json::Value::from(other.clone()).to_string(),
+        }
+    }
+
+    /// Members of a collection as (key, value) pairs
+    ///
+    /// Arrays are keyed by index and sets by their members.
+    pub fn entries(&self) -> Vec<(Value, Value)> {
+        match self {
+            Self::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| (Self::Number(i.into()), item.clone()))
+                .collect(),
+            Self::Object(map) => map.iter().map(|(k, v)| (Self::String(k.clone()), v.clone())).collect(),
+            Self::Set(set) => set.iter().map(|v| (v.clone(), v.clone())).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Member of a collection under `key`
+    pub fn get(&self, key: &Value) -> Option<Value> {
+        match (self, key) {
+            (Self::Array(items), Self::Number(n)) => {
+                let index = n.as_f64().filter(|n| n.fract() == 0.0 && *n >= 0.0)?;
+                items.get(index as usize).cloned()
+            }
+            (Self::Object(map), Self::String(k)) => map.get(k).cloned(),
+            (Self::Set(set), _) => set.contains(key).then(|| key.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether a collection holds `value` as one of its members
+    pub fn contains(&self, value: &Value) -> bool {
+        match self {
+            Self::Array(items) => items.contains(value),
+            Self::Object(map) => map.values().any(|v| v == value),
+            Self::Set(set) => set.contains(value),
+            _ => false,
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::Bool(_) => 1,
+            Self::Number(_) => 2,
+            Self::String(_) => 3,
+            Self::Array(_) => 4,
+            Self::Object(_) => 5,
+            Self::Set(_) => 6,
+        }
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Null, Self::Null) => Ordering::Equal,
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::Number(a), Self::Number(b)) => {
+                let (a, b) = (a.as_f64().unwrap_or(f64::NAN), b.as_f64().unwrap_or(f64::NAN));
+                a.total_cmp(&b)
+            }
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Array(a), Self::Array(b)) => a.cmp(b),
+            (Self::Object(a), Self::Object(b)) => a.cmp(b),
+            (Self::Set(a), Self::Set(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Self::Null,
+            serde_json::Value::Bool(b) => Self::Bool(b),
+            serde_json::Value::Number(n) => Self::Number(n),
+            serde_json::Value::String(s) => Self::String(s),
+            serde_json::Value::Array(items) => Self::Array(items.into_iter().map(Self::from).collect()),
+            serde_json::Value::Object(map) => Self::Object(map.into_iter().map(|(k, v)| (k, Self::from(v))).collect()),
+        }
+    }
+}
+
+/// Sets become arrays
+impl From<Value> for serde_json::Value {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(b) => Self::Bool(b),
+            Value::Number(n) => Self::Number(n),
+            Value::String(s) => Self::String(s),
+            Value::Array(items) => Self::Array(items.into_iter().map(Self::from).collect()),
+            Value::Object(map) => Self::Object(map.into_iter().map(|(k, v)| (k, Self::from(v))).collect()),
+            Value::Set(set) => Self::Array(set.into_iter().map(Self::from).collect()),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::models::{Policy, PolicyVersion};
+
+/// Storage for policies and their version history
+#[async_trait]
+pub trait PolicyRepository: Send + Sync {
+    /// Store version 1 of a new policy, returning `None` if the name is taken
+    async fn create_policy(&self, name: &str, source: &str, author_id: Option<Uuid>) -> Result<Option<Policy>>;
+
+    /// Add a version to a policy and make it current, returning `None` if there is no such policy
+    async fn add_version(&self, name: &str, source: &str, author_id: Uuid) -> Result<Option<Policy>>;
+
+    /// Make an earlier version of a policy current, returning `None` if there is no such version
+    async fn set_current_version(&self, name: &str, version: i32) -> Result<Option<Policy>>;
+
+    /// Enable or disable a policy
+    async fn set_enabled(&self, name: &str, enabled: bool) -> Result<Option<Policy>>;
+
+    /// Get a policy with its current version
+    async fn get_policy(&self, name: &str) -> Result<Option<Policy>>;
+
+    /// List all policies by name
+    async fn list_policies(&self) -> Result<Vec<Policy>>;
+
+    /// List a policy's versions, newest first
+    async fn list_versions(&self, name: &str) -> Result<Vec<PolicyVersion>>;
+}
+
+/// Postgres repository for policies
+pub struct PgPolicyRepository {
+    pool: PgPool,
+}
+
+impl PgPolicyRepository {
+    /// Create a new policy repository
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Selects policies joined with their current version
+const SELECT_POLICIES: &str = r#"
+    SELECT p.name, p.current_version AS version, v.source, v.author_id, p.enabled, p.created_at, p.updated_at
+    FROM policies p
+    JOIN policy_versions v ON v.policy_name = p.name AND v.version = p.current_version
+"#;
+
+#[async_trait]
+impl PolicyRepository for PgPolicyRepository {
+    /// Store version 1 of a new policy
+    async fn create_policy(&self, name: &str, source: &str, author_id: Option<Uuid>) -> Result<Option<Policy>> {
+        let mut tx = self.pool.begin().await?;
+
+        let created = sqlx::query(
+            r#"
+            INSERT INTO policies (name, current_version)
+            VALUES ($1, 1)
+            ON CONFLICT (name) DO NOTHING
+            "#,
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+
+        if created.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO policy_versions (policy_name, version, source, author_id)
+            VALUES ($1, 1, $2, $3)
+            "#,
+        )
+        .bind(name)
+        .bind(source)
+        .bind(author_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.get_policy(name).await
+    }
+
+    /// Add a version to a policy and make it current
+    async fn add_version(&self, name: &str, source: &str, author_id: Uuid) -> Result<Option<Policy>> {
+        let mut tx = self.pool.begin().await?;
+
+        // Locks the policy so concurrent changes get distinct versions
+        let exists: Option<String> = sqlx::query_scalar("SELECT name FROM policies WHERE name = $1 FOR UPDATE")
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        if exists.is_none() {
+            return Ok(None);
+        }
+
+        let latest: i32 = sqlx::query_scalar("SELECT MAX(version) FROM policy_versions WHERE policy_name = $1")
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO policy_versions (policy_name, version, source, author_id)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(name)
+        .bind(latest + 1)
+        .bind(source)
+        .bind(author_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE policies
+            SET current_version = $2, updated_at = NOW()
+            WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .bind(latest + 1)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.get_policy(name).await
+    }
+
+    /// Make an earlier version of a policy current
+    async fn set_current_version(&self, name: &str, version: i32) -> Result<Option<Policy>> {
+        let result = sqlx::query(
+            r#"
+            UPDATE policies
+            SET current_version = $2, updated_at = NOW()
+            WHERE name = $1
+              AND EXISTS (SELECT 1 FROM policy_versions WHERE policy_name = $1 AND version = $2)
+            "#,
+        )
+        .bind(name)
+        .bind(version)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_policy(name).await
+    }
+
+    /// Enable or disable a policy
+    async fn set_enabled(&self, name: &str, enabled: bool) -> Result<Option<Policy>> {
+        let result = sqlx::query(
+            r#"
+            UPDATE policies
+            SET enabled = $2, updated_at = NOW()
+            WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_policy(name).await
+    }
+
+    /// Get a policy with its current version
+    async fn get_policy(&self, name: &str) -> Result<Option<Policy>> {
+        let policy = sqlx::query_as::<_, Policy>(&format!("{} WHERE p.name = $1", SELECT_POLICIES))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(policy)
+    }
+
+    /// List all policies by name
+    async fn list_policies(&self) -> Result<Vec<Policy>> {
+        let policies = sqlx::query_as::<_, Policy>(&format!("{} ORDER BY p.name", SELECT_POLICIES))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(policies)
+    }
+
+    /// List a policy's versions, newest first
+    async fn list_versions(&self, name: &str) -> Result<Vec<PolicyVersion>> {
+        let versions = sqlx::query_as::<_, PolicyVersion>(
+            r#"
+            SELECT policy_name, version, source, author_id, created_at
+            FROM policy_versions
+            WHERE policy_name = $1
+            ORDER BY version DESC
+            "#,
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+}
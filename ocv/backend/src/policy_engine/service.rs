@@ -1,88 +1,475 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
-use serde_json::Value;
+use serde_json::{json, Value};
+use uuid::Uuid;
 
-/// Simple policy engine using Open Policy Agent concepts
+use super::{
+    models::{Decision, Obligation, Policy, PolicyError, PolicyVersion},
+    rego::{self, Module},
+    repository::{PgPolicyRepository, PolicyRepository},
+    sqlite::SqlitePolicyRepository,
+};
+use crate::consent_manager::{
+    models::CreateAuditLogInput,
+    repository::{ConsentRepository, PgConsentRepository},
+    service::VAULT_CLIENT_ID,
+    sqlite::SqliteConsentRepository,
+};
+use crate::storage::Database;
+
+/// Policies every vault starts with, by name
+const DEFAULT_POLICIES: [(&str, &str); 2] = [
+    ("consent", r#"package consent
+
+# Allow users to grant access to their own data
+allow {
+    input.action == "grant"
+    input.user != null
+    not needs_two_factor
+}
+
+# Sensitive domains need two-factor authentication
+needs_two_factor {
+    input.domains[_] == data.sensitive_domains[_]
+    not input.two_factor
+}
+
+reasons[msg] {
+    domain := input.domains[_]
+    domain == data.sensitive_domains[_]
+    not input.two_factor
+    msg := sprintf("%s is a sensitive domain; turn on two-factor authentication first", [domain])
+}
+
+# Allow users to revoke their own grants
+allow {
+    input.action == "revoke"
+    input.user != null
+    input.grant_id != null
+}
+
+# Default deny
+default allow = false
+"#),
+    ("client_registration", r#"package client_registration
+
+# Allow clients to register themselves for any domain
+allow {
+    input.action == "register"
+    count(input.redirect_uris) > 0
+}
+
+# Default deny
+default allow = false
+"#),
+];
+
+/// Compiled policy versions by name and version, which never change once stored
+type CompiledPolicies = HashMap<(String, i32), Arc<Module>>;
+
+/// Policy engine that evaluates stored Rego policies
+///
+/// Policies are versioned in the database, and every change is written to the
+/// audit log of the administrator who made it.
 #[derive(Clone)]
 pub struct PolicyEngine {
-    // In a real implementation, this would use OPA WASM modules
-    // This is a simplified version for demonstration purposes
-    policies: Arc<RwLock<HashMap<String, String>>>,
+    repository: Arc<dyn PolicyRepository>,
+    audit: Arc<dyn ConsentRepository>,
+    compiled: Arc<RwLock<CompiledPolicies>>,
+    /// Domains only users with two-factor authentication may grant access to
+    sensitive_domains: Arc<RwLock<HashSet<String>>>,
 }
 
 impl PolicyEngine {
     /// Create a new policy engine
-    pub fn new() -> Arc<Self> {
-        let mut policies = HashMap::new();
-        
-        // Add default policies
-        policies.insert(
-            "consent".to_string(),
-            r#"
-            package consent
-
-            # Allow users to grant access to their own data
-            allow {
-                input.action == "grant"
-                input.user != null
-            }
-            
-            # Allow users to revoke their own grants
-            allow {
-                input.action == "revoke"
-                input.user != null
-                input.grant_id != null
-            }
-            
-            # Default deny
-            default allow = false
-            "#.to_string(),
-        );
-        
+    pub fn new(db: &Database) -> Arc<Self> {
+        let (repository, audit): (Arc<dyn PolicyRepository>, Arc<dyn ConsentRepository>) = match db {
+            Database::Postgres(pool) => (
+                Arc::new(PgPolicyRepository::new(pool.clone())),
+                Arc::new(PgConsentRepository::new(pool.clone())),
+            ),
+            Database::Sqlite(pool) => (
+                Arc::new(SqlitePolicyRepository::new(pool.clone())),
+                Arc::new(SqliteConsentRepository::new(pool.clone())),
+            ),
+        };
+
         Arc::new(Self {
-            policies: Arc::new(RwLock::new(policies)),
+            repository,
+            audit,
+            compiled: Arc::new(RwLock::new(HashMap::new())),
+            sensitive_domains: Arc::new(RwLock::new(HashSet::new())),
         })
     }
     
-    /// Add or update a policy
-    pub async fn update_policy(&self, name: &str, policy: &str) -> Result<()> {
-        let mut policies = self.policies.write().unwrap();
-        policies.insert(name.to_string(), policy.to_string());
+    /// Store the built-in policies that are not stored yet
+    ///
+    /// Policies already stored, including changed ones, are left alone.
+    pub async fn install_default_policies(&self) -> Result<()> {
+        for (name, source) in DEFAULT_POLICIES {
+            if self.repository.create_policy(name, source, None).await?.is_some() {
+                log::info!("Installed the default {} policy", name);
+            }
+        }
         Ok(())
     }
     
-    /// Evaluate a policy against input data
-    pub async fn evaluate(&self, policy_name: &str, input: Value) -> Result<bool> {
-        let policies = self.policies.read().unwrap();
+    /// Only let users with two-factor authentication grant access to these domains
+    pub fn require_two_factor_for(&self, domains: impl IntoIterator<Item = String>) {
+        let mut sensitive_domains = self.sensitive_domains.write().unwrap();
+        *sensitive_domains = domains.into_iter().collect();
+    }
+    
+    /// Whether granting access to a domain requires two-factor authentication
+    pub fn is_sensitive(&self, domain: &str) -> bool {
+        self.sensitive_domains.read().unwrap().contains(domain)
+    }
+    
+    /// List all policies
+    pub async fn policies(&self) -> Result<Vec<Policy>> {
+        self.repository.list_policies().await
+    }
+    
+    /// Get a policy with its current version
+    pub async fn get_policy(&self, name: &str) -> Result<Option<Policy>> {
+        self.repository.get_policy(name).await
+    }
+    
+    /// List a policy's versions, newest first
+    pub async fn policy_versions(&self, name: &str) -> Result<Vec<PolicyVersion>> {
+        self.repository.list_versions(name).await
+    }
+    
+    /// Add a policy, which must compile and define `allow`
+    pub async fn create_policy(&self, author_id: Uuid, name: &str, source: &str) -> Result<Policy> {
+        validate_name(name)?;
+        compile(name, source)?;
         
-        // Check if the policy exists
-        let policy = match policies.get(policy_name) {
-            Some(p) => p,
-            None => return Err(anyhow::anyhow!("Policy not found: {}", policy_name)),
-        };
+        let policy = self.repository.create_policy(name, source, Some(author_id)).await?
+            .ok_or_else(|| PolicyError::AlreadyExists(name.to_string()))?;
+        
+        self.record_change(author_id, "policy_created", &policy, json!({})).await?;
+        Ok(policy)
+    }
+    
+    /// Replace a policy with a new version, which must compile and define `allow`
+    pub async fn update_policy(&self, author_id: Uuid, name: &str, source: &str) -> Result<Policy> {
+        compile(name, source)?;
         
-        // In a real implementation, this would compile and evaluate the policy
-        // with OPA WASM. This is a simplified version that makes basic decisions.
+        let policy = self.repository.add_version(name, source, author_id).await?
+            .ok_or_else(|| PolicyError::NotFound(name.to_string()))?;
         
-        // For now, we'll just make some simple decisions based on the input
-        if policy_name == "consent" {
-            let action = input.get("action").and_then(|a| a.as_str()).unwrap_or("");
-            
-            match action {
-                "grant" => {
-                    // Allow granting as long as there's a user
-                    input.get("user").is_some()
-                },
-                "revoke" => {
-                    // Allow revoking as long as there's a user and grant_id
-                    input.get("user").is_some() && input.get("grant_id").is_some()
+        self.record_change(author_id, "policy_updated", &policy, json!({})).await?;
+        Ok(policy)
+    }
+    
+    /// Put an earlier version of a policy back in force
+    pub async fn rollback_policy(&self, author_id: Uuid, name: &str, version: i32) -> Result<Policy> {
+        let previous = self.repository.get_policy(name).await?
+            .ok_or_else(|| PolicyError::NotFound(name.to_string()))?;
+        
+        let policy = self.repository.set_current_version(name, version).await?
+            .ok_or_else(|| PolicyError::VersionNotFound { name: name.to_string(), version })?;
+        
+        self.record_change(author_id, "policy_rolled_back", &policy, json!({ "from_version": previous.version })).await?;
+        Ok(policy)
+    }
+    
+    /// Enable or disable a policy; a disabled policy denies every request
+    pub async fn set_policy_enabled(&self, author_id: Uuid, name: &str, enabled: bool) -> Result<Policy> {
+        let policy = self.repository.set_enabled(name, enabled).await?
+            .ok_or_else(|| PolicyError::NotFound(name.to_string()))?;
+        
+        let action = if enabled { "policy_enabled" } else { "policy_disabled" };
+        self.record_change(author_id, action, &policy, json!({})).await?;
+        Ok(policy)
+    }
+    
+    /// Evaluate the current version of a policy against input data
+    ///
+    /// The policy's `allow` rule decides. Its other rules that are `true` are
+    /// listed in the decision, along with the strings in `reasons` and, if the
+    /// request is allowed, the objects in `obligations`. The configured
+    /// sensitive domains are available as `data.sensitive_domains`.
+    pub async fn evaluate(&self, policy_name: &str, input: Value) -> Result<Decision> {
+        let policy = self.repository.get_policy(policy_name).await?
+            .ok_or_else(|| PolicyError::NotFound(policy_name.to_string()))?;
+        if !policy.enabled {
+            return Ok(Decision::deny(format!("The {} policy is disabled", policy_name)));
+        }
+        let module = self.compiled(&policy)?;
+        
+        let mut sensitive_domains: Vec<String> = self.sensitive_domains.read().unwrap().iter().cloned().collect();
+        sensitive_domains.sort();
+        let data = json!({ "sensitive_domains": sensitive_domains });
+        
+        let mut decision = Decision::default();
+        for (rule, value) in module.evaluate_all(&input, &data)? {
+            if value == Value::Bool(true) {
+                decision.rules.push(format!("{}.{}", policy_name, rule));
+            }
+            match rule.as_str() {
+                "allow" => {
+                    decision.allow = value.as_bool()
+                        .ok_or_else(|| PolicyError::NotBoolean(policy_name.to_string(), value.clone()))?;
                 },
-                _ => false,
+                "reasons" => decision.reasons = reasons(policy_name, value)?,
+                "obligations" => decision.obligations = obligations(policy_name, value)?,
+                _ => {},
+            }
+        }
+        
+        if !decision.allow {
+            decision.obligations.clear();
+            if decision.reasons.is_empty() {
+                decision.reasons.push(format!("The {} policy does not allow this", policy_name));
             }
-        } else {
-            // Default deny for unknown policies
-            false
         }
+        Ok(decision)
+    }
+    
+    /// Compiled module of a policy's current version
+    fn compiled(&self, policy: &Policy) -> Result<Arc<Module>, PolicyError> {
+        let key = (policy.name.clone(), policy.version);
+        if let Some(module) = self.compiled.read().unwrap().get(&key) {
+            return Ok(module.clone());
+        }
+        
+        let module = compile(&policy.name, &policy.source)?;
+        self.compiled.write().unwrap().insert(key, module.clone());
+        Ok(module)
+    }
+    
+    /// Write a policy change to the audit log of the administrator who made it
+    async fn record_change(&self, author_id: Uuid, action: &str, policy: &Policy, mut details: Value) -> Result<()> {
+        details["policy"] = json!(policy.name);
+        details["version"] = json!(policy.version);
+        
+        self.audit.create_audit_log(CreateAuditLogInput {
+            user_id: author_id,
+            client_id: VAULT_CLIENT_ID.to_string(),
+            action: action.to_string(),
+            details,
+        }, Utc::now()).await?;
+        
+        Ok(())
+    }
+}
+
+/// Compile a policy, checking it defines `allow`
+fn compile(name: &str, source: &str) -> Result<Arc<Module>, PolicyError> {
+    let module = rego::compile(source).map_err(|error| PolicyError::Compile {
+        name: name.to_string(),
+        error,
+    })?;
+    if !module.defines("allow") {
+        return Err(PolicyError::MissingAllow(name.to_string()));
+    }
+    Ok(Arc::new(module))
+}
+
+/// Strings a policy gave as reasons, from a set, an array or a single string
+fn reasons(name: &str, value: Value) -> Result<Vec<String>, PolicyError> {
+    let invalid = || PolicyError::InvalidReasons(name.to_string(), value.clone());
+    match &value {
+        Value::String(reason) => Ok(vec![reason.clone()]),
+        Value::Array(items) => items.iter().map(|item| item.as_str().map(str::to_string).ok_or_else(invalid)).collect(),
+        _ => Err(invalid()),
+    }
+}
+
+/// Obligations a policy gave, from a set, an array or a single object
+fn obligations(name: &str, value: Value) -> Result<Vec<Obligation>, PolicyError> {
+    let items = match &value {
+        Value::Array(items) => items.clone(),
+        Value::Object(_) => vec![value.clone()],
+        _ => return Err(PolicyError::InvalidObligations(name.to_string(), value)),
+    };
+    items
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()
+        .map_err(|_| PolicyError::InvalidObligations(name.to_string(), value))
+}
+
+fn validate_name(name: &str) -> Result<(), PolicyError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(PolicyError::InvalidName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::identity::repository::IdentityRepository;
+    use crate::identity::sqlite::SqliteIdentityRepository;
+    use crate::identity::CreateUserInput;
+    use crate::storage::migrations;
+
+    /// Engine with the default policies, and an administrator's ID
+    async fn engine() -> (Arc<PolicyEngine>, Uuid, Arc<dyn ConsentRepository>) {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+        let Database::Sqlite(pool) = &db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+        let admin = SqliteIdentityRepository::new(pool.clone())
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let engine = PolicyEngine::new(&db);
+        engine.install_default_policies().await.unwrap();
+        (engine, admin.id, Arc::new(SqliteConsentRepository::new(pool.clone())))
+    }
+
+    #[tokio::test]
+    async fn consent_policy_decisions() {
+        let (engine, _, _) = engine().await;
+        engine.require_two_factor_for(["health".to_string()]);
+
+        let grant = |domains: &[&str], two_factor: bool| {
+            json!({"user": "u", "action": "grant", "client": "c", "domains": domains, "scopes": ["read"], "two_factor": two_factor})
+        };
+        let cases = [
+            (grant(&["travel"], false), true),
+            (grant(&["travel", "health"], false), false),
+            (grant(&["travel", "health"], true), true),
+            (json!({"action": "grant", "domains": ["travel"], "two_factor": false}), false),
+            (json!({"user": "u", "action": "revoke", "client": "c", "grant_id": "g"}), true),
+            (json!({"user": "u", "action": "revoke", "client": "c"}), false),
+            (json!({"user": "u", "action": "delete"}), false),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(engine.evaluate("consent", input.clone()).await.unwrap().allow, expected, "{}", input);
+        }
+
+        let denied = engine.evaluate("consent", grant(&["health"], false)).await.unwrap();
+        assert_eq!(denied.rules, ["consent.needs_two_factor"]);
+        assert_eq!(denied.reasons, ["health is a sensitive domain; turn on two-factor authentication first"]);
+        let allowed = engine.evaluate("consent", grant(&["travel"], false)).await.unwrap();
+        assert_eq!((allowed.rules, allowed.reasons), (vec!["consent.allow".to_string()], vec![]));
+
+        let register = |uris: &[&str]| json!({"action": "register", "redirect_uris": uris});
+        assert!(engine.evaluate("client_registration", register(&["https://app.example/cb"])).await.unwrap().allow);
+        assert!(!engine.evaluate("client_registration", register(&[])).await.unwrap().allow);
+        assert!(engine.evaluate("missing", json!({})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn updated_policies_are_compiled_and_used() {
+        let (engine, admin, _) = engine().await;
+        let input = json!({"action": "register", "redirect_uris": ["https://app.example/cb"], "client_name": "Sketchy"});
+
+        let err = engine
+            .update_policy(admin, "client_registration", "package client_registration\nallow {\n  input.x ==\n}")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PolicyError>(),
+            Some(PolicyError::Compile { error, .. }) if (error.line, error.column) == (3, 13)
+        ));
+        let err = engine.update_policy(admin, "client_registration", "package client_registration\ndeny { true }").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PolicyError>(), Some(PolicyError::MissingAllow(_))));
+        assert!(engine.evaluate("client_registration", input.clone()).await.unwrap().allow);
+
+        let updated = engine
+            .update_policy(
+                admin,
+                "client_registration",
+                r#"
+                package client_registration
+                import rego.v1
+
+                default allow := false
+                allow if {
+                    input.action == "register"
+                    not contains(lower(input.client_name), "sketchy")
+                }
+                "#,
+            )
+            .await
+            .unwrap();
+        assert_eq!((updated.version, updated.author_id), (2, Some(admin)));
+        assert!(!engine.evaluate("client_registration", input.clone()).await.unwrap().allow);
+
+        // Restarts keep the stored version
+        engine.install_default_policies().await.unwrap();
+        assert!(!engine.evaluate("client_registration", input).await.unwrap().allow);
+
+        engine.create_policy(admin, "custom", "package custom\nallow := input.level").await.unwrap();
+        engine
+            .create_policy(
+                admin,
+                "obligated",
+                r#"
+                package obligated
+                import rego.v1
+
+                allow if input.level < 3
+                obligations contains {"type": "expire", "days": 7} if input.level > 1
+                reasons contains "Level 2 access expires within a week" if input.level == 2
+                "#,
+            )
+            .await
+            .unwrap();
+        let decision = engine.evaluate("obligated", json!({"level": 2})).await.unwrap();
+        assert!(decision.allow);
+        assert_eq!(serde_json::to_value(&decision.obligations).unwrap(), json!([{"type": "expire", "days": 7}]));
+        assert_eq!(decision.reasons, ["Level 2 access expires within a week"]);
+        let decision = engine.evaluate("obligated", json!({"level": 3})).await.unwrap();
+        assert!(!decision.allow && decision.obligations.is_empty());
+        assert_eq!(decision.reasons, ["The obligated policy does not allow this"]);
+        assert!(engine.evaluate("custom", json!({"level": 3})).await.is_err());
+        assert!(!engine.evaluate("custom", json!({})).await.unwrap().allow);
+    }
+
+    #[tokio::test]
+    async fn rollbacks_and_disabling_are_audited() {
+        let (engine, admin, audit) = engine().await;
+        let input = json!({"action": "register", "redirect_uris": ["https://app.example/cb"]});
+
+        engine.update_policy(admin, "client_registration", "package client_registration\nallow := false").await.unwrap();
+        assert!(!engine.evaluate("client_registration", input.clone()).await.unwrap().allow);
+
+        let rolled_back = engine.rollback_policy(admin, "client_registration", 1).await.unwrap();
+        assert_eq!(rolled_back.version, 1);
+        assert!(engine.evaluate("client_registration", input.clone()).await.unwrap().allow);
+        let err = engine.rollback_policy(admin, "client_registration", 7).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PolicyError>(), Some(PolicyError::VersionNotFound { .. })));
+
+        // A disabled policy denies everything until it is enabled again
+        engine.set_policy_enabled(admin, "client_registration", false).await.unwrap();
+        assert!(!engine.evaluate("client_registration", input.clone()).await.unwrap().allow);
+        engine.set_policy_enabled(admin, "client_registration", true).await.unwrap();
+        assert!(engine.evaluate("client_registration", input).await.unwrap().allow);
+
+        let err = engine.create_policy(admin, "consent", "package consent\nallow := true").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PolicyError>(), Some(PolicyError::AlreadyExists(_))));
+        let err = engine.create_policy(admin, "Consent-2", "package consent\nallow := true").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<PolicyError>(), Some(PolicyError::InvalidName(_))));
+
+        let logs = audit.get_audit_logs(admin, None, None).await.unwrap();
+        let mut changes: Vec<(&str, i64)> =
+            logs.iter().map(|log| (log.action.as_str(), log.details["version"].as_i64().unwrap())).collect();
+        changes.sort();
+        assert_eq!(
+            changes,
+            [("policy_disabled", 1), ("policy_enabled", 1), ("policy_rolled_back", 1), ("policy_updated", 2)]
+        );
+        let rollback = logs.iter().find(|log| log.action == "policy_rolled_back").unwrap();
+        assert_eq!((rollback.client_id.as_str(), &rollback.details["from_version"]), (VAULT_CLIENT_ID, &json!(2)));
     }
 }
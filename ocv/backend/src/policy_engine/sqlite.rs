@@ -0,0 +1,235 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::models::{Policy, PolicyVersion};
+use super::repository::PolicyRepository;
+
+/// SQLite repository for policies
+pub struct SqlitePolicyRepository {
+    pool: SqlitePool,
+}
+
+impl SqlitePolicyRepository {
+    /// Create a new policy repository
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Selects policies joined with their current version
+const SELECT_POLICIES: &str = r#"
+    SELECT p.name, p.current_version AS version, v.source, v.author_id, p.enabled, p.created_at, p.updated_at
+    FROM policies p
+    JOIN policy_versions v ON v.policy_name = p.name AND v.version = p.current_version
+"#;
+
+#[async_trait]
+impl PolicyRepository for SqlitePolicyRepository {
+    async fn create_policy(&self, name: &str, source: &str, author_id: Option<Uuid>) -> Result<Option<Policy>> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let created = sqlx::query(
+            r#"
+            INSERT INTO policies (name, current_version, created_at, updated_at)
+            VALUES ($1, 1, $2, $2)
+            ON CONFLICT (name) DO NOTHING
+            "#,
+        )
+        .bind(name)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        if created.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO policy_versions (policy_name, version, source, author_id, created_at)
+            VALUES ($1, 1, $2, $3, $4)
+            "#,
+        )
+        .bind(name)
+        .bind(source)
+        .bind(author_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.get_policy(name).await
+    }
+
+    async fn add_version(&self, name: &str, source: &str, author_id: Uuid) -> Result<Option<Policy>> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let latest: Option<i32> = sqlx::query_scalar("SELECT MAX(version) FROM policy_versions WHERE policy_name = $1")
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let Some(latest) = latest else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO policy_versions (policy_name, version, source, author_id, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(name)
+        .bind(latest + 1)
+        .bind(source)
+        .bind(author_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE policies
+            SET current_version = $2, updated_at = $3
+            WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .bind(latest + 1)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.get_policy(name).await
+    }
+
+    async fn set_current_version(&self, name: &str, version: i32) -> Result<Option<Policy>> {
+        let result = sqlx::query(
+            r#"
+            UPDATE policies
+            SET current_version = $2, updated_at = $3
+            WHERE name = $1
+              AND EXISTS (SELECT 1 FROM policy_versions WHERE policy_name = $1 AND version = $2)
+            "#,
+        )
+        .bind(name)
+        .bind(version)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_policy(name).await
+    }
+
+    async fn set_enabled(&self, name: &str, enabled: bool) -> Result<Option<Policy>> {
+        let result = sqlx::query(
+            r#"
+            UPDATE policies
+            SET enabled = $2, updated_at = $3
+            WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .bind(enabled)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_policy(name).await
+    }
+
+    async fn get_policy(&self, name: &str) -> Result<Option<Policy>> {
+        let policy = sqlx::query_as::<_, Policy>(&format!("{} WHERE p.name = $1", SELECT_POLICIES))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(policy)
+    }
+
+    async fn list_policies(&self) -> Result<Vec<Policy>> {
+        let policies = sqlx::query_as::<_, Policy>(&format!("{} ORDER BY p.name", SELECT_POLICIES))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(policies)
+    }
+
+    async fn list_versions(&self, name: &str) -> Result<Vec<PolicyVersion>> {
+        let versions = sqlx::query_as::<_, PolicyVersion>(
+            r#"
+            SELECT policy_name, version, source, author_id, created_at
+            FROM policy_versions
+            WHERE policy_name = $1
+            ORDER BY version DESC
+            "#,
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(versions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::repository::IdentityRepository;
+    use crate::identity::sqlite::SqliteIdentityRepository;
+    use crate::identity::CreateUserInput;
+    use crate::storage::{migrations, Database};
+
+    #[tokio::test]
+    async fn keeps_every_version_of_a_policy() {
+        let db = Database::connect("sqlite::memory:", 1).await.unwrap();
+        migrations::run_migrations(&db).await.unwrap();
+        let Database::Sqlite(pool) = db else {
+            unreachable!("sqlite URL must yield a sqlite pool");
+        };
+        let author = SqliteIdentityRepository::new(pool.clone())
+            .create_user(CreateUserInput {
+                email: "ada@example.com".to_string(),
+                display_name: "Ada".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .await
+            .unwrap()
+            .id;
+        let repo = SqlitePolicyRepository::new(pool);
+
+        let created = repo.create_policy("consent", "v1", None).await.unwrap().unwrap();
+        assert_eq!((created.version, created.author_id, created.enabled), (1, None, true));
+        assert!(repo.create_policy("consent", "again", None).await.unwrap().is_none());
+
+        let updated = repo.add_version("consent", "v2", author).await.unwrap().unwrap();
+        assert_eq!((updated.version, updated.source.as_str(), updated.author_id), (2, "v2", Some(author)));
+        assert!(repo.add_version("missing", "v1", author).await.unwrap().is_none());
+
+        let rolled_back = repo.set_current_version("consent", 1).await.unwrap().unwrap();
+        assert_eq!((rolled_back.version, rolled_back.source.as_str()), (1, "v1"));
+        assert!(repo.set_current_version("consent", 3).await.unwrap().is_none());
+
+        // Versions keep counting up from the latest, not the current one
+        let updated = repo.add_version("consent", "v3", author).await.unwrap().unwrap();
+        assert_eq!(updated.version, 3);
+        let versions: Vec<i32> = repo.list_versions("consent").await.unwrap().iter().map(|v| v.version).collect();
+        assert_eq!(versions, [3, 2, 1]);
+
+        assert!(!repo.set_enabled("consent", false).await.unwrap().unwrap().enabled);
+        assert!(repo.set_enabled("missing", false).await.unwrap().is_none());
+        assert_eq!(repo.list_policies().await.unwrap().len(), 1);
+    }
+}
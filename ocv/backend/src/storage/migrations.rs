@@ -1,96 +1,131 @@
-use sqlx::migrate::MigrateDatabase;
-use sqlx::{PgPool, Postgres};
-use std::env;
-
-/// Run database migrations
-pub async fn run_migrations(url: &str) -> anyhow::Result<()> {
-    // Create the database if it doesn't exist
-    if !Postgres::database_exists(url).await? {
-        Postgres::create_database(url).await?;
+use anyhow::Result;
+use sqlx::migrate::{Migrate, MigrateDatabase, Migrator};
+use sqlx::{Postgres, Sqlite};
+
+use super::{is_sqlite_url, Database};
+
+/// Postgres migrations embedded from `./migrations/postgres` at compile time
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// SQLite migrations embedded from `./migrations/sqlite` at compile time
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// State of a single migration in the database
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    /// Migration version
+    pub version: i64,
+    /// Human-readable description
+    pub description: String,
+    /// Whether the migration has been applied
+    pub applied: bool,
+    /// Whether the applied checksum differs from the embedded SQL
+    pub checksum_mismatch: bool,
+}
+
+/// Create the database if it doesn't exist
+///
+/// Returns true if the database was created.
+pub async fn create_database(url: &str) -> Result<bool> {
+    if is_sqlite_url(url) {
+        if Sqlite::database_exists(url).await? {
+            return Ok(false);
+        }
+        Sqlite::create_database(url).await?;
+        return Ok(true);
     }
-    
-    // Connect to the database
-    let pool = PgPool::connect(url).await?;
-    
-    // Run migrations
-    sqlx::migrate!("./migrations")
-        .run(&pool)
-        .await?;
-    
-    Ok(())
+
+    if Postgres::database_exists(url).await? {
+        return Ok(false);
+    }
+
+    Postgres::create_database(url).await?;
+
+    Ok(true)
 }
 
-/// Create migration files
-pub fn create_migration_files() -> anyhow::Result<()> {
-    // These would be the SQL migration files in a real project
-    // For this example, we'll just print what they would contain
-    
-    println!("Migration 1: Create Users Table");
-    println!("
-    CREATE TABLE users (
-        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-        email TEXT NOT NULL UNIQUE,
-        display_name TEXT NOT NULL,
-        password_hash TEXT NOT NULL,
-        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
-        updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
-    );
-    ");
-    
-    println!("Migration 2: Create Context Shards Table");
-    println!("
-    CREATE EXTENSION IF NOT EXISTS vector;
-    
-    CREATE TABLE context_shards (
-        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-        domain TEXT NOT NULL,
-        content_type TEXT NOT NULL,
-        vector_representation FLOAT[] NULL,
-        metadata JSONB NOT NULL DEFAULT '{}',
-        content BYTEA NOT NULL,
-        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
-        updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
-        version INT NOT NULL DEFAULT 1
-    );
-    
-    CREATE INDEX idx_context_shards_user_domain ON context_shards(user_id, domain);
-    CREATE INDEX idx_context_shards_vector ON context_shards USING ivfflat (vector_representation vector_l2_ops)
-    WITH (lists = 100) WHERE vector_representation IS NOT NULL;
-    ");
-    
-    println!("Migration 3: Create Access Grants Table");
-    println!("
-    CREATE TABLE access_grants (
-        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-        client_id TEXT NOT NULL,
-        scopes TEXT[] NOT NULL,
-        context_domains TEXT[] NOT NULL,
-        expires_at TIMESTAMPTZ NULL,
-        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
-    );
-    
-    CREATE INDEX idx_access_grants_user ON access_grants(user_id);
-    CREATE INDEX idx_access_grants_client ON access_grants(client_id);
-    CREATE INDEX idx_access_grants_expires ON access_grants(expires_at)
-    WHERE expires_at IS NOT NULL;
-    ");
-    
-    println!("Migration 4: Create Consent Audit Logs Table");
-    println!("
-    CREATE TABLE consent_audit_logs (
-        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-        client_id TEXT NOT NULL,
-        action TEXT NOT NULL,
-        details JSONB NOT NULL DEFAULT '{}',
-        timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
-    );
-    
-    CREATE INDEX idx_consent_audit_logs_user ON consent_audit_logs(user_id);
-    CREATE INDEX idx_consent_audit_logs_timestamp ON consent_audit_logs(timestamp);
-    ");
-    
+/// Apply all pending migrations
+///
+/// Safe to call on every start: applied migrations are skipped and
+/// concurrent runners are serialised by a database lock.
+pub async fn run_migrations(db: &Database) -> Result<()> {
+    match db {
+        Database::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await?,
+        Database::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await?,
+    }
+
     Ok(())
 }
+
+/// Revert the most recently applied `count` migrations
+///
+/// Returns the reverted versions, newest first.
+pub async fn revert_migrations(db: &Database, count: usize) -> Result<Vec<i64>> {
+    let mut applied: Vec<i64> = applied_migrations(db)
+        .await?
+        .into_iter()
+        .map(|(version, _)| version)
+        .collect();
+    applied.sort_unstable_by(|a, b| b.cmp(a));
+
+    let count = count.min(applied.len());
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Everything above the target version is reverted
+    let target = applied.get(count).copied().unwrap_or(0);
+    match db {
+        Database::Postgres(pool) => POSTGRES_MIGRATOR.undo(pool, target).await?,
+        Database::Sqlite(pool) => SQLITE_MIGRATOR.undo(pool, target).await?,
+    }
+
+    Ok(applied[..count].to_vec())
+}
+
+/// List every embedded migration with its applied state
+pub async fn migration_status(db: &Database) -> Result<Vec<MigrationStatus>> {
+    let migrator = match db {
+        Database::Postgres(_) => &POSTGRES_MIGRATOR,
+        Database::Sqlite(_) => &SQLITE_MIGRATOR,
+    };
+    let applied = applied_migrations(db).await?;
+
+    let status = migrator
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .map(|m| {
+            let applied = applied.iter().find(|(version, _)| *version == m.version);
+
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: applied.is_some(),
+                checksum_mismatch: applied.is_some_and(|(_, checksum)| checksum[..] != m.checksum[..]),
+            }
+        })
+        .collect();
+
+    Ok(status)
+}
+
+/// Versions and checksums currently recorded as applied
+async fn applied_migrations(db: &Database) -> Result<Vec<(i64, Vec<u8>)>> {
+    let applied = match db {
+        Database::Postgres(pool) => {
+            let mut conn = pool.acquire().await?;
+            conn.ensure_migrations_table().await?;
+            conn.list_applied_migrations().await?
+        },
+        Database::Sqlite(pool) => {
+            let mut conn = pool.acquire().await?;
+            conn.ensure_migrations_table().await?;
+            conn.list_applied_migrations().await?
+        },
+    };
+
+    Ok(applied
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect())
+}
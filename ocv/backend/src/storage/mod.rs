@@ -1,3 +1,63 @@
 pub mod migrations;
 
-// Re-export key types
+use anyhow::Result;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{PgPool, SqlitePool};
+use std::str::FromStr;
+
+/// Connection pool for the configured database backend
+///
+/// The backend is picked from the scheme of the database URL:
+/// `postgres://` for a server deployment, `sqlite:` for a local vault file.
+#[derive(Clone, Debug)]
+pub enum Database {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+impl Database {
+    /// Connect to the database at `url`
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        if is_sqlite_url(url) {
+            let options = SqliteConnectOptions::from_str(url)?
+                .create_if_missing(true)
+                .foreign_keys(true);
+
+            let pool = SqlitePoolOptions::new()
+                .max_connections(max_connections)
+                .connect_with(options)
+                .await?;
+
+            return Ok(Self::Sqlite(pool));
+        }
+
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(url)
+            .await?;
+
+        Ok(Self::Postgres(pool))
+    }
+
+    /// Check that a connection can be acquired
+    pub async fn ping(&self) -> bool {
+        match self {
+            Self::Postgres(pool) => pool.acquire().await.is_ok(),
+            Self::Sqlite(pool) => pool.acquire().await.is_ok(),
+        }
+    }
+
+    /// Close every pooled connection
+    pub async fn close(&self) {
+        match self {
+            Self::Postgres(pool) => pool.close().await,
+            Self::Sqlite(pool) => pool.close().await,
+        }
+    }
+}
+
+/// Whether a database URL refers to SQLite
+pub fn is_sqlite_url(url: &str) -> bool {
+    url.starts_with("sqlite:")
+}
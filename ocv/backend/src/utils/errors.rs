@@ -1,6 +1,8 @@
 use thiserror::Error;
 use async_graphql::ErrorExtensions;
 
+use crate::policy_engine::Decision;
+
 /// Application-specific errors
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -10,6 +12,9 @@ pub enum AppError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
     
+    #[error("Access denied: {}", .0.explanation())]
+    AccessDenied(Decision),
+    
     #[error("Validation error: {0}")]
     ValidationError(String),
     
@@ -29,6 +34,13 @@ impl ErrorExtensions for AppError {
             match self {
                 AppError::NotFound(_) => e.set("code", "NOT_FOUND"),
                 AppError::Unauthorized(_) => e.set("code", "UNAUTHORIZED"),
+                AppError::AccessDenied(decision) => {
+                    e.set("code", "ACCESS_DENIED");
+                    e.set("rules", decision.rules.clone());
+                    e.set("reasons", decision.reasons.clone());
+                    let obligations = serde_json::json!(decision.obligations);
+                    e.set("obligations", async_graphql::Value::from_json(obligations).unwrap_or_default());
+                },
                 AppError::ValidationError(_) => e.set("code", "VALIDATION_ERROR"),
                 AppError::DatabaseError(_) => e.set("code", "DATABASE_ERROR"),
                 AppError::EncryptionError(_) => e.set("code", "ENCRYPTION_ERROR"),
@@ -1 +1,3 @@
+pub mod clock;
 pub mod errors;
+pub mod tokens;
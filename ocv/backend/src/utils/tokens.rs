@@ -0,0 +1,16 @@
+//! Random bearer secrets and the hashes they are stored under
+
+/// Fresh 256-bit random secret, base64url encoded
+pub fn random_token() -> String {
+    base64_url(&sodiumoxide::randombytes::randombytes(32))
+}
+
+/// Base64url SHA-256 of a value, used to store secrets and check PKCE verifiers
+pub fn sha256_base64url(value: &str) -> String {
+    base64_url(ring::digest::digest(&ring::digest::SHA256, value.as_bytes()).as_ref())
+}
+
+/// Unpadded base64url
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
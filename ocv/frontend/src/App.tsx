@@ -12,6 +12,8 @@ import DashboardPage from './pages/DashboardPage';
 import GrantsPage from './pages/GrantsPage';
 import AuditLogPage from './pages/AuditLogPage';
 import ConsentRequestPage from './pages/ConsentRequestPage';
+import VerifyEmailPage from './pages/VerifyEmailPage';
+import ResetPasswordPage from './pages/ResetPasswordPage';
 
 const App: React.FC = () => {
   const { isAuthenticated } = useAuth();
@@ -22,6 +24,8 @@ const App: React.FC = () => {
       <Route path="/login" element={<LoginPage />} />
       <Route path="/register" element={<RegisterPage />} />
       <Route path="/consent-request/:requestId" element={<ConsentRequestPage />} />
+      <Route path="/verify-email" element={<VerifyEmailPage />} />
+      <Route path="/reset-password" element={<ResetPasswordPage />} />
       
       {/* Protected routes */}
       <Route path="/" element={
@@ -1,129 +1,187 @@
-import React, { useState, useEffect } from 'react';
-import { useParams, useNavigate } from 'react-router-dom';
-import { OcvClient } from '@ocv/sdk';
+import React, { useState } from 'react';
+import { useParams } from 'react-router-dom';
 
-interface ConsentRequest {
-  requestId: string;
+const API_URL = process.env.REACT_APP_API_URL || 'http://localhost:8000/api/graphql';
+
+/** Registered details of the client asking for access */
+interface Client {
+  name: string;
+  logoUri: string | null;
+  homepageUri: string | null;
+}
+
+/** Authorization request as returned by the vault */
+interface AuthorizationRequest {
+  id: string;
   clientId: string;
-  clientName: string;
-  scopes: string[];
-  domains: string[];
-  userId: string;
+  client: Client | null;
   redirectUri: string;
+  scopes: string[];
+  contextDomains: string[];
+  expiresAt: string;
+}
+
+/** Extensions the vault adds to its errors */
+interface ErrorExtensions {
+  code?: string;
+  challenge?: string;
+  retryAfter?: number;
+  /** Why a policy or one of the user's consent rules denied the request */
+  reasons?: string[];
+}
+
+/** First error of a GraphQL response, with its extensions */
+class GraphQLError extends Error {
+  constructor(message: string, public extensions: ErrorExtensions = {}) {
+    super(message);
+  }
+}
+
+/** Run a GraphQL operation against the vault, throwing on errors */
+async function graphql<T>(query: string, variables: object, token?: string): Promise<T> {
+  const response = await fetch(API_URL, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+      ...(token ? { Authorization: `Bearer ${token}` } : {}),
+    },
+    body: JSON.stringify({ query, variables }),
+  });
+  const body = await response.json();
+  if (!response.ok || body.errors?.length) {
+    const error = body.errors?.[0];
+    throw new GraphQLError(error?.message || `Request failed with ${response.status}`, error?.extensions);
+  }
+  return body.data;
 }
 
 const ConsentRequestPage: React.FC = () => {
   const { requestId } = useParams<{ requestId: string }>();
-  const navigate = useNavigate();
-  const [request, setRequest] = useState<ConsentRequest | null>(null);
-  const [isLoading, setIsLoading] = useState(true);
+  const [token, setToken] = useState<string | null>(null);
+  const [request, setRequest] = useState<AuthorizationRequest | null>(null);
   const [error, setError] = useState<string | null>(null);
   const [email, setEmail] = useState('');
   const [password, setPassword] = useState('');
+  const [challenge, setChallenge] = useState<string | null>(null);
+  const [code, setCode] = useState('');
   const [isSubmitting, setIsSubmitting] = useState(false);
 
-  useEffect(() => {
-    // In a real implementation, we would fetch the consent request details
-    // For this example, we'll use mock data
-    const mockRequest: ConsentRequest = {
-      requestId: requestId || 'unknown',
-      clientId: 'travel-demo-app',
-      clientName: 'Travel Demo Application',
-      scopes: ['read', 'write'],
-      domains: ['travel-preferences', 'travel-history'],
-      userId: '', // Will be filled after authentication
-      redirectUri: 'http://localhost:3001/callback',
-    };
-    
-    setRequest(mockRequest);
-    setIsLoading(false);
-  }, [requestId]);
+  /** Sign in with the password, or with the authentication code once it is asked for */
+  const signIn = async (): Promise<string | null> => {
+    if (challenge) {
+      const { completeLogin } = await graphql<{ completeLogin: { token: string } }>(
+        `mutation CompleteLogin($challenge: String!, $code: String!) {
+          completeLogin(challenge: $challenge, code: $code) { token }
+        }`,
+        { challenge, code },
+      );
+      return completeLogin.token;
+    }
+
+    try {
+      const { login } = await graphql<{ login: { token: string } | null }>(
+        `mutation Login($email: String!, $password: String!) {
+          login(credentials: { email: $email, password: $password }) { token }
+        }`,
+        { email, password },
+      );
+      if (!login) {
+        throw new Error('Authentication failed');
+      }
+      return login.token;
+    } catch (err) {
+      if (err instanceof GraphQLError && err.extensions.code === 'TWO_FACTOR_REQUIRED') {
+        setChallenge(err.extensions.challenge ?? null);
+        return null;
+      }
+      throw err;
+    }
+  };
 
   const handleLogin = async (e: React.FormEvent) => {
     e.preventDefault();
-    
-    if (!request) return;
-    
+
     setIsSubmitting(true);
     setError(null);
-    
+
     try {
-      // Create a temporary client for authentication
-      const client = new OcvClient({
-        endpoint: process.env.REACT_APP_API_URL || 'http://localhost:8000/api/graphql',
-        clientId: 'ocv-consent-ui',
-      });
-      
-      // Authenticate the user
-      const authResult = await client.identity.login({ email, password });
-      
-      if (!authResult) {
-        throw new Error('Authentication failed');
+      const accessToken = await signIn();
+      if (!accessToken) {
+        return;
+      }
+
+      const { authorizationRequest } = await graphql<{ authorizationRequest: AuthorizationRequest | null }>(
+        `query AuthorizationRequest($id: ID!) {
+          authorizationRequest(id: $id) {
+            id clientId redirectUri scopes contextDomains expiresAt
+            client { name logoUri homepageUri }
+          }
+        }`,
+        { id: requestId },
+        accessToken,
+      );
+
+      setToken(accessToken);
+      setRequest(authorizationRequest);
+      if (!authorizationRequest) {
+        setError('The authorization request is invalid or has expired.');
       }
-      
-      // Now we have the user ID, update the request
-      const updatedRequest = {
-        ...request,
-        userId: authResult.user.id,
-      };
-      
-      // Grant access
-      await client.consent.grantAccess({
-        userId: authResult.user.id,
-        clientId: updatedRequest.clientId,
-        scopes: updatedRequest.scopes,
-        contextDomains: updatedRequest.domains,
-        expiresAt: undefined, // No expiration
-      });
-      
-      // Redirect back to the application
-      window.location.href = `${updatedRequest.redirectUri}?requestId=${updatedRequest.requestId}&granted=true`;
-      
     } catch (err) {
       console.error('Consent flow error:', err);
+      if (err instanceof GraphQLError && err.extensions.code === 'TOO_MANY_REQUESTS') {
+        setError(`Too many failed sign-ins. Try again in ${err.extensions.retryAfter} seconds.`);
+        return;
+      }
       setError('Authentication failed. Please check your credentials and try again.');
+    } finally {
       setIsSubmitting(false);
     }
   };
 
-  const handleDeny = () => {
-    if (!request) return;
-    
-    // Redirect back to the application with granted=false
-    window.location.href = `${request.redirectUri}?requestId=${request.requestId}&granted=false`;
-  };
+  /** Approve or deny the request and follow the redirect back to the client */
+  const decide = async (approve: boolean) => {
+    if (!request || !token) return;
 
-  if (isLoading) {
-    return (
-      <div className="flex min-h-screen items-center justify-center bg-gray-50">
-        <div className="text-center">
-          <div className="inline-block h-8 w-8 animate-spin rounded-full border-4 border-solid border-indigo-600 border-r-transparent"></div>
-          <p className="mt-4 text-gray-700">Loading request...</p>
-        </div>
-      </div>
-    );
-  }
+    setIsSubmitting(true);
+    setError(null);
 
-  if (!request) {
-    return (
-      <div className="flex min-h-screen items-center justify-center bg-gray-50">
-        <div className="text-center">
-          <h2 className="text-xl font-bold text-red-600">Invalid Request</h2>
-          <p className="mt-2 text-gray-600">The consent request is invalid or has expired.</p>
-        </div>
-      </div>
-    );
-  }
+    try {
+      const field = approve ? 'approveAuthorization' : 'denyAuthorization';
+      const data = await graphql<Record<string, string>>(
+        `mutation Decide($id: ID!) { ${field}(id: $id) }`,
+        { id: request.id },
+        token,
+      );
+      window.location.href = data[field];
+    } catch (err) {
+      console.error('Consent flow error:', err);
+      if (err instanceof GraphQLError && err.extensions.code === 'ACCESS_DENIED') {
+        setError(`This access cannot be granted: ${err.extensions.reasons?.join('; ')}.`);
+      } else {
+        setError('The request could not be completed. It may have expired.');
+      }
+      setIsSubmitting(false);
+    }
+  };
 
   return (
     <div className="flex min-h-screen flex-col items-center justify-center bg-gray-50 py-12 px-4 sm:px-6 lg:px-8">
       <div className="w-full max-w-md">
         <div className="text-center">
+          {request?.client?.logoUri && (
+            <img src={request.client.logoUri} alt="" className="mx-auto mb-4 h-12 w-12 rounded" />
+          )}
           <h2 className="text-3xl font-bold tracking-tight text-gray-900">
             Authorization Request
           </h2>
           <p className="mt-2 text-gray-600">
-            <span className="font-semibold">{request.clientName}</span> is requesting access to your data
+            {request ? (
+              <>
+                <span className="font-semibold">{request.client?.name ?? request.clientId}</span> is requesting access to your data
+              </>
+            ) : (
+              'Sign in to review an application\'s request for access to your data'
+            )}
           </p>
         </div>
 
@@ -134,6 +192,7 @@ const ConsentRequestPage: React.FC = () => {
             </div>
           )}
 
+          {request ? (
           <div className="space-y-6">
             <div>
               <h3 className="text-lg font-medium text-gray-900">Requested Permissions</h3>
@@ -156,7 +215,7 @@ const ConsentRequestPage: React.FC = () => {
               <h3 className="text-lg font-medium text-gray-900">Data Categories</h3>
               <div className="mt-2">
                 <div className="flex flex-wrap gap-2">
-                  {request.domains.map((domain) => (
+                  {request.contextDomains.map((domain) => (
                     <span
                       key={domain}
                       className="inline-flex items-center rounded-full bg-indigo-100 px-3 py-0.5 text-sm font-medium text-indigo-800"
@@ -166,9 +225,63 @@ const ConsentRequestPage: React.FC = () => {
                   ))}
                 </div>
               </div>
+              <p className="mt-4 text-xs text-gray-500">
+                You will be returned to {new URL(request.redirectUri).host}.
+                {request.client?.homepageUri && (
+                  <>
+                    {' '}
+                    <a href={request.client.homepageUri} className="text-indigo-600 hover:text-indigo-500">
+                      About this app
+                    </a>
+                  </>
+                )}
+              </p>
             </div>
 
+            <div className="flex justify-between space-x-4">
+              <button
+                type="button"
+                onClick={() => decide(false)}
+                disabled={isSubmitting}
+                className="flex w-full justify-center rounded-md border border-gray-300 bg-white py-2 px-4 text-sm font-medium text-gray-700 shadow-sm hover:bg-gray-50 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2 disabled:opacity-50"
+              >
+                Deny
+              </button>
+              <button
+                type="button"
+                onClick={() => decide(true)}
+                disabled={isSubmitting}
+                className="flex w-full justify-center rounded-md border border-transparent bg-indigo-600 py-2 px-4 text-sm font-medium text-white shadow-sm hover:bg-indigo-700 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2 disabled:opacity-50"
+              >
+                {isSubmitting ? 'Authorizing...' : 'Authorize'}
+              </button>
+            </div>
+          </div>
+          ) : (
             <form className="space-y-6" onSubmit={handleLogin}>
+              {challenge ? (
+              <div>
+                <label htmlFor="code" className="block text-sm font-medium text-gray-700">
+                  Authentication code
+                </label>
+                <div className="mt-1">
+                  <input
+                    id="code"
+                    name="code"
+                    type="text"
+                    autoComplete="one-time-code"
+                    required
+                    value={code}
+                    onChange={(e) => setCode(e.target.value)}
+                    className="block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 sm:text-sm"
+                  />
+                </div>
+                <p className="mt-2 text-xs text-gray-500">
+                  Enter the code from your authenticator app, or one of your recovery codes.
+                </p>
+              </div>
+              ) : (
+              <>
               <div>
                 <label htmlFor="email" className="block text-sm font-medium text-gray-700">
                   Email address
@@ -204,25 +317,18 @@ const ConsentRequestPage: React.FC = () => {
                   />
                 </div>
               </div>
+              </>
+              )}
 
-              <div className="flex justify-between space-x-4">
-                <button
-                  type="button"
-                  onClick={handleDeny}
-                  className="flex w-full justify-center rounded-md border border-gray-300 bg-white py-2 px-4 text-sm font-medium text-gray-700 shadow-sm hover:bg-gray-50 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2"
-                >
-                  Deny
-                </button>
-                <button
-                  type="submit"
-                  disabled={isSubmitting}
-                  className="flex w-full justify-center rounded-md border border-transparent bg-indigo-600 py-2 px-4 text-sm font-medium text-white shadow-sm hover:bg-indigo-700 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2 disabled:opacity-50"
-                >
-                  {isSubmitting ? 'Authorizing...' : 'Authorize'}
-                </button>
-              </div>
+              <button
+                type="submit"
+                disabled={isSubmitting}
+                className="flex w-full justify-center rounded-md border border-transparent bg-indigo-600 py-2 px-4 text-sm font-medium text-white shadow-sm hover:bg-indigo-700 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2 disabled:opacity-50"
+              >
+                {isSubmitting ? 'Signing in...' : 'Sign in'}
+              </button>
             </form>
-          </div>
+          )}
         </div>
       </div>
     </div>
@@ -82,6 +82,12 @@ const LoginPage: React.FC = () => {
               </div>
             </div>
 
+            <div className="text-right text-sm">
+              <Link to="/reset-password" className="font-medium text-indigo-600 hover:text-indigo-500">
+                Forgot your password?
+              </Link>
+            </div>
+
             <div>
               <button
                 type="submit"
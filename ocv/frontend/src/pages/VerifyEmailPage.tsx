@@ -0,0 +1,63 @@
+import React, { useEffect, useState } from 'react';
+import { Link, useSearchParams } from 'react-router-dom';
+
+const API_URL = process.env.REACT_APP_API_URL || 'http://localhost:8000/api/graphql';
+
+type Status = 'verifying' | 'verified' | 'failed';
+
+const VerifyEmailPage: React.FC = () => {
+  const [searchParams] = useSearchParams();
+  const token = searchParams.get('token');
+  const [status, setStatus] = useState<Status>(token ? 'verifying' : 'failed');
+
+  useEffect(() => {
+    if (!token) return;
+
+    const verify = async () => {
+      try {
+        const response = await fetch(API_URL, {
+          method: 'POST',
+          headers: { 'Content-Type': 'application/json' },
+          body: JSON.stringify({
+            query: 'mutation VerifyEmail($token: String!) { verifyEmail(token: $token) { emailVerified } }',
+            variables: { token },
+          }),
+        });
+        const body = await response.json();
+        setStatus(body.data?.verifyEmail?.emailVerified ? 'verified' : 'failed');
+      } catch (err) {
+        console.error('Email verification error:', err);
+        setStatus('failed');
+      }
+    };
+
+    verify();
+  }, [token]);
+
+  return (
+    <div className="flex min-h-screen flex-col justify-center py-12 sm:px-6 lg:px-8 bg-gray-50">
+      <div className="sm:mx-auto sm:w-full sm:max-w-md">
+        <h2 className="mt-6 text-center text-3xl font-bold tracking-tight text-gray-900">
+          Verify your email
+        </h2>
+      </div>
+
+      <div className="mt-8 sm:mx-auto sm:w-full sm:max-w-md">
+        <div className="bg-white py-8 px-4 shadow sm:rounded-lg sm:px-10 text-center text-sm text-gray-700">
+          {status === 'verifying' && <p>Verifying your email address...</p>}
+          {status === 'verified' && <p>Your email address is verified. You can now grant applications access.</p>}
+          {status === 'failed' && (
+            <div className="rounded-md bg-red-50 p-4 text-red-700">
+              This link is invalid or has expired. Sign in to have a new one sent.
+            </div>
+          )}
+          <Link to="/login" className="mt-6 inline-block font-medium text-indigo-600 hover:text-indigo-500">
+            Go to sign in
+          </Link>
+        </div>
+      </div>
+    </div>
+  );
+};
+
+export default VerifyEmailPage;